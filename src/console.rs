@@ -0,0 +1,174 @@
+// Text-mode stand-in for the VGA window (--ascii-vga): composites frames like
+// the window does and draws them in the terminal with 256-color ANSI escapes,
+// so graphics programs can be watched over SSH.
+//
+// Each character cell covers 8x16 logical pixels: an upper-half block whose
+// foreground is the average of the top 8x8 pixels and whose background is the
+// average of the bottom 8x8, giving one colored block per tile.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    sync::{Arc, Mutex, RwLock, atomic::Ordering},
+    thread,
+    time::Duration,
+};
+
+use ::image::Rgba;
+
+use crate::memory::*;
+use crate::render::{Frame, FrameSources, render_and_present_frame};
+
+pub const CONSOLE_COLUMNS: u32 = FRAME_WIDTH / TILE_WIDTH;
+pub const CONSOLE_ROWS: u32 = FRAME_HEIGHT / (2 * TILE_WIDTH);
+const BLOCK_HEIGHT: u32 = TILE_WIDTH;
+
+// Purpose: redraw rate of the console when --ascii-fps is not given.
+// Invariants: every redraw also bumps the VGA frame register and raises the
+// vblank interrupt, as a window update does, so the rate is the guest-visible
+// vblank rate while the console is active.
+pub const DEFAULT_CONSOLE_RATE: u32 = 10;
+
+// Purpose: map an RGB color onto the 6x6x6 cube of the 256-color palette.
+// Outputs: a palette index in 16..=231.
+fn ansi_256(color: Rgba<u8>) -> u8 {
+    let level = |channel: u8| ((u32::from(channel) * 5 + 127) / 255) as u8;
+    16 + 36 * level(color[0]) + 6 * level(color[1]) + level(color[2])
+}
+
+// Average color of the BLOCK_HEIGHT x TILE_WIDTH block at (x, y).
+fn block_color(frame: &Frame, x: u32, y: u32) -> Rgba<u8> {
+    let mut sums = [0u32; 3];
+    for dy in 0..BLOCK_HEIGHT {
+        for dx in 0..TILE_WIDTH {
+            let pixel = frame.get_pixel(x + dx, y + dy);
+            for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+                *sum += u32::from(channel);
+            }
+        }
+    }
+    let count = BLOCK_HEIGHT * TILE_WIDTH;
+    Rgba([
+        (sums[0] / count) as u8,
+        (sums[1] / count) as u8,
+        (sums[2] / count) as u8,
+        255,
+    ])
+}
+
+// Purpose: draw a composited frame as CONSOLE_ROWS lines of colored blocks.
+// Inputs: a FRAME_WIDTH x FRAME_HEIGHT frame.
+// Outputs: the text to print, starting with a cursor-home escape so each
+// frame overwrites the last; colors are only re-sent when they change.
+pub fn frame_to_ansi(frame: &Frame) -> String {
+    let mut out = String::from("\x1b[H");
+    for row in 0..CONSOLE_ROWS {
+        let mut current = None;
+        for column in 0..CONSOLE_COLUMNS {
+            let x = column * TILE_WIDTH;
+            let y = row * 2 * BLOCK_HEIGHT;
+            let top = ansi_256(block_color(frame, x, y));
+            let bottom = ansi_256(block_color(frame, x, y + BLOCK_HEIGHT));
+            if current != Some((top, bottom)) {
+                let _ = write!(out, "\x1b[38;5;{};48;5;{}m", top, bottom);
+                current = Some((top, bottom));
+            }
+            out.push('\u{2580}');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+// Purpose: drive the console until the run finishes.
+// Inputs: the run's memory, the flag the run loop sets when it exits and the
+// redraw rate in frames per second.
+// Outputs: a thread that redraws at `rate` and draws the final frame once the
+// run is over.
+pub fn spawn_console(
+    memory: Arc<Memory>,
+    finished: Arc<Mutex<bool>>,
+    rate: u32,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let sources = FrameSources::from_memory(&memory);
+        let vga_status = memory.get_vga_status_register();
+        let vga_frame = memory.get_vga_frame_register();
+        let pending_interrupt = memory.get_pending_interrupt();
+        let period = Duration::from_secs(1) / rate.max(1);
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+        // Clear the screen once; frames then redraw in place.
+        print!("\x1b[2J");
+        loop {
+            let done = *finished.lock().unwrap();
+            update(&sources, &vga_status, &vga_frame, &mut frame);
+            pending_interrupt.fetch_or(VGA_INTERRUPT_BIT, Ordering::SeqCst);
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(frame_to_ansi(&frame).as_bytes());
+            let _ = stdout.flush();
+            drop(stdout);
+            if done {
+                break;
+            }
+            thread::sleep(period);
+        }
+    })
+}
+
+// One display refresh, mirroring the window: busy status while compositing,
+// then bump the frame counter and report idle.
+fn update(
+    sources: &FrameSources,
+    vga_status: &RwLock<u8>,
+    vga_frame: &RwLock<(u8, u8, u8, u8)>,
+    frame: &mut Frame,
+) {
+    *vga_status.write().unwrap() = 0;
+    render_and_present_frame(sources, frame);
+    let mut counter = vga_frame.write().unwrap();
+    let next = u32::from_le_bytes([counter.0, counter.1, counter.2, counter.3]).wrapping_add(1);
+    let [b0, b1, b2, b3] = next.to_le_bytes();
+    *counter = (b0, b1, b2, b3);
+    drop(counter);
+    *vga_status.write().unwrap() = 3;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::render::render_frame;
+
+    #[test]
+    fn drawn_frame_renders_as_colored_blocks() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        // A red tile at tile (1, 0) over a black background.
+        memory.get_tile_map().write().unwrap().tiles[1]
+            .pixels
+            .chunks_mut(2)
+            .for_each(|pixel| pixel.copy_from_slice(&[0x0F, 0x00]));
+        memory
+            .get_tile_frame_buffer()
+            .write()
+            .unwrap()
+            .set_byte(2, 1);
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+        render_frame(&FrameSources::from_memory(&memory), &mut frame);
+
+        let text = frame_to_ansi(&frame);
+        assert!(text.starts_with("\x1b[H"));
+        assert_eq!(text.lines().count(), CONSOLE_ROWS as usize);
+        assert_eq!(
+            text.matches('\u{2580}').count(),
+            (CONSOLE_COLUMNS * CONSOLE_ROWS) as usize
+        );
+        // Black cell, then a red-over-black cell, then black again.
+        let red = ansi_256(Rgba([240, 0, 0, 255]));
+        let black = ansi_256(Rgba([0, 0, 0, 255]));
+        assert_eq!((red, black), (196, 16));
+        assert!(text.starts_with(
+            "\x1b[H\x1b[38;5;16;48;5;16m\u{2580}\x1b[38;5;196;48;5;16m\u{2580}\x1b[38;5;16;48;5;16m"
+        ));
+    }
+}
@@ -22,9 +22,10 @@ fn fmt_imm_signed(value: i32) -> String {
 }
 
 fn alu_op_name(op: u32) -> Option<&'static str> {
-    const OPS: [&str; 19] = [
+    const OPS: [&str; 29] = [
         "and", "nand", "or", "nor", "xor", "xnor", "not", "lsl", "lsr", "asr", "rotl", "rotr",
-        "lslc", "lsrc", "add", "addc", "sub", "subb", "mul",
+        "lslc", "lsrc", "add", "addc", "sub", "subb", "sxtb", "sxtd", "tncb", "tncd", "udiv",
+        "sdiv", "umod", "smod", "clz", "bswap", "hswap",
     ];
     OPS.get(op as usize).copied()
 }
@@ -65,13 +66,18 @@ fn disassemble_alu_reg(instr: u32) -> String {
     let op = (instr >> 5) & 0x1F;
 
     let Some(name) = alu_op_name(op) else {
-        return format!("data {}", fmt_imm_hex(instr));
+        return format!(".word {}", fmt_imm_hex(instr));
     };
 
     if op == 6 {
         return format!("{} {}, {}", name, reg_name(r_a), reg_name(r_c));
     }
 
+    // single-operand ops that read r_b
+    if (26..=28).contains(&op) {
+        return format!("{} {}, {}", name, reg_name(r_a), reg_name(r_b));
+    }
+
     if op == 16 && r_a == 0 {
         return format!("cmp {}, {}", reg_name(r_b), reg_name(r_c));
     }
@@ -92,7 +98,7 @@ fn disassemble_alu_imm(instr: u32) -> String {
     let imm = instr & 0xFFF;
 
     let Some(name) = alu_op_name(op) else {
-        return format!("data {}", fmt_imm_hex(instr));
+        return format!(".word {}", fmt_imm_hex(instr));
     };
 
     let (imm_str, _is_hex) = decode_alu_imm(op, imm);
@@ -115,14 +121,18 @@ fn disassemble_lui(instr: u32) -> String {
 }
 
 fn disassemble_mem(opcode: u32, instr: u32) -> String {
-    let group = opcode - 3;
+    // Opcodes 23..=28 are the sign-extending loads, laid out like 6..=11.
+    let signed = opcode >= 23;
+    let group = if signed { opcode - 20 } else { opcode - 3 };
     let width_type = group / 3;
     let addr_type = group % 3;
 
-    let (store_base, load_base) = match width_type {
-        0 => ("sw", "lw"),
-        1 => ("sd", "ld"),
-        _ => ("sb", "lb"),
+    let (store_base, load_base) = match (width_type, signed) {
+        (0, _) => ("sw", "lw"),
+        (1, false) => ("sd", "ld"),
+        (1, true) => ("sd", "lds"),
+        (_, false) => ("sb", "lb"),
+        (_, true) => ("sb", "lbs"),
     };
 
     let r_a = (instr >> 22) & 0x1F;
@@ -132,6 +142,10 @@ fn disassemble_mem(opcode: u32, instr: u32) -> String {
         ((instr >> 16) & 1) != 0
     };
 
+    if signed && !is_load {
+        return format!(".word {}", fmt_imm_hex(instr));
+    }
+
     let mut mnemonic = if is_load { load_base } else { store_base }.to_string();
     if addr_type == 0 {
         mnemonic.push('a');
@@ -189,11 +203,16 @@ fn disassemble_mem(opcode: u32, instr: u32) -> String {
     }
 }
 
+// Byte offset from pc + 4 encoded in a branch-immediate word.
+fn branch_offset(instr: u32) -> i32 {
+    sign_extend(instr & 0x3FFFFF, 22).wrapping_mul(4)
+}
+
 fn disassemble_branch_imm(instr: u32) -> String {
     let op = (instr >> 22) & 0x1F;
-    let imm = sign_extend((instr & 0x3FFFFF) << 2, 22);
+    let imm = branch_offset(instr);
     let Some(name) = branch_name(op) else {
-        return format!("data {}", fmt_imm_hex(instr));
+        return format!(".word {}", fmt_imm_hex(instr));
     };
     format!("{} {}", name, fmt_imm_signed(imm))
 }
@@ -203,7 +222,7 @@ fn disassemble_branch_abs(instr: u32) -> String {
     let r_a = (instr >> 5) & 0x1F;
     let r_b = instr & 0x1F;
     let Some(name) = branch_abs_name(op) else {
-        return format!("data {}", fmt_imm_hex(instr));
+        return format!(".word {}", fmt_imm_hex(instr));
     };
     format!("{} {}, {}", name, reg_name(r_a), reg_name(r_b))
 }
@@ -213,7 +232,7 @@ fn disassemble_branch_rel(instr: u32) -> String {
     let r_a = (instr >> 5) & 0x1F;
     let r_b = instr & 0x1F;
     let Some(name) = branch_name(op) else {
-        return format!("data {}", fmt_imm_hex(instr));
+        return format!(".word {}", fmt_imm_hex(instr));
     };
     format!("{} {}, {}", name, reg_name(r_a), reg_name(r_b))
 }
@@ -222,21 +241,19 @@ fn disassemble_trap(instr: u32) -> String {
     if (instr & 0x07FF_FFFF) == 0 {
         "trap".to_string()
     } else {
-        format!("data {}", fmt_imm_hex(instr))
+        format!(".word {}", fmt_imm_hex(instr))
     }
 }
 
 fn disassemble_atomic(opcode: u32, instr: u32) -> String {
-    let is_fadd = opcode <= 18;
-    let is_absolute = opcode == 16 || opcode == 19;
     let is_imm = opcode == 18 || opcode == 21;
 
-    let mnemonic = if is_fadd {
-        if is_absolute { "fada" } else { "fad" }
-    } else if is_absolute {
-        "swpa"
-    } else {
-        "swp"
+    let mnemonic = match opcode {
+        16 => "fada",
+        17 | 18 => "fad",
+        19 => "swpa",
+        29 => "casa",
+        _ => "swp",
     };
 
     let r_a = (instr >> 22) & 0x1F;
@@ -306,7 +323,7 @@ fn disassemble_kernel(instr: u32) -> String {
         }
         3 => {
             if ((instr >> 11) & 1) != 0 {
-                format!("data {}", fmt_imm_hex(instr))
+                format!(".word {}", fmt_imm_hex(instr))
             } else {
                 "rfe".to_string()
             }
@@ -328,7 +345,33 @@ fn disassemble_kernel(instr: u32) -> String {
                 format!("eoi {}", instr & 0xF)
             }
         }
-        _ => format!("kernel {}", fmt_imm_hex(instr)),
+        _ => format!(".word {}", fmt_imm_hex(instr)),
+    }
+}
+
+// Purpose: absolute target of a pc-relative instruction.
+// Inputs: instruction word and the address it was fetched from.
+// Outputs: branch target / memory address / adpc result, or None for
+// instructions that are not pc-relative immediates.
+fn pc_relative_target(instr: u32, pc: u32) -> Option<u32> {
+    let offset = match instr >> 27 {
+        12 if branch_name((instr >> 22) & 0x1F).is_some() => branch_offset(instr),
+        5 | 8 | 11 => sign_extend(instr & 0x1FFFFF, 21),
+        25 | 28 if (instr >> 21) & 1 != 0 => sign_extend(instr & 0x1FFFFF, 21),
+        18 | 21 => sign_extend(instr & 0x1FFFF, 17),
+        22 => sign_extend(instr & 0x3FFFFF, 22),
+        _ => return None,
+    };
+    Some(pc.wrapping_add(4).wrapping_add(offset as u32))
+}
+
+// Like disassemble, but resolves pc-relative immediates against the
+// instruction's address, e.g. `bnz -12 -> 0x00000400`.
+pub fn disassemble_at(instr: u32, pc: u32) -> String {
+    let text = disassemble(instr);
+    match pc_relative_target(instr, pc) {
+        Some(target) => format!("{} -> {}", text, fmt_imm_hex(target)),
+        None => text,
     }
 }
 
@@ -338,21 +381,21 @@ pub fn disassemble(instr: u32) -> String {
         0 => disassemble_alu_reg(instr),
         1 => disassemble_alu_imm(instr),
         2 => disassemble_lui(instr),
-        3..=11 => disassemble_mem(opcode, instr),
+        3..=11 | 23..=28 => disassemble_mem(opcode, instr),
         12 => disassemble_branch_imm(instr),
         13 => disassemble_branch_abs(instr),
         14 => disassemble_branch_rel(instr),
         15 => disassemble_trap(instr),
         22 => disassemble_adpc(instr),
-        16..=21 => disassemble_atomic(opcode, instr),
+        16..=21 | 29 => disassemble_atomic(opcode, instr),
         31 => disassemble_kernel(instr),
-        _ => format!("data {}", fmt_imm_hex(instr)),
+        _ => format!(".word {}", fmt_imm_hex(instr)),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::disassemble;
+    use super::{disassemble, disassemble_at};
 
     #[test]
     fn disassembles_eoi_specific() {
@@ -369,6 +412,72 @@ mod tests {
     #[test]
     fn disassembles_reserved_alt_rfe_encoding_as_data() {
         let instr = (31u32 << 27) | (3u32 << 12) | (1u32 << 11);
-        assert_eq!(disassemble(instr), "data 0xF8003800");
+        assert_eq!(disassemble(instr), ".word 0xF8003800");
+    }
+
+    #[test]
+    fn disassembles_one_sample_per_opcode() {
+        let cases: &[(u32, &str)] = &[
+            (0x0086_01C4, "add r2, r3, r4"),         // 0 alu reg
+            (0x0044_0360, "bswap r1, r2"),           // 0 alu reg, single operand
+            (0x0904_30FF, "nor r4, r2, 0x000000FF"), // 1 alu imm, bitwise
+            (0x0904_EFFC, "add r4, r2, -4"),         // 1 alu imm, arithmetic
+            (0x1080_0001, "lui r2, 0x00000400"),     // 2
+            (0x1887_4FF8, "lwa r2, [r3, -8]!"),      // 3 pre-increment
+            (0x1887_9004, "lwa r2, [r3], 8"),        // 3 post-increment, shifted
+            (0x2086_0010, "sw r2, [r3, 16]"),        // 4
+            (0x28BF_FFFC, "lw r2, [-4]"),            // 5
+            (0x3086_0002, "sda r2, [r3, 2]"),        // 6
+            (0x3887_FFFE, "ld r2, [r3, -2]"),        // 7
+            (0x4080_0001, "sd r2, [1]"),             // 8
+            (0x4887_0000, "lba r2, [r3, 0]"),        // 9
+            (0x5086_0001, "sb r2, [r3, 1]"),         // 10
+            (0x58A0_0003, "lb r2, [3]"),             // 11
+            (0x60BF_FFFD, "bnz -12"),                // 12
+            (0x6500_0000, ".word 0x65000000"),       // 12, unknown condition
+            (0x6C80_0043, "bbea r2, r3"),            // 13
+            (0x7040_0043, "bz r2, r3"),              // 14
+            (0x7800_0000, "trap"),                   // 15
+            (0x8086_3004, "fada r2, r3, [r3, 4]"),   // 16
+            (0x8886_3FFC, "fad r2, r3, [r3, -4]"),   // 17
+            (0x9087_FFFF, "fad r2, r3, [-1]"),       // 18
+            (0x9886_3000, "swpa r2, r3, [r3, 0]"),   // 19
+            (0xA086_3000, "swp r2, r3, [r3, 0]"),    // 20
+            (0xA886_0008, "swp r2, r3, [8]"),        // 21
+            (0xB0BF_FFF0, "adpc r2, -16"),           // 22
+            (0xB887_0000, "ldsa r2, [r3, 0]"),       // 23
+            (0xB800_0000, ".word 0xB8000000"),       // 23, signed store
+            (0xC087_FFFE, "lds r2, [r3, -2]"),       // 24
+            (0xC8A0_0004, "lds r2, [4]"),            // 25
+            (0xD087_0001, "lbsa r2, [r3, 1]"),       // 26
+            (0xD887_0000, "lbs r2, [r3, 0]"),        // 27
+            (0xE0BF_FFFF, "lbs r2, [-1]"),           // 28
+            (0xE886_3004, "casa r2, r3, [r3, 4]"),   // 29
+            (0xF000_0000, ".word 0xF0000000"),       // 30 unused
+            (0xF886_0400, "tlbw r2, r3"),            // 31 kernel
+            (0xF886_1400, "crmv r2, cr3"),
+            (0xF800_2800, "mode halt"),
+            (0xF800_3000, "rfe"),
+            (0xF800_F000, ".word 0xF800F000"),
+        ];
+        for (instr, expected) in cases {
+            assert_eq!(disassemble(*instr), *expected, "instr {:08X}", instr);
+        }
+    }
+
+    #[test]
+    fn resolves_pc_relative_targets() {
+        assert_eq!(disassemble_at(0x60BF_FFFD, 0x40C), "bnz -12 -> 0x00000404");
+        assert_eq!(
+            disassemble_at(0x28BF_FFFC, 0x400),
+            "lw r2, [-4] -> 0x00000400"
+        );
+        assert_eq!(
+            disassemble_at(0xB0BF_FFF0, 0x400),
+            "adpc r2, -16 -> 0x000003F4"
+        );
+        assert_eq!(disassemble_at(0x0086_01C4, 0x400), "add r2, r3, r4");
+        // A far branch keeps its full 22-bit word offset.
+        assert_eq!(disassemble(0x6020_0000), "br -8388608");
     }
 }
@@ -1,7 +1,10 @@
+use std::any::Any;
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
@@ -10,14 +13,31 @@ use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::audio::{AudioOutput, AudioSink};
+use crate::disassembler::disassemble_at;
 use crate::memory::{
-    AUDIO_INTERRUPT_BIT, AUDIO_SAMPLE_RATE_HZ, CLK_REG_START, Memory, PHYSMEM_MAX,
-    SD_INTERRUPT_BIT, SD2_INTERRUPT_BIT, SdSlot, VGA_INTERRUPT_BIT,
+    AUDIO_INTERRUPT_BIT, AUDIO_SAMPLE_RATE_HZ, DEFAULT_KEY_BUFFER_CAP, DEFAULT_PHYSMEM_SIZE,
+    FramebufferVisibility, Memory, SD_INTERRUPT_BIT, SD2_INTERRUPT_BIT, SdSlot, VGA_INTERRUPT_BIT,
 };
 
-use crate::graphics::Graphics;
+use crate::console::spawn_console;
+use crate::graphics::{Graphics, WindowConfig};
+use crate::stdin_keys::spawn_stdin_keys;
 
+mod builder;
 mod debugger;
+mod history;
+mod sections;
+mod snapshot;
+mod state_dump;
+
+pub use builder::EmulatorBuilder;
+pub use debugger::read_debug_script;
+use debugger::{CatchKind, WatchCondition};
+pub use history::DEFAULT_UNDO_DEPTH;
+use history::UndoHistory;
+pub use sections::SectionImage;
+pub use snapshot::EmulatorSnapshot;
+pub use state_dump::StateDump;
 
 // Reset vector for kernel entry (see docs/mem_map.md).
 const RESET_PC: u32 = 0x0000_0400;
@@ -40,16 +60,22 @@ const KERNEL_INT_STACK_START: u32 = 0x000E_0000;
 const KERNEL_INT_STACK_END: u32 = 0x000F_0000;
 const KERNEL_STACK_START: u32 = 0x000F_0000;
 const KERNEL_STACK_END: u32 = 0x0010_0000;
-const TLB_ENTRIES: usize = 16;
+pub const DEFAULT_TLB_ENTRIES: usize = 16;
 const TLB_FLAG_READ: u32 = 0x1;
 const TLB_FLAG_WRITE: u32 = 0x2;
 const TLB_FLAG_EXEC: u32 = 0x4;
 const TLB_FLAG_USER: u32 = 0x8;
+const TLB_FLAG_GLOBAL: u32 = 0x10;
 const TLB_FAULT_ABSENT: u32 = 0x0;
 const EXC_TLB_MISS_VECTOR: u32 = 0x82;
+// Top-level opcodes with no instruction assigned. Malformed encodings of
+// assigned opcodes still raise the invalid-instruction exception (0x80).
+const EXC_ILLEGAL_OPCODE_VECTOR: u32 = 0x83;
 const EXC_MISALIGNED_PC_VECTOR: u32 = 0x84;
+const EXC_MISALIGNED_DATA_VECTOR: u32 = 0x85;
 const PSR_REASON_TLB_MISS: &str = "tlb_miss";
 const PSR_REASON_MISALIGNED_PC: &str = "misaligned_pc";
+const PSR_REASON_MISALIGNED_DATA: &str = "misaligned_data";
 const CREG_PID: usize = 1;
 const CREG_IMR: usize = 3;
 const CREG_EPC: usize = 4;
@@ -59,6 +85,17 @@ const CREG_TLB: usize = 7;
 const CREG_CID: usize = 9;
 const CREG_MBI: usize = 10;
 const CREG_TLBF: usize = 12;
+// Programs return their exit value in r1; `run`, the debugger's halt message
+// and the test harness all read it from here.
+const RESULT_REG: usize = 1;
+// Virtual read-only registers past the stored cregfile: the retired-instruction
+// counter, split into its low and high 32 bits.
+const CREG_CNT: usize = 13;
+const CREG_CNTH: usize = 14;
+// Exception cause: the IVT vector number of the latest exception or interrupt
+// entry (0x80 invalid instruction, 0x81 privileged, 0x83 illegal opcode, ...).
+// Kept outside the stored cregfile; kernel code may overwrite it.
+const CREG_CAUSE: usize = 15;
 
 // Global toggle for interrupt tracing output.
 static TRACE_INTERRUPTS: AtomicBool = AtomicBool::new(false);
@@ -67,11 +104,294 @@ pub fn set_trace_interrupts(enabled: bool) {
     TRACE_INTERRUPTS.store(enabled, Ordering::Relaxed);
 }
 
+// Global toggle for the one-line exception/interrupt entry log (--log-exceptions).
+static LOG_EXCEPTIONS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_log_exceptions(enabled: bool) {
+    LOG_EXCEPTIONS.store(enabled, Ordering::Relaxed);
+}
+
+// Purpose: arm a run's wall-clock limit (RunOptions::timeout).
+// Inputs: the limit, and a callback invoked once on the watchdog thread when
+// it passes.
+// Outputs: none; no thread is spawned without a limit.
+fn spawn_watchdog(timeout: Option<Duration>, on_expire: impl FnOnce() + Send + 'static) {
+    let Some(timeout) = timeout else {
+        return;
+    };
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        on_expire();
+    });
+}
+
+// Linear instruction trace: one line per executed instruction with its
+// disassembly and any registers it changed.
+pub struct InstrTrace {
+    sink: Mutex<Box<dyn Write + Send>>,
+    // Half-open [start, end) PC window; None traces every instruction.
+    range: Option<(u32, u32)>,
+}
+
+impl InstrTrace {
+    pub fn new(sink: Box<dyn Write + Send>, range: Option<(u32, u32)>) -> InstrTrace {
+        InstrTrace {
+            sink: Mutex::new(sink),
+            range,
+        }
+    }
+
+    fn covers(&self, pc: u32) -> bool {
+        self.range
+            .is_none_or(|(start, end)| pc >= start && pc < end)
+    }
+
+    fn record(&self, core_id: u32, pc: u32, instr: u32, before: &[u32; 32], after: &[u32; 32]) {
+        let mut line = format!(
+            "[core {}] {:08X}: {:08X}  {}",
+            core_id,
+            pc,
+            instr,
+            disassemble_at(instr, pc)
+        );
+        for (reg, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+            if old != new {
+                line.push_str(&format!("  r{}={:08X}", reg, new));
+            }
+        }
+        let mut sink = self.sink.lock().unwrap();
+        let _ = writeln!(sink, "{}", line);
+    }
+}
+
+impl fmt::Debug for InstrTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InstrTrace")
+            .field("range", &self.range)
+            .finish_non_exhaustive()
+    }
+}
+
+// Per-machine settings fixed when an emulator is built, from the command
+// line or EmulatorBuilder. Every core of a multicore run shares one.
+#[derive(Clone, Debug)]
+pub struct EmulatorConfig {
+    // Entries in each core's TLB (--tlb-entries); at least one.
+    pub tlb_entries: usize,
+    // TLB replacement policy (--tlb-policy).
+    pub tlb_policy: TlbEvictionPolicy,
+    // Unaligned data accesses trap instead of warning (--strict-align).
+    pub strict_alignment: bool,
+    // Byte order of guest halfwords and words (--endian), for the cores and
+    // for the words of hex programs.
+    pub endianness: Endianness,
+    // Limit on exception nesting, the PSR depth (--max-exception-depth); one
+    // more is a double fault. The default only stops the PSR from wrapping.
+    pub max_exception_depth: u32,
+    // Steps the debugger keeps for `back` (--undo-depth); 0 turns recording
+    // off. Normal runs never record.
+    pub undo_depth: usize,
+    // Count instruction fetches per RAM page for --profile-out and the
+    // debugger's `info profile`. Off by default so the fetch path stays one
+    // load.
+    pub fetch_profiling: bool,
+    // Most entries the VGA window or --stdin-keys may leave queued for the
+    // guest (--key-buffer); at least one.
+    pub key_buffer_cap: usize,
+    // Bytes of RAM (--physmem); a size accepted by `check_physmem_size`.
+    pub physmem_size: u32,
+    // Initial PC (--entry), overriding the entry point of a loaded program
+    // file (RESET_PC, a `#entry` line or a sectioned image's entry).
+    pub entry: Option<u32>,
+    // Further program files loaded over the main one, in order. The main
+    // program's entry point still sets the PC.
+    pub extra_programs: Vec<String>,
+    // Instruction trace (--trace) written by every core; None runs untraced.
+    pub instr_trace: Option<Arc<InstrTrace>>,
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        EmulatorConfig {
+            tlb_entries: DEFAULT_TLB_ENTRIES,
+            tlb_policy: TlbEvictionPolicy::Fifo,
+            strict_alignment: false,
+            endianness: Endianness::Little,
+            max_exception_depth: u32::MAX,
+            undo_depth: DEFAULT_UNDO_DEPTH,
+            fetch_profiling: false,
+            key_buffer_cap: DEFAULT_KEY_BUFFER_CAP,
+            physmem_size: DEFAULT_PHYSMEM_SIZE,
+            entry: None,
+            extra_programs: Vec::new(),
+            instr_trace: None,
+        }
+    }
+}
+
+// Options for one normal run (`run_report_with`, `run_multicore_with_memory`);
+// the debuggers do not use them.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    // Wall-clock limit (--timeout-secs); every core stops with
+    // HaltReason::Timeout when it passes.
+    pub timeout: Option<Duration>,
+    // Host window settings for runs with graphics.
+    pub window: WindowConfig,
+    // Watchpoints (--watch), installed on every core when the run starts.
+    // Each hit is printed and execution continues.
+    watchpoints: Vec<Watchpoint>,
+    // Print the registers after each watchpoint hit (--watch-dump).
+    pub watch_dump: bool,
+    // Redraw rate of the terminal console (--ascii-vga, --ascii-fps); None
+    // leaves the console off.
+    pub ascii_console: Option<u32>,
+    // Type stdin into the guest (--stdin-keys).
+    pub stdin_keys: bool,
+    // Capture every core's final state in RunReport::states
+    // (--dump-state-on-exit).
+    pub dump_state: bool,
+}
+
+impl RunOptions {
+    // Purpose: add a watchpoint for the run.
+    // Inputs: `[r:|w:|rw:]<addr>`; without a prefix both reads and writes are watched.
+    // Outputs: Err with a message when the spec does not parse.
+    pub fn add_watchpoint(&mut self, spec: &str) -> Result<(), String> {
+        let (kind, addr_str) = match spec.split_once(':') {
+            Some((kind, addr)) => (
+                debugger::parse_watch_kind(kind)
+                    .ok_or_else(|| format!("Invalid watch kind: {}", kind))?,
+                addr,
+            ),
+            None => (WatchKind::ReadWrite, spec),
+        };
+        let addr = debugger::parse_addr(addr_str)
+            .ok_or_else(|| format!("Invalid watch address: {}", addr_str))?;
+        debugger::add_watchpoint(&mut self.watchpoints, addr, 1, kind, None);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+// Which TLB entry gets replaced when a new mapping arrives and the TLB is full.
+pub enum TlbEvictionPolicy {
+    // Evict the entry that was inserted first.
+    Fifo,
+    // Evict the entry that was least recently used by a translation.
+    Lru,
+}
+
+impl TlbEvictionPolicy {
+    pub fn parse(token: &str) -> Option<Self> {
+        match token.to_ascii_lowercase().as_str() {
+            "fifo" => Some(TlbEvictionPolicy::Fifo),
+            "lru" => Some(TlbEvictionPolicy::Lru),
+            _ => None,
+        }
+    }
+}
+
+// Byte order of guest halfwords and words in memory, for instruction fetch
+// and data access alike. Memory itself stores words little-endian; a
+// big-endian core swaps at the boundary, so multi-byte device registers also
+// read back big-endian.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endianness {
+    // The byte at the lowest address is bits 0-7.
+    #[default]
+    Little,
+    // The byte at the lowest address is bits 24-31 (8-15 for halfwords).
+    Big,
+}
+
+impl Endianness {
+    pub fn parse(token: &str) -> Option<Self> {
+        match token.to_ascii_lowercase().as_str() {
+            "little" | "le" => Some(Endianness::Little),
+            "big" | "be" => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+
+    // Convert between a word as Memory stores it and a word in this order;
+    // the conversion is its own inverse.
+    fn word(self, value: u32) -> u32 {
+        match self {
+            Endianness::Little => value,
+            Endianness::Big => value.swap_bytes(),
+        }
+    }
+
+    fn half(self, value: u16) -> u16 {
+        match self {
+            Endianness::Little => value,
+            Endianness::Big => value.swap_bytes(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TlbSlot {
+    Private(u32, u32),
+    Global(u32),
+}
+
+// Translation counters for `info tlbstats` and --tlb-stats. A miss is any
+// fault, including a permission fault on a resident entry; `misses` is
+// indexed by the `access` operation (0 read, 1 write, 2 fetch). `writes`
+// counts entries written by tlbw (or the debugger) and `evictions` the
+// entries those writes pushed out of a full TLB.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TlbStats {
+    pub hits: u64,
+    pub misses: [u64; 3],
+    pub writes: u64,
+    pub evictions: u64,
+}
+
+impl TlbStats {
+    pub fn total_misses(&self) -> u64 {
+        self.misses.iter().sum()
+    }
+
+    // Fold another core's counters into these.
+    fn merge(&mut self, other: &TlbStats) {
+        self.hits += other.hits;
+        for (total, misses) in self.misses.iter_mut().zip(other.misses) {
+            *total += misses;
+        }
+        self.writes += other.writes;
+        self.evictions += other.evictions;
+    }
+}
+
+impl fmt::Display for TlbStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TLB hits: {}", self.hits)?;
+        writeln!(
+            f,
+            "TLB misses: {} (read {}, write {}, fetch {})",
+            self.total_misses(),
+            self.misses[0],
+            self.misses[1],
+            self.misses[2]
+        )?;
+        writeln!(f, "TLB writes: {}", self.writes)?;
+        write!(f, "TLB evictions: {}", self.evictions)
+    }
+}
+
 #[derive(Debug)]
 pub struct RandomCache {
     private_table: HashMap<(u32, u32), u32>,
     global_table: HashMap<u32, u32>,
     total_capacity: usize,
+    policy: TlbEvictionPolicy,
+    // Every resident entry, oldest (next to evict) first. FIFO orders by
+    // insertion; LRU moves an entry to the back whenever it translates.
+    order: VecDeque<TlbSlot>,
+    stats: TlbStats,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -85,37 +405,59 @@ impl RandomCache {
         self.private_table.len() + self.global_table.len()
     }
 
+    fn forget(&mut self, slot: TlbSlot) {
+        self.order.retain(|entry| *entry != slot);
+    }
+
+    fn touch(&mut self, slot: TlbSlot) {
+        if self.policy == TlbEvictionPolicy::Lru {
+            self.forget(slot);
+            self.order.push_back(slot);
+        }
+    }
+
     fn evict_one(&mut self, prefer_global: bool) {
-        // Replacement policy is implementation-defined; this emulator uses a
-        // deterministic first-key eviction and prefers evicting from the same
-        // class (global/private) as the incoming entry when possible.
-        if prefer_global {
-            if let Some(evict) = self.global_table.keys().next().cloned() {
-                self.global_table.remove(&evict);
-                return;
-            }
-            if let Some(evict) = self.private_table.keys().next().cloned() {
-                self.private_table.remove(&evict);
-            }
-        } else {
-            if let Some(evict) = self.private_table.keys().next().cloned() {
-                self.private_table.remove(&evict);
-                return;
+        // Replacement policy is implementation-defined; this emulator evicts the
+        // oldest entry under the configured policy and prefers evicting from the
+        // same class (global/private) as the incoming entry when possible.
+        let same_class = self
+            .order
+            .iter()
+            .position(|slot| matches!(slot, TlbSlot::Global(_)) == prefer_global);
+        let Some(index) = same_class.or((!self.order.is_empty()).then_some(0)) else {
+            return;
+        };
+        self.stats.evictions += 1;
+        match self.order.remove(index) {
+            Some(TlbSlot::Private(pid, vpn)) => {
+                self.private_table.remove(&(pid, vpn));
             }
-            if let Some(evict) = self.global_table.keys().next().cloned() {
-                self.global_table.remove(&evict);
+            Some(TlbSlot::Global(vpn)) => {
+                self.global_table.remove(&vpn);
             }
+            None => {}
         }
     }
 
-    pub fn new(capacity: usize) -> RandomCache {
+    pub fn new(capacity: usize, policy: TlbEvictionPolicy) -> RandomCache {
         RandomCache {
             private_table: HashMap::new(),
             global_table: HashMap::new(),
             total_capacity: capacity,
+            policy,
+            order: VecDeque::with_capacity(capacity),
+            stats: TlbStats::default(),
         }
     }
 
+    pub fn stats(&self) -> TlbStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = TlbStats::default();
+    }
+
     fn fault_flags(entry: u32, operation: u32, kmode: bool) -> u32 {
         let mut flags = 0;
         match operation {
@@ -153,7 +495,34 @@ impl RandomCache {
         }
     }
 
-    fn access(&self, pid: u32, vpn: u32, operation: u32, kmode: bool) -> TlbAccess {
+    // Guest translation: counts the lookup in the stats and, under LRU, marks
+    // the entry that hit as most recently used.
+    fn access(&mut self, pid: u32, vpn: u32, operation: u32, kmode: bool) -> TlbAccess {
+        let (access, slot) = self.lookup(pid, vpn, operation, kmode);
+        match access {
+            TlbAccess::Hit(_) => self.stats.hits += 1,
+            TlbAccess::Fault(_) => self.stats.misses[operation as usize] += 1,
+        }
+        if let Some(slot) = slot {
+            self.touch(slot);
+        }
+        access
+    }
+
+    // Debugger translation: the same answer as `access` with no effect on the
+    // stats or the replacement order.
+    fn probe(&self, pid: u32, vpn: u32, operation: u32, kmode: bool) -> TlbAccess {
+        self.lookup(pid, vpn, operation, kmode).0
+    }
+
+    // Returns the result and, on a hit, the entry that translated.
+    fn lookup(
+        &self,
+        pid: u32,
+        vpn: u32,
+        operation: u32,
+        kmode: bool,
+    ) -> (TlbAccess, Option<TlbSlot>) {
         // Memory access keeps the existing private-then-global lookup order so
         // emulator behavior does not change for duplicate private/global entries.
         assert!(self.total_size() <= self.total_capacity);
@@ -162,16 +531,23 @@ impl RandomCache {
         let mut private_fault = None;
         if let Some(entry) = self.private_table.get(&key).copied() {
             match Self::classify_entry(entry, operation, kmode) {
-                TlbAccess::Hit(ppn) => return TlbAccess::Hit(ppn),
+                TlbAccess::Hit(ppn) => {
+                    return (TlbAccess::Hit(ppn), Some(TlbSlot::Private(pid, vpn)));
+                }
                 TlbAccess::Fault(flags) => private_fault = Some(flags),
             }
         }
 
         if let Some(entry) = self.global_table.get(&vpn).copied() {
-            return Self::classify_entry(entry, operation, kmode);
+            let access = Self::classify_entry(entry, operation, kmode);
+            let slot = matches!(access, TlbAccess::Hit(_)).then_some(TlbSlot::Global(vpn));
+            return (access, slot);
         }
 
-        TlbAccess::Fault(private_fault.unwrap_or(TLB_FAULT_ABSENT))
+        (
+            TlbAccess::Fault(private_fault.unwrap_or(TLB_FAULT_ABSENT)),
+            None,
+        )
     }
 
     pub fn read(&self, pid: u32, vpn: u32) -> Option<u32> {
@@ -189,10 +565,16 @@ impl RandomCache {
     }
 
     pub fn write(&mut self, pid: u32, vpn: u32, ppn: u32) {
-        if ppn & 0x00000010 != 0 {
+        self.stats.writes += 1;
+        if ppn & TLB_FLAG_GLOBAL != 0 {
             // global entry
-            if !self.global_table.contains_key(&vpn) && self.total_size() >= self.total_capacity {
-                self.evict_one(true);
+            if self.global_table.contains_key(&vpn) {
+                self.touch(TlbSlot::Global(vpn));
+            } else {
+                if self.total_size() >= self.total_capacity {
+                    self.evict_one(true);
+                }
+                self.order.push_back(TlbSlot::Global(vpn));
             }
 
             // will replace old mapping if one existed
@@ -200,10 +582,13 @@ impl RandomCache {
             assert!(self.total_size() <= self.total_capacity);
         } else {
             // private entry
-            if !self.private_table.contains_key(&(pid, vpn))
-                && self.total_size() >= self.total_capacity
-            {
-                self.evict_one(false);
+            if self.private_table.contains_key(&(pid, vpn)) {
+                self.touch(TlbSlot::Private(pid, vpn));
+            } else {
+                if self.total_size() >= self.total_capacity {
+                    self.evict_one(false);
+                }
+                self.order.push_back(TlbSlot::Private(pid, vpn));
             }
 
             // will replace old mapping if one existed
@@ -214,40 +599,106 @@ impl RandomCache {
     }
 
     pub fn invalidate(&mut self, pid: u32, vpn: u32) {
-        self.private_table.remove(&(pid, vpn));
-        self.global_table.remove(&vpn);
+        if self.private_table.remove(&(pid, vpn)).is_some() {
+            self.forget(TlbSlot::Private(pid, vpn));
+        }
+        if self.global_table.remove(&vpn).is_some() {
+            self.forget(TlbSlot::Global(vpn));
+        }
+    }
+
+    // Resident entries as (pid, vpn, entry) in eviction order; global
+    // entries carry pid 0. Writing them back in this order into an empty
+    // cache rebuilds the same tables and replacement order.
+    fn entries(&self) -> Vec<(u32, u32, u32)> {
+        self.order
+            .iter()
+            .map(|slot| match *slot {
+                TlbSlot::Private(pid, vpn) => (pid, vpn, self.private_table[&(pid, vpn)]),
+                TlbSlot::Global(vpn) => (0, vpn, self.global_table[&vpn]),
+            })
+            .collect()
     }
 
     pub fn clear(&mut self) {
         self.private_table.drain();
         self.global_table.drain();
+        self.order.clear();
+    }
+
+    // Replace the contents with `entries` as returned by `entries`, for
+    // snapshot and undo restores. The counters are left as they were.
+    fn restore_entries(&mut self, entries: &[(u32, u32, u32)]) {
+        let stats = self.stats;
+        self.clear();
+        for &(pid, vpn, entry) in entries {
+            self.write(pid, vpn, entry);
+        }
+        self.stats = stats;
     }
 
-    fn debug_dump(&self) {
-        println!("TLB private: {} entries", self.private_table.len());
+    // Purpose: describe every resident entry for `info tlb`.
+    // Inputs: none.
+    // Outputs: a private and a global section, each oldest first (eviction
+    // order). Private lines carry the owning pid; global lines match any pid.
+    // Each entry shows its PPN and decoded R/W/X/U/G bits next to the raw
+    // word, so a global entry shadowing a private mapping stands out.
+    fn debug_dump(&self) -> String {
+        let mut out = format!("TLB private: {} entries\n", self.private_table.len());
         if self.private_table.is_empty() {
-            println!("  (empty)");
-        } else {
-            for ((pid, vpn), entry) in &self.private_table {
-                println!("  pid {:08X} vpn {:08X} -> {:08X}", pid, vpn, entry);
+            out.push_str("  (empty)\n");
+        }
+        for slot in &self.order {
+            if let TlbSlot::Private(pid, vpn) = *slot {
+                let entry = self.private_table[&(pid, vpn)];
+                out.push_str(&format!(
+                    "  private pid {:08X} vpn {:08X} -> {}\n",
+                    pid,
+                    vpn,
+                    describe_tlb_entry(entry)
+                ));
             }
         }
-        println!("TLB global: {} entries", self.global_table.len());
+        out.push_str(&format!(
+            "TLB global: {} entries\n",
+            self.global_table.len()
+        ));
         if self.global_table.is_empty() {
-            println!("  (empty)");
-        } else {
-            for (vpn, entry) in &self.global_table {
-                println!("  vpn {:08X} -> {:08X}", vpn, entry);
+            out.push_str("  (empty)\n");
+        }
+        for slot in &self.order {
+            if let TlbSlot::Global(vpn) = *slot {
+                out.push_str(&format!(
+                    "  global  pid *        vpn {:08X} -> {}\n",
+                    vpn,
+                    describe_tlb_entry(self.global_table[&vpn])
+                ));
             }
         }
-        println!(
+        out.push_str(&format!(
             "TLB total: {}/{} entries",
             self.total_size(),
             self.total_capacity
-        );
+        ));
+        out
     }
 }
 
+// A TLB entry as "<raw> (ppn <ppn> <R/W/X/U/G flags>)", '-' for a clear bit.
+fn describe_tlb_entry(entry: u32) -> String {
+    let flag = |bit: u32, c: char| if entry & bit != 0 { c } else { '-' };
+    format!(
+        "{:08X} (ppn {:05X} {}{}{}{}{})",
+        entry,
+        entry >> 12,
+        flag(TLB_FLAG_READ, 'R'),
+        flag(TLB_FLAG_WRITE, 'W'),
+        flag(TLB_FLAG_EXEC, 'X'),
+        flag(TLB_FLAG_USER, 'U'),
+        flag(TLB_FLAG_GLOBAL, 'G')
+    )
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 // Scheduler policy for multicore execution.
 pub enum ScheduleMode {
@@ -282,6 +733,183 @@ pub enum AudioMode {
     Fast,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+// Why a core stopped executing.
+pub enum HaltReason {
+    // The program executed `mode halt`.
+    Halt,
+    // The max-cycle budget ran out before the program halted.
+    MaxCycles,
+    // The wall-clock run timeout expired before the program halted.
+    Timeout,
+    // Another core halted and stopped the whole system.
+    Stopped,
+    // An exception arrived with the PSR already at the nesting limit, e.g. a
+    // TLB miss handler that itself misses on every fetch.
+    DoubleFault,
+    // The emulator itself panicked, e.g. on a guest read of an unmapped
+    // device address. Carries the panic message, which has also gone to stderr.
+    Panic(String),
+}
+
+impl fmt::Display for HaltReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HaltReason::Halt => write!(f, "mode halt"),
+            HaltReason::MaxCycles => write!(f, "max cycles reached"),
+            HaltReason::Timeout => write!(f, "timeout reached"),
+            HaltReason::Stopped => write!(f, "stopped by another core"),
+            HaltReason::DoubleFault => write!(f, "double fault: exception nesting limit reached"),
+            HaltReason::Panic(message) => write!(f, "emulator panic: {}", message),
+        }
+    }
+}
+
+// Why a core entered an exception or interrupt handler. TLB misses carry the
+// VPN and PID saved in cr7 and whether the faulting access was made in user mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExceptionCause {
+    TlbMiss { user: bool, vpn: u32, pid: u32 },
+    MisalignedPc,
+    MisalignedData { addr: u32 },
+    Privileged,
+    InvalidInstr,
+    IllegalOpcode { opcode: u32 },
+    Trap,
+    Interrupt { bit: u32 },
+}
+
+impl fmt::Display for ExceptionCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExceptionCause::TlbMiss { user, vpn, pid } => write!(
+                f,
+                "{} vpn=0x{:05X} pid={}",
+                if *user { "TLB_UMISS" } else { "TLB_KMISS" },
+                vpn,
+                pid
+            ),
+            ExceptionCause::MisalignedPc => write!(f, "MISALIGNED_PC"),
+            ExceptionCause::MisalignedData { addr } => {
+                write!(f, "MISALIGNED_DATA addr=0x{:08X}", addr)
+            }
+            ExceptionCause::Privileged => write!(f, "PRIV_INSTR"),
+            ExceptionCause::InvalidInstr => write!(f, "INVALID_INSTR"),
+            ExceptionCause::IllegalOpcode { opcode } => write!(f, "ILLEGAL_OPCODE op={}", opcode),
+            ExceptionCause::Trap => write!(f, "TRAP"),
+            ExceptionCause::Interrupt { bit } => {
+                write!(f, "INT{} ({})", bit, format_interrupts(1 << bit))
+            }
+        }
+    }
+}
+
+// One handler entry: the IVT vector taken, its cause, the PC that was
+// interrupted or faulted, and the handler PC loaded from the vector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExceptionEntry {
+    pub vector: u32,
+    pub cause: ExceptionCause,
+    pub fault_pc: u32,
+    pub new_pc: u32,
+}
+
+impl fmt::Display for ExceptionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} vector 0x{:02X} pc {:08X} -> {:08X}",
+            self.cause, self.vector, self.fault_pc, self.new_pc
+        )
+    }
+}
+
+// Outcome of one `Emulator::step`. `interrupt` is set when a pending interrupt
+// was taken before the fetch, in which case `pc` is the handler's first
+// instruction; `next_pc` is the PC after the step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepResult {
+    // The instruction at `pc` was fetched and executed. `exception` is set when
+    // it trapped (syscall, privileged or invalid instruction, data TLB miss, ...).
+    Executed {
+        pc: u32,
+        instr: u32,
+        interrupt: bool,
+        exception: bool,
+        next_pc: u32,
+    },
+    // Fetching from `pc` raised an exception (a TLB miss, or a misaligned PC
+    // when `tlb_miss` is false) and control moved to its handler.
+    FetchFault {
+        pc: u32,
+        tlb_miss: bool,
+        interrupt: bool,
+        next_pc: u32,
+    },
+    // The core is asleep waiting for an interrupt; nothing ran.
+    Sleeping,
+    // The core has already halted; nothing ran.
+    Halted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+// Summary of a finished run: why it stopped, r1 at that point, and cycle count.
+pub struct RunReport {
+    pub reason: HaltReason,
+    pub value: u32,
+    pub cycles: u32,
+    // Instructions retired by the reporting core, or by every core for
+    // multicore runs.
+    pub retired: u64,
+    // TLB counters of the reporting core, or of every core for multicore runs.
+    pub tlb: TlbStats,
+    // Final state of each core that exited normally, in core order, when
+    // RunOptions::dump_state is set; empty otherwise.
+    pub states: Vec<StateDump>,
+}
+
+impl RunReport {
+    // Stand-in for the report of a core whose thread panicked; its registers
+    // and cycle count are lost with the thread.
+    fn panicked(payload: &(dyn Any + Send)) -> RunReport {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+        RunReport {
+            reason: HaltReason::Panic(message),
+            value: 0,
+            cycles: 0,
+            retired: 0,
+            tlb: TlbStats::default(),
+            states: Vec::new(),
+        }
+    }
+
+    // The program result, or None when the run did not terminate normally.
+    pub fn result(&self) -> Option<u32> {
+        match self.reason {
+            HaltReason::Halt | HaltReason::Stopped => Some(self.value),
+            HaltReason::MaxCycles
+            | HaltReason::Timeout
+            | HaltReason::DoubleFault
+            | HaltReason::Panic(_) => None,
+        }
+    }
+}
+
+// Sets a run's `finished` flag when dropped, including during a panic unwind.
+struct FinishOnDrop(Arc<Mutex<bool>>);
+
+impl Drop for FinishOnDrop {
+    fn drop(&mut self) {
+        *self.0.lock().unwrap_or_else(|err| err.into_inner()) = true;
+    }
+}
+
 struct SchedulerState {
     // Next core allowed to execute in non-free scheduling modes.
     next_core: usize,
@@ -437,6 +1065,17 @@ const TIMER_INTERRUPT_BIT: u32 = 1 << 0;
 const KB_INTERRUPT_BIT: u32 = 1 << 1;
 const UART_INTERRUPT_BIT: u32 = 1 << 2;
 const IPI_INTERRUPT_BIT: u32 = 1 << 5;
+// ISR bits 0-15 have vectors at 0xF0-0xFF.
+const INTERRUPT_VECTORS: u32 = 16;
+
+// Mask selecting the low `bits` bits; valid for 0 through 32.
+fn low_bits_mask(bits: u32) -> u32 {
+    if bits >= 32 {
+        u32::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
 
 fn format_interrupts(bits: u32) -> String {
     let mut parts = Vec::new();
@@ -647,10 +1286,12 @@ impl InterruptController {
 struct RunShared {
     // Global stop signal shared by all cores.
     stop: AtomicBool,
+    // Set by the run-timeout watchdog; turns the stop into HaltReason::Timeout.
+    timed_out: AtomicBool,
     // Track how many cores have exited their run loops.
     halted: AtomicUsize,
-    // Per-core return values (r1) recorded on exit.
-    results: Mutex<Vec<Option<u32>>>,
+    // Per-core run reports recorded on exit.
+    results: Mutex<Vec<Option<RunReport>>>,
     // Shared completion flag for graphics and multi-core coordination.
     finished: Arc<Mutex<bool>>,
     cores: usize,
@@ -660,6 +1301,7 @@ impl RunShared {
     fn new(cores: usize, finished: Arc<Mutex<bool>>) -> RunShared {
         RunShared {
             stop: AtomicBool::new(false),
+            timed_out: AtomicBool::new(false),
             halted: AtomicUsize::new(0),
             results: Mutex::new(vec![None; cores]),
             finished,
@@ -676,8 +1318,22 @@ impl RunShared {
         *self.finished.lock().unwrap() = true;
     }
 
-    fn record_exit(&self, core_id: usize, value: u32) {
-        self.results.lock().unwrap()[core_id] = Some(value);
+    fn expire(&self) {
+        self.timed_out.store(true, Ordering::Relaxed);
+        self.request_stop();
+    }
+
+    // Why a core stopped because of the shared stop signal.
+    fn stop_reason(&self) -> HaltReason {
+        if self.timed_out.load(Ordering::Relaxed) {
+            HaltReason::Timeout
+        } else {
+            HaltReason::Stopped
+        }
+    }
+
+    fn record_exit(&self, core_id: usize, report: RunReport) {
+        self.results.lock().unwrap()[core_id] = Some(report);
         let halted = self.halted.fetch_add(1, Ordering::Relaxed) + 1;
         if halted == self.cores {
             *self.finished.lock().unwrap() = true;
@@ -693,18 +1349,52 @@ pub struct Emulator {
     interrupts: Arc<InterruptController>,
     tlb: RandomCache,
     pc: u32,
+    // Where execution starts: the program's resolved entry point, or
+    // RESET_PC. A warm reset returns here.
+    entry: u32,
     asleep: bool,
     // Distinguish "mode sleep" from a core that starts asleep.
     sleep_armed: bool,
     halted: bool,
+    halt_reason: Option<HaltReason>,
     count: u32,
+    // Clock edges the last instruction still occupies the core (its
+    // cycle_cost minus the edge it issued on).
+    busy_cycles: u32,
+    // Instructions executed to completion; unlike `count` it skips sleep,
+    // clock-divider stall and fetch-fault ticks. Read by the guest as cr13/cr14.
+    retired: u64,
+    // Backs cr15; see CREG_CAUSE.
+    exc_cause: u32,
     core_id: u32,
     use_uart_rx: bool,
     audio_mode: AudioMode,
     audio_sink: Option<Arc<AudioSink>>,
     pending_tlb_fault: Option<u32>,
+    // Unaligned halfword/word data accesses trap instead of warning and masking.
+    strict_alignment: bool,
+    endianness: Endianness,
+    // Copy of EmulatorConfig::fetch_profiling so unprofiled fetches skip the
+    // profiler call entirely.
+    fetch_profiling: bool,
+    // Highest PSR value an exception may leave behind; one more is a double fault.
+    max_exception_depth: u32,
+    pending_misaligned_addr: Option<u32>,
+    instr_trace: Option<Arc<InstrTrace>>,
     watchpoints: Vec<Watchpoint>,
     watchpoint_hit: Option<WatchpointHit>,
+    creg_watches: Vec<usize>,
+    creg_watch_hit: Option<CregWatchHit>,
+    // Exceptions the debugger's `catch` stops on, and the first one taken
+    // since the debugger last looked.
+    catches: Vec<CatchKind>,
+    catch_hit: Option<ExceptionEntry>,
+    // Set whenever the core enters kernel mode for a trap or interrupt; `step`
+    // uses it to report what happened.
+    trap_entered: bool,
+    last_exception: Option<ExceptionEntry>,
+    // Undo records for the debugger's `back`; empty unless a depth is set.
+    history: UndoHistory,
 }
 
 const FAST_AUDIO_BATCH_SAMPLES: usize = (AUDIO_SAMPLE_RATE_HZ as usize) / 100;
@@ -885,6 +1575,9 @@ struct DebugInfo {
 // Loader output: bytes + labels + C debug metadata.
 struct ProgramImage {
     instructions: HashMap<u32, u8>,
+    // Initial PC of core 0: --entry if given, else a `#entry` line in hex
+    // files or the image's own entry for sectioned images, else RESET_PC.
+    entry: u32,
     labels: LabelMap,
     debug: DebugInfo,
 }
@@ -902,18 +1595,77 @@ enum WatchAccess {
     Write,
 }
 
-// Single-byte watchpoints tracked by exact address.
+// Watchpoint on the `len` bytes starting at `addr` (len >= 1).
 #[derive(Clone, Copy, Debug)]
 struct Watchpoint {
     addr: u32,
+    len: u32,
     kind: WatchKind,
+    cond: Option<WatchCondition>,
 }
 
 #[derive(Clone, Copy, Debug)]
 struct WatchpointHit {
     addr: u32,
+    // Physical address of `addr`, used to name the device region it lands in.
+    paddr: u32,
     access: WatchAccess,
-    value: u8,
+    // The whole access that touched `addr`: `size` bytes (1, 2 or 4).
+    value: u32,
+    size: u32,
+}
+
+// Control-register change observed while a creg watch was active.
+#[derive(Clone, Copy, Debug)]
+struct CregWatchHit {
+    creg: usize,
+    old: u32,
+    new: u32,
+    pc: u32,
+}
+
+// Timing model: clock cycles each instruction occupies the core, indexed by
+// opcode. ALU divide and modulo ops cost DIVIDE_CYCLES instead. Tune these to
+// match hardware; a cost of 1 issues one instruction per clock edge.
+const OPCODE_CYCLES: [u32; 32] = [
+    1, 1, 1, // ALU register, ALU immediate, lui
+    1, 1, 1, 1, 1, 1, 1, 1, 1, // word, halfword and byte loads/stores
+    1, 1, 1, // branches
+    1, // trap
+    2, 2, 2, 2, 2, 2, // fadd, swap
+    1, // adpc
+    1, 1, 1, 1, 1, 1, // sign-extending loads
+    2, // casa
+    1, 1, // unassigned, kernel
+];
+const DIVIDE_CYCLES: u32 = 8;
+
+// Purpose: look up an instruction's cost in the timing model.
+// Inputs: raw instruction word.
+// Outputs: clock cycles it occupies the core (at least 1).
+#[inline]
+pub fn cycle_cost(instr: u32) -> u32 {
+    let opcode = instr >> 27;
+    let alu_op = match opcode {
+        0 => (instr >> 5) & 0x1F,
+        1 => (instr >> 12) & 0x1F,
+        _ => return OPCODE_CYCLES[opcode as usize],
+    };
+    // udiv, sdiv, umod, smod
+    if (22..=25).contains(&alu_op) {
+        DIVIDE_CYCLES
+    } else {
+        OPCODE_CYCLES[opcode as usize]
+    }
+}
+
+// Zero- or sign-extend a `bits`-wide loaded value to 32 bits.
+fn extend_load(value: u32, bits: u32, signed: bool) -> u32 {
+    if signed {
+        ((value << (32 - bits)) as i32 >> (32 - bits)) as u32
+    } else {
+        value
+    }
 }
 
 fn parse_hex_u32(token: &str) -> Option<u32> {
@@ -1068,9 +1820,126 @@ fn parse_debug_line(line: &str, debug: &mut DebugInfo) -> bool {
     }
 }
 
-// Load hex (or .debug) program and collect any embedded labels.
-fn load_program(path: &str) -> ProgramImage {
+// Load the byte image of a hex (or .debug) program for `from_instructions`,
+// dropping labels and debug info.
+pub fn load_hex(path: &str) -> HashMap<u32, u8> {
+    load_program(path, &EmulatorConfig::default()).instructions
+}
+
+// Purpose: objdump-style listing of a program for --disasm; nothing executes.
+// Inputs: program path, and the config whose extra programs load too.
+// Outputs: `ADDR: WORD  mnemonic` for every word holding program bytes (missing
+// bytes of a partial word read as 0), `label:` lines before labelled words and
+// `...` wherever the addresses skip ahead.
+pub fn disassemble_program(path: &str, config: &EmulatorConfig) -> String {
+    let image = load_program(path, config);
+    let words: BTreeSet<u32> = image.instructions.keys().map(|addr| addr & !3).collect();
+    let mut labels_by_addr: HashMap<u32, Vec<&str>> = HashMap::new();
+    for (name, addrs) in &image.labels {
+        for addr in addrs {
+            labels_by_addr.entry(*addr).or_default().push(name);
+        }
+    }
+    let mut listing = String::new();
+    let mut next = None;
+    for addr in words {
+        if next.is_some_and(|next| next != addr) {
+            listing.push_str("...\n");
+        }
+        next = addr.checked_add(4);
+        if let Some(names) = labels_by_addr.get_mut(&addr) {
+            names.sort_unstable();
+            listing.push_str(&format!("{}:\n", names.join(", ")));
+        }
+        let mut bytes = [0u8; 4];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = image
+                .instructions
+                .get(&(addr + offset as u32))
+                .copied()
+                .unwrap_or(0);
+        }
+        let instr = config.endianness.word(u32::from_le_bytes(bytes));
+        listing.push_str(&format!(
+            "{:08X}: {:08X}  {}\n",
+            addr,
+            instr,
+            disassemble_at(instr, addr)
+        ));
+    }
+    listing
+}
+
+// Load a program: a sectioned image (see sections.rs) when the file starts with
+// SECTIONS_MAGIC, otherwise hex (or .debug) text with any embedded labels.
+// `config` supplies the extra program files, the entry override and the byte
+// order.
+fn load_program(path: &str, config: &EmulatorConfig) -> ProgramImage {
+    let mut image = load_program_image(path, config.endianness);
+    for extra in &config.extra_programs {
+        let other = load_program_image(extra, config.endianness);
+        merge_program(&mut image, other, extra);
+    }
+    if let Some(entry) = config.entry {
+        image.entry = entry;
+    }
+    image
+}
+
+// Purpose: load another program file into an already loaded image.
+// Inputs: the image so far, the new file's image and its path for messages.
+// Outputs: bytes, labels and debug info merged into `image`; the new file's
+// bytes win where they overlap, with one warning per file. The entry point
+// of `image` is kept.
+fn merge_program(image: &mut ProgramImage, other: ProgramImage, path: &str) {
+    let overlaps: Vec<u32> = other
+        .instructions
+        .keys()
+        .copied()
+        .filter(|addr| image.instructions.contains_key(addr))
+        .collect();
+    if let Some(first) = overlaps.iter().min() {
+        println!(
+            "Warning: {} overwrites {} bytes already loaded, starting at 0x{:08X}",
+            path,
+            overlaps.len(),
+            first
+        );
+    }
+    image.instructions.extend(other.instructions);
+    for (name, addrs) in other.labels {
+        image.labels.entry(name).or_default().extend(addrs);
+    }
+    let debug = &mut image.debug;
+    debug.lines.extend(other.debug.lines);
+    for (addr, locals) in other.debug.locals_by_addr {
+        debug.locals_by_addr.entry(addr).or_default().extend(locals);
+    }
+    debug.globals.extend(other.debug.globals);
+    debug.missing_line_addrs |= other.debug.missing_line_addrs;
+    debug.missing_local_addrs |= other.debug.missing_local_addrs;
+    debug.missing_local_sizes |= other.debug.missing_local_sizes;
+}
+
+// Purpose: load one program file.
+// Inputs: its path, and the byte order hex words are stored in.
+fn load_program_image(path: &str, endianness: Endianness) -> ProgramImage {
+    let mut file = File::open(path).expect("Couldn't open input file");
+    let mut magic = [0u8; 8];
+    if file.read_exact(&mut magic).is_ok() && &magic == sections::SECTIONS_MAGIC {
+        let mut input = magic.chain(io::BufReader::new(file));
+        let image = SectionImage::read_from(&mut input)
+            .unwrap_or_else(|err| panic!("Invalid program image {}: {}", path, err));
+        return ProgramImage {
+            instructions: image.to_bytes(),
+            entry: image.entry,
+            labels: LabelMap::new(),
+            debug: DebugInfo::default(),
+        };
+    }
+
     let mut instructions = HashMap::new();
+    let mut entry = RESET_PC;
     let mut labels = LabelMap::new();
     let mut debug = DebugInfo::default();
 
@@ -1083,6 +1952,12 @@ fn load_program(path: &str) -> ProgramImage {
         }
 
         if line.starts_with('#') {
+            // `#entry <addr>` sets the initial PC; it does not move the load
+            // address the way `@` does.
+            if let Some(addr) = line.strip_prefix("#entry").and_then(parse_hex_u32) {
+                entry = addr;
+                continue;
+            }
             // Debug metadata lines are prefixed with '#'.
             parse_label_line(line, &mut labels);
             parse_debug_line(line, &mut debug);
@@ -1101,21 +1976,42 @@ fn load_program(path: &str) -> ProgramImage {
 
         let instruction = u32::from_str_radix(line, 16).expect("Error parsing hex file");
 
-        instructions.insert(pc, instruction as u8);
-        instructions.insert(pc + 1, (instruction >> 8) as u8);
-        instructions.insert(pc + 2, (instruction >> 16) as u8);
-        instructions.insert(pc + 3, (instruction >> 24) as u8);
+        let bytes = endianness.word(instruction).to_le_bytes();
+        for (offset, byte) in bytes.iter().enumerate() {
+            instructions.insert(pc + offset as u32, *byte);
+        }
 
         pc += 4;
     }
 
     ProgramImage {
         instructions,
+        entry,
         labels,
         debug,
     }
 }
 
+// Purpose: build the memory shared by a new machine's cores.
+// Inputs: the program bytes, UART RX routing, SD DMA speed and the machine
+// settings that belong to memory.
+fn build_memory(
+    instructions: HashMap<u32, u8>,
+    use_uart_rx: bool,
+    sd_dma_ticks_per_word: u32,
+    config: &EmulatorConfig,
+) -> Arc<Memory> {
+    let mut memory = Memory::with_physmem_size(
+        instructions,
+        use_uart_rx,
+        sd_dma_ticks_per_word,
+        config.physmem_size,
+    );
+    memory.set_key_buffer_cap(config.key_buffer_cap);
+    memory.set_fetch_profiling(config.fetch_profiling);
+    Arc::new(memory)
+}
+
 impl Emulator {
     pub fn new(
         path: String,
@@ -1123,15 +2019,19 @@ impl Emulator {
         sd_dma_ticks_per_word: u32,
         sd0_image: Option<&[u8]>,
         sd1_image: Option<&[u8]>,
+        config: &EmulatorConfig,
     ) -> Emulator {
-        let image = load_program(&path);
-        Emulator::from_instructions(
+        let image = load_program(&path, config);
+        let mut cpu = Emulator::from_instructions_with_config(
             image.instructions,
             use_uart_rx,
             sd_dma_ticks_per_word,
             sd0_image,
             sd1_image,
-        )
+            config,
+        );
+        cpu.set_entry(image.entry);
+        cpu
     }
 
     pub fn from_instructions(
@@ -1140,12 +2040,31 @@ impl Emulator {
         sd_dma_ticks_per_word: u32,
         sd0_image: Option<&[u8]>,
         sd1_image: Option<&[u8]>,
+        tlb_entries: usize,
     ) -> Emulator {
-        let memory: Arc<Memory> = Arc::new(Memory::new(
+        Emulator::from_instructions_with_config(
             instructions,
             use_uart_rx,
             sd_dma_ticks_per_word,
-        ));
+            sd0_image,
+            sd1_image,
+            &EmulatorConfig {
+                tlb_entries,
+                ..EmulatorConfig::default()
+            },
+        )
+    }
+
+    // Purpose: `from_instructions` with non-default machine settings.
+    pub fn from_instructions_with_config(
+        instructions: HashMap<u32, u8>,
+        use_uart_rx: bool,
+        sd_dma_ticks_per_word: u32,
+        sd0_image: Option<&[u8]>,
+        sd1_image: Option<&[u8]>,
+        config: &EmulatorConfig,
+    ) -> Emulator {
+        let memory = build_memory(instructions, use_uart_rx, sd_dma_ticks_per_word, config);
         if let Some(image) = sd0_image {
             memory.load_sd_image(SdSlot::Sd0, image);
         }
@@ -1153,7 +2072,7 @@ impl Emulator {
             memory.load_sd_image(SdSlot::Sd1, image);
         }
         let interrupts = InterruptController::new(1);
-        Emulator::from_shared(memory, interrupts, use_uart_rx, 0)
+        Emulator::from_shared(memory, interrupts, use_uart_rx, 0, config)
     }
 
     // Purpose: export one SD device from this emulator instance as a raw host image.
@@ -1163,6 +2082,12 @@ impl Emulator {
         self.memory.dump_sd_image(slot)
     }
 
+    // Script serial input: bytes are read back one per UART_RX load when the
+    // emulator was built with use_uart_rx.
+    pub fn queue_uart_input(&self, bytes: &[u8]) {
+        self.memory.queue_uart_input(bytes);
+    }
+
     // Purpose: expose the shared memory backing this emulator instance.
     // Inputs: none.
     // Outputs: an Arc clone so callers can inspect memory after `run(self, ...)`.
@@ -1175,46 +2100,141 @@ impl Emulator {
         interrupts: Arc<InterruptController>,
         use_uart_rx: bool,
         core_id: u32,
+        config: &EmulatorConfig,
     ) -> Emulator {
-        let mut cregfile = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]; // start cores in kernel mode
-        // CID is a read-only core identifier.
-        cregfile[CREG_CID] = core_id;
-        if core_id != 0 {
-            // Allow IPI wakeups on secondary cores by default.
-            cregfile[CREG_IMR] = 0x80000020;
-        }
-
         Emulator {
             regfile: [
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0,
             ],
-            cregfile,
+            cregfile: Emulator::reset_cregfile(core_id),
             memory,
             interrupts,
-            tlb: RandomCache::new(TLB_ENTRIES),
+            tlb: RandomCache::new(config.tlb_entries, config.tlb_policy),
             pc: RESET_PC,
+            entry: RESET_PC,
             asleep: core_id != 0,
             sleep_armed: false,
             halted: false,
+            halt_reason: None,
             count: 0,
+            busy_cycles: 0,
+            retired: 0,
+            exc_cause: 0,
             core_id,
             use_uart_rx,
             audio_mode: AudioMode::Disabled,
             audio_sink: None,
             pending_tlb_fault: None,
+            strict_alignment: config.strict_alignment,
+            endianness: config.endianness,
+            fetch_profiling: config.fetch_profiling,
+            max_exception_depth: config.max_exception_depth,
+            pending_misaligned_addr: None,
+            instr_trace: config.instr_trace.clone(),
             watchpoints: Vec::new(),
             watchpoint_hit: None,
+            creg_watches: Vec::new(),
+            creg_watch_hit: None,
+            catches: Vec::new(),
+            catch_hit: None,
+            trap_entered: false,
+            last_exception: None,
+            history: UndoHistory::default(),
         }
     }
 
-    fn configure_audio(&mut self, audio_mode: AudioMode, sink: Option<Arc<AudioSink>>) {
-        self.audio_mode = audio_mode;
-        self.audio_sink = sink;
+    fn reset_cregfile(core_id: u32) -> [u32; 13] {
+        let mut cregfile = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]; // start cores in kernel mode
+        // CID is a read-only core identifier.
+        cregfile[CREG_CID] = core_id;
+        if core_id != 0 {
+            // Allow IPI wakeups on secondary cores by default.
+            cregfile[CREG_IMR] = 0x80000020;
+        }
+        cregfile
     }
 
-    fn read_isr(&self) -> u32 {
-        self.cregfile[2]
+    // Start execution at `pc`, now and after every warm reset.
+    fn set_entry(&mut self, pc: u32) {
+        self.entry = pc;
+        self.pc = pc;
+    }
+
+    // Purpose: warm reset; return the core to its power-on state without touching memory.
+    // Inputs: none.
+    // Outputs: registers, cregfile, PC, TLB and its counters, sleep/halt state
+    // and the cycle and retired-instruction counts are reinitialized. RAM, SD storage, device
+    // registers (including the PIT) in the shared `Memory` and debugger
+    // watchpoints are preserved.
+    pub fn reset(&mut self) {
+        self.regfile = [0; 32];
+        self.cregfile = Emulator::reset_cregfile(self.core_id);
+        self.tlb.clear();
+        self.tlb.reset_stats();
+        self.pc = self.entry;
+        self.asleep = self.core_id != 0;
+        self.sleep_armed = false;
+        self.halted = false;
+        self.halt_reason = None;
+        self.count = 0;
+        self.busy_cycles = 0;
+        self.retired = 0;
+        self.exc_cause = 0;
+        self.pending_tlb_fault = None;
+        self.pending_misaligned_addr = None;
+        self.watchpoint_hit = None;
+        self.creg_watch_hit = None;
+        self.catch_hit = None;
+        self.last_exception = None;
+        self.history.clear();
+    }
+
+    // Purpose: the instruction at the PC, for front-ends showing what runs next.
+    // Inputs: none; the PC is translated like a fetch, but the read touches
+    // neither the TLB stats and LRU order nor any device.
+    // Outputs: (pc, raw word, disassembly), or None when the PC is misaligned
+    // or misses in the TLB. No exception is raised either way.
+    pub fn current_instruction(&self) -> Option<(u32, u32, String)> {
+        let pc = self.pc;
+        self.peek_instr(pc)
+            .map(|instr| (pc, instr, disassemble_at(instr, pc)))
+    }
+
+    // Most recent exception or interrupt entry on this core, if any.
+    pub fn last_exception(&self) -> Option<ExceptionEntry> {
+        self.last_exception
+    }
+
+    // Purpose: record a handler entry and print it when --log-exceptions is on.
+    // Inputs: vector number, cause and the PC that faulted or was interrupted;
+    // call after `self.pc` has been loaded from the vector.
+    // Outputs: updates `last_exception` and the cause register (cr15), and
+    // records a `catch` hit when the debugger is catching this cause.
+    fn log_exception(&mut self, vector: u32, cause: ExceptionCause, fault_pc: u32) {
+        let entry = ExceptionEntry {
+            vector,
+            cause,
+            fault_pc,
+            new_pc: self.pc,
+        };
+        if LOG_EXCEPTIONS.load(Ordering::Relaxed) {
+            println!("[core {}] exception {}", self.core_id, entry);
+        }
+        self.last_exception = Some(entry);
+        self.exc_cause = vector;
+        if self.catch_hit.is_none() && self.catches.iter().any(|kind| kind.matches(cause)) {
+            self.catch_hit = Some(entry);
+        }
+    }
+
+    fn configure_audio(&mut self, audio_mode: AudioMode, sink: Option<Arc<AudioSink>>) {
+        self.audio_mode = audio_mode;
+        self.audio_sink = sink;
+    }
+
+    fn read_isr(&self) -> u32 {
+        self.cregfile[2]
     }
 
     fn write_isr(&mut self, value: u32) {
@@ -1237,6 +2257,20 @@ impl Emulator {
         }
     }
 
+    // Purpose: raise an interrupt as if its device had fired, so handlers can
+    // be exercised without wiring up the device.
+    // Inputs: interrupt number 0 through 15 (vector 0xF0 + bit).
+    // Outputs: false for numbers without a vector. Otherwise the ISR bit is
+    // set and the interrupt is taken on the next step or tick once IMR and
+    // the global enable bit allow it.
+    pub fn raise_interrupt(&mut self, bit: u32) -> bool {
+        if bit >= INTERRUPT_VECTORS {
+            return false;
+        }
+        self.cregfile[2] |= 1 << bit;
+        true
+    }
+
     fn read_mbi(&self) -> u32 {
         self.cregfile[10]
     }
@@ -1250,18 +2284,25 @@ impl Emulator {
             // ISR and MBI are core-local control registers.
             2 => self.read_isr(),
             CREG_MBI => self.read_mbi(),
-            _ => self.cregfile[idx],
+            CREG_CNT => self.retired as u32,
+            CREG_CNTH => (self.retired >> 32) as u32,
+            CREG_CAUSE => self.exc_cause,
+            _ => self.cregfile.get(idx).copied().unwrap_or(0),
         }
     }
 
     fn write_creg(&mut self, idx: usize, value: u32) {
         match idx {
             // Route ISR/MBI through helpers so we can track clears and core-local state.
-            2 | CREG_CID => {
-                // CID is read-only.
+            2 | CREG_CID | CREG_CNT | CREG_CNTH => {
+                // CID and the instruction counter are read-only.
                 println!("Warning: attempt to write read-only register cr{}", idx);
             }
             CREG_MBI => self.write_mbi(value),
+            CREG_CAUSE => self.exc_cause = value,
+            _ if idx >= self.cregfile.len() => {
+                println!("Warning: attempt to write nonexistent register cr{}", idx);
+            }
 
             _ => {
                 if idx == 0 && TRACE_INTERRUPTS.load(Ordering::Relaxed) {
@@ -1277,6 +2318,7 @@ impl Emulator {
 
     fn clear_pending_tlb_fault(&mut self) {
         self.pending_tlb_fault = None;
+        self.pending_misaligned_addr = None;
     }
 
     fn record_pending_tlb_fault(&mut self, flags: u32) {
@@ -1292,12 +2334,38 @@ impl Emulator {
         self.cregfile[0] != 0
     }
 
+    // Stop the core and remember why; the first recorded reason wins.
+    fn halt(&mut self, reason: HaltReason) {
+        self.halted = true;
+        if self.halt_reason.is_none() {
+            self.halt_reason = Some(reason);
+        }
+    }
+
+    // Why the core stopped, or None while it is still running.
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        self.halt_reason.clone()
+    }
+
+    fn report(&self, reason: HaltReason) -> RunReport {
+        RunReport {
+            reason,
+            value: self.regfile[RESULT_REG],
+            cycles: self.count,
+            retired: self.retired,
+            tlb: self.tlb.stats(),
+            states: Vec::new(),
+        }
+    }
+
     fn psr_inc_checked(&mut self, reason: &str) {
-        if self.cregfile[0] == u32::MAX {
-            panic!("too many nested exceptions!");
+        if self.cregfile[0] >= self.max_exception_depth {
+            self.halt(HaltReason::DoubleFault);
+            return;
         }
         let old = self.cregfile[0];
         self.cregfile[0] = self.cregfile[0].wrapping_add(1);
+        self.trap_entered = true;
         if TRACE_INTERRUPTS.load(Ordering::Relaxed) {
             println!(
                 "[core {}] psr inc {:08X} -> {:08X} ({} pc=0x{:08X})",
@@ -1357,35 +2425,62 @@ impl Emulator {
         }
     }
 
+    // Install the run's --watch watchpoints before a normal run.
+    // Outputs: whether any are set, so run loops can skip the per-tick check.
+    fn arm_run_watchpoints(&mut self, options: &RunOptions) -> bool {
+        self.watchpoints.clone_from(&options.watchpoints);
+        !self.watchpoints.is_empty()
+    }
+
+    // Print the watchpoint hit recorded by the last tick of a normal run, if
+    // any, followed by the registers when `dump_regs` is set.
+    fn report_run_watchpoint(&mut self, dump_regs: bool) {
+        let Some(hit) = self.watchpoint_hit.take() else {
+            return;
+        };
+        print!("[core {}] ", self.core_id);
+        debugger::print_watchpoint_hit(hit, self.pc);
+        if dump_regs {
+            self.print_regs();
+        }
+    }
+
     // Record the first watchpoint hit so the debugger can stop after stepping.
-    fn maybe_watch(&mut self, addr: u32, access: WatchAccess, value: u8) {
+    // The access covers `size` bytes from `addr` and carries `value`; it hits a
+    // watchpoint whose range overlaps those bytes and whose condition, if set,
+    // holds for `value`. The reported address is the first watched byte touched.
+    fn maybe_watch(&mut self, addr: u32, paddr: u32, size: u32, access: WatchAccess, value: u32) {
         if self.watchpoint_hit.is_some() || self.watchpoints.is_empty() {
             return;
         }
-        for wp in &self.watchpoints {
-            if wp.addr == addr {
-                let matches = match (wp.kind, access) {
-                    (WatchKind::Read, WatchAccess::Read) => true,
-                    (WatchKind::Write, WatchAccess::Write) => true,
-                    (WatchKind::ReadWrite, _) => true,
-                    _ => false,
-                };
-                if matches {
-                    self.watchpoint_hit = Some(WatchpointHit {
-                        addr,
-                        access,
-                        value,
-                    });
-                    break;
-                }
-            }
+        let hit = self.watchpoints.iter().find(|wp| {
+            let kind_matches = match (wp.kind, access) {
+                (WatchKind::Read, WatchAccess::Read) => true,
+                (WatchKind::Write, WatchAccess::Write) => true,
+                (WatchKind::ReadWrite, _) => true,
+                _ => false,
+            };
+            let overlaps = u64::from(wp.addr) < u64::from(addr) + u64::from(size)
+                && u64::from(addr) < u64::from(wp.addr) + u64::from(wp.len);
+            overlaps && kind_matches && wp.cond.is_none_or(|cond| cond.holds(value))
+        });
+        if let Some(wp) = hit {
+            let hit_addr = wp.addr.max(addr);
+            self.watchpoint_hit = Some(WatchpointHit {
+                addr: hit_addr,
+                paddr: paddr.wrapping_add(hit_addr.wrapping_sub(addr)),
+                access,
+                value,
+                size,
+            });
         }
     }
 
     fn convert_mem_address(&mut self, addr: u32, operation: u32) -> Option<u32> {
         let kmode = self.get_kmode();
         if kmode {
-            if addr <= PHYSMEM_MAX {
+            // Kernel mode identity-maps exactly the physical address space.
+            if self.memory.is_physical(addr) {
                 Some(addr)
             } else {
                 match self
@@ -1442,6 +2537,12 @@ impl Emulator {
         // save address and pid that caused exception
         self.cregfile[CREG_TLB] = (addr >> 12) | (self.cregfile[CREG_PID] << 20);
         self.cregfile[CREG_TLBF] = flags;
+        let cause = ExceptionCause::TlbMiss {
+            user: !self.get_kmode(),
+            vpn: self.cregfile[CREG_TLB] & 0xFFFFF,
+            pid: self.cregfile[CREG_TLB] >> 20,
+        };
+        let fault_pc = self.pc;
 
         self.save_state();
 
@@ -1449,9 +2550,16 @@ impl Emulator {
         self.pc = self
             .mem_read32(EXC_TLB_MISS_VECTOR * 4)
             .expect("shouldnt fail");
+        self.log_exception(EXC_TLB_MISS_VECTOR, cause, fault_pc);
     }
 
+    // Memory ops report every failure through here; an alignment fault
+    // recorded by check_alignment takes precedence over a TLB miss.
     fn raise_pending_tlb_miss(&mut self, addr: u32) {
+        if let Some(misaligned) = self.pending_misaligned_addr.take() {
+            self.raise_misaligned_data(misaligned);
+            return;
+        }
         let flags = self.take_pending_tlb_fault();
         self.raise_tlb_miss(addr, flags);
     }
@@ -1469,6 +2577,85 @@ impl Emulator {
         self.pc = self
             .mem_read32(EXC_MISALIGNED_PC_VECTOR * 4)
             .expect("misaligned-pc vector read should succeed");
+        self.log_exception(EXC_MISALIGNED_PC_VECTOR, ExceptionCause::MisalignedPc, pc);
+    }
+
+    fn raise_misaligned_data(&mut self, addr: u32) {
+        if TRACE_INTERRUPTS.load(Ordering::Relaxed) {
+            println!(
+                "[core {}] exception misaligned_data addr=0x{:08X} pc=0x{:08X} psr=0x{:08X}",
+                self.core_id, addr, self.pc, self.cregfile[0]
+            );
+        }
+
+        let fault_pc = self.pc;
+        self.save_state();
+        self.psr_inc_checked(PSR_REASON_MISALIGNED_DATA);
+        self.pc = self
+            .mem_read32(EXC_MISALIGNED_DATA_VECTOR * 4)
+            .expect("misaligned-data vector read should succeed");
+        self.log_exception(
+            EXC_MISALIGNED_DATA_VECTOR,
+            ExceptionCause::MisalignedData { addr },
+            fault_pc,
+        );
+    }
+
+    pub fn set_strict_alignment(&mut self, enabled: bool) {
+        self.strict_alignment = enabled;
+    }
+
+    // Byte order of a halfword or word access at `paddr`. Device registers
+    // hold values rather than memory images, so a PIT reload or DMA address
+    // reads and writes as the same number whatever the core's byte order.
+    fn order_at(&self, paddr: u32) -> Endianness {
+        if Memory::addr_touches_mmio(paddr) {
+            Endianness::Little
+        } else {
+            self.endianness
+        }
+    }
+
+    // Halfword and word access to physical memory in the core's byte order.
+    fn load_u16(&self, paddr: u32) -> u16 {
+        self.order_at(paddr).half(self.memory.read_u16(paddr))
+    }
+
+    fn load_u32(&self, paddr: u32) -> u32 {
+        self.order_at(paddr).word(self.memory.read_u32(paddr))
+    }
+
+    fn store_u16(&self, paddr: u32, value: u16) {
+        self.memory
+            .write_u16(paddr, self.order_at(paddr).half(value));
+    }
+
+    fn store_u32(&self, paddr: u32, value: u32) {
+        self.memory
+            .write_u32(paddr, self.order_at(paddr).word(value));
+    }
+
+    // Exceptions taken with the PSR at `depth` halt the core with
+    // HaltReason::DoubleFault instead of nesting further.
+    pub fn set_max_exception_depth(&mut self, depth: u32) {
+        self.max_exception_depth = depth;
+    }
+
+    // Purpose: enforce natural alignment for a data access of (mask + 1) bytes.
+    // Inputs: virtual address and alignment mask (1 for halfwords, 3 for words).
+    // Outputs: false when strict alignment rejects the access (the fault is
+    // raised by raise_pending_tlb_miss); otherwise warns and returns true so the
+    // caller masks the low bits.
+    fn check_alignment(&mut self, addr: u32, mask: u32) -> bool {
+        if (addr & mask) == 0 {
+            return true;
+        }
+        if self.strict_alignment {
+            self.pending_misaligned_addr = Some(addr);
+            return false;
+        }
+        println!("Warning: unaligned memory access at {:08x}", addr);
+        true
     }
 
     // memory operations must be aligned
@@ -1482,11 +2669,15 @@ impl Emulator {
         }
 
         let vaddr = addr;
-        let addr = self.convert_mem_address(addr, 1);
+        let addr = self
+            .convert_mem_address(addr, 1)
+            .filter(|paddr| self.memory.is_physical(*paddr));
 
         if let Some(addr) = addr {
             self.maybe_log_memmap_write(vaddr, addr, 1);
-            self.maybe_watch(vaddr, WatchAccess::Write, data);
+            self.maybe_watch(vaddr, addr, 1, WatchAccess::Write, u32::from(data));
+            self.memory.record_access(addr, true);
+            self.record_undo_bytes(addr, 1);
             self.memory.write(addr, data);
             true
         } else {
@@ -1496,9 +2687,8 @@ impl Emulator {
 
     fn mem_write16(&mut self, addr: u32, data: u16) -> bool {
         self.clear_pending_tlb_fault();
-        if (addr & 1) != 0 {
-            // unaligned access
-            println!("Warning: unaligned memory access at 0x{:08x}", addr);
+        if !self.check_alignment(addr, 1) {
+            return false;
         }
         if addr == 0 {
             println!(
@@ -1507,11 +2697,10 @@ impl Emulator {
             );
         }
         let addr = addr & 0xFFFFFFFE;
-        let bytes = data.to_le_bytes();
         let Some(paddr) = self.convert_mem_address(addr, 1) else {
             return false;
         };
-        if paddr > PHYSMEM_MAX - 1 {
+        if !self.memory.is_physical_range(paddr, 2) {
             return false;
         }
         let addrs = [paddr, paddr + 1];
@@ -1523,17 +2712,17 @@ impl Emulator {
                 }
             }
         }
-        self.maybe_watch(addr, WatchAccess::Write, bytes[0]);
-        self.maybe_watch(addr + 1, WatchAccess::Write, bytes[1]);
-        self.memory.write_u16(paddr, data);
+        self.maybe_watch(addr, paddr, 2, WatchAccess::Write, u32::from(data));
+        self.memory.record_access(paddr, true);
+        self.record_undo_bytes(paddr, 2);
+        self.store_u16(paddr, data);
         true
     }
 
     fn mem_write32(&mut self, addr: u32, data: u32) -> bool {
         self.clear_pending_tlb_fault();
-        if (addr & 3) != 0 {
-            // unaligned access
-            println!("Warning: unaligned memory access at {:08x}", addr);
+        if !self.check_alignment(addr, 3) {
+            return false;
         }
         if addr == 0 {
             println!(
@@ -1542,11 +2731,10 @@ impl Emulator {
             );
         }
         let addr = addr & 0xFFFFFFFC;
-        let bytes = data.to_le_bytes();
         let Some(paddr) = self.convert_mem_address(addr, 1) else {
             return false;
         };
-        if paddr > PHYSMEM_MAX - 3 {
+        if !self.memory.is_physical_range(paddr, 4) {
             return false;
         }
         let addrs = [paddr, paddr + 1, paddr + 2, paddr + 3];
@@ -1558,10 +2746,10 @@ impl Emulator {
                 }
             }
         }
-        for (i, byte) in bytes.iter().enumerate() {
-            self.maybe_watch(addr + i as u32, WatchAccess::Write, *byte);
-        }
-        self.memory.write_u32(paddr, data);
+        self.maybe_watch(addr, paddr, 4, WatchAccess::Write, data);
+        self.memory.record_access(paddr, true);
+        self.record_undo_bytes(paddr, 4);
+        self.store_u32(paddr, data);
         true
     }
 
@@ -1575,11 +2763,14 @@ impl Emulator {
         }
 
         let vaddr = addr;
-        let addr = self.convert_mem_address(addr, 0);
+        let addr = self
+            .convert_mem_address(addr, 0)
+            .filter(|paddr| self.memory.is_physical(*paddr));
 
         if let Some(addr) = addr {
+            self.memory.record_access(addr, false);
             let value = self.memory.read(addr);
-            self.maybe_watch(vaddr, WatchAccess::Read, value);
+            self.maybe_watch(vaddr, addr, 1, WatchAccess::Read, u32::from(value));
             Some(value)
         } else {
             None
@@ -1588,9 +2779,8 @@ impl Emulator {
 
     fn mem_read16(&mut self, addr: u32) -> Option<u16> {
         self.clear_pending_tlb_fault();
-        if (addr & 1) != 0 {
-            // unaligned access
-            println!("Warning: unaligned memory access at {:08x}", addr);
+        if !self.check_alignment(addr, 1) {
+            return None;
         }
         if addr == 0 {
             println!(
@@ -1600,20 +2790,19 @@ impl Emulator {
         }
         let addr = addr & 0xFFFFFFFE;
         let paddr = self.convert_mem_address(addr, 0)?;
-        if paddr > PHYSMEM_MAX - 1 {
+        if !self.memory.is_physical_range(paddr, 2) {
             return None;
         }
-        let bytes = self.memory.read_u16(paddr).to_le_bytes();
-        self.maybe_watch(addr, WatchAccess::Read, bytes[0]);
-        self.maybe_watch(addr + 1, WatchAccess::Read, bytes[1]);
-        Some(u16::from_le_bytes(bytes))
+        self.memory.record_access(paddr, false);
+        let value = self.load_u16(paddr);
+        self.maybe_watch(addr, paddr, 2, WatchAccess::Read, u32::from(value));
+        Some(value)
     }
 
     fn mem_read32(&mut self, addr: u32) -> Option<u32> {
         self.clear_pending_tlb_fault();
-        if (addr & 3) != 0 {
-            // unaligned access
-            println!("Warning: unaligned memory access at {:08x}", addr);
+        if !self.check_alignment(addr, 3) {
+            return None;
         }
         if addr == 0 {
             println!(
@@ -1623,85 +2812,200 @@ impl Emulator {
         }
         let addr = addr & 0xFFFFFFFC;
         let paddr = self.convert_mem_address(addr, 0)?;
-        if paddr > PHYSMEM_MAX - 3 {
+        if !self.memory.is_physical_range(paddr, 4) {
             return None;
         }
-        let bytes = self.memory.read_u32(paddr).to_le_bytes();
-        for (i, byte) in bytes.iter().enumerate() {
-            self.maybe_watch(addr + i as u32, WatchAccess::Read, *byte);
-        }
-        Some(u32::from_le_bytes(bytes))
+        self.memory.record_access(paddr, false);
+        let value = self.load_u32(paddr);
+        self.maybe_watch(addr, paddr, 4, WatchAccess::Read, value);
+        Some(value)
     }
 
     fn mem_atomic_swap32(&mut self, addr: u32, value: u32) -> Option<u32> {
         self.clear_pending_tlb_fault();
-        if (addr & 3) != 0 {
-            println!("Warning: unaligned memory access at {:08x}", addr);
+        if !self.check_alignment(addr, 3) {
+            return None;
         }
         let addr = addr & 0xFFFFFFFC;
         let read_addr = self.convert_mem_address(addr, 0)?;
         let write_addr = self.convert_mem_address(addr, 1)?;
-        if read_addr != write_addr {
+        if read_addr != write_addr || !self.memory.is_physical_range(read_addr, 4) {
             return None;
         }
         self.maybe_log_memmap_write(addr, write_addr, 4);
-        let prev = self.memory.atomic_swap_u32(read_addr, value);
-        let prev_bytes = prev.to_le_bytes();
-        let new_bytes = value.to_le_bytes();
-        for i in 0..4 {
-            let vaddr = addr + i as u32;
-            self.maybe_watch(vaddr, WatchAccess::Read, prev_bytes[i]);
-            self.maybe_watch(vaddr, WatchAccess::Write, new_bytes[i]);
-        }
+        self.memory.record_access(read_addr, false);
+        self.memory.record_access(write_addr, true);
+        self.record_undo_bytes(read_addr, 4);
+        let order = self.order_at(read_addr);
+        let prev = order.word(self.memory.atomic_swap_u32(read_addr, order.word(value)));
+        self.maybe_watch(addr, read_addr, 4, WatchAccess::Read, prev);
+        self.maybe_watch(addr, write_addr, 4, WatchAccess::Write, value);
         Some(prev)
     }
 
     fn mem_atomic_add32(&mut self, addr: u32, value: u32) -> Option<u32> {
         self.clear_pending_tlb_fault();
-        if (addr & 3) != 0 {
-            println!("Warning: unaligned memory access at {:08x}", addr);
+        if !self.check_alignment(addr, 3) {
+            return None;
         }
         let addr = addr & 0xFFFFFFFC;
         let read_addr = self.convert_mem_address(addr, 0)?;
         let write_addr = self.convert_mem_address(addr, 1)?;
-        if read_addr != write_addr {
+        if read_addr != write_addr || !self.memory.is_physical_range(read_addr, 4) {
             return None;
         }
         self.maybe_log_memmap_write(addr, write_addr, 4);
-        let prev = self.memory.atomic_add_u32(read_addr, value);
+        self.memory.record_access(read_addr, false);
+        self.memory.record_access(write_addr, true);
+        self.record_undo_bytes(read_addr, 4);
+        let order = self.order_at(read_addr);
+        let prev = order.word(self.memory.atomic_update_u32(read_addr, |prev| {
+            Some(order.word(order.word(prev).wrapping_add(value)))
+        }));
         let next = u32::wrapping_add(prev, value);
-        let prev_bytes = prev.to_le_bytes();
-        let next_bytes = next.to_le_bytes();
-        for i in 0..4 {
-            let vaddr = addr + i as u32;
-            self.maybe_watch(vaddr, WatchAccess::Read, prev_bytes[i]);
-            self.maybe_watch(vaddr, WatchAccess::Write, next_bytes[i]);
-        }
+        self.maybe_watch(addr, read_addr, 4, WatchAccess::Read, prev);
+        self.maybe_watch(addr, write_addr, 4, WatchAccess::Write, next);
         Some(prev)
     }
 
-    fn read_phys32(&mut self, addr: u32) -> Option<u32> {
-        if addr > PHYSMEM_MAX || addr + 3 > PHYSMEM_MAX {
+    // Compare-and-swap: store `value` only if the word equals `expected`.
+    // Needs read and write permission even when the comparison fails, like
+    // the other atomics; returns the previous word.
+    fn mem_atomic_cas32(&mut self, addr: u32, expected: u32, value: u32) -> Option<u32> {
+        self.clear_pending_tlb_fault();
+        if !self.check_alignment(addr, 3) {
+            return None;
+        }
+        let addr = addr & 0xFFFFFFFC;
+        let read_addr = self.convert_mem_address(addr, 0)?;
+        let write_addr = self.convert_mem_address(addr, 1)?;
+        if read_addr != write_addr || !self.memory.is_physical_range(read_addr, 4) {
             return None;
         }
-        Some(self.memory.read_u32(addr))
+        self.memory.record_access(read_addr, false);
+        self.record_undo_bytes(read_addr, 4);
+        let order = self.order_at(read_addr);
+        let prev = order.word(self.memory.atomic_cas_u32(
+            read_addr,
+            order.word(expected),
+            order.word(value),
+        ));
+        self.maybe_watch(addr, read_addr, 4, WatchAccess::Read, prev);
+        if prev == expected {
+            self.maybe_log_memmap_write(addr, write_addr, 4);
+            self.memory.record_access(write_addr, true);
+            self.maybe_watch(addr, write_addr, 4, WatchAccess::Write, value);
+        }
+        Some(prev)
+    }
+
+    // Debug reads use `Memory::peek`, so inspecting a device register never
+    // consumes input or trips a watchpoint.
+    fn read_phys32(&self, addr: u32) -> Option<u32> {
+        let mut bytes = [0u8; 4];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.memory.peek(addr.checked_add(offset as u32)?)?;
+        }
+        Some(self.order_at(addr).word(u32::from_le_bytes(bytes)))
+    }
+
+    fn read_phys8_debug(&self, addr: u32) -> Option<u8> {
+        self.memory.peek(addr)
+    }
+
+    // Debug writes to physical memory go through device dispatch like a guest
+    // store but skip translation and watchpoints. `size` is 1, 2 or 4 bytes;
+    // returns false when the access runs past the end of physical memory.
+    fn write_phys_debug(&mut self, addr: u32, value: u32, size: u32) -> bool {
+        if !self.memory.is_physical_range(addr, size) {
+            return false;
+        }
+        match size {
+            1 => self.memory.write(addr, value as u8),
+            2 => self.store_u16(addr, value as u16),
+            _ => self.store_u32(addr, value),
+        }
+        true
+    }
+
+    fn read_virt8_debug(&self, addr: u32) -> Option<u8> {
+        self.virt_to_phys_debug(addr)
+            .and_then(|paddr| self.memory.peek(paddr))
+    }
+
+    fn virt_to_phys_debug(&self, addr: u32) -> Option<u32> {
+        self.translate_debug(addr, 0)
+    }
+
+    // Translate a virtual address for the debugger the way a guest access
+    // with `operation` would, without touching the TLB stats or LRU order or
+    // leaving a fault pending.
+    fn translate_debug(&self, addr: u32, operation: u32) -> Option<u32> {
+        if self.get_kmode() && self.memory.is_physical(addr) {
+            return Some(addr);
+        }
+        match self.tlb.probe(
+            self.cregfile[CREG_PID],
+            addr >> 12,
+            operation,
+            self.get_kmode(),
+        ) {
+            TlbAccess::Hit(result) => Some(result | (addr & 0xFFF)),
+            TlbAccess::Fault(_) => None,
+        }
     }
 
-    // Debug reads bypass watchpoints so inspection doesn't change execution flow.
-    fn read_phys8_debug(&mut self, addr: u32) -> Option<u8> {
-        if addr > PHYSMEM_MAX {
+    // Read the instruction word at `pc` for the debugger, translated as a
+    // fetch but without side effects: a misaligned PC, a TLB miss or an
+    // address past the end of RAM yields None and nothing is left pending.
+    fn peek_instr(&self, pc: u32) -> Option<u32> {
+        if pc & 3 != 0 {
             return None;
         }
-        Some(self.memory.read(addr))
+        let paddr = self.translate_debug(pc, 2)?;
+        let mut word = 0;
+        for i in 0..4 {
+            word |= u32::from(self.memory.peek(paddr.checked_add(i)?)?) << (8 * i);
+        }
+        Some(self.endianness.word(word))
+    }
+
+    // Debug writes go through normal translation and device dispatch but skip
+    // watchpoints. Returns the number of bytes written before the first unmapped
+    // address.
+    fn write_virt_bytes_debug(&mut self, addr: u32, bytes: &[u8]) -> usize {
+        for (offset, byte) in bytes.iter().enumerate() {
+            let vaddr = addr.wrapping_add(offset as u32);
+            let Some(paddr) = self
+                .convert_mem_address(vaddr, 1)
+                .filter(|paddr| self.memory.is_physical(*paddr))
+            else {
+                self.clear_pending_tlb_fault();
+                return offset;
+            };
+            self.memory.write(paddr, *byte);
+        }
+        bytes.len()
     }
 
-    // Debug reads bypass watchpoints so inspection doesn't change execution flow.
-    fn read_virt8_debug(&mut self, addr: u32) -> Option<u8> {
-        self.convert_mem_address(addr, 0)
-            .map(|paddr| self.memory.read(paddr))
+    // Fetch the instruction about to issue; unlike the debugger's peeks, this
+    // counts toward the fetch profile.
+    fn issue_fetch(&mut self, vaddr: u32) -> Option<u32> {
+        let paddr = self.fetch_address(vaddr)?;
+        if self.fetch_profiling {
+            self.memory.record_fetch(paddr);
+        }
+        let word = self.memory.read_u32(paddr);
+        if self.endianness == Endianness::Little {
+            // MMIO is little-endian too, so no per-address check is needed.
+            return Some(word);
+        }
+        Some(self.order_at(paddr).word(word))
     }
 
-    fn fetch(&mut self, vaddr: u32) -> Option<u32> {
+    // Translate a fetch address, raising a misaligned-PC exception or leaving a
+    // pending TLB fault as a real fetch would.
+    fn fetch_address(&mut self, vaddr: u32) -> Option<u32> {
         self.clear_pending_tlb_fault();
         if (vaddr & 3) != 0 {
             self.raise_misaligned_pc(vaddr);
@@ -1711,31 +3015,120 @@ impl Emulator {
             println!("Warning: fetching from virtual address 0x00000000");
         }
 
-        let paddr = self.convert_mem_address(vaddr, 2);
+        self.convert_mem_address(vaddr, 2)
+            .filter(|paddr| self.memory.is_physical(*paddr))
+    }
+
+    // Purpose: execute exactly one instruction, ignoring the clock divider.
+    // Inputs: none.
+    // Outputs: what ran and whether an interrupt or exception redirected control.
+    // Watchpoint and control-register watch hits are recorded as in a run.
+    pub fn step(&mut self) -> StepResult {
+        if self.halted {
+            return StepResult::Halted;
+        }
+        self.recorded_step(Emulator::step_watched)
+    }
+
+    fn step_watched(&mut self) -> StepResult {
+        if self.creg_watches.is_empty() {
+            return self.step_inner();
+        }
+        // Snapshot control registers around the step so creg watches see every
+        // mutation path (crmv, exception/interrupt entry, rfe, flag updates).
+        let pc = self.pc;
+        let before: [u32; 13] = std::array::from_fn(|idx| self.read_creg(idx));
+        let result = self.step_inner();
+        self.check_creg_watches(&before, pc);
+        result
+    }
 
-        if let Some(addr) = paddr {
-            Some(self.memory.read_u32(addr))
-        } else {
-            None
+    fn step_inner(&mut self) -> StepResult {
+        self.trap_entered = false;
+        // A debugger step always issues an instruction, so it is a clock edge.
+        self.check_for_interrupts(true);
+        self.handle_interrupts();
+        let interrupt = std::mem::take(&mut self.trap_entered);
+
+        if self.asleep {
+            return StepResult::Sleeping;
+        }
+
+        let pc = self.pc;
+        let instr = self.issue_fetch(pc);
+        if self.pc != pc {
+            // Misaligned fetch; the exception redirect is already installed.
+            self.count = self.count.wrapping_add(1);
+            return StepResult::FetchFault {
+                pc,
+                tlb_miss: false,
+                interrupt,
+                next_pc: self.pc,
+            };
         }
+        let Some(instr) = instr else {
+            self.raise_pending_tlb_miss(pc);
+            self.count = self.count.wrapping_add(1);
+            return StepResult::FetchFault {
+                pc,
+                tlb_miss: true,
+                interrupt,
+                next_pc: self.pc,
+            };
+        };
+        self.execute(instr);
+        // A step runs the whole instruction, so charge all of its cycles.
+        self.count = self.count.wrapping_add(cycle_cost(instr));
+        self.retired = self.retired.wrapping_add(1);
+        StepResult::Executed {
+            pc,
+            instr,
+            interrupt,
+            exception: self.trap_entered,
+            next_pc: self.pc,
+        }
+    }
+
+    // Execute one instruction, logging it when it falls in the --trace range.
+    #[cold]
+    fn execute_traced(&mut self, pc: u32, instr: u32) {
+        let Some(trace) = self.instr_trace.clone().filter(|trace| trace.covers(pc)) else {
+            self.execute(instr);
+            return;
+        };
+        let before = self.regfile;
+        self.execute(instr);
+        trace.record(self.core_id, pc, instr, &before, &self.regfile);
     }
 
     fn tick(&mut self) {
-        self.check_for_interrupts();
-        self.handle_interrupts();
+        let clk_divider = self.memory.clock_divider();
+        let clock_edge = (self.count % cmp::max(u32::wrapping_add(clk_divider, 1), 1)) == 0;
 
-        let clk_divider = self.memory.read_u32(CLK_REG_START);
+        self.check_for_interrupts(clock_edge);
+        self.handle_interrupts();
 
-        if !self.asleep && ((self.count % cmp::max(u32::wrapping_add(clk_divider, 1), 1)) == 0) {
+        if !self.asleep && clock_edge && self.busy_cycles > 0 {
+            // The previous instruction still occupies the core.
+            self.busy_cycles -= 1;
+        } else if !self.asleep && clock_edge {
             let fetch_pc = self.pc;
-            let instr = self.fetch(fetch_pc);
+            let instr = self.issue_fetch(fetch_pc);
 
             // Fetch can raise a synchronous exception before any instruction is
             // decoded, so avoid reclassifying that cycle as a TLB miss.
             if self.pc != fetch_pc {
                 // Exception redirect already installed by fetch.
             } else if let Some(instr) = instr {
-                self.execute(instr);
+                if self.instr_trace.is_none() {
+                    self.execute(instr);
+                } else {
+                    self.execute_traced(fetch_pc, instr);
+                }
+                // Counted after execute, so crmv reading cr13 sees only the
+                // instructions before it.
+                self.retired = self.retired.wrapping_add(1);
+                self.busy_cycles = cycle_cost(instr) - 1;
             } else {
                 self.raise_pending_tlb_miss(fetch_pc);
             }
@@ -1752,32 +3145,34 @@ impl Emulator {
         }
     }
 
-    pub fn run(
+    pub fn run(self, max_iters: u32, with_graphics: bool, audio_mode: AudioMode) -> Option<u32> {
+        self.run_report(max_iters, with_graphics, audio_mode)
+            .result()
+    }
+
+    // Purpose: run a single core until it halts, faults, or exhausts `max_iters`.
+    // Inputs: cycle budget (0 = unlimited), graphics toggle, host audio policy.
+    // Outputs: a report carrying the halt reason, r1, and the cycle count.
+    pub fn run_report(
+        self,
+        max_iters: u32,
+        with_graphics: bool,
+        audio_mode: AudioMode,
+    ) -> RunReport {
+        self.run_report_with(max_iters, with_graphics, audio_mode, &RunOptions::default())
+    }
+
+    // Purpose: `run_report` with the command line's run options.
+    pub fn run_report_with(
         mut self,
         max_iters: u32,
         with_graphics: bool,
         audio_mode: AudioMode,
-    ) -> Option<u32> {
+        options: &RunOptions,
+    ) -> RunReport {
         let mut graphics: Option<Graphics> = None;
         if with_graphics {
-            graphics = Some(Graphics::new(
-                self.memory.get_pixel_frame_buffer(),
-                self.memory.get_tile_frame_buffer(),
-                self.memory.get_tile_map(),
-                self.memory.get_io_buffer(),
-                self.memory.get_input_pending(),
-                self.memory.get_tile_vscroll_register(),
-                self.memory.get_tile_hscroll_register(),
-                self.memory.get_pixel_vscroll_register(),
-                self.memory.get_pixel_hscroll_register(),
-                self.memory.get_sprite_map(),
-                self.memory.get_tile_scale_register(),
-                self.memory.get_pixel_scale_register(),
-                self.memory.get_sprite_scale_registers(),
-                self.memory.get_vga_status_register(),
-                self.memory.get_vga_frame_register(),
-                self.memory.get_pending_interrupt(),
-            ));
+            graphics = Some(Graphics::new(&self.memory, options.window));
         }
         let (audio_mode, audio_output) = AudioPlayback::start(audio_mode, Arc::clone(&self.memory));
         let emulated_sink = audio_output
@@ -1785,28 +3180,50 @@ impl Emulator {
             .and_then(|output| output.emulated_sink());
         self.configure_audio(audio_mode, emulated_sink);
 
-        // Return value and termination signal
-        let ret: Arc<Mutex<Option<u32>>> = Arc::new(Mutex::new(None));
+        let memory = Arc::clone(&self.memory);
         let finished: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
+        spawn_watchdog(options.timeout, {
+            let timed_out = Arc::clone(&timed_out);
+            move || timed_out.store(true, Ordering::Relaxed)
+        });
 
+        let console = options
+            .ascii_console
+            .map(|rate| spawn_console(Arc::clone(&self.memory), Arc::clone(&finished), rate));
+        let stdin_keys = options
+            .stdin_keys
+            .then(|| spawn_stdin_keys(Arc::clone(&self.memory), Arc::clone(&finished)));
+        let watching = self.arm_run_watchpoints(options);
+        let watch_dump = options.watch_dump;
+        let dump_state = options.dump_state;
         // Runs emulator on thread because graphics must use main thread
         let handle = thread::spawn({
-            let ret_clone = Arc::clone(&ret);
             let finished_clone = Arc::clone(&finished);
             move || {
+                // Close the window even if the core panics.
+                let _finish = FinishOnDrop(Arc::clone(&finished_clone));
                 self.count = 0;
                 while !self.halted {
                     self.tick();
+                    if watching {
+                        self.report_run_watchpoint(watch_dump);
+                    }
                     if max_iters != 0 && self.count > max_iters {
-                        *ret_clone.lock().unwrap() = None;
-                        *finished_clone.lock().unwrap() = true;
-                        return;
+                        self.halt(HaltReason::MaxCycles);
+                    }
+                    if timed_out.load(Ordering::Relaxed) {
+                        self.halt(HaltReason::Timeout);
                     }
                 }
-
-                // return the value in r3
-                *ret_clone.lock().unwrap() = Some(self.regfile[1]);
                 *finished_clone.lock().unwrap() = true;
+
+                // the program's exit value is in r1 (RESULT_REG)
+                let mut report = self.report(self.halt_reason.clone().unwrap_or(HaltReason::Halt));
+                if dump_state {
+                    report.states = vec![self.state_dump()];
+                }
+                report
             }
         });
 
@@ -1814,16 +3231,23 @@ impl Emulator {
             graphics.unwrap().start(finished, false);
         }
 
-        handle.join().unwrap();
+        let report = handle
+            .join()
+            .unwrap_or_else(|payload| RunReport::panicked(&*payload));
+        if let Some(console) = console {
+            let _ = console.join();
+        }
+        if let Some(stdin_keys) = stdin_keys {
+            let _ = stdin_keys.join();
+        }
         drop(audio_output);
-
-        // return the value in r3
-        return *ret.lock().unwrap();
+        memory.flush_output();
+        report
     }
 
     // Purpose: run the multicore emulator and keep the shared memory alive for inspection.
     // Inputs: program path, runtime configuration, and optional SD preload images.
-    // Outputs: core-0 r1 plus the shared memory state after all cores exit.
+    // Outputs: core-0 run report plus the shared memory state after all cores exit.
     pub fn run_multicore_with_memory(
         path: String,
         cores: usize,
@@ -1832,27 +3256,39 @@ impl Emulator {
         with_graphics: bool,
         audio_mode: AudioMode,
         use_uart_rx: bool,
+        uart_input: &[u8],
         sd_dma_ticks_per_word: u32,
         sd0_image: Option<&[u8]>,
         sd1_image: Option<&[u8]>,
-    ) -> (Option<u32>, Arc<Memory>) {
+        fb_visibility: FramebufferVisibility,
+        config: &EmulatorConfig,
+        options: &RunOptions,
+    ) -> (RunReport, Arc<Memory>) {
         assert!((1..=4).contains(&cores), "cores must be in 1..=4");
-        let image = load_program(&path);
-        let memory: Arc<Memory> = Arc::new(Memory::new(
+        let image = load_program(&path, config);
+        let entry = image.entry;
+        let memory = build_memory(
             image.instructions,
             use_uart_rx,
             sd_dma_ticks_per_word,
-        ));
+            config,
+        );
+        memory.set_framebuffer_visibility(fb_visibility);
         if let Some(image) = sd0_image {
             memory.load_sd_image(SdSlot::Sd0, image);
         }
         if let Some(image) = sd1_image {
             memory.load_sd_image(SdSlot::Sd1, image);
         }
+        memory.queue_uart_input(uart_input);
         let interrupts = InterruptController::new(cores);
 
         let finished = Arc::new(Mutex::new(false));
         let shared = Arc::new(RunShared::new(cores, Arc::clone(&finished)));
+        spawn_watchdog(options.timeout, {
+            let shared = Arc::clone(&shared);
+            move || shared.expire()
+        });
 
         let scheduler = match sched {
             ScheduleMode::Free => None,
@@ -1861,24 +3297,7 @@ impl Emulator {
 
         let mut graphics = None;
         if with_graphics {
-            graphics = Some(Graphics::new(
-                memory.get_pixel_frame_buffer(),
-                memory.get_tile_frame_buffer(),
-                memory.get_tile_map(),
-                memory.get_io_buffer(),
-                memory.get_input_pending(),
-                memory.get_tile_vscroll_register(),
-                memory.get_tile_hscroll_register(),
-                memory.get_pixel_vscroll_register(),
-                memory.get_pixel_hscroll_register(),
-                memory.get_sprite_map(),
-                memory.get_tile_scale_register(),
-                memory.get_pixel_scale_register(),
-                memory.get_sprite_scale_registers(),
-                memory.get_vga_status_register(),
-                memory.get_vga_frame_register(),
-                memory.get_pending_interrupt(),
-            ));
+            graphics = Some(Graphics::new(&memory, options.window));
         }
         let (audio_mode, audio_output) = AudioPlayback::start(audio_mode, Arc::clone(&memory));
         let emulated_sink = audio_output
@@ -1892,19 +3311,49 @@ impl Emulator {
                 Arc::clone(&interrupts),
                 use_uart_rx,
                 core_id as u32,
+                config,
             );
             if core_id == 0 {
+                cpu.set_entry(entry);
                 cpu.configure_audio(audio_mode, emulated_sink.clone());
             }
+            cpu.arm_run_watchpoints(options);
+            let watch_dump = options.watch_dump;
+            let dump_state = options.dump_state;
             // Each core runs in its own thread to allow real races.
             let shared_clone = Arc::clone(&shared);
             let scheduler_clone = scheduler.clone();
             let handle = thread::spawn(move || {
-                run_core_loop(cpu, max_iters, scheduler_clone, shared_clone, core_id);
+                let run = panic::catch_unwind(AssertUnwindSafe(|| {
+                    run_core_loop(
+                        cpu,
+                        max_iters,
+                        scheduler_clone.clone(),
+                        Arc::clone(&shared_clone),
+                        core_id,
+                        watch_dump,
+                        dump_state,
+                    )
+                }));
+                if let Err(payload) = run {
+                    // Stop the other cores rather than leave them waiting on
+                    // a turn or a result that will never come.
+                    shared_clone.request_stop();
+                    if let Some(sched) = &scheduler_clone {
+                        sched.stop();
+                    }
+                    shared_clone.record_exit(core_id, RunReport::panicked(&*payload));
+                }
             });
             handles.push(handle);
         }
 
+        let console = options
+            .ascii_console
+            .map(|rate| spawn_console(Arc::clone(&memory), Arc::clone(&finished), rate));
+        let stdin_keys = options
+            .stdin_keys
+            .then(|| spawn_stdin_keys(Arc::clone(&memory), Arc::clone(&finished)));
         if let Some(mut graphics) = graphics {
             graphics.start(Arc::clone(&finished), false);
         }
@@ -1912,11 +3361,36 @@ impl Emulator {
         for handle in handles {
             handle.join().unwrap();
         }
+        if let Some(console) = console {
+            let _ = console.join();
+        }
+        if let Some(stdin_keys) = stdin_keys {
+            let _ = stdin_keys.join();
+        }
         drop(audio_output);
+        memory.flush_output();
 
-        // Return value is r1 from core 0.
+        // Return value is r1 from core 0; a panic on any core fails the run.
         let results = shared.results.lock().unwrap();
-        (results.get(0).copied().unwrap_or(None), memory)
+        let mut report = results[0]
+            .clone()
+            .expect("core 0 must record a run report on exit");
+        if let Some(panicked) = results
+            .iter()
+            .flatten()
+            .find(|result| matches!(result.reason, HaltReason::Panic(_)))
+        {
+            report.reason = panicked.reason.clone();
+        }
+        report.tlb = TlbStats::default();
+        report.retired = 0;
+        report.states = Vec::new();
+        for result in results.iter().flatten() {
+            report.tlb.merge(&result.tlb);
+            report.retired += result.retired;
+            report.states.extend(result.states.iter().cloned());
+        }
+        (report, memory)
     }
 
     // Purpose: run the multicore emulator to completion and return core 0's result.
@@ -1933,8 +3407,9 @@ impl Emulator {
         sd_dma_ticks_per_word: u32,
         sd0_image: Option<&[u8]>,
         sd1_image: Option<&[u8]>,
+        tlb_entries: usize,
     ) -> Option<u32> {
-        let (result, _) = Self::run_multicore_with_memory(
+        let (report, _) = Self::run_multicore_with_memory(
             path,
             cores,
             sched,
@@ -1942,14 +3417,25 @@ impl Emulator {
             with_graphics,
             audio_mode,
             use_uart_rx,
+            &[],
             sd_dma_ticks_per_word,
             sd0_image,
             sd1_image,
+            FramebufferVisibility::WriteThrough,
+            &EmulatorConfig {
+                tlb_entries,
+                ..EmulatorConfig::default()
+            },
+            &RunOptions::default(),
         );
-        result
+        report.result()
     }
 
-    fn check_for_interrupts(&mut self) {
+    // Purpose: sample device interrupt lines and advance core-0 shared devices.
+    // Inputs: `clock_edge` is true on cycles where the clock divider lets an
+    // instruction issue; the PIT only counts on those.
+    // Outputs: newly raised interrupts are merged into this core's ISR.
+    fn check_for_interrupts(&mut self, clock_edge: bool) {
         // Input routing only needs a queue-empty check, not the full queue lock.
         let io_nonempty = self.memory.has_pending_input();
         self.interrupts
@@ -1960,7 +3446,7 @@ impl Emulator {
             self.interrupts.dispatch_device_interrupts(ints);
 
             // Shared PIT countdown is advanced by core 0 only.
-            if self.memory.tick_pit() {
+            if clock_edge && self.memory.tick_pit() {
                 self.interrupts.broadcast_timer();
             }
 
@@ -1986,8 +3472,46 @@ impl Emulator {
         }
     }
 
+    // Purpose: whether a sleeping core can still be woken.
+    // Inputs: none.
+    // Outputs: false when IMR masks every interrupt. Only the core itself can
+    // write IMR, so such a sleep lasts until another core halts the system or
+    // the run stops (max cycles, timeout, debugger).
+    pub fn can_wake(&self) -> bool {
+        self.cregfile[CREG_IMR] & low_bits_mask(INTERRUPT_VECTORS) != 0
+    }
+
+    // Leave sleep; "mode sleep" advances to the next instruction.
+    fn wake(&mut self) {
+        if self.asleep && self.sleep_armed {
+            self.pc += 4;
+        }
+        self.asleep = false;
+        self.sleep_armed = false;
+    }
+
     fn handle_interrupts(&mut self) {
-        if self.cregfile[3] >> 31 != 0 {
+        if self.cregfile[3] >> 31 == 0 {
+            // With interrupts globally disabled, a sleeping core still wakes
+            // for an interrupt IMR enables, like a wait-for-interrupt: it
+            // resumes after the sleep without vectoring and the interrupt
+            // stays pending in ISR until software enables or acknowledges it.
+            if !self.asleep {
+                return;
+            }
+            let waking = self.cregfile[3] & self.read_isr() & low_bits_mask(INTERRUPT_VECTORS);
+            if waking != 0 {
+                if TRACE_INTERRUPTS.load(Ordering::Relaxed) {
+                    println!(
+                        "[core {}] wake on {} with interrupts disabled (pc={:08X})",
+                        self.core_id,
+                        format_interrupts(waking),
+                        self.pc
+                    );
+                }
+                self.wake();
+            }
+        } else {
             // top bit activates/disables all interrupts
             let active_ints = self.cregfile[3] & self.read_isr();
 
@@ -2006,14 +3530,8 @@ impl Emulator {
                 );
             }
 
-            // Undo sleep; "mode sleep" advances to the next instruction.
-            if self.asleep {
-                if self.sleep_armed {
-                    self.pc += 4;
-                }
-            }
-            self.asleep = false;
-            self.sleep_armed = false;
+            self.wake();
+            let fault_pc = self.pc;
 
             self.save_state();
 
@@ -2088,6 +3606,11 @@ impl Emulator {
                     .mem_read32(0xF0 * 4)
                     .expect("this address shouldn't error");
             }
+            let vectored = active_ints & 0xFFFF;
+            if vectored != 0 {
+                let bit = 31 - vectored.leading_zeros();
+                self.log_exception(0xF0 + bit, ExceptionCause::Interrupt { bit }, fault_pc);
+            }
         }
     }
 
@@ -2101,12 +3624,34 @@ impl Emulator {
             );
         }
 
+        let fault_pc = self.pc;
         self.save_state();
 
         self.psr_inc_checked("invalid_instr");
 
         self.pc = self.mem_read32(0x80 * 4).expect("shouldn't fail");
-        return;
+        self.log_exception(0x80, ExceptionCause::InvalidInstr, fault_pc);
+    }
+
+    fn raise_illegal_opcode(&mut self, opcode: u32) {
+        if TRACE_INTERRUPTS.load(Ordering::Relaxed) {
+            println!(
+                "[core {}] exception illegal_opcode op={} pc=0x{:08X} psr=0x{:08X}",
+                self.core_id, opcode, self.pc, self.cregfile[0]
+            );
+        }
+
+        let fault_pc = self.pc;
+        self.save_state();
+        self.psr_inc_checked("illegal_opcode");
+        self.pc = self
+            .mem_read32(EXC_ILLEGAL_OPCODE_VECTOR * 4)
+            .expect("illegal-opcode vector read should succeed");
+        self.log_exception(
+            EXC_ILLEGAL_OPCODE_VECTOR,
+            ExceptionCause::IllegalOpcode { opcode },
+            fault_pc,
+        );
     }
 
     fn execute(&mut self, instr: u32) {
@@ -2118,19 +3663,29 @@ impl Emulator {
             2 => self.load_upper_immediate(instr),
 
             // 32 bit mem instructions
-            3 => self.mem_absolute(instr, 2),
-            4 => self.mem_relative(instr, 2),
-            5 => self.mem_imm(instr, 2),
+            3 => self.mem_absolute(instr, 2, false),
+            4 => self.mem_relative(instr, 2, false),
+            5 => self.mem_imm(instr, 2, false),
 
             // 16 bit mem instructions
-            6 => self.mem_absolute(instr, 1),
-            7 => self.mem_relative(instr, 1),
-            8 => self.mem_imm(instr, 1),
+            6 => self.mem_absolute(instr, 1, false),
+            7 => self.mem_relative(instr, 1, false),
+            8 => self.mem_imm(instr, 1, false),
 
             // 8 bit mem instructions
-            9 => self.mem_absolute(instr, 0),
-            10 => self.mem_relative(instr, 0),
-            11 => self.mem_imm(instr, 0),
+            9 => self.mem_absolute(instr, 0, false),
+            10 => self.mem_relative(instr, 0, false),
+            11 => self.mem_imm(instr, 0, false),
+
+            // sign-extending 16 bit loads
+            23 => self.mem_absolute(instr, 1, true),
+            24 => self.mem_relative(instr, 1, true),
+            25 => self.mem_imm(instr, 1, true),
+
+            // sign-extending 8 bit loads
+            26 => self.mem_absolute(instr, 0, true),
+            27 => self.mem_relative(instr, 0, true),
+            28 => self.mem_imm(instr, 0, true),
 
             12 => self.branch_imm(instr),
             13 => self.branch_absolute(instr),
@@ -2150,8 +3705,11 @@ impl Emulator {
             20 => self.atomic_relative(instr, 1),
             21 => self.atomic_imm(instr, 1),
 
+            // compare-and-swap
+            29 => self.atomic_absolute(instr, 2),
+
             31 => self.kernel_instr(instr),
-            _ => self.raise_exc_instr(),
+            _ => self.raise_illegal_opcode(opcode),
         }
     }
 
@@ -2200,11 +3758,13 @@ impl Emulator {
                 // Shift op
                 Some(imm & 0x1F)
             }
-            14..=18 => {
+            14..=18 | 22..=25 => {
                 // Arithmetic op
                 Some(imm | (0xFFFFF000 * ((imm >> 11) & 1))) // sign extend
             }
             _ => {
+                // includes single-operand ops with no immediate form (clz,
+                // bswap, hswap)
                 self.raise_exc_instr();
                 return None;
             }
@@ -2228,13 +3788,30 @@ impl Emulator {
         let r_b = self.get_reg(r_b);
 
         let r_c = if imm {
-            self.decode_alu_imm(op, instr & 0xFFF)
-                .expect("immediate decoding failed")
+            // decode_alu_imm has already raised exc_instr for ops without an
+            // immediate form
+            let Some(value) = self.decode_alu_imm(op, instr & 0xFFF) else {
+                return;
+            };
+            value
         } else {
             let r_c = instr & 0x1F;
             self.get_reg(r_c)
         };
 
+        // division with immediate does imm / reg, matching sub
+        let (dividend, divisor) = if imm { (r_c, r_b) } else { (r_b, r_c) };
+        if (22..=25).contains(&op) && divisor == 0 {
+            // divide by zero faults before any flags or registers change
+            self.raise_exc_instr();
+            return;
+        }
+
+        // Register shift amounts are full 32-bit values. Amounts of 32 or more
+        // shift every bit out (carry set when any bit was shifted out) and
+        // rotates wrap modulo 32; amounts below 32 keep their original results.
+        let rotate = r_c % 32;
+
         let prev_carry = self.cregfile[5] & 1;
 
         self.cregfile[5] &= 0xFFFFFFF0; // clear arithmetic flags
@@ -2264,45 +3841,71 @@ impl Emulator {
                 !r_c // not
             }
             7 => {
-                // set carry flag
-                self.cregfile[5] |= (r_b >> if r_c > 0 { 32 - r_c } else { 0 } != 0) as u32;
-                r_b << r_c // lsl
+                if r_c >= 32 {
+                    self.cregfile[5] |= (r_b != 0) as u32;
+                    0
+                } else {
+                    // set carry flag
+                    self.cregfile[5] |= (r_b >> if r_c > 0 { 32 - r_c } else { 0 } != 0) as u32;
+                    r_b << r_c // lsl
+                }
             }
             8 => {
-                // set carry flag
-                self.cregfile[5] |= (r_b & ((1 << r_c) - 1) != 0) as u32;
-                r_b >> r_c // lsr
+                if r_c >= 32 {
+                    self.cregfile[5] |= (r_b != 0) as u32;
+                    0
+                } else {
+                    // set carry flag
+                    self.cregfile[5] |= (r_b & ((1 << r_c) - 1) != 0) as u32;
+                    r_b >> r_c // lsr
+                }
             }
             9 => {
                 // set carry flag
                 let carry = r_b & 1;
                 let sign = r_b >> 31;
                 self.cregfile[5] |= carry;
-                (r_b >> r_c) | (0xFFFFFFFF * sign << if r_c > 0 { 32 - r_c } else { 0 }) // asr
+                if r_c >= 32 {
+                    0xFFFFFFFF * sign
+                } else {
+                    (r_b >> r_c) | ((0xFFFFFFFF * sign) << if r_c > 0 { 32 - r_c } else { 0 }) // asr
+                }
             }
             10 => {
                 // set carry flag
-                let carry = r_b >> if r_c > 0 { 32 - r_c } else { 0 };
+                let carry = r_b >> if rotate > 0 { 32 - rotate } else { 0 };
                 self.cregfile[5] |= (carry != 0) as u32;
-                (r_b << r_c) | carry // rotl
+                (r_b << rotate) | carry // rotl
             }
             11 => {
                 // set carry flag
-                let carry = r_b & ((1 << r_c) - 1);
+                let carry = r_b & ((1 << rotate) - 1);
                 self.cregfile[5] |= (carry != 0) as u32;
-                (r_b >> r_c) | (carry << if r_c > 0 { 32 - r_c } else { 0 }) // rotr
+                (r_b >> rotate) | (carry << if rotate > 0 { 32 - rotate } else { 0 }) // rotr
             }
             12 => {
-                // set carry flag
-                let carry = if r_c > 0 { r_b >> (32 - r_c) } else { 0 };
-                self.cregfile[5] |= (carry != 0) as u32;
-                (r_b << r_c) | if r_c > 0 { prev_carry << (r_c - 1) } else { 0 } // lslc
+                if r_c >= 32 {
+                    // the previous carry lands in bit 31, as for a shift of 32
+                    self.cregfile[5] |= (r_b != 0) as u32;
+                    prev_carry << 31
+                } else {
+                    // set carry flag
+                    let carry = if r_c > 0 { r_b >> (32 - r_c) } else { 0 };
+                    self.cregfile[5] |= (carry != 0) as u32;
+                    (r_b << r_c) | if r_c > 0 { prev_carry << (r_c - 1) } else { 0 } // lslc
+                }
             }
             13 => {
-                // set carry flag
-                let carry = r_b & ((1 << r_c) - 1);
-                self.cregfile[5] |= (carry != 0) as u32;
-                (r_b >> r_c) | (prev_carry << if r_c > 0 { 32 - r_c } else { 0 }) // lsrc
+                if r_c >= 32 {
+                    // the previous carry lands in bit 0, as for a shift of 32
+                    self.cregfile[5] |= (r_b != 0) as u32;
+                    prev_carry
+                } else {
+                    // set carry flag
+                    let carry = r_b & ((1 << r_c) - 1);
+                    self.cregfile[5] |= (carry != 0) as u32;
+                    (r_b >> r_c) | (prev_carry << if r_c > 0 { 32 - r_c } else { 0 }) // lsrc
+                }
             }
             14 => {
                 // add
@@ -2346,7 +3949,7 @@ impl Emulator {
                 // two's complement
                 let result = if imm {
                     let r_b = 1 + u64::from(!(u32::wrapping_add(u32::from(prev_carry == 0), r_b)));
-                    u64::from(imm) + r_b
+                    u64::from(r_c) + r_b
                 } else {
                     let r_c = 1 + u64::from(!(u32::wrapping_add(u32::from(prev_carry == 0), r_c)));
                     r_c + u64::from(r_b)
@@ -2383,6 +3986,37 @@ impl Emulator {
                 // tncd (truncate to double)
                 r_c & 0xFFFF
             }
+            22 => {
+                // udiv
+                dividend / divisor
+            }
+            23 => {
+                // sdiv
+                // the only overflowing case is INT_MIN / -1, which wraps to INT_MIN
+                let overflow = dividend == 0x80000000 && divisor == 0xFFFFFFFF;
+                self.cregfile[5] |= (overflow as u32) << 3;
+                (dividend as i32).wrapping_div(divisor as i32) as u32
+            }
+            24 => {
+                // umod
+                dividend % divisor
+            }
+            25 => {
+                // smod (sign follows the dividend)
+                (dividend as i32).wrapping_rem(divisor as i32) as u32
+            }
+            26 => {
+                // clz (count leading zeros of r_b; 32 for zero)
+                r_b.leading_zeros()
+            }
+            27 => {
+                // bswap (reverse the bytes of r_b)
+                r_b.swap_bytes()
+            }
+            28 => {
+                // hswap (swap the halfwords of r_b)
+                r_b.rotate_left(16)
+            }
             _ => {
                 self.raise_exc_instr();
                 return;
@@ -2407,7 +4041,7 @@ impl Emulator {
         self.pc += 4;
     }
 
-    fn mem_absolute(&mut self, instr: u32, size: u8) {
+    fn mem_absolute(&mut self, instr: u32, size: u8, signed: bool) {
         // instruction format is
         // 00011aaaaabbbbb?yyzziiiiiiiiiiii
         // op (5 bits) | r_a (5 bits) | r_b (5 bits) | op (1 bit) | y (2 bits) | z (2 bits) | imm (12 bits)
@@ -2415,6 +4049,11 @@ impl Emulator {
         let r_a = (instr >> 22) & 0x1F;
         let r_b = (instr >> 17) & 0x1F;
         let is_load = ((instr >> 16) & 1) != 0; // is this a load? else is store
+        if signed && !is_load {
+            // signed opcodes only encode loads
+            self.raise_exc_instr();
+            return;
+        }
         let y = (instr >> 14) & 3; // offset type: 0 = signed offset, 1 = preinc, 2 = postinc, 3 = reserved
         let z = (instr >> 12) & 3; // shift amount
         let imm = instr & 0xFFF;
@@ -2441,11 +4080,13 @@ impl Emulator {
             let data = match size {
                 0 => {
                     // byte
-                    self.mem_read8(addr).map(|v| u32::from(v))
+                    self.mem_read8(addr)
+                        .map(|v| extend_load(u32::from(v), 8, signed))
                 }
                 1 => {
                     // halfword
-                    self.mem_read16(addr).map(|v| u32::from(v))
+                    self.mem_read16(addr)
+                        .map(|v| extend_load(u32::from(v), 16, signed))
                 }
                 2 => {
                     // word
@@ -2498,7 +4139,7 @@ impl Emulator {
         self.pc += 4;
     }
 
-    fn mem_relative(&mut self, instr: u32, size: u8) {
+    fn mem_relative(&mut self, instr: u32, size: u8, signed: bool) {
         // instruction format is
         // 00100aaaaabbbbb?iiiiiiiiiiiiiiii
         // op (5 bits) | r_a (5 bits) | r_b (5 bits) | op (1 bit) | imm (16 bits)
@@ -2506,6 +4147,11 @@ impl Emulator {
         let r_a = (instr >> 22) & 0x1F;
         let r_b = (instr >> 17) & 0x1F;
         let is_load = ((instr >> 16) & 1) != 0; // is this a load? else is store
+        if signed && !is_load {
+            // signed opcodes only encode loads
+            self.raise_exc_instr();
+            return;
+        }
         let imm = instr & 0xFFFF;
 
         // sign extend imm
@@ -2523,11 +4169,13 @@ impl Emulator {
             let data = match size {
                 0 => {
                     // byte
-                    self.mem_read8(addr).map(|v| u32::from(v))
+                    self.mem_read8(addr)
+                        .map(|v| extend_load(u32::from(v), 8, signed))
                 }
                 1 => {
                     // halfword
-                    self.mem_read16(addr).map(|v| u32::from(v))
+                    self.mem_read16(addr)
+                        .map(|v| extend_load(u32::from(v), 16, signed))
                 }
                 2 => {
                     // word
@@ -2577,13 +4225,18 @@ impl Emulator {
         self.pc += 4;
     }
 
-    fn mem_imm(&mut self, instr: u32, size: u8) {
+    fn mem_imm(&mut self, instr: u32, size: u8, signed: bool) {
         // instruction format is
         // 00101aaaaa?iiiiiiiiiiiiiiiiiiiii
         // op (5 bits) | r_a (5 bits) | op (1 bit) | imm (21 bits)
 
         let r_a = (instr >> 22) & 0x1F;
         let is_load = ((instr >> 21) & 1) != 0; // is this a load? else is store
+        if signed && !is_load {
+            // signed opcodes only encode loads
+            self.raise_exc_instr();
+            return;
+        }
         let imm = instr & 0x1FFFFF;
 
         // sign extend imm
@@ -2597,11 +4250,13 @@ impl Emulator {
             let data = match size {
                 0 => {
                     // byte
-                    self.mem_read8(addr).map(|v| u32::from(v))
+                    self.mem_read8(addr)
+                        .map(|v| extend_load(u32::from(v), 8, signed))
                 }
                 1 => {
                     // halfword
-                    self.mem_read16(addr).map(|v| u32::from(v))
+                    self.mem_read16(addr)
+                        .map(|v| extend_load(u32::from(v), 16, signed))
                 }
                 2 => {
                     // word
@@ -2654,8 +4309,9 @@ impl Emulator {
     fn atomic_absolute(&mut self, instr: u32, type_: u8) {
         // instruction format is
         // 10000aaaaabbbbbccccciiiiiiiiiiii - fadd
-        // opcode is 10011 for swap
+        // opcode is 10011 for swap, 11101 for compare-and-swap
         // op (5 bits) | r_a (5 bits) | r_c (5 bits) | r_b (5 bits) | imm (12 bits)
+        // cas stores r_a if the word equals r_c; all three write the old word to r_a
 
         let r_a = (instr >> 22) & 0x1F;
         let r_c = (instr >> 17) & 0x1F;
@@ -2673,6 +4329,10 @@ impl Emulator {
         let data = match type_ {
             0 => self.mem_atomic_add32(addr, r_c_out),
             1 => self.mem_atomic_swap32(addr, r_c_out),
+            2 => {
+                let r_a_out = self.get_reg(r_a);
+                self.mem_atomic_cas32(addr, r_c_out, r_a_out)
+            }
             _ => panic!("invalid atomic type"),
         };
         if let Some(data) = data {
@@ -2862,6 +4522,14 @@ impl Emulator {
         }
     }
 
+    // `trap` is the only system-call instruction and carries no call number:
+    // every non-zero payload is a reserved encoding. Call numbers are a
+    // software convention between user code and the guest kernel, which
+    // installs its dispatcher at the shared trap vector (0x04):
+    // - r1 = call number on entry, return value on exit
+    // - r2.. = arguments
+    // Services such as putchar/getchar or heap growth are implemented by that
+    // handler on top of the UART/PS2 MMIO registers, not by the emulator.
     fn trap_instr(&mut self, instr: u32) {
         const TRAP_PAYLOAD_MASK: u32 = 0x07FF_FFFF;
         const TRAP_VECTOR_ADDR: u32 = 0x04;
@@ -2875,6 +4543,7 @@ impl Emulator {
 
         // Trap entry resumes at the following instruction, but otherwise
         // snapshots architectural trap state like any other exception entry.
+        let fault_pc = self.pc;
         self.save_state();
         self.cregfile[4] = self.pc.wrapping_add(4);
         self.psr_inc_checked("trap");
@@ -2882,6 +4551,7 @@ impl Emulator {
         self.pc = self
             .mem_read32(TRAP_VECTOR_ADDR)
             .expect("trap vector read should succeed");
+        self.log_exception(TRAP_VECTOR_ADDR / 4, ExceptionCause::Trap, fault_pc);
     }
 
     // carry flag handled separately in each alu operation
@@ -2891,13 +4561,17 @@ impl Emulator {
         let rhs_sign = rhs >> 31;
 
         let is_sub = op == 16 || op == 17;
+        // division sets overflow in alu_op; carry stays clear
+        let is_div = (22..=25).contains(&op);
 
         // set the zero flag
         self.cregfile[5] |= ((result == 0) as u32) << 1;
         // set the sign flag
         self.cregfile[5] |= ((result_sign != 0) as u32) << 2;
         // set the overflow flag
-        self.cregfile[5] |= if is_sub {
+        self.cregfile[5] |= if is_div {
+            0
+        } else if is_sub {
             (((result_sign != lhs_sign) && (lhs_sign != rhs_sign)) as u32) << 3
         } else {
             (((result_sign != lhs_sign) && (lhs_sign == rhs_sign)) as u32) << 3
@@ -2916,11 +4590,13 @@ impl Emulator {
                 );
             }
 
+            let fault_pc = self.pc;
             self.save_state();
 
             self.psr_inc_checked("priv");
 
             self.pc = self.mem_read32(0x81 * 4).expect("shouldn't fail");
+            self.log_exception(0x81, ExceptionCause::Privileged, fault_pc);
             return;
         }
 
@@ -3047,13 +4723,14 @@ impl Emulator {
             // mode run
             self.pc += 4;
         } else if op == 1 {
-            // mode sleep
+            // mode sleep: wait for an interrupt IMR enables (see
+            // handle_interrupts for the globally disabled case).
             self.asleep = true;
             // Mark as a sleep instruction so interrupts advance PC.
             self.sleep_armed = true;
         } else {
             // mode halt
-            self.halted = true;
+            self.halt(HaltReason::Halt);
         }
     }
 
@@ -3084,18 +4761,23 @@ fn run_core_loop(
     scheduler: Option<Arc<Scheduler>>,
     shared: Arc<RunShared>,
     core_id: usize,
+    watch_dump: bool,
+    dump_state: bool,
 ) {
     cpu.count = 0;
+    let watching = !cpu.watchpoints.is_empty();
     loop {
         if shared.should_stop() {
             if let Some(sched) = &scheduler {
                 sched.stop();
             }
+            cpu.halt(shared.stop_reason());
             break;
         }
         if let Some(sched) = &scheduler {
             // Non-free scheduling blocks until this core is chosen.
             if !sched.wait_turn(core_id) {
+                cpu.halt(shared.stop_reason());
                 break;
             }
         }
@@ -3103,6 +4785,7 @@ fn run_core_loop(
             if let Some(sched) = &scheduler {
                 sched.stop();
             }
+            cpu.halt(shared.stop_reason());
             break;
         }
         if cpu.halted {
@@ -3117,6 +4800,9 @@ fn run_core_loop(
 
         // Advance one CPU tick per scheduling turn.
         cpu.tick();
+        if watching {
+            cpu.report_run_watchpoint(watch_dump);
+        }
 
         if cpu.halted {
             // Any core halting stops the entire system.
@@ -3129,6 +4815,7 @@ fn run_core_loop(
         }
 
         if max_iters != 0 && cpu.count > max_iters {
+            cpu.halt(HaltReason::MaxCycles);
             shared.request_stop();
             if let Some(sched) = &scheduler {
                 sched.stop();
@@ -3141,18 +4828,30 @@ fn run_core_loop(
         }
     }
 
-    shared.record_exit(core_id, cpu.regfile[1]);
+    let reason = cpu.halt_reason.clone().unwrap_or(HaltReason::Stopped);
+    let mut report = cpu.report(reason);
+    if dump_state {
+        report.states = vec![cpu.state_dump()];
+    }
+    shared.record_exit(core_id, report);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::memory::CLK_REG_START;
 
     #[test]
     fn write_isr_preserves_concurrently_pending_ipi() {
         let memory = Arc::new(Memory::new(HashMap::new(), false, 1));
         let interrupts = InterruptController::new(2);
-        let mut cpu = Emulator::from_shared(Arc::clone(&memory), Arc::clone(&interrupts), false, 0);
+        let mut cpu = Emulator::from_shared(
+            Arc::clone(&memory),
+            Arc::clone(&interrupts),
+            false,
+            0,
+            &EmulatorConfig::default(),
+        );
 
         cpu.cregfile[2] = TIMER_INTERRUPT_BIT;
 
@@ -3169,7 +4868,7 @@ mod tests {
             "MBI must reflect the visible pending IPI payload",
         );
 
-        cpu.check_for_interrupts();
+        cpu.check_for_interrupts(true);
 
         assert_eq!(
             cpu.cregfile[2], IPI_INTERRUPT_BIT,
@@ -3185,7 +4884,13 @@ mod tests {
     fn send_ipi_fails_until_target_acknowledges_ipi() {
         let memory = Arc::new(Memory::new(HashMap::new(), false, 1));
         let interrupts = InterruptController::new(1);
-        let mut cpu = Emulator::from_shared(Arc::clone(&memory), Arc::clone(&interrupts), false, 0);
+        let mut cpu = Emulator::from_shared(
+            Arc::clone(&memory),
+            Arc::clone(&interrupts),
+            false,
+            0,
+            &EmulatorConfig::default(),
+        );
 
         assert!(interrupts.send_ipi(0, 0x1111_2222));
         assert!(
@@ -3193,7 +4898,7 @@ mod tests {
             "a second IPI to the same core must fail while the first payload is pending",
         );
 
-        cpu.check_for_interrupts();
+        cpu.check_for_interrupts(true);
 
         assert_eq!(
             cpu.cregfile[2] & IPI_INTERRUPT_BIT,
@@ -3222,7 +4927,7 @@ mod tests {
             "IPI delivery must reopen after the target acknowledges the IPI",
         );
 
-        cpu.check_for_interrupts();
+        cpu.check_for_interrupts(true);
 
         assert_eq!(
             cpu.cregfile[10], 0x7777_8888,
@@ -3264,7 +4969,8 @@ mod tests {
     fn crmv_write_to_isr_is_ignored() {
         let memory = Arc::new(Memory::new(HashMap::new(), false, 1));
         let interrupts = InterruptController::new(1);
-        let mut cpu = Emulator::from_shared(memory, interrupts, false, 0);
+        let mut cpu =
+            Emulator::from_shared(memory, interrupts, false, 0, &EmulatorConfig::default());
 
         cpu.cregfile[2] = TIMER_INTERRUPT_BIT;
         cpu.regfile[1] = 0xFFFF_FFFF;
@@ -3282,7 +4988,8 @@ mod tests {
     fn eoi_specific_clears_only_selected_isr_bit() {
         let memory = Arc::new(Memory::new(HashMap::new(), false, 1));
         let interrupts = InterruptController::new(1);
-        let mut cpu = Emulator::from_shared(memory, interrupts, false, 0);
+        let mut cpu =
+            Emulator::from_shared(memory, interrupts, false, 0, &EmulatorConfig::default());
 
         cpu.cregfile[2] = TIMER_INTERRUPT_BIT | SD_INTERRUPT_BIT;
 
@@ -3299,7 +5006,13 @@ mod tests {
     fn eoi_all_preserves_concurrently_pending_ipi() {
         let memory = Arc::new(Memory::new(HashMap::new(), false, 1));
         let interrupts = InterruptController::new(2);
-        let mut cpu = Emulator::from_shared(Arc::clone(&memory), Arc::clone(&interrupts), false, 0);
+        let mut cpu = Emulator::from_shared(
+            Arc::clone(&memory),
+            Arc::clone(&interrupts),
+            false,
+            0,
+            &EmulatorConfig::default(),
+        );
 
         cpu.cregfile[2] = TIMER_INTERRUPT_BIT | SD_INTERRUPT_BIT;
         assert!(interrupts.send_ipi(0, 0xCAFE_BABE));
@@ -3316,4 +5029,1073 @@ mod tests {
             "eoi all must expose the visible pending IPI payload in MBI",
         );
     }
+
+    // Build a single-core emulator with `words` placed at the reset PC.
+    fn program_ram(words: &[u32]) -> HashMap<u32, u8> {
+        let mut ram = HashMap::new();
+        for (idx, word) in words.iter().enumerate() {
+            for (byte_idx, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(RESET_PC + (idx * 4 + byte_idx) as u32, *byte);
+            }
+        }
+        ram
+    }
+
+    fn cpu_with_program(words: &[u32]) -> Emulator {
+        Emulator::from_instructions(
+            program_ram(words),
+            false,
+            1,
+            None,
+            None,
+            DEFAULT_TLB_ENTRIES,
+        )
+    }
+
+    const MODE_HALT: u32 = (31u32 << 27) | (2u32 << 12) | (2u32 << 10);
+    const BR_SELF: u32 = (12u32 << 27) | 0x3F_FFFF;
+
+    #[test]
+    fn sleep_with_interrupts_disabled_wakes_without_vectoring() {
+        const MODE_SLEEP: u32 = (31u32 << 27) | (2u32 << 12) | (1u32 << 10);
+        let mut cpu = cpu_with_program(&[MODE_SLEEP, MODE_HALT]);
+        cpu.memory.write_u32(0xF0 * 4, 0x3000);
+
+        // Nothing unmasked: the sleep cannot end, even with ISR set.
+        assert!(matches!(cpu.step(), StepResult::Executed { .. }));
+        assert!(cpu.asleep && !cpu.can_wake());
+        assert!(cpu.raise_interrupt(0));
+        assert_eq!(cpu.step(), StepResult::Sleeping);
+
+        // Timer enabled in IMR but interrupts globally disabled: the core
+        // wakes after the sleep, the timer stays pending and is not taken.
+        cpu.cregfile[CREG_IMR] = 1;
+        assert!(cpu.can_wake());
+        assert!(matches!(
+            cpu.step(),
+            StepResult::Executed {
+                pc: 0x404,
+                interrupt: false,
+                ..
+            }
+        ));
+        assert!(cpu.halted && !cpu.asleep);
+        assert_eq!(cpu.halt_reason, Some(HaltReason::Halt));
+        assert_eq!(cpu.read_isr() & 1, 1);
+        assert_eq!(cpu.cregfile[CREG_EPC], 0);
+        assert!(cpu.last_exception().is_none());
+    }
+
+    #[test]
+    fn raised_interrupt_vectors_once_imr_allows_it() {
+        // 0x400: add r1, r0, 1; 0x404: br .; 0x408: br . (timer handler)
+        let mut ram = program_ram(&[0x0840_E001, BR_SELF, BR_SELF]);
+        for (offset, byte) in 0x408u32.to_le_bytes().iter().enumerate() {
+            ram.insert(0xF0 * 4 + offset as u32, *byte);
+        }
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        assert!(!cpu.raise_interrupt(INTERRUPT_VECTORS));
+
+        // Enabled globally but masked in IMR: the bit stays pending.
+        cpu.cregfile[CREG_IMR] = 0x8000_0000;
+        assert!(cpu.raise_interrupt(0));
+        cpu.step();
+        assert_eq!((cpu.pc, cpu.regfile[1]), (0x404, 1));
+        assert_eq!(cpu.cregfile[2], TIMER_INTERRUPT_BIT);
+
+        cpu.cregfile[CREG_IMR] |= TIMER_INTERRUPT_BIT;
+        let result = cpu.step();
+        assert!(matches!(
+            result,
+            StepResult::Executed {
+                pc: 0x408,
+                interrupt: true,
+                ..
+            }
+        ));
+        assert_eq!(cpu.cregfile[CREG_EPC], 0x404);
+        assert_eq!(cpu.cregfile[CREG_IMR] >> 31, 0);
+    }
+
+    #[test]
+    fn kernel_loads_past_reduced_physmem_take_a_tlb_miss() {
+        // lw r4, [r2 - 4]; lw r3, [r2]
+        let load = (3u32 << 27) | (2 << 17) | (1 << 16);
+        let mut ram = program_ram(&[load | (4 << 22) | 0xFFC, load | (3 << 22)]);
+        ram.extend([
+            (0xFFFC, 0x78),
+            (0xFFFD, 0x56),
+            (0xFFFE, 0x34),
+            (0xFFFF, 0x12),
+        ]);
+        let memory = Arc::new(Memory::with_physmem_size(ram, false, 1, 0x10000));
+        let mut cpu = Emulator::from_shared(
+            memory,
+            InterruptController::new(1),
+            false,
+            0,
+            &EmulatorConfig::default(),
+        );
+        cpu.regfile[2] = 0x10000;
+
+        cpu.step();
+        assert_eq!(cpu.regfile[4], 0x1234_5678);
+        assert!(matches!(
+            cpu.step(),
+            StepResult::Executed {
+                exception: true,
+                ..
+            }
+        ));
+        assert_eq!(cpu.regfile[3], 0);
+        assert_eq!(cpu.cregfile[CREG_EPC], RESET_PC + 4);
+    }
+
+    #[test]
+    fn endianness_orders_fetches_and_data_access() {
+        // lw r3, [0x40C]; lh r4, [0x40C]; mode halt; data bytes 11 22 33 44.
+        let program = [
+            0x28E0_0008,
+            (6 << 27) | (4 << 22) | (1 << 16) | 0x40C,
+            MODE_HALT,
+        ];
+        for order in [Endianness::Little, Endianness::Big] {
+            let mut ram = HashMap::new();
+            for (idx, word) in program.iter().enumerate() {
+                for (offset, byte) in order.word(*word).to_le_bytes().iter().enumerate() {
+                    ram.insert(RESET_PC + (idx * 4 + offset) as u32, *byte);
+                }
+            }
+            for (offset, byte) in [0x11, 0x22, 0x33, 0x44].into_iter().enumerate() {
+                ram.insert(0x40C + offset as u32, byte);
+            }
+            let mut cpu =
+                Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+            cpu.endianness = order;
+            while !cpu.halted {
+                cpu.step();
+            }
+            let expected = match order {
+                Endianness::Little => (0x4433_2211, 0x2211),
+                Endianness::Big => (0x1122_3344, 0x1122),
+            };
+            assert_eq!((cpu.regfile[3], cpu.regfile[4]), expected, "{:?}", order);
+
+            // Stores lay the bytes back down in the same order.
+            assert!(cpu.mem_write32(0x500, 0xAABB_CCDD));
+            assert!(cpu.mem_write16(0x504, 0xEEFF));
+            let bytes: Vec<u8> = (0x500..0x506)
+                .map(|a| cpu.memory.peek(a).unwrap())
+                .collect();
+            let expected: [u8; 6] = match order {
+                Endianness::Little => [0xDD, 0xCC, 0xBB, 0xAA, 0xFF, 0xEE],
+                Endianness::Big => [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF],
+            };
+            assert_eq!(bytes, expected);
+            assert_eq!(cpu.mem_atomic_add32(0x500, 1), Some(0xAABB_CCDD));
+            assert_eq!(cpu.mem_read32(0x500), Some(0xAABB_CCDE));
+            assert_eq!(cpu.read_phys32(0x500), Some(0xAABB_CCDE));
+
+            // Device registers keep their values in either order.
+            assert!(cpu.mem_write32(CLK_REG_START, 0x0000_0102));
+            assert_eq!(cpu.memory.read_u32(CLK_REG_START), 0x0000_0102);
+            assert_eq!(cpu.mem_read32(CLK_REG_START), Some(0x0000_0102));
+        }
+        assert_eq!(Endianness::parse("BE"), Some(Endianness::Big));
+        assert_eq!(Endianness::parse("middle"), None);
+    }
+
+    #[test]
+    fn guest_loads_and_stores_are_counted_per_page() {
+        // lw r3, [0x40C]; sw r3, [0x40C]; mode halt; data word
+        let cpu = cpu_with_program(&[0x28E0_0008, 0x28C0_0004, MODE_HALT, 7]);
+        let memory = Arc::clone(&cpu.memory);
+        cpu.run_report(0, false, AudioMode::Disabled);
+        let stats = memory.access_stats();
+        assert_eq!(
+            stats.regions,
+            vec![("ram", 1, 1)],
+            "fetches are not counted"
+        );
+        assert_eq!(stats.pages, vec![(0x0000, 1, 1, 0)]);
+    }
+
+    #[test]
+    fn profiling_counts_a_loop_over_an_array() {
+        let program = [
+            0x0880_E00A, // add r2, r0, 10
+            0x08C0_2130, // or r3, r0, 0x3000
+            0x1847_0000, // loop: lw r1, [r3]
+            0x0884_EFFF, // add r2, r2, -1
+            0x60BF_FFFD, // bnz loop
+            MODE_HALT,
+        ];
+        let cpu = Emulator::from_instructions_with_config(
+            program_ram(&program),
+            false,
+            1,
+            None,
+            None,
+            &EmulatorConfig {
+                fetch_profiling: true,
+                ..EmulatorConfig::default()
+            },
+        );
+        let memory = Arc::clone(&cpu.memory);
+        cpu.run_report(0, false, AudioMode::Disabled);
+        let stats = memory.access_stats();
+        // 2 setup instructions, 10 passes through the 3-instruction loop, halt.
+        assert_eq!(stats.pages, vec![(0x0000, 0, 0, 33), (0x3000, 10, 0, 0)]);
+    }
+
+    #[test]
+    fn signed_loads_sign_extend_and_unsigned_loads_zero_extend() {
+        // Absolute loads from [r2 + 0]: load bit (16) set, r_a = r1.
+        let load = |opcode: u32| (opcode << 27) | (1 << 22) | (2 << 17) | (1 << 16);
+        for (opcode, value, expected) in [
+            (9, 0xFF, 0x0000_00FF),
+            (26, 0xFF, 0xFFFF_FFFF),
+            (26, 0x7F, 0x0000_007F),
+            (6, 0x8001, 0x0000_8001),
+            (23, 0x8001, 0xFFFF_8001),
+        ] {
+            let mut cpu = cpu_with_program(&[load(opcode)]);
+            cpu.regfile[2] = 0x2000;
+            cpu.memory.write_u32(0x2000, value);
+            cpu.step();
+            assert_eq!(
+                cpu.regfile[1], expected,
+                "opcode {} loading {:X}",
+                opcode, value
+            );
+        }
+
+        // lbs r1, [12]: pc-relative immediate form.
+        let mut cpu = cpu_with_program(&[(28 << 27) | (1 << 22) | (1 << 21) | 12]);
+        cpu.memory.write(RESET_PC + 16, 0x80);
+        cpu.step();
+        assert_eq!(cpu.regfile[1], 0xFFFF_FF80);
+
+        // A signed opcode with the store bit clear is not a valid instruction.
+        let mut cpu = cpu_with_program(&[(27 << 27) | (1 << 22)]);
+        cpu.step();
+        assert_eq!(cpu.read_creg(CREG_CAUSE), 0x80);
+    }
+
+    #[test]
+    fn cas_stores_only_when_memory_matches_and_returns_the_old_word() {
+        // casa r1, r2, [r3, 4]
+        let cas = (29 << 27) | (1 << 22) | (2 << 17) | (3 << 12) | 4;
+        let mut cpu = cpu_with_program(&[cas, cas]);
+        cpu.regfile[3] = 0x2000;
+        cpu.memory.write_u32(0x2004, 7);
+
+        // Success: memory held the expected 7, so 9 is stored.
+        cpu.regfile[1] = 9;
+        cpu.regfile[2] = 7;
+        cpu.step();
+        assert_eq!(cpu.regfile[1], 7);
+        assert_eq!(cpu.memory.read_u32(0x2004), 9);
+
+        // Failure: memory now holds 9, not 7; it is left unchanged.
+        cpu.regfile[1] = 11;
+        cpu.step();
+        assert_eq!(cpu.regfile[1], 9);
+        assert_eq!(cpu.memory.read_u32(0x2004), 9);
+        assert_eq!(cpu.pc, RESET_PC + 8);
+    }
+
+    #[test]
+    fn cas_without_write_permission_raises_a_tlb_miss() {
+        // User mode, page 0x2000 mapped read-only: casa r1, r2, [r3, 0].
+        let mut cpu = cpu_with_program(&[(29 << 27) | (1 << 22) | (2 << 17) | (3 << 12)]);
+        cpu.memory.write_u32(EXC_TLB_MISS_VECTOR * 4, 0x3000);
+        cpu.tlb
+            .write(0, 0x2, (0x2 << 12) | TLB_FLAG_READ | TLB_FLAG_USER);
+        cpu.cregfile[0] = 0;
+        cpu.regfile[3] = 0x2000;
+        cpu.memory.write_u32(0x2000, 5);
+        cpu.regfile[1] = 6;
+        cpu.regfile[2] = 5;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x3000);
+        assert_eq!(cpu.memory.read_u32(0x2000), 5);
+        assert_eq!(cpu.regfile[1], 6);
+    }
+
+    #[test]
+    fn unassigned_opcode_takes_the_illegal_opcode_vector() {
+        // Opcode 30 is unassigned; crmv with kernel sub-op 6 is a malformed
+        // encoding of an assigned opcode.
+        let mut cpu = cpu_with_program(&[30 << 27, (31 << 27) | (6 << 12)]);
+        cpu.memory.write_u32(EXC_ILLEGAL_OPCODE_VECTOR * 4, 0x3000);
+        cpu.memory.write_u32(0x80 * 4, 0x4000);
+
+        cpu.step();
+        assert_eq!(cpu.pc, 0x3000);
+        assert_eq!(cpu.cregfile[CREG_EPC], RESET_PC);
+        assert_eq!(cpu.read_creg(CREG_CAUSE), EXC_ILLEGAL_OPCODE_VECTOR);
+        assert_eq!(
+            cpu.last_exception().map(|entry| entry.cause),
+            Some(ExceptionCause::IllegalOpcode { opcode: 30 })
+        );
+
+        cpu.pc = RESET_PC + 4;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x4000);
+        assert_eq!(cpu.read_creg(CREG_CAUSE), 0x80);
+    }
+
+    #[test]
+    fn user_store_to_unmapped_page_logs_a_tlb_umiss() {
+        // sw r1, [r2]
+        let mut cpu = cpu_with_program(&[(3 << 27) | (1 << 22) | (2 << 17)]);
+        cpu.memory.write_u32(EXC_TLB_MISS_VECTOR * 4, 0x3000);
+        // Map the code page for user execution; leave the data page unmapped.
+        cpu.tlb
+            .write(0, 0, TLB_FLAG_READ | TLB_FLAG_EXEC | TLB_FLAG_USER);
+        cpu.cregfile[0] = 0;
+        cpu.regfile[1] = 0x0001_2344;
+        cpu.regfile[2] = 0x0001_2344;
+
+        cpu.step();
+
+        let entry = cpu.last_exception().expect("store should fault");
+        assert_eq!(
+            entry,
+            ExceptionEntry {
+                vector: EXC_TLB_MISS_VECTOR,
+                cause: ExceptionCause::TlbMiss {
+                    user: true,
+                    vpn: 0x12,
+                    pid: 0
+                },
+                fault_pc: RESET_PC,
+                new_pc: 0x3000,
+            }
+        );
+        assert_eq!(
+            entry.to_string(),
+            "TLB_UMISS vpn=0x00012 pid=0 vector 0x82 pc 00000400 -> 00003000"
+        );
+    }
+
+    #[test]
+    fn instruction_counter_counts_retired_instructions_not_ticks() {
+        let crmv_r1_cnt =
+            (31u32 << 27) | (1u32 << 22) | ((CREG_CNT as u32) << 17) | (1u32 << 12) | (1u32 << 10);
+        // add r2, r0, 5; loop: add r2, r2, -1; bnz loop; crmv r1, cr13; mode halt
+        let program = [
+            alu_imm(14, 2, 0, 5),
+            alu_imm(14, 2, 2, 0xFFF),
+            0x60BF_FFFE,
+            crmv_r1_cnt,
+            MODE_HALT,
+        ];
+        for clk_divider in [0, 3] {
+            let mut cpu = cpu_with_program(&program);
+            cpu.memory.write_u32(CLK_REG_START, clk_divider);
+            while !cpu.halted {
+                cpu.tick();
+            }
+            // 1 setup instruction plus 5 iterations of the two-instruction loop.
+            assert_eq!(cpu.regfile[1], 11, "divider {}", clk_divider);
+            assert_eq!(cpu.retired, 13, "crmv and halt retire too");
+            assert_eq!(cpu.read_creg(CREG_CNTH), 0);
+            assert!(cpu.count >= 13 * (clk_divider + 1) - clk_divider);
+        }
+    }
+
+    #[test]
+    fn pit_interval_counts_instructions_under_the_clock_divider() {
+        use crate::memory::{PIT_CTRL_ONESHOT, PIT_CTRL_START, PIT_START};
+
+        // Record how many instructions had retired whenever the timer fires.
+        fn timer_hits(cpu: &mut Emulator, instructions: u64) -> Vec<u64> {
+            let mut hits = Vec::new();
+            while cpu.retired < instructions {
+                cpu.tick();
+                if cpu.cregfile[2] & TIMER_INTERRUPT_BIT != 0 {
+                    hits.push(cpu.retired);
+                    cpu.cregfile[2] &= !TIMER_INTERRUPT_BIT;
+                }
+            }
+            hits
+        }
+
+        for clk_divider in [0, 3] {
+            let mut cpu = cpu_with_program(&[BR_SELF]);
+            cpu.memory.write_u32(CLK_REG_START, clk_divider);
+            cpu.memory.write_u32(PIT_START, 7);
+            let hits = timer_hits(&mut cpu, 40);
+            // Latched on the clock edge that issues every 7th instruction.
+            assert_eq!(hits, [7, 14, 21, 28, 35], "divider {}", clk_divider);
+            assert!(cpu.memory.read_u32(PIT_START) <= 7);
+
+            cpu.memory.write_u32(PIT_CTRL_START, PIT_CTRL_ONESHOT);
+            cpu.memory.write_u32(PIT_START, 5);
+            let start = cpu.retired;
+            let hits = timer_hits(&mut cpu, start + 20);
+            assert_eq!(hits, [start + 5], "divider {}", clk_divider);
+            assert_eq!(cpu.memory.read_u32(PIT_START), 0);
+        }
+    }
+
+    #[test]
+    fn step_reports_each_instruction_and_redirect() {
+        // add r1, r0, 5; op 19 has no immediate form, so this traps; then halt
+        let mut cpu = cpu_with_program(&[alu_imm(14, 1, 0, 5), alu_imm(19, 1, 1, 0), MODE_HALT]);
+        assert_eq!(
+            cpu.step(),
+            StepResult::Executed {
+                pc: RESET_PC,
+                instr: alu_imm(14, 1, 0, 5),
+                interrupt: false,
+                exception: false,
+                next_pc: RESET_PC + 4,
+            }
+        );
+        let StepResult::Executed {
+            exception, next_pc, ..
+        } = cpu.step()
+        else {
+            panic!("the trapping instruction should still execute");
+        };
+        assert!(exception);
+        assert_eq!(next_pc, cpu.pc);
+
+        let mut cpu = cpu_with_program(&[MODE_HALT]);
+        cpu.pc = RESET_PC + 2;
+        assert!(matches!(
+            cpu.step(),
+            StepResult::FetchFault { pc, tlb_miss: false, .. } if pc == RESET_PC + 2
+        ));
+
+        let mut cpu = cpu_with_program(&[MODE_HALT]);
+        assert!(matches!(cpu.step(), StepResult::Executed { .. }));
+        assert_eq!(cpu.step(), StepResult::Halted);
+        assert_eq!(cpu.retired, 1);
+    }
+
+    #[test]
+    fn run_returns_the_value_in_r1() {
+        // add r1, r0, 42; add r3, r0, 7; mode halt
+        let cpu = cpu_with_program(&[alu_imm(14, 1, 0, 42), alu_imm(14, 3, 0, 7), MODE_HALT]);
+        assert_eq!(cpu.run(0, false, AudioMode::Disabled), Some(42));
+    }
+
+    fn alu_reg(op: u32, r_a: u32, r_b: u32, r_c: u32) -> u32 {
+        (r_a << 22) | (r_b << 17) | (op << 5) | r_c
+    }
+
+    fn alu_imm(op: u32, r_a: u32, r_b: u32, imm: u32) -> u32 {
+        (1u32 << 27) | (r_a << 22) | (r_b << 17) | (op << 12) | (imm & 0xFFF)
+    }
+
+    #[test]
+    fn subb_immediate_propagates_borrow_across_words() {
+        // {hi, lo} = {imm_hi, imm_lo} - {r3, r2}, using the imm - reg form.
+        let mut cpu = cpu_with_program(&[]);
+        cpu.regfile[2] = 7;
+        cpu.regfile[3] = 0;
+        cpu.alu_op(alu_imm(16, 4, 2, 5), true);
+        cpu.alu_op(alu_imm(17, 5, 3, 0), true);
+        assert_eq!((cpu.regfile[5], cpu.regfile[4]), (0xFFFF_FFFF, 0xFFFF_FFFE));
+
+        cpu.regfile[2] = 2;
+        cpu.regfile[3] = 1;
+        cpu.alu_op(alu_imm(16, 4, 2, 9), true);
+        cpu.alu_op(alu_imm(17, 5, 3, 3), true);
+        assert_eq!(
+            (cpu.regfile[5], cpu.regfile[4]),
+            (2, 7),
+            "no borrow out of the low word"
+        );
+    }
+
+    #[test]
+    fn clz_counts_leading_zero_bits() {
+        let mut cpu = cpu_with_program(&[]);
+        for (value, expected) in [(0, 32), (1, 31), (0x8000_0000, 0), (0xFFFF_FFFF, 0)] {
+            cpu.regfile[2] = value;
+            cpu.alu_op(alu_reg(26, 1, 2, 0), false);
+            assert_eq!(cpu.regfile[1], expected, "clz {:#x}", value);
+            assert_eq!(
+                cpu.cregfile[5] & 0x2 != 0,
+                expected == 0,
+                "zero flag tracks the result"
+            );
+        }
+    }
+
+    #[test]
+    fn bswap_and_hswap_reorder_bytes_and_halfwords() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.regfile[2] = 0x1122_3344;
+        cpu.alu_op(alu_reg(27, 1, 2, 0), false);
+        assert_eq!(cpu.regfile[1], 0x4433_2211, "bswap");
+        cpu.alu_op(alu_reg(28, 1, 2, 0), false);
+        assert_eq!(cpu.regfile[1], 0x3344_1122, "hswap");
+
+        cpu.regfile[2] = 0x0000_0080;
+        cpu.alu_op(alu_reg(27, 1, 2, 0), false);
+        assert_eq!(cpu.regfile[1], 0x8000_0000);
+        assert_eq!(cpu.cregfile[5] & 0x6, 0x4, "sign flag tracks the result");
+
+        let psr = cpu.cregfile[0];
+        cpu.alu_op(alu_imm(27, 1, 2, 5), true);
+        assert_eq!(cpu.cregfile[0], psr + 1, "bswap has no immediate form");
+    }
+
+    #[test]
+    fn clz_immediate_form_raises_instruction_exception() {
+        let mut cpu = cpu_with_program(&[]);
+        let psr = cpu.cregfile[0];
+        cpu.alu_op(alu_imm(26, 1, 2, 5), true);
+        assert_eq!(cpu.cregfile[0], psr + 1);
+    }
+
+    #[test]
+    fn divide_ops_compute_quotient_and_remainder() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.regfile[2] = 100;
+        cpu.regfile[3] = 7;
+        cpu.alu_op(alu_reg(22, 1, 2, 3), false);
+        assert_eq!(cpu.regfile[1], 14, "udiv");
+        cpu.alu_op(alu_reg(24, 1, 2, 3), false);
+        assert_eq!(cpu.regfile[1], 2, "umod");
+
+        cpu.regfile[2] = (-100i32) as u32;
+        cpu.alu_op(alu_reg(23, 1, 2, 3), false);
+        assert_eq!(cpu.regfile[1] as i32, -14, "sdiv truncates toward zero");
+        cpu.alu_op(alu_reg(25, 1, 2, 3), false);
+        assert_eq!(cpu.regfile[1] as i32, -2, "smod takes the dividend's sign");
+        assert_eq!(
+            cpu.cregfile[5] & 0xF,
+            4,
+            "negative result sets only the sign flag"
+        );
+
+        // Immediate form divides the immediate by the register, like sub.
+        cpu.alu_op(alu_imm(22, 1, 3, 100), true);
+        assert_eq!(cpu.regfile[1], 14, "udiv immediate computes imm / r_b");
+    }
+
+    #[test]
+    fn signed_divide_overflow_sets_overflow_flag() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.regfile[2] = 0x8000_0000;
+        cpu.regfile[3] = 0xFFFF_FFFF;
+        cpu.alu_op(alu_reg(23, 1, 2, 3), false);
+        assert_eq!(cpu.regfile[1], 0x8000_0000);
+        assert_eq!(cpu.cregfile[5] & 0xF, 0b1100, "overflow and sign flags");
+
+        cpu.alu_op(alu_reg(25, 1, 2, 3), false);
+        assert_eq!(cpu.regfile[1], 0);
+        assert_eq!(cpu.cregfile[5] & 0xF, 0b0010, "remainder has no overflow");
+    }
+
+    #[test]
+    fn divide_by_zero_raises_instruction_exception() {
+        let mut cpu = cpu_with_program(&[]);
+        let psr = cpu.cregfile[0];
+        cpu.regfile[1] = 0x55;
+        cpu.regfile[2] = 9;
+        cpu.cregfile[5] = 0b0101;
+        cpu.alu_op(alu_reg(22, 1, 2, 3), false);
+        assert_eq!(cpu.regfile[1], 0x55, "destination must be untouched");
+        assert_eq!(cpu.cregfile[CREG_EFG], 0b0101, "flags are saved unchanged");
+        assert_eq!(cpu.cregfile[0], psr + 1, "exc_instr must be raised");
+    }
+
+    #[test]
+    fn run_report_records_mode_halt() {
+        let cpu = cpu_with_program(&[MODE_HALT]);
+        let report = cpu.run_report(0, false, AudioMode::Disabled);
+        assert_eq!(report.reason, HaltReason::Halt);
+        assert_eq!(report.result(), Some(0));
+    }
+
+    // Write sink that appends into a buffer the test can still inspect.
+    struct SharedSink(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn uart_tx_writes_to_configured_sink() {
+        const SB_R4_R3: u32 = (9 << 27) | (4 << 22) | (3 << 17); // sb r4, [r3]
+        let mut program = vec![
+            (2 << 27) | (3 << 22) | (0x7FE5800 >> 10), // lui r3, UART_TX & !0x3FF
+            alu_imm(2, 3, 3, 2),                       // or r3, r3, 2
+        ];
+        for byte in b"Hi!\n" {
+            program.push(alu_imm(2, 4, 0, u32::from(*byte)));
+            program.push(SB_R4_R3);
+        }
+        program.push(MODE_HALT);
+        let cpu = cpu_with_program(&program);
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        cpu.shared_memory()
+            .set_uart_tx_sink(Box::new(SharedSink(Arc::clone(&captured))));
+
+        let report = cpu.run_report(0, false, AudioMode::Disabled);
+        assert_eq!(report.reason, HaltReason::Halt);
+        assert_eq!(captured.lock().unwrap().as_slice(), b"Hi!\n");
+    }
+
+    #[test]
+    fn buffered_uart_sink_is_flushed_when_the_run_ends() {
+        // Write "bye\n", then halt or spin until the cycle cap.
+        let endings = [
+            (MODE_HALT, 0, HaltReason::Halt),
+            ((12 << 27) | 0x3F_FFFF, 1_000, HaltReason::MaxCycles), // br .
+        ];
+        for (last, max_iters, reason) in endings {
+            let mut program = vec![
+                (2 << 27) | (3 << 22) | (0x7FE5800 >> 10), // lui r3, UART_TX & !0x3FF
+                alu_imm(2, 3, 3, 2),                       // or r3, r3, 2
+            ];
+            for byte in b"bye\n" {
+                program.push(alu_imm(2, 4, 0, u32::from(*byte)));
+                program.push((9 << 27) | (4 << 22) | (3 << 17)); // sb r4, [r3]
+            }
+            program.push(last);
+            let cpu = cpu_with_program(&program);
+            let captured = Arc::new(Mutex::new(Vec::new()));
+            let sink = io::BufWriter::new(SharedSink(Arc::clone(&captured)));
+            // Holding the memory keeps the sink from being flushed on drop,
+            // as when main leaves through process::exit.
+            let memory = cpu.shared_memory();
+            memory.set_uart_tx_sink(Box::new(sink));
+
+            let report = cpu.run_report(max_iters, false, AudioMode::Disabled);
+            assert_eq!(report.reason, reason);
+            assert_eq!(captured.lock().unwrap().as_slice(), b"bye\n");
+        }
+    }
+
+    #[test]
+    fn scripted_uart_input_echoes_to_tx() {
+        let program = [
+            (2 << 27) | (3 << 22) | (0x7FE5800 >> 10), // lui r3, UART_TX & !0x3FF
+            alu_imm(2, 3, 3, 2),                       // or r3, r3, 2
+            (9 << 27) | (4 << 22) | (3 << 17) | (1 << 16) | 1, // lb r4, [r3 + 1]
+            alu_imm(0, 4, 4, 0xFF),                    // and r4, r4, 0xFF
+            (12 << 27) | (1 << 22) | 2,                // bz done
+            (9 << 27) | (4 << 22) | (3 << 17),         // sb r4, [r3]
+            (12 << 27) | 0x3F_FFFB,                    // br -5
+            MODE_HALT,
+        ];
+        let cpu = Emulator::from_instructions(
+            program_ram(&program),
+            true,
+            1,
+            None,
+            None,
+            DEFAULT_TLB_ENTRIES,
+        );
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        cpu.shared_memory()
+            .set_uart_tx_sink(Box::new(SharedSink(Arc::clone(&captured))));
+        cpu.queue_uart_input(b"echo\n");
+
+        let report = cpu.run_report(10_000, false, AudioMode::Disabled);
+        assert_eq!(report.reason, HaltReason::Halt);
+        assert_eq!(captured.lock().unwrap().as_slice(), b"echo\n");
+    }
+
+    // or r3, r0, 0x81; lh r4, [r3]; halt
+    const ODD_HALFWORD_LOAD: [u32; 3] = [
+        (1 << 27) | (3 << 22) | (2 << 12) | 0x81,
+        (6 << 27) | (4 << 22) | (3 << 17) | (1 << 16),
+        MODE_HALT,
+    ];
+
+    #[test]
+    fn unaligned_halfword_load_masks_by_default() {
+        let mut cpu = cpu_with_program(&ODD_HALFWORD_LOAD);
+        cpu.memory.write_u16(0x80, 0xBEEF);
+        cpu.tick();
+        cpu.tick();
+        assert_eq!(cpu.regfile[4], 0xBEEF, "address must be masked to 0x80");
+        assert_eq!(cpu.pc, RESET_PC + 8);
+    }
+
+    #[test]
+    fn unaligned_halfword_load_traps_with_strict_alignment() {
+        const HANDLER: u32 = 0x1000;
+        let mut cpu = cpu_with_program(&ODD_HALFWORD_LOAD);
+        cpu.set_strict_alignment(true);
+        cpu.memory.write_u16(0x80, 0xBEEF);
+        cpu.memory
+            .write_u32(EXC_MISALIGNED_DATA_VECTOR * 4, HANDLER);
+        let psr = cpu.cregfile[0];
+        cpu.tick();
+        cpu.tick();
+        assert_eq!(cpu.pc, HANDLER);
+        assert_eq!(cpu.cregfile[CREG_EPC], RESET_PC + 4);
+        assert_eq!(cpu.cregfile[0], psr + 1);
+        assert_eq!(cpu.regfile[4], 0, "faulting load must not write r4");
+    }
+
+    #[test]
+    fn instr_trace_logs_pc_range_with_changed_registers() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let cpu = Emulator::from_instructions_with_config(
+            program_ram(&[
+                alu_imm(2, 4, 0, 10), // or r4, r0, 10
+                alu_imm(14, 4, 4, 5), // add r4, r4, 5
+                MODE_HALT,
+            ]),
+            false,
+            1,
+            None,
+            None,
+            &EmulatorConfig {
+                instr_trace: Some(Arc::new(InstrTrace::new(
+                    Box::new(SharedSink(Arc::clone(&captured))),
+                    Some((RESET_PC + 4, RESET_PC + 8)),
+                ))),
+                ..EmulatorConfig::default()
+            },
+        );
+        cpu.run_report(0, false, AudioMode::Disabled);
+
+        let trace = String::from_utf8(captured.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 1, "only the in-range instruction is traced");
+        assert!(lines[0].starts_with("[core 0] 00000404: "));
+        assert!(lines[0].contains("add"));
+        assert!(lines[0].ends_with("r4=0000000F"));
+    }
+
+    #[test]
+    fn run_report_records_max_cycles() {
+        let cpu = cpu_with_program(&[BR_SELF]);
+        let report = cpu.run_report(100, false, AudioMode::Disabled);
+        assert_eq!(report.reason, HaltReason::MaxCycles);
+        assert_eq!(
+            report.result(),
+            None,
+            "a timed-out run must not produce a result"
+        );
+    }
+
+    #[test]
+    fn emulator_panic_is_reported_as_a_halt_reason() {
+        // lui r2, 0x1FF96 (0x7FE5800); lbu r1, [r2 + 12]: no device there.
+        let cpu = cpu_with_program(&[0x1081_FF96, 0x4845_000C, MODE_HALT]);
+        let report = cpu.run_report(100, false, AudioMode::Disabled);
+        assert_eq!(
+            report.reason,
+            HaltReason::Panic("read from unmapped IO address 0x07FE580C".to_string())
+        );
+        assert_eq!(report.result(), None);
+    }
+
+    #[test]
+    fn exception_nesting_overflow_halts_with_double_fault() {
+        // Opcode 30 is unassigned, so executing it raises exc_instr.
+        let mut cpu = cpu_with_program(&[30u32 << 27]);
+        cpu.cregfile[0] = u32::MAX;
+        let report = cpu.run_report(100, false, AudioMode::Disabled);
+        assert_eq!(report.reason, HaltReason::DoubleFault);
+        assert_eq!(report.result(), None);
+    }
+
+    #[test]
+    fn divide_costs_its_configured_cycles() {
+        // udiv r1, r3, 6 (6 / r3); add r2, r0, 1; mode halt
+        let udiv = 0x0847_6006;
+        let add = 0x0880_E001;
+        assert_eq!(cycle_cost(udiv), DIVIDE_CYCLES);
+        assert_eq!(cycle_cost(add), 1);
+
+        let mut cpu = cpu_with_program(&[udiv, add, MODE_HALT]);
+        cpu.regfile[3] = 2;
+        cpu.step();
+        assert_eq!(cpu.regfile[1], 3);
+        assert_eq!(cpu.count, DIVIDE_CYCLES);
+        cpu.step();
+        assert_eq!(cpu.count, DIVIDE_CYCLES + 1);
+
+        // In a run the add issues only once the divide's cycles have passed.
+        let mut cpu = cpu_with_program(&[udiv, add, MODE_HALT]);
+        cpu.regfile[3] = 2;
+        for _ in 0..DIVIDE_CYCLES {
+            cpu.tick();
+        }
+        assert_eq!((cpu.retired, cpu.regfile[2]), (1, 0));
+        cpu.tick();
+        assert_eq!((cpu.retired, cpu.regfile[2]), (2, 1));
+    }
+
+    #[test]
+    fn current_instruction_disassembles_the_word_at_pc() {
+        // add r1, r0, 5; mode halt
+        let mut cpu = cpu_with_program(&[0x0840_E005, MODE_HALT]);
+        assert_eq!(
+            cpu.current_instruction(),
+            Some((RESET_PC, 0x0840_E005, "add r1, r0, 5".to_string()))
+        );
+        cpu.step();
+        assert_eq!(
+            cpu.current_instruction(),
+            Some((RESET_PC + 4, MODE_HALT, "mode halt".to_string()))
+        );
+
+        // Nothing is mapped above PHYSMEM_MAX, so this fetch would miss.
+        cpu.pc = 0x8000_0000;
+        let psr = cpu.cregfile[0];
+        assert_eq!(cpu.current_instruction(), None);
+        assert_eq!(cpu.pc, 0x8000_0000);
+        assert_eq!(cpu.cregfile[0], psr);
+        assert!(cpu.last_exception().is_none());
+        assert_eq!(cpu.tlb.stats(), TlbStats::default());
+    }
+
+    #[test]
+    fn repeated_tlb_misses_stop_at_the_nesting_limit() {
+        // The TLB miss vector points above PHYSMEM_MAX with nothing mapped, so
+        // every handler fetch misses again and nests one level deeper.
+        let mut cpu = cpu_with_program(&[]);
+        cpu.memory.write_u32(EXC_TLB_MISS_VECTOR * 4, 0x8000_0000);
+        cpu.pc = 0x8000_0000;
+        cpu.set_max_exception_depth(8);
+        for _ in 0..1000 {
+            if cpu.halted {
+                break;
+            }
+            cpu.step();
+        }
+        assert_eq!(cpu.halt_reason, Some(HaltReason::DoubleFault));
+        assert_eq!(cpu.cregfile[0], 8);
+    }
+
+    // Fill an 8-entry TLB with private mappings for vpn 0..8 (pid 1).
+    fn full_tlb(policy: TlbEvictionPolicy) -> RandomCache {
+        let mut tlb = RandomCache::new(8, policy);
+        for vpn in 0..8 {
+            tlb.write(1, vpn, ((vpn + 1) << 12) | TLB_FLAG_READ);
+        }
+        tlb
+    }
+
+    fn resident_vpns(tlb: &RandomCache) -> Vec<u32> {
+        (0..16).filter(|vpn| tlb.read(1, *vpn).is_some()).collect()
+    }
+
+    #[test]
+    fn fifo_tlb_evicts_oldest_insert() {
+        let mut tlb = full_tlb(TlbEvictionPolicy::Fifo);
+        // Translations do not refresh entries under FIFO.
+        assert!(matches!(tlb.access(1, 0, 0, true), TlbAccess::Hit(_)));
+        tlb.write(1, 8, (9 << 12) | TLB_FLAG_READ);
+        assert_eq!(resident_vpns(&tlb), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // Rewriting a resident mapping keeps its place in the queue.
+        tlb.write(1, 1, (2 << 12) | TLB_FLAG_READ);
+        tlb.write(1, 9, (10 << 12) | TLB_FLAG_READ);
+        assert_eq!(resident_vpns(&tlb), vec![2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn lru_tlb_evicts_least_recently_translated() {
+        let mut tlb = full_tlb(TlbEvictionPolicy::Lru);
+        assert!(matches!(tlb.access(1, 0, 0, true), TlbAccess::Hit(_)));
+        assert!(matches!(tlb.access(1, 1, 0, true), TlbAccess::Hit(_)));
+        tlb.write(1, 8, (9 << 12) | TLB_FLAG_READ);
+        assert_eq!(resident_vpns(&tlb), vec![0, 1, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn tlb_probe_leaves_lru_order_alone() {
+        let mut tlb = full_tlb(TlbEvictionPolicy::Lru);
+        // A debugger read of the oldest page must not save it from eviction.
+        assert!(matches!(tlb.probe(1, 0, 0, true), TlbAccess::Hit(_)));
+        assert!(matches!(tlb.probe(1, 20, 0, true), TlbAccess::Fault(_)));
+        tlb.write(1, 8, (9 << 12) | TLB_FLAG_READ);
+        assert_eq!(resident_vpns(&tlb), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn tlb_stats_count_hits_misses_and_evictions() {
+        let mut tlb = full_tlb(TlbEvictionPolicy::Fifo);
+        assert!(matches!(tlb.access(1, 0, 0, true), TlbAccess::Hit(_)));
+        assert!(matches!(tlb.access(1, 1, 0, true), TlbAccess::Hit(_)));
+        // Unmapped pages miss, and so does a store to a read-only page.
+        assert!(matches!(tlb.access(1, 20, 0, true), TlbAccess::Fault(_)));
+        assert!(matches!(tlb.access(1, 0, 1, true), TlbAccess::Fault(_)));
+        assert!(matches!(tlb.access(1, 20, 2, true), TlbAccess::Fault(_)));
+        assert!(matches!(tlb.access(1, 20, 2, true), TlbAccess::Fault(_)));
+        // Debugger probes are not guest accesses and are not counted.
+        assert!(matches!(tlb.probe(1, 0, 0, true), TlbAccess::Hit(_)));
+        assert!(matches!(tlb.probe(1, 20, 0, true), TlbAccess::Fault(_)));
+        // The TLB is full, so only the first new mapping evicts.
+        tlb.write(1, 8, (9 << 12) | TLB_FLAG_READ);
+        tlb.invalidate(1, 8);
+        tlb.write(1, 9, (10 << 12) | TLB_FLAG_READ);
+
+        let expected = TlbStats {
+            hits: 2,
+            misses: [1, 1, 2],
+            writes: 10,
+            evictions: 1,
+        };
+        assert_eq!(tlb.stats(), expected);
+        assert_eq!(tlb.stats().total_misses(), 4);
+        assert!(
+            tlb.stats()
+                .to_string()
+                .contains("TLB misses: 4 (read 1, write 1, fetch 2)")
+        );
+
+        // Restoring a snapshot's entries is not guest activity.
+        let entries = tlb.entries();
+        tlb.restore_entries(&entries);
+        assert_eq!(tlb.stats(), expected);
+        tlb.reset_stats();
+        assert_eq!(tlb.stats(), TlbStats::default());
+    }
+
+    #[test]
+    fn tlb_eviction_prefers_same_class_and_tracks_invalidate() {
+        let mut tlb = RandomCache::new(2, TlbEvictionPolicy::Fifo);
+        tlb.write(1, 0x10, (1 << 12) | TLB_FLAG_GLOBAL);
+        tlb.write(1, 0x20, 2 << 12);
+        // A new private entry evicts the private one even though the global
+        // entry is older.
+        tlb.write(1, 0x30, 3 << 12);
+        assert_eq!(tlb.read(1, 0x20), None);
+        assert!(tlb.read(9, 0x10).is_some());
+
+        tlb.invalidate(1, 0x10);
+        assert_eq!(tlb.total_size(), 1);
+        tlb.write(1, 0x40, 4 << 12);
+        assert_eq!(tlb.total_size(), 2, "invalidate must free a slot");
+        assert!(tlb.read(1, 0x30).is_some());
+    }
+
+    #[test]
+    fn tlb_dump_separates_global_and_private_entries() {
+        let mut tlb = RandomCache::new(4, TlbEvictionPolicy::Fifo);
+        // The same page mapped globally and privately for pid 3.
+        tlb.write(
+            1,
+            0x10,
+            (0x22 << 12) | TLB_FLAG_GLOBAL | TLB_FLAG_EXEC | TLB_FLAG_READ,
+        );
+        tlb.write(
+            3,
+            0x10,
+            (0x45 << 12) | TLB_FLAG_USER | TLB_FLAG_WRITE | TLB_FLAG_READ,
+        );
+
+        assert_eq!(
+            tlb.debug_dump(),
+            "TLB private: 1 entries\n\
+             \x20 private pid 00000003 vpn 00000010 -> 0004500B (ppn 00045 RW-U-)\n\
+             TLB global: 1 entries\n\
+             \x20 global  pid *        vpn 00000010 -> 00022015 (ppn 00022 R-X-G)\n\
+             TLB total: 2/4 entries"
+        );
+
+        tlb.clear();
+        assert_eq!(
+            tlb.debug_dump(),
+            "TLB private: 0 entries\n  (empty)\nTLB global: 0 entries\n  (empty)\nTLB total: 0/4 entries"
+        );
+    }
+
+    #[test]
+    fn tlb_invalidate_of_missing_mapping_keeps_sizes_zero() {
+        let mut tlb = RandomCache::new(8, TlbEvictionPolicy::Fifo);
+        tlb.invalidate(1, 0x10);
+        tlb.invalidate(1, 0x10);
+        assert_eq!(tlb.total_size(), 0);
+        assert!(tlb.order.is_empty());
+
+        // A global entry shadowing a private one is removed together with it,
+        // and a second invalidate is a no-op.
+        tlb.write(1, 0x10, 1 << 12);
+        tlb.write(1, 0x10, (2 << 12) | TLB_FLAG_GLOBAL);
+        assert_eq!(tlb.total_size(), 2);
+        tlb.invalidate(1, 0x10);
+        tlb.invalidate(1, 0x10);
+        assert_eq!(tlb.total_size(), 0);
+        assert!(tlb.order.is_empty());
+        tlb.write(1, 0x20, 3 << 12);
+        assert_eq!(tlb.total_size(), 1);
+    }
+
+    #[test]
+    fn constructor_tlb_size_controls_eviction() {
+        let mut small = Emulator::from_instructions(HashMap::new(), false, 1, None, None, 2);
+        let mut default = cpu_with_program(&[]);
+        for cpu in [&mut small, &mut default] {
+            for vpn in 0..3 {
+                cpu.tlb.write(1, vpn, ((vpn + 1) << 12) | TLB_FLAG_READ);
+            }
+        }
+        assert_eq!(small.tlb.total_size(), 2);
+        assert_eq!(small.tlb.read(1, 0), None, "2-entry TLB must evict vpn 0");
+        assert!(small.tlb.read(1, 2).is_some());
+        assert_eq!(default.tlb.total_size(), 3);
+        assert!(default.tlb.read(1, 0).is_some());
+    }
+
+    #[test]
+    fn warm_reset_preserves_memory_and_resets_cpu_state() {
+        let mut cpu = cpu_with_program(&[MODE_HALT]);
+        cpu.memory.write(0x2000, 0xAB);
+        cpu.regfile[5] = 0x1234;
+        cpu.cregfile[CREG_PID] = 7;
+        cpu.tlb.write(7, 0x10, 0x20);
+        cpu.pc = 0x800;
+        cpu.count = 99;
+        cpu.halt(HaltReason::Halt);
+
+        cpu.reset();
+
+        assert_eq!(
+            cpu.memory.read(0x2000),
+            0xAB,
+            "warm reset must keep RAM contents"
+        );
+        assert_eq!(cpu.regfile[5], 0);
+        assert_eq!(cpu.cregfile[CREG_PID], 0);
+        assert_eq!(cpu.tlb.read(7, 0x10), None, "warm reset must flush the TLB");
+        assert_eq!(cpu.pc, RESET_PC);
+        assert_eq!(cpu.count, 0);
+        assert!(!cpu.halted);
+        assert_eq!(cpu.halt_reason(), None);
+    }
+
+    #[test]
+    fn warm_reset_returns_to_the_program_entry() {
+        let mut cpu = EmulatorBuilder::new()
+            .load_bytes(
+                0x800,
+                &[alu_imm(14, 1, 1, 1), MODE_HALT]
+                    .map(u32::to_le_bytes)
+                    .concat(),
+            )
+            .entry(0x800)
+            .build();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x804);
+
+        cpu.reset();
+
+        assert_eq!(cpu.pc, 0x800, "reset must not fall back to RESET_PC");
+    }
 }
@@ -0,0 +1,186 @@
+// Builder for embedding the emulator as a library: assemble a machine from raw
+// byte images instead of a .hex file.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{Emulator, EmulatorConfig, Endianness, InstrTrace, RESET_PC, TlbEvictionPolicy};
+
+// Configuration collected by `EmulatorBuilder` and applied by `build`.
+// Unset options match `Emulator::new`: no UART RX routing, one tick per SD DMA
+// word, empty SD cards, EmulatorConfig::default() and execution from RESET_PC.
+#[derive(Clone, Debug)]
+pub struct EmulatorBuilder {
+    ram: HashMap<u32, u8>,
+    entry: u32,
+    regs: Vec<(usize, u32)>,
+    cregs: Vec<(usize, u32)>,
+    use_uart_rx: bool,
+    sd_dma_ticks_per_word: u32,
+    sd0_image: Option<Vec<u8>>,
+    sd1_image: Option<Vec<u8>>,
+    config: EmulatorConfig,
+}
+
+impl Default for EmulatorBuilder {
+    fn default() -> Self {
+        EmulatorBuilder::new()
+    }
+}
+
+impl EmulatorBuilder {
+    pub fn new() -> EmulatorBuilder {
+        EmulatorBuilder {
+            ram: HashMap::new(),
+            entry: RESET_PC,
+            regs: Vec::new(),
+            cregs: Vec::new(),
+            use_uart_rx: false,
+            sd_dma_ticks_per_word: 1,
+            sd0_image: None,
+            sd1_image: None,
+            config: EmulatorConfig::default(),
+        }
+    }
+
+    // Copy `bytes` into physical memory starting at `addr`. Later loads
+    // overwrite earlier ones where they overlap.
+    pub fn load_bytes(mut self, addr: u32, bytes: &[u8]) -> EmulatorBuilder {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.ram.insert(addr.wrapping_add(offset as u32), *byte);
+        }
+        self
+    }
+
+    // Initial PC; a later warm reset returns here too.
+    pub fn entry(mut self, pc: u32) -> EmulatorBuilder {
+        self.entry = pc;
+        self
+    }
+
+    // Initial value of general-purpose register `n` (1..=31; r0 is always 0).
+    pub fn reg(mut self, n: usize, value: u32) -> EmulatorBuilder {
+        assert!((1..32).contains(&n), "register r{} out of range", n);
+        self.regs.push((n, value));
+        self
+    }
+
+    // Initial value of control register `n` (0..=12), written directly so
+    // read-only registers such as ISR can be seeded too.
+    pub fn creg(mut self, n: usize, value: u32) -> EmulatorBuilder {
+        assert!(n < 13, "control register cr{} out of range", n);
+        self.cregs.push((n, value));
+        self
+    }
+
+    pub fn uart_rx(mut self, enabled: bool) -> EmulatorBuilder {
+        self.use_uart_rx = enabled;
+        self
+    }
+
+    pub fn sd_dma_ticks(mut self, ticks_per_word: u32) -> EmulatorBuilder {
+        assert!(ticks_per_word >= 1, "SD DMA ticks must be >= 1");
+        self.sd_dma_ticks_per_word = ticks_per_word;
+        self
+    }
+
+    pub fn sd0(mut self, image: &[u8]) -> EmulatorBuilder {
+        self.sd0_image = Some(image.to_vec());
+        self
+    }
+
+    pub fn sd1(mut self, image: &[u8]) -> EmulatorBuilder {
+        self.sd1_image = Some(image.to_vec());
+        self
+    }
+
+    pub fn tlb_entries(mut self, entries: usize) -> EmulatorBuilder {
+        assert!(entries >= 1, "TLB must hold at least one entry");
+        self.config.tlb_entries = entries;
+        self
+    }
+
+    pub fn tlb_policy(mut self, policy: TlbEvictionPolicy) -> EmulatorBuilder {
+        self.config.tlb_policy = policy;
+        self
+    }
+
+    pub fn strict_alignment(mut self, enabled: bool) -> EmulatorBuilder {
+        self.config.strict_alignment = enabled;
+        self
+    }
+
+    // Byte order of the core; `load_bytes` images are stored as given.
+    pub fn endianness(mut self, order: Endianness) -> EmulatorBuilder {
+        self.config.endianness = order;
+        self
+    }
+
+    pub fn max_exception_depth(mut self, depth: u32) -> EmulatorBuilder {
+        assert!(depth >= 1, "exception depth must be >= 1");
+        self.config.max_exception_depth = depth;
+        self
+    }
+
+    pub fn fetch_profiling(mut self, enabled: bool) -> EmulatorBuilder {
+        self.config.fetch_profiling = enabled;
+        self
+    }
+
+    pub fn key_buffer_cap(mut self, cap: usize) -> EmulatorBuilder {
+        assert!(cap >= 1, "key buffer must hold at least one entry");
+        self.config.key_buffer_cap = cap;
+        self
+    }
+
+    // Bytes of RAM; panics at `build` unless `check_physmem_size` accepts it.
+    pub fn physmem_size(mut self, size: u32) -> EmulatorBuilder {
+        self.config.physmem_size = size;
+        self
+    }
+
+    // Log every instruction in the trace's PC range as it executes.
+    pub fn instr_trace(mut self, trace: InstrTrace) -> EmulatorBuilder {
+        self.config.instr_trace = Some(Arc::new(trace));
+        self
+    }
+
+    pub fn build(self) -> Emulator {
+        let mut cpu = Emulator::from_instructions_with_config(
+            self.ram,
+            self.use_uart_rx,
+            self.sd_dma_ticks_per_word,
+            self.sd0_image.as_deref(),
+            self.sd1_image.as_deref(),
+            &self.config,
+        );
+        cpu.set_entry(self.entry);
+        for (n, value) in self.regs {
+            cpu.regfile[n] = value;
+        }
+        for (n, value) in self.cregs {
+            cpu.cregfile[n] = value;
+        }
+        cpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::AudioMode;
+
+    #[test]
+    fn builds_and_runs_a_program_loaded_from_bytes() {
+        // add r1, r1, r2; add r1, r1, 3; mode halt
+        let program = [0x0042_01C2u32, 0x0842_E003, 0xF800_2800];
+        let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let cpu = EmulatorBuilder::new()
+            .load_bytes(0x2000, &bytes)
+            .entry(0x2000)
+            .reg(1, 10)
+            .reg(2, 20)
+            .build();
+        assert_eq!(cpu.run(0, false, AudioMode::Disabled), Some(33));
+    }
+}
@@ -1,20 +1,36 @@
 // Debugger written by Codex
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, VecDeque};
 use std::env;
-use std::fs::File;
-use std::io::{self, BufRead, Write};
+use std::fs::{self, File};
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 
-use crate::disassembler::disassemble;
-use crate::memory::PHYSMEM_MAX;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+use crate::disassembler::disassemble_at;
+use crate::memory::Memory;
 
 use super::{
-    DebugInfo, DebugLine, DebugLocal, Emulator, LabelMap, WatchAccess, WatchKind, Watchpoint,
-    WatchpointHit, load_program,
+    CREG_CAUSE, CREG_CNT, CREG_CNTH, CREG_IMR, CregWatchHit, DebugInfo, DebugLine, DebugLocal,
+    Emulator, EmulatorConfig, EmulatorSnapshot, Endianness, ExceptionCause, ExceptionEntry,
+    INTERRUPT_VECTORS, LabelMap, RESULT_REG, StepResult, WatchAccess, WatchKind, Watchpoint,
+    WatchpointHit, describe_tlb_entry, format_interrupts, load_program,
 };
 
-fn parse_addr(token: &str) -> Option<u32> {
+// Frame-walk parameters for `bt`. The emulator does not pin down the ABI, so
+// these describe the frame layout the backtrace assumes: the frame pointer
+// holds the address of the caller's saved frame pointer and the return address
+// is saved one word above it. Leaf functions keep theirs in the link register.
+const BT_FP_REG: u32 = BP_REG;
+const BT_LINK_REG: u32 = RA_REG;
+const BT_SAVED_FP_OFFSET: u32 = 0;
+const BT_SAVED_RA_OFFSET: u32 = 4;
+// Frames printed by `bt` when no depth is given.
+const BT_DEFAULT_DEPTH: usize = 32;
+
+pub(super) fn parse_addr(token: &str) -> Option<u32> {
     let s = token.trim();
     if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
         return u32::from_str_radix(hex, 16).ok();
@@ -55,6 +71,98 @@ fn read_source_line(path: &Path, line: u32) -> Result<String, String> {
     Err(format!("File {} has no line {}", path.display(), line))
 }
 
+// History file for the debugger prompts, kept in $HOME across sessions.
+const HISTORY_FILE: &str = ".dioptase_debug_history";
+
+// Line source for the `dbg>` prompt. On a terminal it is a line editor with
+// arrow-key history and editing, saved to HISTORY_FILE on exit; otherwise
+// (pipes, redirected files, tests) it reads stdin directly so scripted
+// sessions see the same prompt and output as before.
+struct PromptReader {
+    editor: Option<DefaultEditor>,
+    history_path: Option<PathBuf>,
+}
+
+impl PromptReader {
+    fn new() -> PromptReader {
+        let editor = if io::stdin().is_terminal() {
+            DefaultEditor::new().ok()
+        } else {
+            None
+        };
+        let history_path = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let mut reader = PromptReader {
+            editor,
+            history_path,
+        };
+        if let (Some(editor), Some(path)) = (reader.editor.as_mut(), reader.history_path.as_ref()) {
+            // A missing history file is normal on first use.
+            let _ = editor.load_history(path);
+        }
+        reader
+    }
+
+    // Purpose: show `prompt` and read one command line.
+    // Inputs: prompt text.
+    // Outputs: the line (possibly empty), or None at end of input (Ctrl-D or
+    // closed stdin). Ctrl-C abandons the current line and returns "".
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        match self.editor.as_mut() {
+            Some(editor) => match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let _ = editor.add_history_entry(line.as_str());
+                    }
+                    Some(line)
+                }
+                Err(ReadlineError::Interrupted) => Some(String::new()),
+                Err(_) => None,
+            },
+            None => {
+                print!("{}", prompt);
+                io::stdout().flush().unwrap();
+                let mut line = String::new();
+                match io::stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => None,
+                    Ok(_) => Some(line),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for PromptReader {
+    fn drop(&mut self) {
+        if let (Some(editor), Some(path)) = (self.editor.as_mut(), self.history_path.as_ref())
+            && let Err(err) = editor.save_history(path)
+        {
+            println!(
+                "Warning: failed to save history to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+// Purpose: load a debugger command script for `--debug-script` or `source`.
+// Inputs: path to a text file with one debugger command per line.
+// Outputs: the commands in order, trimmed, with blank lines and `#` comments
+// dropped; or an error message naming the file.
+pub fn read_debug_script(path: &str) -> Result<Vec<String>, String> {
+    let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    let mut commands = Vec::new();
+    for line_result in io::BufReader::new(file).lines() {
+        let text = line_result.map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        commands.push(text.to_string());
+    }
+    Ok(commands)
+}
+
 fn build_labels_by_addr(labels: &LabelMap) -> HashMap<u32, Vec<String>> {
     let mut by_addr: HashMap<u32, Vec<String>> = HashMap::new();
     for (name, addrs) in labels {
@@ -65,34 +173,347 @@ fn build_labels_by_addr(labels: &LabelMap) -> HashMap<u32, Vec<String>> {
     by_addr
 }
 
-enum StepOutcome {
-    Executed { pc: u32, instr: u32 },
-    Sleeping,
-    TlbMiss { pc: u32 },
-}
-
 enum RunOutcome {
     Breakpoint(u32),
     Halted,
     Watchpoint(WatchpointHit),
+    CregWatch(CregWatchHit),
+    Catch(ExceptionEntry),
+    // Asleep with every interrupt masked, so continuing would never return.
+    SleepForever,
+}
+
+// Expression registered with `display`, printed again after every stop.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum DisplayExpr {
+    Reg(String),
+    Virt(u32),
+    Phys(u32),
+}
+
+fn describe_display(expr: &DisplayExpr) -> String {
+    match expr {
+        DisplayExpr::Reg(name) => name.clone(),
+        DisplayExpr::Virt(addr) => format!("v {:08X}", addr),
+        DisplayExpr::Phys(addr) => format!("p {:08X}", addr),
+    }
+}
+
+// Print each display as "id: value", in the order they were added.
+fn print_displays(cpu: &mut Emulator, displays: &[(u32, DisplayExpr)]) {
+    for (id, expr) in displays {
+        print!("{}: ", id);
+        match expr {
+            DisplayExpr::Reg(name) => {
+                println!("{}", cpu.format_single_reg(name).unwrap_or_default())
+            }
+            DisplayExpr::Virt(addr) => cpu.print_virt(*addr),
+            DisplayExpr::Phys(addr) => cpu.print_phys(*addr),
+        }
+    }
+}
+
+fn list_displays(displays: &[(u32, DisplayExpr)]) {
+    if displays.is_empty() {
+        println!("No displays set.");
+        return;
+    }
+    for (id, expr) in displays {
+        println!("{}: {}", id, describe_display(expr));
+    }
+}
+
+// Breakpoint address -> optional condition; unconditional breakpoints map to None.
+type Breakpoints = HashMap<u32, Option<BreakCondition>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CondOperand {
+    Pc,
+    Reg(u32),
+    Creg(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CondOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+}
+
+impl CondOp {
+    fn parse(token: &str) -> Result<CondOp, String> {
+        match token {
+            "==" => Ok(CondOp::Eq),
+            "!=" => Ok(CondOp::Ne),
+            "<" => Ok(CondOp::Lt),
+            ">" => Ok(CondOp::Gt),
+            _ => Err(format!("Unknown comparison {} (use ==, !=, < or >)", token)),
+        }
+    }
+
+    fn compare(self, current: u32, value: u32) -> bool {
+        match self {
+            CondOp::Eq => current == value,
+            CondOp::Ne => current != value,
+            CondOp::Lt => current < value,
+            CondOp::Gt => current > value,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            CondOp::Eq => "==",
+            CondOp::Ne => "!=",
+            CondOp::Lt => "<",
+            CondOp::Gt => ">",
+        }
+    }
+}
+
+// Condition attached by `break <addr> if <reg> <op> <value>`.
+// Comparisons are unsigned 32-bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BreakCondition {
+    operand: CondOperand,
+    op: CondOp,
+    value: u32,
+}
+
+// Purpose: resolve a condition operand (`pc`, `rN`, `sp`/`bp`/`ra`, `crN` or a creg name).
+fn parse_cond_operand(token: &str) -> Option<CondOperand> {
+    let lower = token.to_ascii_lowercase();
+    match lower.as_str() {
+        "pc" => return Some(CondOperand::Pc),
+        "sp" => return Some(CondOperand::Reg(SP_REG)),
+        "bp" => return Some(CondOperand::Reg(BP_REG)),
+        "ra" => return Some(CondOperand::Reg(RA_REG)),
+        _ => {}
+    }
+    if let Some(Ok(idx)) = lower.strip_prefix('r').map(str::parse::<u32>) {
+        return (idx < 32).then_some(CondOperand::Reg(idx));
+    }
+    parse_creg(&lower).map(CondOperand::Creg)
+}
+
+// Purpose: parse the tokens following `if`, e.g. `r3 == 0x10`.
+// Outputs: the condition, or a message describing what was wrong.
+fn parse_break_condition(tokens: &[&str]) -> Result<BreakCondition, String> {
+    let [operand, op, value] = tokens else {
+        return Err("Condition must look like <reg> <==|!=|<|>> <value>".to_string());
+    };
+    let operand =
+        parse_cond_operand(operand).ok_or_else(|| format!("Unknown register {}", operand))?;
+    let op = CondOp::parse(op)?;
+    let value = parse_addr(value).ok_or_else(|| format!("Invalid value {}", value))?;
+    Ok(BreakCondition { operand, op, value })
+}
+
+// Value filter attached by `watch [r|w|rw] <addr> [len] <op> <value>`. It is compared
+// (unsigned) against the whole byte, halfword or word access that touched the
+// watched byte, so `watch w 0x1000 == 0xDEAD` matches a halfword store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct WatchCondition {
+    op: CondOp,
+    value: u32,
+}
+
+impl WatchCondition {
+    pub(super) fn holds(&self, current: u32) -> bool {
+        self.op.compare(current, self.value)
+    }
+}
+
+impl std::fmt::Display for WatchCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "value {} 0x{:08X}", self.op.symbol(), self.value)
+    }
+}
+
+// Purpose: parse the optional `<op> <value>` after a watch address.
+// Outputs: None when no tokens follow, or a message describing what was wrong.
+fn parse_watch_condition(tokens: &[&str]) -> Result<Option<WatchCondition>, String> {
+    match tokens {
+        [] => Ok(None),
+        [op, value] => {
+            let op = CondOp::parse(op)?;
+            let value = parse_addr(value).ok_or_else(|| format!("Invalid value {}", value))?;
+            Ok(Some(WatchCondition { op, value }))
+        }
+        _ => Err("Condition must look like <==|!=|<|>> <value>".to_string()),
+    }
+}
+
+impl BreakCondition {
+    fn holds(&self, cpu: &Emulator) -> bool {
+        let current = match self.operand {
+            CondOperand::Pc => cpu.pc,
+            CondOperand::Reg(idx) => cpu.get_reg(idx),
+            CondOperand::Creg(idx) => cpu.read_creg(idx),
+        };
+        self.op.compare(current, self.value)
+    }
+}
+
+impl std::fmt::Display for BreakCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.operand {
+            CondOperand::Pc => write!(f, "pc")?,
+            CondOperand::Reg(idx) => write!(f, "r{}", idx)?,
+            CondOperand::Creg(idx) => write!(f, "{}", CREG_NAMES[idx])?,
+        }
+        write!(f, " {} 0x{:08X}", self.op.symbol(), self.value)
+    }
+}
+
+fn breakpoint_triggers(breakpoints: &Breakpoints, cpu: &Emulator) -> bool {
+    match breakpoints.get(&cpu.pc) {
+        Some(Some(cond)) => cond.holds(cpu),
+        Some(None) => true,
+        None => false,
+    }
+}
+
+fn run_until_breakpoint(cpu: &mut Emulator, breakpoints: &Breakpoints) -> RunOutcome {
+    loop {
+        if cpu.halted {
+            return RunOutcome::Halted;
+        }
+        if breakpoint_triggers(breakpoints, cpu) {
+            return RunOutcome::Breakpoint(cpu.pc);
+        }
+        if let Some(outcome) = step_for_run(cpu) {
+            return outcome;
+        }
+    }
+}
+
+// Execute one instruction on behalf of a run command.
+// Outputs: the watchpoint/creg-watch/catch stop it triggered, if any, or
+// SleepForever once the core sleeps with no way to wake.
+fn step_for_run(cpu: &mut Emulator) -> Option<RunOutcome> {
+    cpu.step();
+    if let Some(hit) = cpu.take_watchpoint_hit() {
+        return Some(RunOutcome::Watchpoint(hit));
+    }
+    if let Some(hit) = cpu.creg_watch_hit.take() {
+        return Some(RunOutcome::CregWatch(hit));
+    }
+    if let Some(entry) = cpu.catch_hit.take() {
+        return Some(RunOutcome::Catch(entry));
+    }
+    if cpu.asleep && !cpu.can_wake() {
+        return Some(RunOutcome::SleepForever);
+    }
+    None
+}
+
+// Register-target branches (absolute and relative) write pc+4 to r_a when
+// taken; with r_a != r0 that makes them calls.
+fn is_linking_branch(instr: u32) -> bool {
+    let opcode = instr >> 27;
+    let r_a = (instr >> 5) & 0x1F;
+    (opcode == 13 || opcode == 14) && r_a != 0
 }
 
-fn run_until_breakpoint(cpu: &mut Emulator, breakpoints: &HashSet<u32>) -> RunOutcome {
+// Purpose: run until control comes back to `ret_addr` in the frame that made
+// the call, for step-over and step-out.
+// Inputs: `frame_sp` is the stack pointer of that frame; the temporary stop only
+// fires once sp is back at or above it, so recursive activations returning to
+// the same address do not end the run early.
+// Outputs: Breakpoint(ret_addr) on return; user breakpoints, watchpoints and
+// halts stop the run as they do for `c`.
+fn run_to_return(
+    cpu: &mut Emulator,
+    breakpoints: &Breakpoints,
+    ret_addr: u32,
+    frame_sp: u32,
+) -> RunOutcome {
+    // Always execute the current instruction so a breakpoint on it does not
+    // stop the run before it starts.
+    if let Some(outcome) = step_for_run(cpu) {
+        return outcome;
+    }
     loop {
         if cpu.halted {
             return RunOutcome::Halted;
         }
-        if breakpoints.contains(&cpu.pc) {
+        if cpu.pc == ret_addr && cpu.get_reg(SP_REG) >= frame_sp {
             return RunOutcome::Breakpoint(cpu.pc);
         }
-        match cpu.step_instruction() {
-            StepOutcome::Executed { .. } => {}
-            StepOutcome::Sleeping => {}
-            StepOutcome::TlbMiss { .. } => {}
+        if breakpoint_triggers(breakpoints, cpu) {
+            return RunOutcome::Breakpoint(cpu.pc);
+        }
+        if let Some(outcome) = step_for_run(cpu) {
+            return outcome;
+        }
+    }
+}
+
+// Purpose: `until`: run until pc first reaches `target`.
+// Inputs: the target is added to `breakpoints` as a one-shot stop for this run
+// and the set is restored afterwards, including any breakpoint already there.
+// Outputs: Breakpoint(target) when reached; permanent breakpoints, watchpoints
+// and halts stop the run first as they do for `c`. The current instruction
+// always executes, so `until` on the current pc runs to its next arrival.
+fn run_until_target(cpu: &mut Emulator, breakpoints: &mut Breakpoints, target: u32) -> RunOutcome {
+    let previous = breakpoints.insert(target, None);
+    let outcome = match step_for_run(cpu) {
+        Some(outcome) => outcome,
+        None => run_until_breakpoint(cpu, breakpoints),
+    };
+    match previous {
+        Some(cond) => breakpoints.insert(target, cond),
+        None => breakpoints.remove(&target),
+    };
+    outcome
+}
+
+fn print_run_outcome(
+    outcome: RunOutcome,
+    labels_by_addr: &HashMap<u32, Vec<String>>,
+    cpu: &mut Emulator,
+) {
+    match outcome {
+        RunOutcome::Breakpoint(addr) => {
+            print_breakpoint(addr, labels_by_addr, cpu);
+        }
+        RunOutcome::Halted => {
+            print_halted(cpu);
         }
-        if let Some(hit) = cpu.take_watchpoint_hit() {
-            return RunOutcome::Watchpoint(hit);
+        RunOutcome::Watchpoint(hit) => {
+            print_watchpoint_hit(hit, cpu.pc);
         }
+        RunOutcome::CregWatch(hit) => {
+            print_creg_watch_hit(hit);
+        }
+        RunOutcome::Catch(entry) => {
+            print_catch_hit(entry);
+        }
+        RunOutcome::SleepForever => {
+            print_sleeping(cpu);
+        }
+    }
+}
+
+fn print_sleeping(cpu: &Emulator) {
+    if cpu.can_wake() {
+        println!("CPU sleeping; waiting for interrupt.");
+    } else {
+        println!(
+            "CPU sleeping at {:08X} with every interrupt masked in IMR; it cannot wake.",
+            cpu.pc
+        );
+    }
+}
+
+fn print_halted(cpu: &Emulator) {
+    match &cpu.halt_reason {
+        Some(reason) => println!(
+            "Program halted ({}). r1 = {:08X}",
+            reason, cpu.regfile[RESULT_REG]
+        ),
+        None => println!("Program halted. r1 = {:08X}", cpu.regfile[RESULT_REG]),
     }
 }
 
@@ -112,18 +533,26 @@ fn format_breakpoint(addr: u32, labels_by_addr: &HashMap<u32, Vec<String>>) -> S
     }
 }
 
-fn list_breakpoints(breakpoints: &HashSet<u32>, labels_by_addr: &HashMap<u32, Vec<String>>) {
+fn list_breakpoints(breakpoints: &Breakpoints, labels_by_addr: &HashMap<u32, Vec<String>>) {
     if breakpoints.is_empty() {
         println!("No breakpoints set.");
         return;
     }
-    let mut list: Vec<u32> = breakpoints.iter().copied().collect();
-    list.sort_unstable();
-    for addr in list {
-        println!("{}", format_breakpoint(addr, labels_by_addr));
+    let mut list: Vec<(u32, Option<BreakCondition>)> = breakpoints
+        .iter()
+        .map(|(addr, cond)| (*addr, *cond))
+        .collect();
+    list.sort_unstable_by_key(|(addr, _)| *addr);
+    for (addr, cond) in list {
+        match cond {
+            Some(cond) => println!("{} if {}", format_breakpoint(addr, labels_by_addr), cond),
+            None => println!("{}", format_breakpoint(addr, labels_by_addr)),
+        }
     }
 }
 
+const WATCH_USAGE: &str = "watch [r|w|rw] <addr> [len] [<==|!=|<|>> <value>]";
+
 fn watch_kind_label(kind: WatchKind) -> &'static str {
     match kind {
         WatchKind::Read => "r",
@@ -139,7 +568,7 @@ fn watch_access_label(access: WatchAccess) -> &'static str {
     }
 }
 
-fn parse_watch_kind(token: &str) -> Option<WatchKind> {
+pub(super) fn parse_watch_kind(token: &str) -> Option<WatchKind> {
     match token {
         "r" => Some(WatchKind::Read),
         "w" => Some(WatchKind::Write),
@@ -156,15 +585,31 @@ fn merge_watch_kind(existing: WatchKind, new_kind: WatchKind) -> WatchKind {
     }
 }
 
-fn add_watchpoint(list: &mut Vec<Watchpoint>, addr: u32, kind: WatchKind) -> WatchKind {
+// Watchpoints are keyed by start address: re-watching one merges the access
+// kinds and replaces its length and condition.
+pub(super) fn add_watchpoint(
+    list: &mut Vec<Watchpoint>,
+    addr: u32,
+    len: u32,
+    kind: WatchKind,
+    cond: Option<WatchCondition>,
+) -> Watchpoint {
     for wp in list.iter_mut() {
         if wp.addr == addr {
             wp.kind = merge_watch_kind(wp.kind, kind);
-            return wp.kind;
+            wp.len = len;
+            wp.cond = cond;
+            return *wp;
         }
     }
-    list.push(Watchpoint { addr, kind });
-    kind
+    let wp = Watchpoint {
+        addr,
+        len,
+        kind,
+        cond,
+    };
+    list.push(wp);
+    wp
 }
 
 fn remove_watchpoint(list: &mut Vec<Watchpoint>, addr: u32) -> bool {
@@ -173,6 +618,21 @@ fn remove_watchpoint(list: &mut Vec<Watchpoint>, addr: u32) -> bool {
     before != list.len()
 }
 
+// `addr (kind)` for one byte or `start..end (kind)` for a range (end exclusive),
+// followed by the value condition if any.
+fn describe_watchpoint(wp: &Watchpoint) -> String {
+    let mut text = if wp.len == 1 {
+        format!("{:08X}", wp.addr)
+    } else {
+        format!("{:08X}..{:08X}", wp.addr, wp.addr.wrapping_add(wp.len))
+    };
+    text.push_str(&format!(" ({})", watch_kind_label(wp.kind)));
+    if let Some(cond) = wp.cond {
+        text.push_str(&format!(" if {}", cond));
+    }
+    text
+}
+
 fn list_watchpoints(list: &[Watchpoint]) {
     if list.is_empty() {
         println!("No watchpoints set.");
@@ -181,26 +641,173 @@ fn list_watchpoints(list: &[Watchpoint]) {
     let mut sorted = list.to_vec();
     sorted.sort_by_key(|wp| wp.addr);
     for wp in sorted {
-        println!("{:08X} ({})", wp.addr, watch_kind_label(wp.kind));
+        println!("{}", describe_watchpoint(&wp));
     }
 }
 
-fn print_watchpoint_hit(hit: WatchpointHit, pc: u32) {
+// Name of the device region holding physical address `paddr`; None for RAM
+// and unmapped addresses.
+fn device_region_name(paddr: u32) -> Option<&'static str> {
+    Memory::device_region_at(paddr).map(|region| region.name)
+}
+
+pub(super) fn print_watchpoint_hit(hit: WatchpointHit, pc: u32) {
+    let region = device_region_name(hit.paddr)
+        .map(|name| format!(" [{}]", name))
+        .unwrap_or_default();
     println!(
-        "Watchpoint hit ({} at {:08X} = {:02X}) pc {:08X}",
+        "Watchpoint hit ({} at {:08X} = {:0width$X}) pc {:08X}{}",
         watch_access_label(hit.access),
         hit.addr,
         hit.value,
-        pc
+        pc,
+        region,
+        width = hit.size as usize * 2
+    );
+}
+
+pub(super) const CREG_NAMES: [&str; 13] = [
+    "psr", "pid", "isr", "imr", "epc", "flg", "efg", "tlb", "ksp", "cid", "mbi", "mbo", "tlbf",
+];
+
+// Accept `crN` or the conventional control-register name.
+fn parse_creg(token: &str) -> Option<usize> {
+    let token = token.to_ascii_lowercase();
+    if let Some(Ok(idx)) = token.strip_prefix("cr").map(str::parse::<usize>) {
+        return (idx < CREG_NAMES.len()).then_some(idx);
+    }
+    CREG_NAMES.iter().position(|name| *name == token)
+}
+
+// Purpose: accept `watch cr <n|name>` and `unwatch cr <n|name>`.
+// Inputs: a trimmed command line.
+// Outputs: the equivalent cwatch/uncwatch line, or None for any other command.
+fn creg_watch_alias(line: &str) -> Option<String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (cmd, reg) = match words[..] {
+        ["watch", "cr", reg] => ("cwatch", reg),
+        ["unwatch", "cr", reg] => ("uncwatch", reg),
+        _ => return None,
+    };
+    if reg.parse::<usize>().is_ok() {
+        Some(format!("{} cr{}", cmd, reg))
+    } else {
+        Some(format!("{} {}", cmd, reg))
+    }
+}
+
+fn format_creg(idx: usize) -> String {
+    format!("cr{} ({})", idx, CREG_NAMES[idx])
+}
+
+fn list_creg_watches(list: &[usize]) {
+    if list.is_empty() {
+        println!("No control-register watches set.");
+        return;
+    }
+    let mut sorted = list.to_vec();
+    sorted.sort_unstable();
+    for idx in sorted {
+        println!("{}", format_creg(idx));
+    }
+}
+
+fn print_creg_watch_hit(hit: CregWatchHit) {
+    println!(
+        "Control register write ({} {:08X} -> {:08X}) pc {:08X}",
+        format_creg(hit.creg),
+        hit.old,
+        hit.new,
+        hit.pc
+    );
+}
+
+const CATCH_USAGE: &str = "catch <tlb-miss|misaligned|illegal|privileged|syscall|timer|keyboard|uart|sd0|sd1|vga|audio|ipi>";
+
+// Exception class stopped on by `catch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum CatchKind {
+    TlbMiss,
+    Misaligned,
+    Illegal,
+    Privileged,
+    Syscall,
+    Interrupt(u32),
+}
+
+impl CatchKind {
+    // Interrupts are named as in `--trace-ints` output (timer, keyboard, ...).
+    fn parse(token: &str) -> Option<CatchKind> {
+        let kind = match token.to_ascii_lowercase().as_str() {
+            "tlb-miss" => CatchKind::TlbMiss,
+            "misaligned" => CatchKind::Misaligned,
+            "illegal" => CatchKind::Illegal,
+            "privileged" => CatchKind::Privileged,
+            "syscall" => CatchKind::Syscall,
+            name => {
+                let bit = (0..INTERRUPT_VECTORS).find(|bit| format_interrupts(1 << bit) == name)?;
+                CatchKind::Interrupt(bit)
+            }
+        };
+        Some(kind)
+    }
+
+    fn name(self) -> String {
+        match self {
+            CatchKind::TlbMiss => "tlb-miss".to_string(),
+            CatchKind::Misaligned => "misaligned".to_string(),
+            CatchKind::Illegal => "illegal".to_string(),
+            CatchKind::Privileged => "privileged".to_string(),
+            CatchKind::Syscall => "syscall".to_string(),
+            CatchKind::Interrupt(bit) => format_interrupts(1 << bit),
+        }
+    }
+
+    // Illegal covers both invalid encodings and unassigned opcodes; syscalls
+    // are taken through the trap vector.
+    pub(super) fn matches(self, cause: ExceptionCause) -> bool {
+        match (self, cause) {
+            (CatchKind::TlbMiss, ExceptionCause::TlbMiss { .. }) => true,
+            (
+                CatchKind::Misaligned,
+                ExceptionCause::MisalignedPc | ExceptionCause::MisalignedData { .. },
+            ) => true,
+            (
+                CatchKind::Illegal,
+                ExceptionCause::InvalidInstr | ExceptionCause::IllegalOpcode { .. },
+            ) => true,
+            (CatchKind::Privileged, ExceptionCause::Privileged) => true,
+            (CatchKind::Syscall, ExceptionCause::Trap) => true,
+            (CatchKind::Interrupt(want), ExceptionCause::Interrupt { bit }) => want == bit,
+            _ => false,
+        }
+    }
+}
+
+fn list_catches(list: &[CatchKind]) {
+    if list.is_empty() {
+        println!("No catches set.");
+        return;
+    }
+    for kind in list {
+        println!("{}", kind.name());
+    }
+}
+
+// The PC is already at the handler; EPC (cr4) holds the faulting PC.
+fn print_catch_hit(entry: ExceptionEntry) {
+    println!(
+        "Caught {} at {:08X}; handler at {:08X} (vector {:02X})",
+        entry.cause, entry.fault_pc, entry.new_pc, entry.vector
     );
 }
 
-fn delete_breakpoint(target: &str, breakpoints: &mut HashSet<u32>, labels: &LabelMap) {
+fn delete_breakpoint(target: &str, breakpoints: &mut Breakpoints, labels: &LabelMap) {
     match resolve_label_or_addr(target, labels) {
         Ok(addrs) => {
             if addrs.len() == 1 {
                 let addr = addrs[0];
-                if breakpoints.remove(&addr) {
+                if breakpoints.remove(&addr).is_some() {
                     println!("Breakpoint removed at {:08X}", addr);
                 } else {
                     println!("No breakpoint set at {:08X}", addr);
@@ -213,67 +820,772 @@ fn delete_breakpoint(target: &str, breakpoints: &mut HashSet<u32>, labels: &Labe
     }
 }
 
-fn dump_bytes<F>(base: u32, len: u32, mut read_byte: F)
+// Clamp a dump length so it neither exceeds MAX_DUMP_BYTES nor runs past the
+// top of the 32-bit address space.
+fn clamp_dump_len(base: u32, len: u32) -> u32 {
+    let to_end = u64::from(u32::MAX - base) + 1;
+    let capped = u64::from(len).min(u64::from(MAX_DUMP_BYTES)).min(to_end);
+    capped as u32
+}
+
+// Output format of `x`, picked with a GDB-style suffix such as `x/w`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DumpFormat {
+    Bytes,
+    Words,
+    String,
+}
+
+// Longest string `x/s` prints when no length is given.
+const DUMP_STRING_DEFAULT_LEN: u32 = 256;
+const DUMP_BYTES_PER_ROW: u32 = 16;
+const DUMP_WORDS_PER_ROW: u32 = 4;
+
+// Append the device regions `paddr` falls in to a row's list.
+fn note_region(row_regions: &mut Vec<&'static str>, paddr: u32) {
+    if let Some(name) = device_region_name(paddr)
+        && !row_regions.contains(&name)
+    {
+        row_regions.push(name);
+    }
+}
+
+fn end_row(mut line: String, row_regions: &mut Vec<&'static str>) -> String {
+    if !row_regions.is_empty() {
+        line.push_str(&format!(" [{}]", row_regions.join(", ")));
+        row_regions.clear();
+    }
+    line
+}
+
+// Purpose: format `x` output as rows of 16 bytes.
+// Inputs: start address, byte count, and a reader returning the physical
+// address and value of each byte (None when unmapped).
+// Outputs: one line per row: hex bytes (`??` when unmapped), an ASCII gutter
+// (`.` for unprintable or unmapped bytes) and, for rows that touch device
+// regions, their names, e.g. `[sd0_dma]`.
+fn byte_dump_lines<F>(base: u32, len: u32, mut read_byte: F) -> Vec<String>
 where
-    F: FnMut(u32) -> Option<u8>,
+    F: FnMut(u32) -> Option<(u32, u8)>,
 {
     if len == 0 {
-        println!("(empty range)");
-        return;
+        return vec!["(empty range)".to_string()];
     }
-    for offset in 0..len {
-        if offset % 16 == 0 {
-            print!("{:08X}: ", base.wrapping_add(offset));
+    let mut lines = Vec::new();
+    let mut row_regions = Vec::new();
+    for row in (0..len).step_by(DUMP_BYTES_PER_ROW as usize) {
+        let row_len = (len - row).min(DUMP_BYTES_PER_ROW);
+        let mut line = format!("{:08X}: ", base.wrapping_add(row));
+        let mut ascii = String::new();
+        for offset in row..row + row_len {
+            match read_byte(base.wrapping_add(offset)) {
+                Some((paddr, val)) => {
+                    line.push_str(&format!("{:02X} ", val));
+                    ascii.push(if val.is_ascii_graphic() || val == b' ' {
+                        val as char
+                    } else {
+                        '.'
+                    });
+                    note_region(&mut row_regions, paddr);
+                }
+                None => {
+                    line.push_str("?? ");
+                    ascii.push('.');
+                }
+            }
         }
-        match read_byte(base.wrapping_add(offset)) {
-            Some(val) => print!("{:02X} ", val),
-            None => print!("?? "),
+        // Pad a short last row so the gutter lines up.
+        for _ in row_len..DUMP_BYTES_PER_ROW {
+            line.push_str("   ");
         }
-        if offset % 16 == 15 || offset + 1 == len {
-            println!();
+        line.push_str(&format!("|{}|", ascii));
+        lines.push(end_row(line, &mut row_regions));
+    }
+    lines
+}
+
+// Purpose: format `x/w` output as rows of four 32-bit words.
+// Inputs: start address, word count, the core's byte order and the same
+// reader as `byte_dump_lines`.
+// Outputs: one line per row; a word with any unmapped byte prints `????????`.
+fn word_dump_lines<F>(base: u32, count: u32, order: Endianness, mut read_byte: F) -> Vec<String>
+where
+    F: FnMut(u32) -> Option<(u32, u8)>,
+{
+    if count == 0 {
+        return vec!["(empty range)".to_string()];
+    }
+    let mut lines = Vec::new();
+    let mut row_regions = Vec::new();
+    for row in (0..count).step_by(DUMP_WORDS_PER_ROW as usize) {
+        let row_addr = base.wrapping_add(row * 4);
+        let mut line = format!("{:08X}:", row_addr);
+        for word in row..(row + DUMP_WORDS_PER_ROW).min(count) {
+            let mut bytes = [0u8; 4];
+            let mut mapped = true;
+            for (offset, byte) in bytes.iter_mut().enumerate() {
+                match read_byte(base.wrapping_add(word * 4 + offset as u32)) {
+                    Some((paddr, val)) => {
+                        *byte = val;
+                        note_region(&mut row_regions, paddr);
+                    }
+                    None => mapped = false,
+                }
+            }
+            if mapped {
+                line.push_str(&format!(" {:08X}", order.word(u32::from_le_bytes(bytes))));
+            } else {
+                line.push_str(" ????????");
+            }
         }
+        lines.push(end_row(line, &mut row_regions));
     }
+    lines
 }
 
-fn resolve_label_or_addr(target: &str, labels: &LabelMap) -> Result<Vec<u32>, String> {
-    if let Some(addr) = parse_addr(target) {
-        return Ok(vec![addr]);
+// Purpose: format `x/s` output: the NUL-terminated string at `base`.
+// Inputs: start address, the most bytes to read and the same reader as
+// `byte_dump_lines`.
+// Outputs: `ADDR: "text"`, escaping quotes, backslashes and unprintable
+// bytes; followed by `...` when no NUL was found within `max` bytes, or by
+// the first unmapped address when the string runs into one.
+fn string_dump_line<F>(base: u32, max: u32, mut read_byte: F) -> String
+where
+    F: FnMut(u32) -> Option<(u32, u8)>,
+{
+    let mut text = String::new();
+    for offset in 0..max {
+        let addr = base.wrapping_add(offset);
+        let Some((_, byte)) = read_byte(addr) else {
+            return format!("{:08X}: \"{}\" (unmapped at {:08X})", base, text, addr);
+        };
+        match byte {
+            0 => return format!("{:08X}: \"{}\"", base, text),
+            b'\n' => text.push_str("\\n"),
+            b'\r' => text.push_str("\\r"),
+            b'\t' => text.push_str("\\t"),
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            b' '..=b'~' => text.push(byte as char),
+            _ => text.push_str(&format!("\\x{:02X}", byte)),
+        }
     }
-    if let Some(addrs) = labels.get(target) {
-        return Ok(addrs.clone());
+    format!("{:08X}: \"{}\"...", base, text)
+}
+
+const DISAS_DEFAULT_COUNT: u32 = 8;
+const DISAS_MAX_COUNT: u32 = 1024;
+
+// Purpose: disassemble `count` words starting at virtual address `addr`.
+// Inputs: CPU (for translation and the current PC), start address (rounded
+// down to a word), instruction count, and label annotations.
+// Outputs: one line per word; the current PC is marked with `=>` and
+// unmapped words print `??` without raising a TLB miss.
+fn disas_lines(
+    cpu: &mut Emulator,
+    addr: u32,
+    count: u32,
+    labels_by_addr: &HashMap<u32, Vec<String>>,
+) -> Vec<String> {
+    let base = addr & !3;
+    let mut lines = Vec::new();
+    for index in 0..count {
+        let pc = base.wrapping_add(index * 4);
+        if let Some(names) = labels_by_addr.get(&pc) {
+            lines.push(format!("{}:", names.join(", ")));
+        }
+        let marker = if pc == cpu.pc { "=>" } else { "  " };
+        match cpu.peek_instr(pc) {
+            Some(instr) => lines.push(format!(
+                "{} {:08X}: {:08X}  {}",
+                marker,
+                pc,
+                instr,
+                disassemble_at(instr, pc)
+            )),
+            None => lines.push(format!("{} {:08X}: ??", marker, pc)),
+        }
     }
-    Err(format!("Unknown label {}", target))
+    lines
 }
 
-fn print_step(pc: u32, instr: u32, labels_by_addr: &HashMap<u32, Vec<String>>) {
-    let disasm = disassemble(instr);
-    if let Some(names) = labels_by_addr.get(&pc) {
-        println!(
-            "{:08X}: {:08X}  {} ({})",
-            pc,
-            instr,
-            disasm,
-            names.join(", ")
-        );
+// Name `addr` as `label` or `label+0xN` using the nearest label at or below it.
+fn symbolize(addr: u32, labels_by_addr: &HashMap<u32, Vec<String>>) -> Option<String> {
+    let (base, names) = labels_by_addr
+        .iter()
+        .filter(|(label_addr, _)| **label_addr <= addr)
+        .max_by_key(|(label_addr, _)| **label_addr)?;
+    let name = names.iter().min()?;
+    if *base == addr {
+        Some(name.clone())
     } else {
-        println!("{:08X}: {:08X}  {}", pc, instr, disasm);
+        Some(format!("{}+0x{:X}", name, addr - base))
     }
 }
 
-fn print_breakpoint(addr: u32, labels_by_addr: &HashMap<u32, Vec<String>>, cpu: &mut Emulator) {
-    if let Some(instr) = cpu.fetch(addr) {
-        print_step(addr, instr, labels_by_addr);
+// Purpose: walk the frame-pointer chain for `bt`.
+// Inputs: `max_depth` caps the number of frames returned.
+// Outputs: frame PCs innermost first (the current pc, then return addresses),
+// plus the reason the walk was cut short, if it was.
+fn backtrace_frames(cpu: &mut Emulator, max_depth: usize) -> (Vec<u32>, Option<String>) {
+    let mut frames = vec![cpu.pc];
+    let mut fp = cpu.get_reg(BT_FP_REG);
+    let link = cpu.get_reg(BT_LINK_REG);
+    // Once the prologue has run, the link register is also saved in the
+    // current frame; only report it separately when it is not.
+    let saved_link = if fp == 0 {
+        None
     } else {
-        println!("Breakpoint hit at {:08X}", addr);
+        read_debug32_virt(cpu, fp.wrapping_add(BT_SAVED_RA_OFFSET))
+    };
+    if link != 0 && saved_link != Some(link) {
+        frames.push(link);
     }
-}
+
+    let mut stopped = None;
+    while fp != 0 {
+        if frames.len() >= max_depth {
+            stopped = Some(format!("depth limit {} reached", max_depth));
+            break;
+        }
+        let ra = read_debug32_virt(cpu, fp.wrapping_add(BT_SAVED_RA_OFFSET));
+        let next_fp = read_debug32_virt(cpu, fp.wrapping_add(BT_SAVED_FP_OFFSET));
+        let (Some(ra), Some(next_fp)) = (ra, next_fp) else {
+            stopped = Some(format!("frame pointer {:08X} is not mapped", fp));
+            break;
+        };
+        if ra == 0 {
+            break;
+        }
+        frames.push(ra);
+        // Caller frames sit above callee frames on the downward-growing stack.
+        if next_fp != 0 && next_fp <= fp {
+            stopped = Some(format!("frame pointer chain loops at {:08X}", next_fp));
+            break;
+        }
+        fp = next_fp;
+    }
+    frames.truncate(max_depth);
+    (frames, stopped)
+}
+
+fn format_frame(index: usize, pc: u32, labels_by_addr: &HashMap<u32, Vec<String>>) -> String {
+    match symbolize(pc, labels_by_addr) {
+        Some(name) => format!("#{:<2} {:08X} in {}", index, pc, name),
+        None => format!("#{:<2} {:08X}", index, pc),
+    }
+}
+
+// Accept a write size in bytes (1, 2, 4) or as b/h/w.
+fn parse_mem_size(token: &str) -> Option<u32> {
+    match token {
+        "1" | "b" => Some(1),
+        "2" | "h" => Some(2),
+        "4" | "w" => Some(4),
+        _ => None,
+    }
+}
+
+// Purpose: implement `set mem`: write `size` bytes of `value` at `addr`.
+// Inputs: virtual writes use the guest store path (`mem_write*`), so they
+// translate through the TLB and reach devices; physical writes bypass the TLB.
+// Outputs: Err with a message for misaligned, oversized or unmapped writes.
+fn poke_mem(
+    cpu: &mut Emulator,
+    physical: bool,
+    addr: u32,
+    value: u32,
+    size: u32,
+) -> Result<(), String> {
+    if addr & (size - 1) != 0 {
+        return Err(format!("Address {:08X} is not {}-byte aligned", addr, size));
+    }
+    if size < 4 && value >> (8 * size) != 0 {
+        return Err(format!(
+            "Value {:08X} does not fit in {} bytes",
+            value, size
+        ));
+    }
+    if physical {
+        if !cpu.write_phys_debug(addr, value, size) {
+            return Err(format!("Physical address {:08X} is out of range", addr));
+        }
+        return Ok(());
+    }
+    let written = match size {
+        1 => cpu.mem_write8(addr, value as u8),
+        2 => cpu.mem_write16(addr, value as u16),
+        _ => cpu.mem_write32(addr, value),
+    };
+    // A debugger write is not a guest access: never report it as a watchpoint
+    // hit or leave a fault pending for the next instruction.
+    cpu.take_watchpoint_hit();
+    cpu.clear_pending_tlb_fault();
+    if written {
+        Ok(())
+    } else {
+        Err(format!("TLB miss writing vaddr 0x{:08X}", addr))
+    }
+}
+
+// Purpose: implement `dump`: copy `len` bytes starting at `addr` to `out`.
+// Inputs: virtual reads translate through the TLB like `x` (without raising a
+// fault); physical reads bypass it. The caller keeps the range below 2^32.
+// Outputs: the number of unmapped or out-of-range bytes, which are written
+// as 0, or the first write error.
+fn dump_range_debug(
+    cpu: &mut Emulator,
+    physical: bool,
+    addr: u32,
+    len: u32,
+    out: &mut impl Write,
+) -> io::Result<u32> {
+    // Read in page-sized chunks so a large dump needs no large buffer.
+    let mut chunk = Vec::with_capacity(4096);
+    let mut unmapped = 0;
+    let mut done: u32 = 0;
+    while done < len {
+        let n = (len - done).min(4096);
+        chunk.clear();
+        for offset in done..done + n {
+            let a = addr + offset;
+            let byte = if physical {
+                cpu.read_phys8_debug(a)
+            } else {
+                cpu.read_virt8_debug(a)
+            };
+            if byte.is_none() {
+                unmapped += 1;
+            }
+            chunk.push(byte.unwrap_or(0));
+        }
+        out.write_all(&chunk)?;
+        done += n;
+    }
+    Ok(unmapped)
+}
+
+// Purpose: implement `loadbin`: copy `bytes` into memory at `addr`.
+// Inputs: virtual writes translate through the TLB; physical writes bypass it.
+// Both reach devices but skip watchpoints.
+// Outputs: the number of bytes written before the first unmapped or
+// out-of-range address.
+fn write_range_debug(cpu: &mut Emulator, physical: bool, addr: u32, bytes: &[u8]) -> usize {
+    if !physical {
+        return cpu.write_virt_bytes_debug(addr, bytes);
+    }
+    for (offset, byte) in bytes.iter().enumerate() {
+        let Some(a) = addr.checked_add(offset as u32) else {
+            return offset;
+        };
+        if !cpu.write_phys_debug(a, u32::from(*byte), 1) {
+            return offset;
+        }
+    }
+    bytes.len()
+}
+
+// Purpose: implement `fill` and `zero`: write `byte` across `len` bytes.
+// Inputs: the same translation and device rules as `loadbin`. The caller keeps
+// the range below 2^32.
+// Outputs: the message to print, naming the first unmapped address if the
+// fill stopped early.
+fn fill_range_debug(cpu: &mut Emulator, physical: bool, addr: u32, len: u32, byte: u8) -> String {
+    // Write in page-sized chunks so a large fill needs no large buffer.
+    let chunk = [byte; 4096];
+    let mut done: u32 = 0;
+    while done < len {
+        let n = (len - done).min(chunk.len() as u32);
+        let written = write_range_debug(cpu, physical, addr + done, &chunk[..n as usize]) as u32;
+        done += written;
+        if written < n {
+            let stop = addr + done;
+            return if physical {
+                format!(
+                    "Filled {} of {} bytes at {:08X}; paddr 0x{:08X} is out of range",
+                    done, len, addr, stop
+                )
+            } else {
+                format!(
+                    "Filled {} of {} bytes at {:08X}; TLB miss at vaddr 0x{:08X}",
+                    done, len, addr, stop
+                )
+            };
+        }
+    }
+    format!("Filled {} bytes at {:08X} with {:02X}", len, addr, byte)
+}
+
+// Most match addresses one `find` prints; later matches are only counted.
+const FIND_MAX_MATCHES: usize = 32;
+
+// Purpose: parse the pattern of `find`.
+// Inputs: either a double-quoted string (escapes as for `set str`, with no
+// NUL terminator added) or hex bytes in memory order, e.g. `de ad be ef`,
+// `deadbeef` or `0xdead 0xbeef`.
+// Outputs: the bytes to search for, or an error for an empty or malformed
+// pattern.
+fn parse_find_pattern(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    if text.starts_with('"') {
+        bytes = parse_string_literal(text)?;
+        bytes.pop();
+    } else {
+        for token in text.split_whitespace() {
+            let digits = token.strip_prefix("0x").unwrap_or(token);
+            if digits.is_empty()
+                || digits.len() % 2 != 0
+                || !digits.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(format!("Invalid hex bytes {}", token));
+            }
+            for pair in digits.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair).unwrap();
+                bytes.push(u8::from_str_radix(pair, 16).unwrap());
+            }
+        }
+    }
+    if bytes.is_empty() {
+        return Err("Empty pattern".to_string());
+    }
+    Ok(bytes)
+}
+
+// Purpose: implement `find`: search `len` bytes from `addr` for `pattern`.
+// Inputs: virtual reads translate through the TLB like `x` (without raising a
+// fault); physical reads bypass it. The caller keeps the range below 2^32.
+// Outputs: the first FIND_MAX_MATCHES match addresses, in the address space
+// searched, and the total number of matches. Overlapping matches count, and
+// a match never spans an unmapped byte.
+fn find_pattern_debug(
+    cpu: &mut Emulator,
+    physical: bool,
+    addr: u32,
+    len: u32,
+    pattern: &[u8],
+) -> (Vec<u32>, usize) {
+    let mut found = Vec::new();
+    let mut total = 0;
+    let mut window: VecDeque<u8> = VecDeque::with_capacity(pattern.len());
+    for offset in 0..len {
+        let a = addr + offset;
+        let byte = if physical {
+            cpu.read_phys8_debug(a)
+        } else {
+            cpu.read_virt8_debug(a)
+        };
+        let Some(byte) = byte else {
+            window.clear();
+            continue;
+        };
+        if window.len() == pattern.len() {
+            window.pop_front();
+        }
+        window.push_back(byte);
+        if window.iter().eq(pattern.iter()) {
+            total += 1;
+            if found.len() < FIND_MAX_MATCHES {
+                found.push(a + 1 - pattern.len() as u32);
+            }
+        }
+    }
+    (found, total)
+}
+
+// Describe one TLB lookup result: the raw entry, its PPN and the R/W/X/U/G bits.
+fn format_tlb_entry(pid: u32, vpn: u32, entry: Option<u32>) -> String {
+    let Some(entry) = entry else {
+        return format!("pid {:08X} vpn {:08X} -> (no entry)", pid, vpn);
+    };
+    format!(
+        "pid {:08X} vpn {:08X} -> {}",
+        pid,
+        vpn,
+        describe_tlb_entry(entry)
+    )
+}
+
+// Purpose: implement `tlb read|set|inv|clear` on the current core's TLB.
+// Inputs: the arguments after `tlb`. `set` takes the entry as a guest `tlbw`
+// would write it: PPN in bits 26..12 and flag bits below.
+// Outputs: the line to print, or a usage/parse error.
+fn tlb_command(cpu: &mut Emulator, args: &[&str]) -> Result<String, String> {
+    let parse = |token: &str| parse_addr(token).ok_or_else(|| format!("Invalid value {}", token));
+    match args {
+        ["read", pid, vpn] => {
+            let (pid, vpn) = (parse(pid)?, parse(vpn)?);
+            Ok(format_tlb_entry(pid, vpn, cpu.tlb.read(pid, vpn)))
+        }
+        ["set", pid, vpn, ppn] => {
+            let (pid, vpn, ppn) = (parse(pid)?, parse(vpn)?, parse(ppn)?);
+            // Same mask as tlbw, so an entry can't carry bits the hardware drops.
+            cpu.tlb.write(pid, vpn, ppn & 0x7FF_FFFF);
+            Ok(format_tlb_entry(pid, vpn, cpu.tlb.read(pid, vpn)))
+        }
+        ["inv", pid, vpn] => {
+            let (pid, vpn) = (parse(pid)?, parse(vpn)?);
+            cpu.tlb.invalidate(pid, vpn);
+            Ok(format_tlb_entry(pid, vpn, cpu.tlb.read(pid, vpn)))
+        }
+        ["clear"] => {
+            cpu.tlb.clear();
+            Ok("TLB cleared".to_string())
+        }
+        _ => Err(
+            "Usage: tlb read <pid> <vpn> | tlb set <pid> <vpn> <ppn> | tlb inv <pid> <vpn> | tlb clear"
+                .to_string(),
+        ),
+    }
+}
+
+// Return the text after the first `skip` whitespace-separated tokens so
+// commands can accept free-form trailing arguments (e.g. quoted strings).
+fn command_rest(line: &str, skip: usize) -> &str {
+    let mut rest = line.trim_start();
+    for _ in 0..skip {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest.trim_end()
+}
+
+// Parse a double-quoted string literal into ASCII bytes plus a NUL terminator.
+// Supports \n, \r, \t, \0, \\, \" and \xNN escapes.
+fn parse_string_literal(token: &str) -> Result<Vec<u8>, String> {
+    let Some(body) = token
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    else {
+        return Err("String must be enclosed in double quotes".to_string());
+    };
+    let mut bytes = Vec::with_capacity(body.len() + 1);
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            if !ch.is_ascii() {
+                return Err(format!("Non-ASCII character {:?} in string", ch));
+            }
+            bytes.push(ch as u8);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('"') => bytes.push(b'"'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid escape \\x{}", hex));
+                }
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+            }
+            Some(other) => return Err(format!("Unknown escape \\{}", other)),
+            None => return Err("Trailing backslash in string".to_string()),
+        }
+    }
+    bytes.push(0);
+    Ok(bytes)
+}
+
+fn resolve_label_or_addr(target: &str, labels: &LabelMap) -> Result<Vec<u32>, String> {
+    if let Some(addr) = parse_addr(target) {
+        return Ok(vec![addr]);
+    }
+    if let Some(addrs) = labels.get(target) {
+        return Ok(addrs.clone());
+    }
+    Err(format!("Unknown label {}", target))
+}
+
+// Execute a single instruction for `n` and report it along with any
+// watchpoint, creg-watch or halt it caused, plus the registers it changed
+// when `diff_regs` is on.
+fn step_and_print(cpu: &mut Emulator, diff_regs: bool, labels_by_addr: &HashMap<u32, Vec<String>>) {
+    let before = diff_regs.then(|| cpu.reg_snapshot());
+    let result = cpu.step();
+    print_step_result(cpu, result, labels_by_addr);
+    if let Some(before) = before {
+        print_reg_diff(&before, &cpu.reg_snapshot());
+    }
+}
+
+// Register state compared by `set diffregs on`. The PC and the retired
+// counter (cr13/cr14) change on every step, so they are left out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct RegSnapshot {
+    regs: [u32; 32],
+    cregs: [u32; CREG_NAMES.len()],
+    cause: u32,
+}
+
+// Purpose: describe what one step changed, for `set diffregs on`.
+// Inputs: register state before and after the step.
+// Outputs: one "name: old -> new" line per changed register, general
+// registers first, then control registers in cr order.
+fn reg_diff_lines(before: &RegSnapshot, after: &RegSnapshot) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (idx, (old, new)) in before.regs.iter().zip(&after.regs).enumerate() {
+        if old != new {
+            lines.push(format!("r{}: {:08X} -> {:08X}", idx, old, new));
+        }
+    }
+    for (idx, (old, new)) in before.cregs.iter().zip(&after.cregs).enumerate() {
+        if old != new {
+            lines.push(format!("{}: {:08X} -> {:08X}", format_creg(idx), old, new));
+        }
+    }
+    if before.cause != after.cause {
+        lines.push(format!(
+            "cr{} (cause): {:08X} -> {:08X}",
+            CREG_CAUSE, before.cause, after.cause
+        ));
+    }
+    lines
+}
+
+fn print_reg_diff(before: &RegSnapshot, after: &RegSnapshot) {
+    for line in reg_diff_lines(before, after) {
+        println!("{}", line);
+    }
+}
+
+// Purpose: step up to `count` instructions for `n <count>`.
+// Inputs: `print_all` prints every step; otherwise only the last one is shown.
+// Outputs: stops early, printing the step that caused it, on a watchpoint,
+// control-register watch, halt, fetch fault or sleep, and before any
+// instruction after the first that has a breakpoint.
+fn step_count(
+    cpu: &mut Emulator,
+    breakpoints: &Breakpoints,
+    count: u32,
+    print_all: bool,
+    diff_regs: bool,
+    labels_by_addr: &HashMap<u32, Vec<String>>,
+) {
+    for done in 0..count {
+        if done > 0 && breakpoint_triggers(breakpoints, cpu) {
+            println!("Stopped after {} of {} instructions.", done, count);
+            print_breakpoint(cpu.pc, labels_by_addr, cpu);
+            return;
+        }
+        let before = diff_regs.then(|| cpu.reg_snapshot());
+        let result = cpu.step();
+        let stop = match result {
+            StepResult::Executed { .. } => {
+                cpu.watchpoint_hit.is_some()
+                    || cpu.creg_watch_hit.is_some()
+                    || cpu.catch_hit.is_some()
+                    || cpu.halted
+            }
+            _ => true,
+        };
+        if print_all || stop || done + 1 == count {
+            print_step_result(cpu, result, labels_by_addr);
+            if let Some(before) = before {
+                print_reg_diff(&before, &cpu.reg_snapshot());
+            }
+        }
+        if stop {
+            if done + 1 < count {
+                println!("Stopped after {} of {} instructions.", done + 1, count);
+            }
+            return;
+        }
+    }
+}
+
+fn print_step_result(
+    cpu: &mut Emulator,
+    result: StepResult,
+    labels_by_addr: &HashMap<u32, Vec<String>>,
+) {
+    match result {
+        StepResult::Executed {
+            pc,
+            instr,
+            interrupt,
+            exception,
+            next_pc,
+        } => {
+            if interrupt {
+                println!("Interrupt taken; handler at {:08X}", pc);
+            }
+            print_step(pc, instr, labels_by_addr);
+            if exception {
+                println!("Exception taken; handler at {:08X}", next_pc);
+            }
+            if let Some(hit) = cpu.take_watchpoint_hit() {
+                print_watchpoint_hit(hit, cpu.pc);
+            }
+            if let Some(hit) = cpu.creg_watch_hit.take() {
+                print_creg_watch_hit(hit);
+            }
+            if let Some(entry) = cpu.catch_hit.take() {
+                print_catch_hit(entry);
+            }
+            if cpu.halted {
+                print_halted(cpu);
+            }
+        }
+        StepResult::FetchFault { pc, tlb_miss, .. } => {
+            print_fetch_fault(pc, tlb_miss);
+            if let Some(entry) = cpu.catch_hit.take() {
+                print_catch_hit(entry);
+            }
+        }
+        StepResult::Sleeping => print_sleeping(cpu),
+        StepResult::Halted => print_halted(cpu),
+    }
+}
+
+fn print_fetch_fault(pc: u32, tlb_miss: bool) {
+    if tlb_miss {
+        println!("TLB miss at {:08X}", pc);
+    } else {
+        println!("Misaligned fetch at {:08X}", pc);
+    }
+}
+
+fn print_step(pc: u32, instr: u32, labels_by_addr: &HashMap<u32, Vec<String>>) {
+    let disasm = disassemble_at(instr, pc);
+    if let Some(names) = labels_by_addr.get(&pc) {
+        println!(
+            "{:08X}: {:08X}  {} ({})",
+            pc,
+            instr,
+            disasm,
+            names.join(", ")
+        );
+    } else {
+        println!("{:08X}: {:08X}  {}", pc, instr, disasm);
+    }
+}
+
+fn print_breakpoint(addr: u32, labels_by_addr: &HashMap<u32, Vec<String>>, cpu: &mut Emulator) {
+    if let Some(instr) = cpu.peek_instr(addr) {
+        print_step(addr, instr, labels_by_addr);
+    } else {
+        println!("Breakpoint hit at {:08X}", addr);
+    }
+}
 
 // Avoid infinite loops when source lines do not advance.
 const MAX_STEP_INSTRUCTIONS: u32 = 1_000_000;
+// ABI return-address register (r29), written by linking branches.
+const RA_REG: u32 = 29;
 // ABI base pointer register (r30).
 const BP_REG: u32 = 30;
+// ABI stack pointer register (r31).
+const SP_REG: u32 = 31;
 // Debug display uses word-sized (4-byte) reads.
 const DEBUG_WORD_BYTES: u32 = 4;
+// Largest range a single `x` command will print; bigger requests are truncated.
+const MAX_DUMP_BYTES: u32 = 0x10000;
+// Number of RAM pages listed by `info memstats`.
+const MEMSTATS_TOP_PAGES: usize = 16;
 
 fn build_line_index(lines: &[DebugLine]) -> HashMap<String, HashMap<u32, Vec<u32>>> {
     let mut index: HashMap<String, HashMap<u32, Vec<u32>>> = HashMap::new();
@@ -346,12 +1658,12 @@ fn format_breakpoint_c(addr: u32, lines: &[DebugLine]) -> String {
     }
 }
 
-fn list_breakpoints_c(breakpoints: &HashSet<u32>, lines: &[DebugLine]) {
+fn list_breakpoints_c(breakpoints: &Breakpoints, lines: &[DebugLine]) {
     if breakpoints.is_empty() {
         println!("No breakpoints set.");
         return;
     }
-    let mut list: Vec<u32> = breakpoints.iter().copied().collect();
+    let mut list: Vec<u32> = breakpoints.keys().copied().collect();
     list.sort_unstable();
     for addr in list {
         println!("{}", format_breakpoint_c(addr, lines));
@@ -569,7 +1881,7 @@ fn read_debug32_virt(cpu: &mut Emulator, addr: u32) -> Option<u32> {
         let byte = cpu.read_virt8_debug(addr.wrapping_add(i))?;
         value |= u32::from(byte) << (8 * i);
     }
-    Some(value)
+    Some(cpu.endianness.word(value))
 }
 
 fn read_debug_bytes_virt(cpu: &mut Emulator, addr: u32, size: u32) -> Option<Vec<u8>> {
@@ -613,30 +1925,37 @@ impl Emulator {
         self.watchpoint_hit.take()
     }
 
-    fn step_instruction(&mut self) -> StepOutcome {
-        self.check_for_interrupts();
-        self.handle_interrupts();
-
-        if self.asleep {
-            return StepOutcome::Sleeping;
+    // Record the first watched control register whose value changed since `before`.
+    // Rewriting a register with the value it already holds is deliberately not
+    // a hit: FLG is rewritten by nearly every ALU instruction, and comparing
+    // snapshots catches every mutation path without hooking each one.
+    pub(super) fn check_creg_watches(&mut self, before: &[u32; 13], pc: u32) {
+        if self.creg_watch_hit.is_some() {
+            return;
         }
-
-        let pc = self.pc;
-        match self.fetch(pc) {
-            Some(instr) => {
-                self.execute(instr);
-                self.count = self.count.wrapping_add(1);
-                StepOutcome::Executed { pc, instr }
-            }
-            None => {
-                self.raise_pending_tlb_miss(pc);
-                self.count = self.count.wrapping_add(1);
-                StepOutcome::TlbMiss { pc }
+        for &idx in &self.creg_watches {
+            let new = self.read_creg(idx);
+            if new != before[idx] {
+                self.creg_watch_hit = Some(CregWatchHit {
+                    creg: idx,
+                    old: before[idx],
+                    new,
+                    pc,
+                });
+                return;
             }
         }
     }
 
-    fn print_regs(&self) {
+    fn reg_snapshot(&self) -> RegSnapshot {
+        RegSnapshot {
+            regs: std::array::from_fn(|idx| self.get_reg(idx as u32)),
+            cregs: std::array::from_fn(|idx| self.read_creg(idx)),
+            cause: self.read_creg(CREG_CAUSE),
+        }
+    }
+
+    pub(super) fn print_regs(&self) {
         println!("pc: {:08X} kmode: {}", self.pc, self.get_kmode());
         for row in 0..8 {
             let base = row * 4;
@@ -695,82 +2014,89 @@ impl Emulator {
         println!("cr10 (mbi): {:08X}", self.read_creg(10));
         println!("cr11 (mbo): {:08X}", self.read_creg(11));
         println!("cr12 (tlbf): {:08X}", self.read_creg(12));
+        println!("cr13 (cnt): {:08X}", self.read_creg(CREG_CNT));
+        println!("cr14 (cnth): {:08X}", self.read_creg(CREG_CNTH));
+        println!("cr15 (cause): {:08X}", self.read_creg(CREG_CAUSE));
+    }
+
+    // Purpose: report how far execution has progressed.
+    // Inputs: none.
+    // Outputs: prints the retired-instruction counter (cr13/cr14) and the raw tick
+    // count, which also includes sleep and clock-divider stall ticks.
+    fn print_count(&self) {
+        println!("instructions retired: {}", self.retired);
+        println!("ticks: {}", self.count);
     }
 
     fn print_single_reg(&self, token: &str) -> bool {
+        match self.format_single_reg(token) {
+            Some(text) => {
+                println!("{}", text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Purpose: render one register as `info <reg>` shows it.
+    // Inputs: a register name (pc, rN, crN or an ABI/control-register alias).
+    // Outputs: "name = value", or None for an unknown name.
+    fn format_single_reg(&self, token: &str) -> Option<String> {
         let token = token.to_ascii_lowercase();
         match token.as_str() {
             "pc" => {
-                println!("pc = {:08X}", self.pc);
-                return true;
+                return Some(format!("pc = {:08X}", self.pc));
             }
             "sp" => {
-                println!("sp (r31) = {:08X}", self.get_reg(31));
-                return true;
+                return Some(format!("sp (r31) = {:08X}", self.get_reg(31)));
             }
             "bp" => {
-                println!("bp (r30) = {:08X}", self.get_reg(30));
-                return true;
+                return Some(format!("bp (r30) = {:08X}", self.get_reg(30)));
             }
             "ra" => {
-                println!("ra (r29) = {:08X}", self.get_reg(29));
-                return true;
+                return Some(format!("ra (r29) = {:08X}", self.get_reg(29)));
             }
             "ksp" => {
-                println!("ksp (cr8) = {:08X}", self.read_creg(8));
-                return true;
+                return Some(format!("ksp (cr8) = {:08X}", self.read_creg(8)));
             }
             "psr" => {
-                println!("psr (cr0) = {:08X}", self.read_creg(0));
-                return true;
+                return Some(format!("psr (cr0) = {:08X}", self.read_creg(0)));
             }
             "pid" => {
-                println!("pid (cr1) = {:08X}", self.read_creg(1));
-                return true;
+                return Some(format!("pid (cr1) = {:08X}", self.read_creg(1)));
             }
             "isr" => {
-                println!("isr (cr2) = {:08X}", self.read_creg(2));
-                return true;
+                return Some(format!("isr (cr2) = {:08X}", self.read_creg(2)));
             }
             "imr" => {
-                println!("imr (cr3) = {:08X}", self.read_creg(3));
-                return true;
+                return Some(format!("imr (cr3) = {:08X}", self.read_creg(3)));
             }
             "epc" => {
-                println!("epc (cr4) = {:08X}", self.read_creg(4));
-                return true;
+                return Some(format!("epc (cr4) = {:08X}", self.read_creg(4)));
             }
             "flg" => {
-                println!("flg (cr5) = {:08X}", self.read_creg(5));
-                return true;
+                return Some(format!("flg (cr5) = {:08X}", self.read_creg(5)));
             }
             "efg" => {
-                println!("efg (cr6) = {:08X}", self.read_creg(6));
-                return true;
+                return Some(format!("efg (cr6) = {:08X}", self.read_creg(6)));
             }
             "cdv" => {
-                println!("cdv (cr6) = {:08X}", self.read_creg(6));
-                return true;
+                return Some(format!("cdv (cr6) = {:08X}", self.read_creg(6)));
             }
             "tlb" => {
-                println!("tlb (cr7) = {:08X}", self.read_creg(7));
-                return true;
+                return Some(format!("tlb (cr7) = {:08X}", self.read_creg(7)));
             }
             "cid" => {
-                println!("cid (cr9) = {:08X}", self.read_creg(9));
-                return true;
+                return Some(format!("cid (cr9) = {:08X}", self.read_creg(9)));
             }
             "mbi" => {
-                println!("mbi (cr10) = {:08X}", self.read_creg(10));
-                return true;
+                return Some(format!("mbi (cr10) = {:08X}", self.read_creg(10)));
             }
             "mbo" => {
-                println!("mbo (cr11) = {:08X}", self.read_creg(11));
-                return true;
+                return Some(format!("mbo (cr11) = {:08X}", self.read_creg(11)));
             }
             "tlbf" => {
-                println!("tlbf (cr12) = {:08X}", self.read_creg(12));
-                return true;
+                return Some(format!("tlbf (cr12) = {:08X}", self.read_creg(12)));
             }
             _ => {}
         }
@@ -778,8 +2104,7 @@ impl Emulator {
         if let Some(num) = token.strip_prefix("r") {
             if let Ok(idx) = num.parse::<u32>() {
                 if idx < 32 {
-                    println!("r{} = {:08X}", idx, self.get_reg(idx));
-                    return true;
+                    return Some(format!("r{} = {:08X}", idx, self.get_reg(idx)));
                 }
             }
         }
@@ -787,13 +2112,12 @@ impl Emulator {
         if let Some(num) = token.strip_prefix("cr") {
             if let Ok(idx) = num.parse::<usize>() {
                 if idx < self.cregfile.len() {
-                    println!("cr{} = {:08X}", idx, self.read_creg(idx));
-                    return true;
+                    return Some(format!("cr{} = {:08X}", idx, self.read_creg(idx)));
                 }
             }
         }
 
-        false
+        None
     }
 
     fn set_reg_value(&mut self, token: &str, value: u32) -> bool {
@@ -900,17 +2224,92 @@ impl Emulator {
     }
 
     fn print_tlb(&self) {
-        self.tlb.debug_dump();
+        println!("{}", self.tlb.debug_dump());
+    }
+
+    // Print guest data access counts per device region, then the busiest RAM
+    // pages. Counts accumulate from the last cold reset.
+    fn print_memstats(&self) {
+        let stats = self.memory.access_stats();
+        if stats.regions.is_empty() {
+            println!("No guest memory accesses recorded");
+            return;
+        }
+        println!("{:<12} {:>12} {:>12}", "region", "reads", "writes");
+        for (name, reads, writes) in &stats.regions {
+            println!("{:<12} {:>12} {:>12}", name, reads, writes);
+        }
+
+        let mut pages = stats.pages;
+        if pages.is_empty() {
+            return;
+        }
+        pages.sort_by(|a, b| (b.1 + b.2).cmp(&(a.1 + a.2)).then(a.0.cmp(&b.0)));
+        let shown = pages.len().min(MEMSTATS_TOP_PAGES);
+        println!("Hottest RAM pages ({} of {} touched):", shown, pages.len());
+        for (base, reads, writes, _) in &pages[..shown] {
+            println!(
+                "  {:08X}-{:08X} {:>12} {:>12}",
+                base,
+                base + 0xFFF,
+                reads,
+                writes
+            );
+        }
+    }
+
+    // Print reads, writes and instruction fetches for every touched RAM page,
+    // in address order. Fetches are counted while profiling is on, which the
+    // debugger always enables.
+    fn print_profile(&self) {
+        let stats = self.memory.access_stats();
+        if stats.pages.is_empty() {
+            println!("No RAM accesses recorded");
+            return;
+        }
+        println!(
+            "{:<17} {:>12} {:>12} {:>12}",
+            "page", "reads", "writes", "fetches"
+        );
+        for (base, reads, writes, fetches) in &stats.pages {
+            println!(
+                "{:08X}-{:08X} {:>12} {:>12} {:>12}",
+                base,
+                base + 0xFFF,
+                reads,
+                writes,
+                fetches
+            );
+        }
+    }
+
+    // Print every peripheral register snapshot from `Memory::io_registers`.
+    fn print_io(&self) {
+        for (name, value) in self.memory.io_registers() {
+            println!("{:<14} {:08X}", name, value);
+        }
+    }
+
+    fn print_memmap(&self) {
+        for region in self.memory.regions() {
+            println!(
+                "{:08X}-{:08X} {:<14} {:?}",
+                region.start,
+                region.end - 1,
+                region.name,
+                region.kind
+            );
+        }
     }
 
     fn print_phys(&mut self, addr: u32) {
-        if addr > PHYSMEM_MAX {
+        if !self.memory.is_physical(addr) {
             println!("Warning: physical address out of range 0x{:08X}", addr);
             return;
         }
         match self.read_phys32(addr) {
             Some(word) => println!("paddr {:08X} = {:08X}", addr, word),
-            None => println!("Warning: physical address out of range 0x{:08X}", addr),
+            None => println!("Warning: no readable memory at paddr 0x{:08X}", addr),
         }
     }
 
@@ -918,7 +2317,7 @@ impl Emulator {
         match self.convert_mem_address(addr, 0) {
             Some(paddr) => match self.read_phys32(paddr) {
                 Some(word) => println!("vaddr {:08X} -> paddr {:08X} = {:08X}", addr, paddr, word),
-                None => println!("Warning: physical address out of range 0x{:08X}", paddr),
+                None => println!("Warning: no readable memory at paddr 0x{:08X}", paddr),
             },
             None => println!("Warning: no TLB mapping for vaddr 0x{:08X}", addr),
         }
@@ -930,52 +2329,119 @@ impl Emulator {
         sd_dma_ticks_per_word: u32,
         sd0_image: Option<&[u8]>,
         sd1_image: Option<&[u8]>,
+        config: &EmulatorConfig,
+        script: Vec<String>,
     ) -> Emulator {
-        let image = load_program(&path);
+        let image = load_program(&path, config);
         let labels_by_addr = build_labels_by_addr(&image.labels);
-        let mut breakpoints: HashSet<u32> = HashSet::new();
+        // Commands from --debug-script and `source` run before stdin is read.
+        let mut pending: VecDeque<String> = script.into();
+        let mut breakpoints: Breakpoints = HashMap::new();
         let mut watchpoints: Vec<Watchpoint> = Vec::new();
-        let mut cpu = Emulator::from_instructions(
+        let mut creg_watches: Vec<usize> = Vec::new();
+        let mut catches: Vec<CatchKind> = Vec::new();
+        let mut displays: Vec<(u32, DisplayExpr)> = Vec::new();
+        let mut diff_regs = false;
+        let mut next_display_id = 1;
+        let mut cpu = Emulator::from_instructions_with_config(
             image.instructions.clone(),
             use_uart_rx,
             sd_dma_ticks_per_word,
             sd0_image,
             sd1_image,
+            config,
         );
+        cpu.set_entry(image.entry);
         cpu.set_watchpoints(&watchpoints);
+        cpu.set_undo_depth(config.undo_depth);
 
         println!("Debug mode:");
-        println!("  r                 reset and run until break/watchpoint/halt");
+        println!(
+            "  r                 cold reset (reload program) and run until break/watchpoint/halt"
+        );
+        println!("  reset             warm reset: CPU state only; RAM, SD and devices are kept");
         println!("  c                 continue execution");
-        println!("  n                 step one instruction");
+        println!("  n | si [count] [all] step instructions (default 1); `all` prints each one");
+        println!("  back | rn [count] undo the last instruction(s) (registers, TLB, PIT, RAM)");
+        println!("  next | so         step over: run a call until it returns");
+        println!("  finish | out      step out: run until return to the address in ra (r29)");
+        println!("  until <label|addr> run until pc first reaches the target");
         println!("  break <label|addr> set breakpoint");
+        println!("  break <label|addr> if <reg> <==|!=|<|>> <value> conditional breakpoint");
         println!("  breaks            list breakpoints");
         println!("  delete <label|addr> remove breakpoint");
-        println!("  watch [r|w|rw] <addr> stop on memory access");
+        println!(
+            "  watch [r|w|rw] <addr> [len] [op value] stop on access to a byte or range (optionally value-matched)"
+        );
         println!("  watchs            list watchpoints");
         println!("  unwatch <addr>    remove watchpoint");
+        println!("  cwatch <crN|name> stop when a control register changes");
+        println!("  watch cr <n|name> same as cwatch <crN|name>");
+        println!("  cwatchs           list control-register watches");
+        println!("  uncwatch <crN|name> remove control-register watch");
+        println!("  catch [<exc>]     stop when an exception or interrupt is taken; no arg lists");
+        println!("  uncatch <exc>     stop catching an exception or interrupt");
+        println!("  display [<reg>|[v|p] <addr>] print a register or word after every stop");
+        println!("  undisplay <id>    remove a display");
         println!("  info regs         print all registers");
         println!("  info cregs        print control registers + kmode");
         println!("  info <reg>        print a single register");
         println!("  info tlb          dump TLB maps");
+        println!("  tlb read <pid> <vpn> print the TLB entry for a page");
+        println!("  tlb set <pid> <vpn> <ppn> write a TLB entry (ppn includes flag bits)");
+        println!("  tlb inv <pid> <vpn> invalidate a TLB entry");
+        println!("  tlb clear         remove all TLB entries");
+        println!("  info memstats     guest load/store counts per region and hottest RAM pages");
+        println!("  info tlbstats     TLB hits, misses by access type, writes and evictions");
+        println!("  info profile      reads/writes/fetches for every touched RAM page");
+        println!("  info count        instructions retired (cr13/cr14) and ticks");
+        println!("  info io           scroll/scale/VGA/clock/PIT/SD registers and pending IRQs");
+        println!("  info json         registers, TLB and device registers as JSON");
+        println!("  info memmap       physical memory map: RAM and device regions");
         println!("  info p <addr>     print word at physical address");
         println!("  info v <addr>     print word + resolved physical address");
-        println!("  x [v|p] <addr> <len> dump memory range");
+        println!("  x [v|p] <addr> <len> dump memory range as bytes and ASCII");
+        println!("  x/w [v|p] <addr> <count> dump memory as 32-bit words");
+        println!("  x/s [v|p] <addr> [maxlen] print a NUL-terminated string");
+        println!("  x/i [addr] [count] same as disas");
+        println!("  dump [v|p] <addr> <len> <file> write raw bytes of a range to a file");
+        println!("  loadbin [v|p] <addr> <file> copy a file's bytes into memory");
+        println!("  fill [v|p] <addr> <len> <byte> write a byte across a range");
+        println!(
+            "  find [v|p] <addr> <len> <pattern> search a range for hex bytes or a \"string\""
+        );
+        println!("  zero [v|p] <addr> <len> clear a range");
+        println!("  disas [addr] [count] disassemble (default: pc, 8 instructions)");
+        println!("  bt [depth]        backtrace via the r30 frame chain (default 32 frames)");
         println!("  set reg <reg> <value> write a register");
+        println!("  set str <addr> \"text\" write a NUL-terminated string");
+        println!("  set mem [v|p] <addr> <value> [1|2|4] write a byte/halfword/word");
+        println!("  set diffregs on|off print the registers each step changes");
+        println!("  int <n>           raise interrupt n (0-15) as if its device fired");
+        println!("  save <file>       save CPU, TLB, PIT and RAM state to a snapshot file");
+        println!("  load <file>       restore a snapshot saved with `save`");
+        println!("  source <file>     run debugger commands from a file");
         println!("  q                 quit");
 
+        let mut prompt = PromptReader::new();
         loop {
-            print!("dbg> ");
-            io::stdout().flush().unwrap();
-
-            let mut line = String::new();
-            if io::stdin().read_line(&mut line).is_err() {
-                break;
-            }
+            // Script commands are echoed so the transcript reads like a session.
+            let line = match pending.pop_front() {
+                Some(command) => {
+                    println!("dbg> {}", command);
+                    command
+                }
+                None => match prompt.read_line("dbg> ") {
+                    Some(line) => line,
+                    None => break,
+                },
+            };
             let line = line.trim();
             if line.is_empty() {
                 continue;
             }
+            let aliased = creg_watch_alias(line);
+            let line = aliased.as_deref().unwrap_or(line);
 
             let mut parts = line.split_whitespace();
             let cmd = parts.next().unwrap();
@@ -984,94 +2450,275 @@ impl Emulator {
                 "q" | "quit" => break,
                 "h" | "help" => {
                     println!("Commands:");
-                    println!("  r                 reset and run until break/watchpoint/halt");
+                    println!(
+                        "  r                 cold reset (reload program) and run until break/watchpoint/halt"
+                    );
+                    println!(
+                        "  reset             warm reset: CPU state only; RAM, SD and devices are kept"
+                    );
                     println!("  c                 continue execution");
-                    println!("  n                 step one instruction");
+                    println!(
+                        "  n | si [count] [all] step instructions (default 1); `all` prints each one"
+                    );
+                    println!(
+                        "  back | rn [count] undo the last instruction(s) (registers, TLB, PIT, RAM)"
+                    );
+                    println!("  next | so         step over: run a call until it returns");
+                    println!(
+                        "  finish | out      step out: run until return to the address in ra (r29)"
+                    );
+                    println!("  until <label|addr> run until pc first reaches the target");
                     println!("  break <label|addr> set breakpoint");
+                    println!(
+                        "  break <label|addr> if <reg> <==|!=|<|>> <value> conditional breakpoint"
+                    );
                     println!("  breaks            list breakpoints");
                     println!("  delete <label|addr> remove breakpoint");
-                    println!("  watch [r|w|rw] <addr> stop on memory access");
+                    println!(
+                        "  watch [r|w|rw] <addr> [len] [op value] stop on access to a byte or range (optionally value-matched)"
+                    );
                     println!("  watchs            list watchpoints");
                     println!("  unwatch <addr>    remove watchpoint");
+                    println!("  cwatch <crN|name> stop when a control register changes");
+                    println!("  watch cr <n|name> same as cwatch <crN|name>");
+                    println!("  cwatchs           list control-register watches");
+                    println!("  uncwatch <crN|name> remove control-register watch");
+                    println!(
+                        "  catch [<exc>]     stop when an exception or interrupt is taken; no arg lists"
+                    );
+                    println!("  uncatch <exc>     stop catching an exception or interrupt");
+                    println!(
+                        "  display [<reg>|[v|p] <addr>] print a register or word after every stop"
+                    );
+                    println!("  undisplay <id>    remove a display");
                     println!("  info regs         print all registers");
                     println!("  info cregs        print control registers + kmode");
                     println!("  info <reg>        print a single register");
                     println!("  info tlb          dump TLB maps");
+                    println!("  tlb read <pid> <vpn> print the TLB entry for a page");
+                    println!(
+                        "  tlb set <pid> <vpn> <ppn> write a TLB entry (ppn includes flag bits)"
+                    );
+                    println!("  tlb inv <pid> <vpn> invalidate a TLB entry");
+                    println!("  tlb clear         remove all TLB entries");
+                    println!(
+                        "  info memstats     guest load/store counts per region and hottest RAM pages"
+                    );
+                    println!(
+                        "  info tlbstats     TLB hits, misses by access type, writes and evictions"
+                    );
+                    println!("  info profile      reads/writes/fetches for every touched RAM page");
+                    println!("  info count        instructions retired (cr13/cr14) and ticks");
+                    println!(
+                        "  info io           scroll/scale/VGA/clock/PIT/SD registers and pending IRQs"
+                    );
+                    println!("  info json         registers, TLB and device registers as JSON");
+                    println!("  info memmap       physical memory map: RAM and device regions");
                     println!("  info p <addr>     print word at physical address");
                     println!("  info v <addr>     print word + resolved physical address");
-                    println!("  x [v|p] <addr> <len> dump memory range");
+                    println!("  x [v|p] <addr> <len> dump memory range as bytes and ASCII");
+                    println!("  x/w [v|p] <addr> <count> dump memory as 32-bit words");
+                    println!("  x/s [v|p] <addr> [maxlen] print a NUL-terminated string");
+                    println!("  x/i [addr] [count] same as disas");
+                    println!(
+                        "  dump [v|p] <addr> <len> <file> write raw bytes of a range to a file"
+                    );
+                    println!("  loadbin [v|p] <addr> <file> copy a file's bytes into memory");
+                    println!("  fill [v|p] <addr> <len> <byte> write a byte across a range");
+                    println!(
+                        "  find [v|p] <addr> <len> <pattern> search a range for hex bytes or a \"string\""
+                    );
+                    println!("  zero [v|p] <addr> <len> clear a range");
+                    println!("  disas [addr] [count] disassemble (default: pc, 8 instructions)");
+                    println!(
+                        "  bt [depth]        backtrace via the r30 frame chain (default 32 frames)"
+                    );
                     println!("  set reg <reg> <value> write a register");
+                    println!("  set str <addr> \"text\" write a NUL-terminated string");
+                    println!("  set mem [v|p] <addr> <value> [1|2|4] write a byte/halfword/word");
+                    println!("  set diffregs on|off print the registers each step changes");
+                    println!("  int <n>           raise interrupt n (0-15) as if its device fired");
+                    println!(
+                        "  save <file>       save CPU, TLB, PIT and RAM state to a snapshot file"
+                    );
+                    println!("  load <file>       restore a snapshot saved with `save`");
+                    println!("  source <file>     run debugger commands from a file");
                     println!("  q                 quit");
                 }
                 "r" => {
-                    cpu = Emulator::from_instructions(
+                    cpu = Emulator::from_instructions_with_config(
                         image.instructions.clone(),
                         use_uart_rx,
                         sd_dma_ticks_per_word,
                         sd0_image,
                         sd1_image,
+                        config,
                     );
+                    cpu.set_entry(image.entry);
                     cpu.set_watchpoints(&watchpoints);
-                    match run_until_breakpoint(&mut cpu, &breakpoints) {
-                        RunOutcome::Breakpoint(addr) => {
-                            print_breakpoint(addr, &labels_by_addr, &mut cpu);
-                        }
-                        RunOutcome::Halted => {
-                            println!("Program halted. r1 = {:08X}", cpu.regfile[1]);
-                        }
-                        RunOutcome::Watchpoint(hit) => {
-                            print_watchpoint_hit(hit, cpu.pc);
+                    cpu.creg_watches.clone_from(&creg_watches);
+                    cpu.catches.clone_from(&catches);
+                    cpu.set_undo_depth(config.undo_depth);
+                    let outcome = run_until_breakpoint(&mut cpu, &breakpoints);
+                    print_run_outcome(outcome, &labels_by_addr, &mut cpu);
+                }
+                "reset" | "warm-reset" => {
+                    cpu.reset();
+                    println!("CPU reset (warm); memory preserved. pc = {:08X}", cpu.pc);
+                }
+                "c" => {
+                    let outcome = run_until_breakpoint(&mut cpu, &breakpoints);
+                    print_run_outcome(outcome, &labels_by_addr, &mut cpu);
+                }
+                "n" | "si" => {
+                    let count = match parts.next() {
+                        Some(token) => match token.parse::<u32>() {
+                            Ok(count) if count >= 1 => count,
+                            _ => {
+                                println!("Usage: n [count] [all]");
+                                continue;
+                            }
+                        },
+                        None => 1,
+                    };
+                    let print_all = match parts.next() {
+                        Some("all") => true,
+                        Some(_) => {
+                            println!("Usage: n [count] [all]");
+                            continue;
                         }
+                        None => false,
+                    };
+                    if cpu.halted {
+                        println!("Program already halted.");
+                        continue;
                     }
+                    step_count(
+                        &mut cpu,
+                        &breakpoints,
+                        count,
+                        print_all,
+                        diff_regs,
+                        &labels_by_addr,
+                    );
                 }
-                "c" => match run_until_breakpoint(&mut cpu, &breakpoints) {
-                    RunOutcome::Breakpoint(addr) => {
-                        print_breakpoint(addr, &labels_by_addr, &mut cpu);
+                "back" | "rn" => {
+                    let count = match parts.next() {
+                        Some(token) => match token.parse::<usize>() {
+                            Ok(count) if count >= 1 => count,
+                            _ => {
+                                println!("Usage: back [count]");
+                                continue;
+                            }
+                        },
+                        None => 1,
+                    };
+                    let mut undone = 0;
+                    while undone < count && cpu.step_back() {
+                        undone += 1;
                     }
-                    RunOutcome::Halted => {
-                        println!("Program halted. r1 = {:08X}", cpu.regfile[1]);
+                    if undone == 0 {
+                        println!("No history to step back through.");
+                        continue;
                     }
-                    RunOutcome::Watchpoint(hit) => {
-                        print_watchpoint_hit(hit, cpu.pc);
+                    if undone < count {
+                        println!(
+                            "Stepped back {} of {} instructions; history exhausted.",
+                            undone, count
+                        );
                     }
-                },
-                "n" => {
+                    let pc = cpu.pc;
+                    for line in disas_lines(&mut cpu, pc, 1, &labels_by_addr) {
+                        println!("{}", line);
+                    }
+                }
+                "next" | "so" => {
                     if cpu.halted {
                         println!("Program already halted.");
                         continue;
                     }
-
-                    match cpu.step_instruction() {
-                        StepOutcome::Executed { pc, instr } => {
-                            print_step(pc, instr, &labels_by_addr);
-                            if let Some(hit) = cpu.take_watchpoint_hit() {
-                                print_watchpoint_hit(hit, cpu.pc);
-                            }
-                            if cpu.halted {
-                                println!("Program halted. r1 = {:08X}", cpu.regfile[1]);
-                            }
-                        }
-                        StepOutcome::Sleeping => {
-                            println!("CPU sleeping; waiting for interrupt.");
+                    let pc = cpu.pc;
+                    if cpu.peek_instr(pc).is_some_and(is_linking_branch) {
+                        let frame_sp = cpu.get_reg(SP_REG);
+                        let outcome =
+                            run_to_return(&mut cpu, &breakpoints, pc.wrapping_add(4), frame_sp);
+                        print_run_outcome(outcome, &labels_by_addr, &mut cpu);
+                    } else {
+                        step_and_print(&mut cpu, diff_regs, &labels_by_addr);
+                    }
+                }
+                "until" => {
+                    if cpu.halted {
+                        println!("Program already halted.");
+                        continue;
+                    }
+                    let Some(target) = parts.next() else {
+                        println!("Usage: until <label|addr>");
+                        continue;
+                    };
+                    let addr = match resolve_label_or_addr(target, &image.labels) {
+                        Ok(addrs) if addrs.len() == 1 => addrs[0],
+                        Ok(addrs) => {
+                            println!("Ambiguous label {} -> {}", target, format_addr_list(&addrs));
+                            continue;
                         }
-                        StepOutcome::TlbMiss { pc } => {
-                            println!("TLB miss at {:08X}", pc);
+                        Err(msg) => {
+                            println!("{}", msg);
+                            continue;
                         }
+                    };
+                    let outcome = run_until_target(&mut cpu, &mut breakpoints, addr);
+                    if matches!(outcome, RunOutcome::Halted) {
+                        println!("Target {:08X} not reached.", addr);
                     }
+                    print_run_outcome(outcome, &labels_by_addr, &mut cpu);
+                }
+                "finish" | "out" => {
+                    if cpu.halted {
+                        println!("Program already halted.");
+                        continue;
+                    }
+                    let ret_addr = cpu.get_reg(RA_REG);
+                    let frame_sp = cpu.get_reg(SP_REG);
+                    println!("Run till return to {:08X}", ret_addr);
+                    let outcome = run_to_return(&mut cpu, &breakpoints, ret_addr, frame_sp);
+                    print_run_outcome(outcome, &labels_by_addr, &mut cpu);
                 }
                 "break" | "b" => {
                     let target = parts.next();
                     if target.is_none() {
-                        println!("Usage: break <label|addr>");
+                        println!("Usage: break <label|addr> [if <reg> <==|!=|<|>> <value>]");
                         continue;
                     }
                     let target = target.unwrap();
+                    let condition = match parts.next() {
+                        None => None,
+                        Some("if") => {
+                            let tokens: Vec<&str> = parts.by_ref().collect();
+                            match parse_break_condition(&tokens) {
+                                Ok(cond) => Some(cond),
+                                Err(msg) => {
+                                    println!("{}", msg);
+                                    continue;
+                                }
+                            }
+                        }
+                        Some(_) => {
+                            println!("Usage: break <label|addr> [if <reg> <==|!=|<|>> <value>]");
+                            continue;
+                        }
+                    };
                     match resolve_label_or_addr(target, &image.labels) {
                         Ok(addrs) => {
                             if addrs.len() == 1 {
                                 let addr = addrs[0];
-                                breakpoints.insert(addr);
-                                println!("Breakpoint set at {:08X}", addr);
+                                breakpoints.insert(addr, condition);
+                                match condition {
+                                    Some(cond) => {
+                                        println!("Breakpoint set at {:08X} if {}", addr, cond)
+                                    }
+                                    None => println!("Breakpoint set at {:08X}", addr),
+                                }
                             } else {
                                 println!(
                                     "Ambiguous label {} -> {}",
@@ -1104,20 +2751,35 @@ impl Emulator {
                         }
                     }
                     let Some(addr_str) = addr_token else {
-                        println!("Usage: watch [r|w|rw] <addr>");
+                        println!("Usage: {}", WATCH_USAGE);
                         continue;
                     };
                     let Some(addr) = parse_addr(addr_str) else {
                         println!("Invalid address {}", addr_str);
                         continue;
                     };
-                    let final_kind = add_watchpoint(&mut watchpoints, addr, kind);
+                    let mut rest: Vec<&str> = parts.collect();
+                    // An optional length comes before the condition.
+                    let mut len = 1;
+                    if let Some(parsed) = rest.first().and_then(|token| parse_addr(token)) {
+                        if parsed == 0 || u64::from(addr) + u64::from(parsed) > 1 << 32 {
+                            println!("Invalid length {}", rest[0]);
+                            continue;
+                        }
+                        len = parsed;
+                        rest.remove(0);
+                    }
+                    let cond = match parse_watch_condition(&rest) {
+                        Ok(cond) => cond,
+                        Err(msg) => {
+                            println!("{}", msg);
+                            println!("Usage: {}", WATCH_USAGE);
+                            continue;
+                        }
+                    };
+                    let wp = add_watchpoint(&mut watchpoints, addr, len, kind, cond);
                     cpu.set_watchpoints(&watchpoints);
-                    println!(
-                        "Watchpoint set at {:08X} ({})",
-                        addr,
-                        watch_kind_label(final_kind)
-                    );
+                    println!("Watchpoint set at {}", describe_watchpoint(&wp));
                 }
                 "watchs" | "watchpoints" => {
                     list_watchpoints(&watchpoints);
@@ -1138,7 +2800,126 @@ impl Emulator {
                         println!("No watchpoint set at {:08X}", addr);
                     }
                 }
-                "x" => {
+                "cwatch" => {
+                    let Some(token) = parts.next() else {
+                        println!("Usage: cwatch <crN|name>");
+                        continue;
+                    };
+                    let Some(idx) = parse_creg(token) else {
+                        println!("Unknown control register {}", token);
+                        continue;
+                    };
+                    if !creg_watches.contains(&idx) {
+                        creg_watches.push(idx);
+                    }
+                    cpu.creg_watches.clone_from(&creg_watches);
+                    println!("Control register watch set on {}", format_creg(idx));
+                }
+                "cwatchs" => {
+                    list_creg_watches(&creg_watches);
+                }
+                "uncwatch" => {
+                    let Some(token) = parts.next() else {
+                        println!("Usage: uncwatch <crN|name>");
+                        continue;
+                    };
+                    let Some(idx) = parse_creg(token) else {
+                        println!("Unknown control register {}", token);
+                        continue;
+                    };
+                    let before = creg_watches.len();
+                    creg_watches.retain(|watched| *watched != idx);
+                    cpu.creg_watches.clone_from(&creg_watches);
+                    if creg_watches.len() != before {
+                        println!("Control register watch removed on {}", format_creg(idx));
+                    } else {
+                        println!("No control register watch on {}", format_creg(idx));
+                    }
+                }
+                "catch" => {
+                    let Some(token) = parts.next() else {
+                        list_catches(&catches);
+                        continue;
+                    };
+                    let Some(kind) = CatchKind::parse(token) else {
+                        println!("Usage: {}", CATCH_USAGE);
+                        continue;
+                    };
+                    if !catches.contains(&kind) {
+                        catches.push(kind);
+                    }
+                    cpu.catches.clone_from(&catches);
+                    println!("Catching {}", kind.name());
+                }
+                "uncatch" => {
+                    let Some(kind) = parts.next().and_then(CatchKind::parse) else {
+                        println!("Usage: un{}", CATCH_USAGE);
+                        continue;
+                    };
+                    let before = catches.len();
+                    catches.retain(|caught| *caught != kind);
+                    cpu.catches.clone_from(&catches);
+                    if catches.len() != before {
+                        println!("No longer catching {}", kind.name());
+                    } else {
+                        println!("Not catching {}", kind.name());
+                    }
+                }
+                "display" => {
+                    let expr = match (parts.next(), parts.next()) {
+                        (None, _) => {
+                            list_displays(&displays);
+                            continue;
+                        }
+                        (Some(mode @ ("v" | "p")), Some(addr_str)) => {
+                            let Some(addr) = parse_addr(addr_str) else {
+                                println!("Invalid address {}", addr_str);
+                                continue;
+                            };
+                            if mode == "p" {
+                                DisplayExpr::Phys(addr)
+                            } else {
+                                DisplayExpr::Virt(addr)
+                            }
+                        }
+                        (Some(token), None) if cpu.format_single_reg(token).is_some() => {
+                            DisplayExpr::Reg(token.to_ascii_lowercase())
+                        }
+                        (Some(token), None) if token != "v" && token != "p" => {
+                            let Some(addr) = parse_addr(token) else {
+                                println!("Unknown register or address {}", token);
+                                continue;
+                            };
+                            DisplayExpr::Virt(addr)
+                        }
+                        _ => {
+                            println!("Usage: display [<reg>|[v|p] <addr>]");
+                            continue;
+                        }
+                    };
+                    displays.push((next_display_id, expr));
+                    next_display_id += 1;
+                    print_displays(&mut cpu, &displays[displays.len() - 1..]);
+                }
+                "undisplay" => {
+                    let Some(id) = parts.next().and_then(|token| token.parse::<u32>().ok()) else {
+                        println!("Usage: undisplay <id>");
+                        continue;
+                    };
+                    let before = displays.len();
+                    displays.retain(|(display_id, _)| *display_id != id);
+                    if displays.len() != before {
+                        println!("Display {} removed", id);
+                    } else {
+                        println!("No display {}", id);
+                    }
+                }
+                "x" | "x/b" | "x/w" | "x/s" => {
+                    let (format, usage) = match cmd {
+                        "x/w" => (DumpFormat::Words, "Usage: x/w [v|p] <addr> <count>"),
+                        "x/s" => (DumpFormat::String, "Usage: x/s [v|p] <addr> [maxlen]"),
+                        _ => (DumpFormat::Bytes, "Usage: x [v|p] <addr> <len>"),
+                    };
                     let mut mode = "v";
                     let mut addr_token = parts.next();
                     if let Some(token) = addr_token {
@@ -1148,31 +2929,442 @@ impl Emulator {
                         }
                     }
                     let Some(addr_str) = addr_token else {
-                        println!("Usage: x [v|p] <addr> <len>");
+                        println!("{}", usage);
+                        continue;
+                    };
+                    let Some(addr) = parse_addr(addr_str) else {
+                        println!("Invalid address {}", addr_str);
+                        continue;
+                    };
+                    let len = match (parts.next(), format) {
+                        (Some(len_str), _) => match parse_addr(len_str) {
+                            Some(len) => len,
+                            None => {
+                                println!("Invalid length {}", len_str);
+                                continue;
+                            }
+                        },
+                        (None, DumpFormat::String) => DUMP_STRING_DEFAULT_LEN,
+                        (None, _) => {
+                            println!("{}", usage);
+                            continue;
+                        }
+                    };
+                    // x/w counts words; the byte limit applies to all formats.
+                    let unit = if format == DumpFormat::Words { 4 } else { 1 };
+                    let clamped = clamp_dump_len(addr, len.saturating_mul(unit)) / unit;
+                    if clamped != len {
+                        println!(
+                            "Warning: length {} truncated to {} (limit {} bytes, no wrap past FFFFFFFF)",
+                            len, clamped, MAX_DUMP_BYTES
+                        );
+                    }
+                    let len = clamped;
+                    let order = cpu.endianness;
+                    let read_byte = |a: u32| {
+                        let paddr = if mode == "p" {
+                            a
+                        } else {
+                            cpu.virt_to_phys_debug(a)?
+                        };
+                        cpu.read_phys8_debug(paddr).map(|val| (paddr, val))
+                    };
+                    let lines = match format {
+                        DumpFormat::Bytes => byte_dump_lines(addr, len, read_byte),
+                        DumpFormat::Words => word_dump_lines(addr, len, order, read_byte),
+                        DumpFormat::String => vec![string_dump_line(addr, len, read_byte)],
+                    };
+                    for line in lines {
+                        println!("{}", line);
+                    }
+                }
+                "fill" | "zero" => {
+                    let usage = if cmd == "fill" {
+                        "Usage: fill [v|p] <addr> <len> <byte>"
+                    } else {
+                        "Usage: zero [v|p] <addr> <len>"
+                    };
+                    let mut physical = false;
+                    let mut addr_token = parts.next();
+                    if let Some(token @ ("v" | "p")) = addr_token {
+                        physical = token == "p";
+                        addr_token = parts.next();
+                    }
+                    let (Some(addr_str), Some(len_str)) = (addr_token, parts.next()) else {
+                        println!("{}", usage);
+                        continue;
+                    };
+                    let byte_str = if cmd == "fill" {
+                        parts.next()
+                    } else {
+                        Some("0")
+                    };
+                    let Some(byte_str) = byte_str else {
+                        println!("{}", usage);
+                        continue;
+                    };
+                    let Some(addr) = parse_addr(addr_str) else {
+                        println!("Invalid address {}", addr_str);
+                        continue;
+                    };
+                    let Some(len) = parse_addr(len_str) else {
+                        println!("Invalid length {}", len_str);
+                        continue;
+                    };
+                    let Some(byte) = parse_addr(byte_str).and_then(|b| u8::try_from(b).ok()) else {
+                        println!("Invalid byte {}", byte_str);
+                        continue;
+                    };
+                    if len == 0 || addr.checked_add(len - 1).is_none() {
+                        println!(
+                            "Invalid length {}: the range must fit below FFFFFFFF",
+                            len_str
+                        );
+                        continue;
+                    }
+                    println!("{}", fill_range_debug(&mut cpu, physical, addr, len, byte));
+                }
+                "find" => {
+                    let mut physical = false;
+                    let mut skip = 3;
+                    let mut addr_token = parts.next();
+                    if let Some(token @ ("v" | "p")) = addr_token {
+                        physical = token == "p";
+                        skip += 1;
+                        addr_token = parts.next();
+                    }
+                    let pattern_text = command_rest(line, skip);
+                    let (Some(addr_str), Some(len_str)) = (addr_token, parts.next()) else {
+                        println!("Usage: find [v|p] <addr> <len> <hex bytes|\"string\">");
+                        continue;
+                    };
+                    let Some(addr) = parse_addr(addr_str) else {
+                        println!("Invalid address {}", addr_str);
+                        continue;
+                    };
+                    let Some(len) = parse_addr(len_str) else {
+                        println!("Invalid length {}", len_str);
+                        continue;
+                    };
+                    if len == 0 || addr.checked_add(len - 1).is_none() {
+                        println!(
+                            "Invalid length {}: the range must fit below FFFFFFFF",
+                            len_str
+                        );
+                        continue;
+                    }
+                    let pattern = match parse_find_pattern(pattern_text) {
+                        Ok(pattern) => pattern,
+                        Err(msg) => {
+                            println!("{}", msg);
+                            println!("Usage: find [v|p] <addr> <len> <hex bytes|\"string\">");
+                            continue;
+                        }
+                    };
+                    let (found, total) =
+                        find_pattern_debug(&mut cpu, physical, addr, len, &pattern);
+                    for match_addr in &found {
+                        println!("Found at {:08X}", match_addr);
+                    }
+                    if total == 0 {
+                        println!("Pattern not found");
+                    } else if total > found.len() {
+                        println!("{} matches; first {} shown", total, found.len());
+                    }
+                }
+                "dump" => {
+                    let mut physical = false;
+                    let mut skip = 3;
+                    let mut addr_token = parts.next();
+                    if let Some(token @ ("v" | "p")) = addr_token {
+                        physical = token == "p";
+                        skip += 1;
+                        addr_token = parts.next();
+                    }
+                    let path = command_rest(line, skip);
+                    let (Some(addr_str), Some(len_str)) = (addr_token, parts.next()) else {
+                        println!("Usage: dump [v|p] <addr> <len> <file>");
+                        continue;
+                    };
+                    if path.is_empty() {
+                        println!("Usage: dump [v|p] <addr> <len> <file>");
+                        continue;
+                    }
+                    let Some(addr) = parse_addr(addr_str) else {
+                        println!("Invalid address {}", addr_str);
+                        continue;
+                    };
+                    let Some(len) = parse_addr(len_str) else {
+                        println!("Invalid length {}", len_str);
+                        continue;
+                    };
+                    if len == 0 || addr.checked_add(len - 1).is_none() {
+                        println!(
+                            "Invalid length {}: the range must fit below FFFFFFFF",
+                            len_str
+                        );
+                        continue;
+                    }
+                    let written = File::create(path).and_then(|mut file| {
+                        dump_range_debug(&mut cpu, physical, addr, len, &mut file)
+                    });
+                    match written {
+                        Ok(0) => {
+                            println!("Wrote {} bytes from {:08X} to {}", len, addr, path)
+                        }
+                        Ok(unmapped) => println!(
+                            "Wrote {} bytes from {:08X} to {} ({} unmapped bytes written as 0)",
+                            len, addr, path, unmapped
+                        ),
+                        Err(err) => println!("Failed to write {}: {}", path, err),
+                    }
+                }
+                "loadbin" => {
+                    let mut physical = false;
+                    let mut skip = 2;
+                    let mut addr_token = parts.next();
+                    if let Some(token @ ("v" | "p")) = addr_token {
+                        physical = token == "p";
+                        skip += 1;
+                        addr_token = parts.next();
+                    }
+                    let path = command_rest(line, skip);
+                    let Some(addr_str) = addr_token else {
+                        println!("Usage: loadbin [v|p] <addr> <file>");
                         continue;
                     };
-                    let Some(len_str) = parts.next() else {
-                        println!("Usage: x [v|p] <addr> <len>");
+                    if path.is_empty() {
+                        println!("Usage: loadbin [v|p] <addr> <file>");
                         continue;
-                    };
+                    }
                     let Some(addr) = parse_addr(addr_str) else {
                         println!("Invalid address {}", addr_str);
                         continue;
                     };
-                    let Some(len) = parse_addr(len_str) else {
-                        println!("Invalid length {}", len_str);
-                        continue;
+                    let bytes = match fs::read(path) {
+                        Ok(bytes) => bytes,
+                        Err(err) => {
+                            println!("Failed to read {}: {}", path, err);
+                            continue;
+                        }
                     };
-                    if mode == "p" {
-                        dump_bytes(addr, len, |a| cpu.read_phys8_debug(a));
+                    let written = write_range_debug(&mut cpu, physical, addr, &bytes);
+                    if written == bytes.len() {
+                        println!("Loaded {} bytes at {:08X} from {}", written, addr, path);
                     } else {
-                        dump_bytes(addr, len, |a| cpu.read_virt8_debug(a));
+                        println!(
+                            "Loaded {} of {} bytes at {:08X}; no {} memory at 0x{:08X}",
+                            written,
+                            bytes.len(),
+                            addr,
+                            if physical { "physical" } else { "mapped" },
+                            addr.wrapping_add(written as u32)
+                        );
+                    }
+                }
+                "int" => {
+                    let bit = match parts.next().and_then(parse_addr) {
+                        Some(bit) if cpu.raise_interrupt(bit) => bit,
+                        _ => {
+                            println!("Usage: int <n>  (n is 0-15)");
+                            continue;
+                        }
+                    };
+                    println!("Raised interrupt {} (vector {:02X})", bit, 0xF0 + bit);
+                    let imr = cpu.cregfile[CREG_IMR];
+                    if imr >> 31 == 0 || imr & (1 << bit) == 0 {
+                        println!("Pending until IMR enables it (imr {:08X})", imr);
+                    }
+                }
+                "tlb" => {
+                    let args: Vec<&str> = parts.by_ref().collect();
+                    match tlb_command(&mut cpu, &args) {
+                        Ok(msg) | Err(msg) => println!("{}", msg),
+                    }
+                }
+                "disas" | "x/i" => {
+                    let addr = match parts.next() {
+                        Some(token) => match parse_addr(token) {
+                            Some(addr) => addr,
+                            None => {
+                                println!("Usage: disas [addr] [count]");
+                                continue;
+                            }
+                        },
+                        None => cpu.pc,
+                    };
+                    let count = match parts.next() {
+                        Some(token) => match parse_addr(token) {
+                            Some(count) if count > 0 => count,
+                            _ => {
+                                println!("Usage: disas [addr] [count]");
+                                continue;
+                            }
+                        },
+                        None => DISAS_DEFAULT_COUNT,
+                    };
+                    if count > DISAS_MAX_COUNT {
+                        println!("Warning: count {} truncated to {}", count, DISAS_MAX_COUNT);
+                    }
+                    for line in
+                        disas_lines(&mut cpu, addr, count.min(DISAS_MAX_COUNT), &labels_by_addr)
+                    {
+                        println!("{}", line);
+                    }
+                }
+                "save" => {
+                    let path = command_rest(line, 1);
+                    if path.is_empty() {
+                        println!("Usage: save <file>");
+                        continue;
+                    }
+                    let result = File::create(path).and_then(|file| {
+                        let mut out = io::BufWriter::new(file);
+                        cpu.save_snapshot().write_to(&mut out)?;
+                        out.flush()
+                    });
+                    match result {
+                        Ok(()) => println!("Saved snapshot to {}", path),
+                        Err(err) => println!("Failed to save {}: {}", path, err),
+                    }
+                }
+                "source" => {
+                    let path = command_rest(line, 1);
+                    if path.is_empty() {
+                        println!("Usage: source <file>");
+                        continue;
+                    }
+                    match read_debug_script(path) {
+                        // Queue ahead of any remaining commands so a nested
+                        // script finishes before its caller continues.
+                        Ok(commands) => {
+                            for command in commands.into_iter().rev() {
+                                pending.push_front(command);
+                            }
+                        }
+                        Err(err) => println!("{}", err),
+                    }
+                }
+                "load" => {
+                    let path = command_rest(line, 1);
+                    if path.is_empty() {
+                        println!("Usage: load <file>");
+                        continue;
+                    }
+                    let result = File::open(path).and_then(|file| {
+                        EmulatorSnapshot::read_from(&mut io::BufReader::new(file))
+                    });
+                    match result {
+                        Ok(snap) => {
+                            cpu.load_snapshot(&snap);
+                            println!("Loaded snapshot from {}; pc = {:08X}", path, cpu.pc);
+                        }
+                        Err(err) => println!("Failed to load {}: {}", path, err),
+                    }
+                }
+                "bt" => {
+                    let depth = match parts.next() {
+                        Some(token) => match token.parse::<usize>() {
+                            Ok(depth) if depth > 0 => depth,
+                            _ => {
+                                println!("Usage: bt [depth]");
+                                continue;
+                            }
+                        },
+                        None => BT_DEFAULT_DEPTH,
+                    };
+                    let (frames, stopped) = backtrace_frames(&mut cpu, depth);
+                    for (index, pc) in frames.iter().enumerate() {
+                        println!("{}", format_frame(index, *pc, &labels_by_addr));
+                    }
+                    if let Some(reason) = stopped {
+                        println!("Backtrace stopped: {}", reason);
                     }
                 }
                 "set" => {
                     let sub = parts.next();
+                    if sub == Some("str") {
+                        let Some(addr_str) = parts.next() else {
+                            println!("Usage: set str <addr> \"text\"");
+                            continue;
+                        };
+                        let Some(addr) = parse_addr(addr_str) else {
+                            println!("Invalid address {}", addr_str);
+                            continue;
+                        };
+                        let bytes = match parse_string_literal(command_rest(line, 3)) {
+                            Ok(bytes) => bytes,
+                            Err(msg) => {
+                                println!("{}", msg);
+                                continue;
+                            }
+                        };
+                        let written = cpu.write_virt_bytes_debug(addr, &bytes);
+                        if written == bytes.len() {
+                            println!("Wrote {} bytes at {:08X}", written, addr);
+                        } else {
+                            println!(
+                                "Wrote {} of {} bytes at {:08X}; no mapping for vaddr 0x{:08X}",
+                                written,
+                                bytes.len(),
+                                addr,
+                                addr.wrapping_add(written as u32)
+                            );
+                        }
+                        continue;
+                    }
+                    if sub == Some("mem") {
+                        let mut physical = false;
+                        let mut addr_token = parts.next();
+                        if let Some(token @ ("v" | "p")) = addr_token {
+                            physical = token == "p";
+                            addr_token = parts.next();
+                        }
+                        let (Some(addr_str), Some(value_str)) = (addr_token, parts.next()) else {
+                            println!("Usage: set mem [v|p] <addr> <value> [1|2|4]");
+                            continue;
+                        };
+                        let Some(addr) = parse_addr(addr_str) else {
+                            println!("Invalid address {}", addr_str);
+                            continue;
+                        };
+                        let Some(value) = parse_addr(value_str) else {
+                            println!("Invalid value {}", value_str);
+                            continue;
+                        };
+                        let size = match parts.next() {
+                            Some(token) => match parse_mem_size(token) {
+                                Some(size) => size,
+                                None => {
+                                    println!("Usage: set mem [v|p] <addr> <value> [1|2|4]");
+                                    continue;
+                                }
+                            },
+                            None => 4,
+                        };
+                        match poke_mem(&mut cpu, physical, addr, value, size) {
+                            Ok(()) => println!("Wrote {} bytes at {:08X}", size, addr),
+                            Err(msg) => println!("{}", msg),
+                        }
+                        continue;
+                    }
+                    if sub == Some("diffregs") {
+                        match parts.next() {
+                            Some("on") => diff_regs = true,
+                            Some("off") => diff_regs = false,
+                            _ => {
+                                println!("Usage: set diffregs on|off");
+                                continue;
+                            }
+                        }
+                        println!(
+                            "Register diffs after steps {}",
+                            if diff_regs { "on" } else { "off" }
+                        );
+                        continue;
+                    }
                     if sub != Some("reg") {
-                        println!("Usage: set reg <reg> <value>");
+                        println!("Usage: set <reg|str|mem|diffregs> ...");
                         continue;
                     }
                     let Some(reg_name) = parts.next() else {
@@ -1195,6 +3387,13 @@ impl Emulator {
                     Some("regs") => cpu.print_regs(),
                     Some("cregs") => cpu.print_cregs(),
                     Some("tlb") => cpu.print_tlb(),
+                    Some("memstats") => cpu.print_memstats(),
+                    Some("tlbstats") => println!("{}", cpu.tlb.stats()),
+                    Some("profile") => cpu.print_profile(),
+                    Some("count") => cpu.print_count(),
+                    Some("io") => cpu.print_io(),
+                    Some("memmap") => cpu.print_memmap(),
+                    Some("json") => println!("{}", cpu.state_json()),
                     Some("p") => {
                         if let Some(arg) = parts.next() {
                             if let Some(addr) = parse_addr(arg) {
@@ -1222,12 +3421,23 @@ impl Emulator {
                             println!("Unknown info target {}", token);
                         }
                     }
-                    None => println!("Usage: info <regs|cregs|tlb|p|v|reg>"),
+                    None => println!(
+                        "Usage: info <regs|cregs|tlb|memstats|tlbstats|profile|count|json|p|v|reg>"
+                    ),
                 },
                 _ => println!("Unknown command: {}", cmd),
             }
+
+            // Commands that run or rewind the program end at a new stop.
+            if matches!(
+                cmd,
+                "r" | "c" | "n" | "si" | "back" | "rn" | "next" | "so" | "until" | "finish" | "out"
+            ) {
+                print_displays(&mut cpu, &displays);
+            }
         }
 
+        cpu.memory.flush_output();
         cpu
     }
 
@@ -1237,8 +3447,9 @@ impl Emulator {
         sd_dma_ticks_per_word: u32,
         sd0_image: Option<&[u8]>,
         sd1_image: Option<&[u8]>,
+        config: &EmulatorConfig,
     ) -> Emulator {
-        let image = load_program(&path);
+        let image = load_program(&path, config);
         let mut lines = image.debug.lines.clone();
         lines.sort_by_key(|line| line.addr);
         let line_index = build_line_index(&lines);
@@ -1269,17 +3480,20 @@ impl Emulator {
             println!("Warning: some #local entries lack sizes; defaulting to 4-byte reads.");
         }
 
-        let mut breakpoints: HashSet<u32> = HashSet::new();
-        let mut cpu = Emulator::from_instructions(
+        let mut breakpoints: Breakpoints = HashMap::new();
+        let mut cpu = Emulator::from_instructions_with_config(
             image.instructions.clone(),
             use_uart_rx,
             sd_dma_ticks_per_word,
             sd0_image,
             sd1_image,
+            config,
         );
+        cpu.set_entry(image.entry);
 
         println!("C debug mode:");
         println!("  r                   reset and run until break/halt");
+        println!("  reset               warm reset without reloading the program");
         println!("  c                   continue execution");
         println!("  step                step to the next source line");
         println!("  next                step over calls to the next source line");
@@ -1291,16 +3505,11 @@ impl Emulator {
         println!("  delete <target>     remove breakpoint");
         println!("  info locals         print locals for current frame");
         println!("  info globals        print global data symbols");
+        println!("  info json           print registers, TLB and devices as JSON");
         println!("  q                   quit");
 
-        loop {
-            print!("dbg> ");
-            io::stdout().flush().unwrap();
-
-            let mut line = String::new();
-            if io::stdin().read_line(&mut line).is_err() {
-                break;
-            }
+        let mut prompt = PromptReader::new();
+        while let Some(line) = prompt.read_line("dbg> ") {
             let line = line.trim();
             if line.is_empty() {
                 continue;
@@ -1314,6 +3523,7 @@ impl Emulator {
                 "h" | "help" => {
                     println!("Commands:");
                     println!("  r                   reset and run until break/halt");
+                    println!("  reset               warm reset without reloading the program");
                     println!("  c                   continue execution");
                     println!("  step                step to the next source line");
                     println!("  next                step over calls to the next source line");
@@ -1325,38 +3535,49 @@ impl Emulator {
                     println!("  delete <target>     remove breakpoint");
                     println!("  info locals         print locals for current frame");
                     println!("  info globals        print global data symbols");
+                    println!("  info json           print registers, TLB and devices as JSON");
                     println!("  q                   quit");
                 }
                 "r" => {
-                    cpu = Emulator::from_instructions(
+                    cpu = Emulator::from_instructions_with_config(
                         image.instructions.clone(),
                         use_uart_rx,
                         sd_dma_ticks_per_word,
                         sd0_image,
                         sd1_image,
+                        config,
                     );
+                    cpu.set_entry(image.entry);
                     match run_until_breakpoint(&mut cpu, &breakpoints) {
                         RunOutcome::Breakpoint(addr) => {
                             print_c_location(addr, line_for_pc(&lines, addr));
                         }
                         RunOutcome::Halted => {
-                            println!("Program halted. r1 = {:08X}", cpu.regfile[1]);
+                            print_halted(&cpu);
                         }
-                        RunOutcome::Watchpoint(_) => {
+                        RunOutcome::Watchpoint(_) | RunOutcome::CregWatch(_) => {
                             println!("Watchpoints are not supported in C debug mode.");
                         }
+                        RunOutcome::Catch(entry) => print_catch_hit(entry),
+                        RunOutcome::SleepForever => print_sleeping(&cpu),
                     }
                 }
+                "reset" => {
+                    cpu.reset();
+                    println!("CPU reset (warm); memory preserved.");
+                }
                 "c" => match run_until_breakpoint(&mut cpu, &breakpoints) {
                     RunOutcome::Breakpoint(addr) => {
                         print_c_location(addr, line_for_pc(&lines, addr));
                     }
                     RunOutcome::Halted => {
-                        println!("Program halted. r1 = {:08X}", cpu.regfile[1]);
+                        print_halted(&cpu);
                     }
-                    RunOutcome::Watchpoint(_) => {
+                    RunOutcome::Watchpoint(_) | RunOutcome::CregWatch(_) => {
                         println!("Watchpoints are not supported in C debug mode.");
                     }
+                    RunOutcome::Catch(entry) => print_catch_hit(entry),
+                    RunOutcome::SleepForever => print_sleeping(&cpu),
                 },
                 "step" | "s" => {
                     if cpu.halted {
@@ -1372,19 +3593,19 @@ impl Emulator {
                             );
                             break;
                         }
-                        match cpu.step_instruction() {
-                            StepOutcome::Executed { .. } => {}
-                            StepOutcome::Sleeping => {
-                                println!("CPU sleeping; waiting for interrupt.");
+                        match cpu.step() {
+                            StepResult::Executed { .. } | StepResult::Halted => {}
+                            StepResult::Sleeping => {
+                                print_sleeping(&cpu);
                                 break;
                             }
-                            StepOutcome::TlbMiss { pc } => {
-                                println!("TLB miss at {:08X}", pc);
+                            StepResult::FetchFault { pc, tlb_miss, .. } => {
+                                print_fetch_fault(pc, tlb_miss);
                                 break;
                             }
                         }
                         steps += 1;
-                        if breakpoints.contains(&cpu.pc) {
+                        if breakpoints.contains_key(&cpu.pc) {
                             break;
                         }
                         let next_line = line_for_pc(&lines, cpu.pc);
@@ -1396,7 +3617,7 @@ impl Emulator {
                         }
                     }
                     if cpu.halted {
-                        println!("Program halted. r1 = {:08X}", cpu.regfile[1]);
+                        print_halted(&cpu);
                     } else {
                         print_c_location(cpu.pc, line_for_pc(&lines, cpu.pc));
                     }
@@ -1416,19 +3637,19 @@ impl Emulator {
                             );
                             break;
                         }
-                        match cpu.step_instruction() {
-                            StepOutcome::Executed { .. } => {}
-                            StepOutcome::Sleeping => {
-                                println!("CPU sleeping; waiting for interrupt.");
+                        match cpu.step() {
+                            StepResult::Executed { .. } | StepResult::Halted => {}
+                            StepResult::Sleeping => {
+                                print_sleeping(&cpu);
                                 break;
                             }
-                            StepOutcome::TlbMiss { pc } => {
-                                println!("TLB miss at {:08X}", pc);
+                            StepResult::FetchFault { pc, tlb_miss, .. } => {
+                                print_fetch_fault(pc, tlb_miss);
                                 break;
                             }
                         }
                         steps += 1;
-                        if breakpoints.contains(&cpu.pc) {
+                        if breakpoints.contains_key(&cpu.pc) {
                             break;
                         }
                         if cpu.get_reg(BP_REG) != start_bp {
@@ -1443,7 +3664,7 @@ impl Emulator {
                         }
                     }
                     if cpu.halted {
-                        println!("Program halted. r1 = {:08X}", cpu.regfile[1]);
+                        print_halted(&cpu);
                     } else {
                         print_c_location(cpu.pc, line_for_pc(&lines, cpu.pc));
                     }
@@ -1466,7 +3687,7 @@ impl Emulator {
                         Ok(addrs) => {
                             let mut added = 0;
                             for addr in addrs {
-                                if breakpoints.insert(addr) {
+                                if breakpoints.insert(addr, None).is_none() {
                                     added += 1;
                                 }
                             }
@@ -1500,7 +3721,7 @@ impl Emulator {
                         Ok(addrs) => {
                             let mut removed = 0;
                             for addr in addrs {
-                                if breakpoints.remove(&addr) {
+                                if breakpoints.remove(&addr).is_some() {
                                     removed += 1;
                                 }
                             }
@@ -1588,12 +3809,14 @@ impl Emulator {
                             }
                         }
                     }
-                    _ => println!("Usage: info <locals|globals>"),
+                    Some("json") => println!("{}", cpu.state_json()),
+                    _ => println!("Usage: info <locals|globals|json>"),
                 },
                 _ => println!("Unknown command: {}", cmd),
             }
         }
 
+        cpu.memory.flush_output();
         cpu
     }
 }
@@ -1601,6 +3824,8 @@ impl Emulator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::emulator::{DEFAULT_TLB_ENTRIES, RandomCache, TlbEvictionPolicy};
+    use crate::memory::PHYSMEM_MAX;
 
     #[test]
     fn parse_addr_accepts_hex_and_dec() {
@@ -1614,12 +3839,63 @@ mod tests {
     #[test]
     fn watchpoint_merge_upgrades_kind() {
         let mut list = Vec::new();
-        add_watchpoint(&mut list, 0x10, WatchKind::Read);
-        let merged = add_watchpoint(&mut list, 0x10, WatchKind::Write);
-        assert_eq!(merged, WatchKind::ReadWrite);
+        add_watchpoint(&mut list, 0x10, 1, WatchKind::Read, None);
+        let merged = add_watchpoint(&mut list, 0x10, 1, WatchKind::Write, None);
+        assert_eq!(merged.kind, WatchKind::ReadWrite);
         assert_eq!(list.len(), 1);
     }
 
+    #[test]
+    fn value_watchpoint_ignores_non_matching_writes() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        let cond = parse_watch_condition(&["==", "0xDEAD"]).unwrap();
+        let mut list = Vec::new();
+        add_watchpoint(&mut list, 0x1001, 1, WatchKind::Write, cond);
+        cpu.set_watchpoints(&list);
+
+        assert!(cpu.mem_write16(0x1000, 0xBEEF));
+        assert!(cpu.mem_write8(0x1001, 0xDE));
+        assert!(cpu.take_watchpoint_hit().is_none());
+
+        assert!(cpu.mem_write16(0x1000, 0xDEAD));
+        let hit = cpu
+            .take_watchpoint_hit()
+            .expect("matching write should hit");
+        assert_eq!((hit.addr, hit.value, hit.size), (0x1001, 0xDEAD, 2));
+
+        assert!(parse_watch_condition(&[]).unwrap().is_none());
+        assert!(parse_watch_condition(&[">=", "1"]).is_err());
+        assert!(parse_watch_condition(&["=="]).is_err());
+    }
+
+    #[test]
+    fn range_watchpoint_hits_writes_inside_and_at_its_bounds() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        let mut list = Vec::new();
+        let wp = add_watchpoint(&mut list, 0x1000, 0x10, WatchKind::Write, None);
+        assert_eq!(describe_watchpoint(&wp), "00001000..00001010 (w)");
+        cpu.set_watchpoints(&list);
+
+        // Inside, first byte and last byte.
+        for addr in [0x1008, 0x1000, 0x100F] {
+            assert!(cpu.mem_write8(addr, 1));
+            let hit = cpu
+                .take_watchpoint_hit()
+                .expect("write in range should hit");
+            assert_eq!(hit.addr, addr);
+        }
+        // A word overlapping the end reports the first watched byte it touches.
+        assert!(cpu.mem_write32(0x100C, 0));
+        assert_eq!(cpu.take_watchpoint_hit().map(|hit| hit.addr), Some(0x100C));
+
+        // One past the end and just before the start.
+        assert!(cpu.mem_write8(0x1010, 1));
+        assert!(cpu.mem_write16(0x0FFE, 1));
+        assert!(cpu.take_watchpoint_hit().is_none());
+    }
+
     #[test]
     fn parse_watch_kind_variants() {
         assert_eq!(parse_watch_kind("r"), Some(WatchKind::Read));
@@ -1628,4 +3904,589 @@ mod tests {
         assert_eq!(parse_watch_kind("wr"), Some(WatchKind::ReadWrite));
         assert_eq!(parse_watch_kind("x"), None);
     }
+
+    #[test]
+    fn parse_string_literal_handles_escapes() {
+        assert_eq!(parse_string_literal("\"hi\"").unwrap(), b"hi\0".to_vec());
+        assert_eq!(
+            parse_string_literal("\"a b\\n\\x41\\0\"").unwrap(),
+            b"a b\nA\0\0".to_vec()
+        );
+        assert!(parse_string_literal("hi").is_err());
+        assert!(parse_string_literal("\"\\xZ1\"").is_err());
+    }
+
+    #[test]
+    fn x_formats_bytes_words_and_strings() {
+        // A string at 0x100, 0x107 unmapped, a word at 0x108.
+        let mut memory: HashMap<u32, u8> = HashMap::new();
+        for (offset, byte) in b"Hi\t\"x\"\0".iter().enumerate() {
+            memory.insert(0x100 + offset as u32, *byte);
+        }
+        for (offset, byte) in [0x11, 0x22, 0x33, 0x44].into_iter().enumerate() {
+            memory.insert(0x108 + offset as u32, byte);
+        }
+        let read = |a: u32| memory.get(&a).map(|val| (a, *val));
+
+        assert_eq!(
+            byte_dump_lines(0x104, 5, read),
+            [format!(
+                "00000104: 78 22 00 ?? 11 {}|x\"...|",
+                " ".repeat(33)
+            )]
+        );
+        assert_eq!(
+            word_dump_lines(0x108, 2, Endianness::Little, read),
+            ["00000108: 44332211 ????????"]
+        );
+        assert_eq!(
+            word_dump_lines(0x108, 1, Endianness::Big, read),
+            ["00000108: 11223344"]
+        );
+        assert_eq!(
+            string_dump_line(0x100, 256, read),
+            r#"00000100: "Hi\t\"x\"""#
+        );
+        assert_eq!(string_dump_line(0x100, 2, read), r#"00000100: "Hi"..."#);
+        assert_eq!(
+            string_dump_line(0x108, 256, read),
+            r#"00000108: "\x11\"3D" (unmapped at 0000010C)"#
+        );
+    }
+
+    #[test]
+    fn clamp_dump_len_caps_huge_and_wrapping_ranges() {
+        assert_eq!(clamp_dump_len(0x1000, 0x40), 0x40);
+        assert_eq!(clamp_dump_len(0x1000, 0xFFFF_FFFF), MAX_DUMP_BYTES);
+        assert_eq!(clamp_dump_len(0xFFFF_FFF0, 0x100), 0x10);
+        assert_eq!(clamp_dump_len(0, MAX_DUMP_BYTES), MAX_DUMP_BYTES);
+    }
+
+    #[test]
+    fn parse_creg_accepts_numbers_and_names() {
+        assert_eq!(parse_creg("cr3"), Some(3));
+        assert_eq!(parse_creg("IMR"), Some(3));
+        assert_eq!(parse_creg("tlbf"), Some(12));
+        assert_eq!(parse_creg("cr13"), None);
+        assert_eq!(parse_creg("r3"), None);
+    }
+
+    #[test]
+    fn creg_watch_reports_crmv_write() {
+        // crmv cr3, r1
+        let crmv = (31u32 << 27) | (3u32 << 22) | (1u32 << 17) | (1u32 << 12);
+        let mut ram = HashMap::new();
+        for (idx, byte) in crmv.to_le_bytes().iter().enumerate() {
+            ram.insert(0x400 + idx as u32, *byte);
+        }
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.regfile[1] = 0x8000_0001;
+        cpu.creg_watches.push(3);
+
+        cpu.step();
+
+        let hit = cpu
+            .creg_watch_hit
+            .take()
+            .expect("IMR write must trigger the creg watch");
+        assert_eq!(hit.creg, 3);
+        assert_eq!(hit.old, 0);
+        assert_eq!(hit.new, 0x8000_0001);
+        assert_eq!(hit.pc, 0x400);
+    }
+
+    #[test]
+    fn creg_watch_fires_on_interrupt_enable_and_disable() {
+        // crmv cr3, r1; crmv cr3, r0
+        let program = [
+            (31u32 << 27) | (3 << 22) | (1 << 17) | (1 << 12),
+            (31u32 << 27) | (3 << 22) | (1 << 12),
+        ];
+        let mut ram = HashMap::new();
+        for (index, word) in program.iter().enumerate() {
+            for (offset, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(0x400 + (index * 4 + offset) as u32, *byte);
+            }
+        }
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.regfile[1] = 0x8000_0001;
+        assert_eq!(
+            creg_watch_alias("watch cr 3").as_deref(),
+            Some("cwatch cr3")
+        );
+        assert_eq!(
+            creg_watch_alias("unwatch cr imr").as_deref(),
+            Some("uncwatch imr")
+        );
+        assert_eq!(creg_watch_alias("watch 0x200"), None);
+        cpu.creg_watches.push(parse_creg("cr3").unwrap());
+
+        cpu.step();
+        let hit = cpu.creg_watch_hit.take().expect("enabling interrupts");
+        assert_eq!(
+            (hit.creg, hit.old, hit.new, hit.pc),
+            (3, 0, 0x8000_0001, 0x400)
+        );
+        cpu.step();
+        let hit = cpu.creg_watch_hit.take().expect("disabling interrupts");
+        assert_eq!(
+            (hit.creg, hit.old, hit.new, hit.pc),
+            (3, 0x8000_0001, 0, 0x404)
+        );
+    }
+
+    #[test]
+    fn catch_tlb_miss_stops_at_a_user_load_from_an_unmapped_page() {
+        use crate::emulator::{
+            CREG_EPC, EXC_TLB_MISS_VECTOR, TLB_FLAG_EXEC, TLB_FLAG_READ, TLB_FLAG_USER,
+        };
+
+        // 0x400: add r1, r0, 1; 0x404: lw r2, [r3]; 0x3000: br . (handler)
+        let program = [0x0840_E001u32, (3 << 27) | (2 << 22) | (3 << 17)];
+        let mut ram = HashMap::new();
+        for (index, word) in program.iter().enumerate() {
+            for (offset, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(0x400 + (index * 4 + offset) as u32, *byte);
+            }
+        }
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.memory.write_u32(EXC_TLB_MISS_VECTOR * 4, 0x3000);
+        cpu.memory.write_u32(0x3000, (12 << 27) | 0x3F_FFFF);
+        // User mode with the code page mapped and the data page left unmapped.
+        cpu.tlb
+            .write(0, 0, TLB_FLAG_READ | TLB_FLAG_EXEC | TLB_FLAG_USER);
+        cpu.cregfile[0] = 0;
+        cpu.regfile[3] = 0x0004_5678;
+
+        assert_eq!(CatchKind::parse("timer"), Some(CatchKind::Interrupt(0)));
+        assert_eq!(CatchKind::parse("page-fault"), None);
+        cpu.catches.push(CatchKind::parse("tlb-miss").unwrap());
+        let RunOutcome::Catch(entry) = run_until_breakpoint(&mut cpu, &HashMap::new()) else {
+            panic!("the load must be caught");
+        };
+        assert_eq!(entry.fault_pc, 0x404);
+        assert_eq!(
+            entry.cause,
+            ExceptionCause::TlbMiss {
+                user: true,
+                vpn: 0x45,
+                pid: 0
+            }
+        );
+        assert_eq!(cpu.regfile[1], 1);
+        assert_eq!(cpu.read_creg(CREG_EPC), 0x404);
+        assert_eq!(cpu.pc, 0x3000);
+
+        // Catches of other classes let the run continue into the handler.
+        cpu.catches = vec![CatchKind::Syscall];
+        cpu.cregfile[0] = 0;
+        cpu.pc = 0x404;
+        assert!(step_for_run(&mut cpu).is_none());
+        assert_eq!(cpu.pc, 0x3000);
+    }
+
+    #[test]
+    fn parse_break_condition_accepts_regs_and_cregs() {
+        assert_eq!(
+            parse_break_condition(&["r3", "==", "0x10"]),
+            Ok(BreakCondition {
+                operand: CondOperand::Reg(3),
+                op: CondOp::Eq,
+                value: 0x10,
+            })
+        );
+        assert_eq!(
+            parse_break_condition(&["IMR", "!=", "0"]).map(|cond| cond.operand),
+            Ok(CondOperand::Creg(3))
+        );
+        assert_eq!(
+            parse_break_condition(&["sp", ">", "100"]).map(|cond| (cond.operand, cond.value)),
+            Ok((CondOperand::Reg(31), 100))
+        );
+        assert!(parse_break_condition(&["r32", "==", "1"]).is_err());
+        assert!(parse_break_condition(&["r1", ">=", "1"]).is_err());
+        assert!(parse_break_condition(&["r1", "=="]).is_err());
+        assert_eq!(
+            parse_break_condition(&["cr5", "<", "7"])
+                .unwrap()
+                .to_string(),
+            "flg < 0x00000007"
+        );
+    }
+
+    #[test]
+    fn conditional_breakpoint_stops_only_when_condition_holds() {
+        // loop: add r3, r3, 1; br loop
+        let words = [0x08C6_E001u32, 0x603F_FFFE];
+        let mut ram = HashMap::new();
+        for (idx, word) in words.iter().enumerate() {
+            for (byte_idx, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(0x400 + (idx * 4 + byte_idx) as u32, *byte);
+            }
+        }
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        let mut breakpoints: Breakpoints = HashMap::new();
+        breakpoints.insert(
+            0x400,
+            Some(parse_break_condition(&["r3", "==", "5"]).unwrap()),
+        );
+
+        match run_until_breakpoint(&mut cpu, &breakpoints) {
+            RunOutcome::Breakpoint(addr) => assert_eq!(addr, 0x400),
+            _ => panic!("expected the conditional breakpoint to fire"),
+        }
+        assert_eq!(cpu.regfile[3], 5);
+
+        // False conditions let execution run through both breakpoint addresses.
+        breakpoints.insert(
+            0x404,
+            Some(parse_break_condition(&["r3", "<", "5"]).unwrap()),
+        );
+        breakpoints.insert(
+            0x400,
+            Some(parse_break_condition(&["r3", ">", "8"]).unwrap()),
+        );
+        match run_until_breakpoint(&mut cpu, &breakpoints) {
+            RunOutcome::Breakpoint(addr) => assert_eq!(addr, 0x400),
+            _ => panic!("expected the conditional breakpoint to fire"),
+        }
+        assert_eq!(cpu.regfile[3], 9);
+    }
+
+    // 0x400: add r2, r0, 0x410; call r2; add r1, r1, 1; mode halt
+    // 0x410: add r3, r3, 1; add r3, r3, 1; ret
+    const CALL_PROGRAM: [u32; 7] = [
+        0x0880_E410,
+        0x6800_03A2,
+        0x0842_E001,
+        0xF800_2800,
+        0x08C6_E001,
+        0x08C6_E001,
+        0x6800_001D,
+    ];
+
+    fn call_program_cpu() -> Emulator {
+        let mut ram = HashMap::new();
+        for (idx, word) in CALL_PROGRAM.iter().enumerate() {
+            for (byte_idx, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(0x400 + (idx * 4 + byte_idx) as u32, *byte);
+            }
+        }
+        Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES)
+    }
+
+    #[test]
+    fn step_over_runs_call_to_return_address() {
+        let mut cpu = call_program_cpu();
+        cpu.step();
+        assert!(is_linking_branch(cpu.peek_instr(0x404).unwrap()));
+        assert!(!is_linking_branch(CALL_PROGRAM[6]), "ret links into r0");
+
+        let sp = cpu.get_reg(SP_REG);
+        match run_to_return(&mut cpu, &HashMap::new(), 0x408, sp) {
+            RunOutcome::Breakpoint(addr) => assert_eq!(addr, 0x408),
+            _ => panic!("expected step-over to stop after the call"),
+        }
+        assert_eq!(cpu.regfile[3], 2);
+
+        // User breakpoints inside the callee still stop the run.
+        let mut cpu = call_program_cpu();
+        cpu.step();
+        let mut breakpoints: Breakpoints = HashMap::new();
+        breakpoints.insert(0x414, None);
+        match run_to_return(&mut cpu, &breakpoints, 0x408, sp) {
+            RunOutcome::Breakpoint(addr) => assert_eq!(addr, 0x414),
+            _ => panic!("expected the user breakpoint to fire"),
+        }
+    }
+
+    #[test]
+    fn step_out_returns_to_link_register() {
+        let mut cpu = call_program_cpu();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x410);
+
+        // A breakpoint on the current instruction must not stop the run.
+        let mut breakpoints: Breakpoints = HashMap::new();
+        breakpoints.insert(0x410, None);
+        let ret_addr = cpu.get_reg(RA_REG);
+        let sp = cpu.get_reg(SP_REG);
+        match run_to_return(&mut cpu, &breakpoints, ret_addr, sp) {
+            RunOutcome::Breakpoint(addr) => assert_eq!(addr, 0x408),
+            _ => panic!("expected step-out to stop at the return address"),
+        }
+        assert_eq!(cpu.regfile[3], 2);
+    }
+
+    #[test]
+    fn until_stops_at_target_once_and_restores_breakpoints() {
+        let mut cpu = call_program_cpu();
+        let mut breakpoints: Breakpoints = HashMap::new();
+        breakpoints.insert(0x410, None);
+
+        // A permanent breakpoint reached first still stops the run.
+        match run_until_target(&mut cpu, &mut breakpoints, 0x414) {
+            RunOutcome::Breakpoint(addr) => assert_eq!(addr, 0x410),
+            _ => panic!("expected the permanent breakpoint to fire"),
+        }
+        assert_eq!(breakpoints.len(), 1);
+        match run_until_target(&mut cpu, &mut breakpoints, 0x414) {
+            RunOutcome::Breakpoint(addr) => assert_eq!(addr, 0x414),
+            _ => panic!("expected until to stop at its target"),
+        }
+        assert_eq!(cpu.regfile[3], 1);
+        assert_eq!(breakpoints.keys().copied().collect::<Vec<_>>(), vec![0x410]);
+
+        // A target that is never reached runs to the halt.
+        assert!(matches!(
+            run_until_target(&mut cpu, &mut breakpoints, 0x500),
+            RunOutcome::Halted
+        ));
+        assert!(!breakpoints.contains_key(&0x500));
+    }
+
+    #[test]
+    fn backtrace_walks_link_register_and_frame_chain() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.pc = 0x404;
+        // Leaf function: return address only in r29.
+        cpu.regfile[RA_REG as usize] = 0x508;
+        // mid's frame at 0x8000 returns into outer, whose frame ends the chain.
+        cpu.regfile[BP_REG as usize] = 0x8000;
+        let frame_words: [(u32, u32); 4] =
+            [(0x8000, 0x8100), (0x8004, 0x604), (0x8100, 0), (0x8104, 0)];
+        for (addr, value) in frame_words {
+            cpu.write_virt_bytes_debug(addr, &value.to_le_bytes());
+        }
+        let mut labels = HashMap::new();
+        labels.insert(0x400, vec!["leaf".to_string()]);
+        labels.insert(0x500, vec!["mid".to_string()]);
+        labels.insert(0x600, vec!["outer".to_string()]);
+
+        let (frames, stopped) = backtrace_frames(&mut cpu, BT_DEFAULT_DEPTH);
+        assert_eq!(frames, vec![0x404, 0x508, 0x604]);
+        assert_eq!(stopped, None);
+        assert_eq!(format_frame(1, 0x508, &labels), "#1  00000508 in mid+0x8");
+        assert_eq!(format_frame(0, 0x400, &labels), "#0  00000400 in leaf");
+
+        let (frames, stopped) = backtrace_frames(&mut cpu, 2);
+        assert_eq!(frames, vec![0x404, 0x508]);
+        assert!(stopped.is_some());
+
+        // After mid's prologue r29 is also saved in its frame: no duplicate.
+        cpu.regfile[RA_REG as usize] = 0x604;
+        let (frames, _) = backtrace_frames(&mut cpu, BT_DEFAULT_DEPTH);
+        assert_eq!(frames, vec![0x404, 0x604]);
+
+        // A frame pointer outside mapped memory ends the walk.
+        cpu.cregfile[0] = 0;
+        let (frames, stopped) = backtrace_frames(&mut cpu, BT_DEFAULT_DEPTH);
+        assert_eq!(frames, vec![0x404, 0x604]);
+        assert!(stopped.unwrap().contains("not mapped"));
+    }
+
+    #[test]
+    fn poked_memory_is_read_by_the_program() {
+        // lw r1, [pc + 0xFC] (0x500); mode halt
+        let words = [0x2860_00FCu32, 0xF800_2800];
+        let mut ram = HashMap::new();
+        for (idx, word) in words.iter().enumerate() {
+            for (byte_idx, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(0x400 + (idx * 4 + byte_idx) as u32, *byte);
+            }
+        }
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+
+        assert_eq!(poke_mem(&mut cpu, false, 0x500, 0xCAFE_BABE, 4), Ok(()));
+        assert_eq!(poke_mem(&mut cpu, true, 0x503, 0x12, 1), Ok(()));
+        assert!(poke_mem(&mut cpu, false, 0x502, 0x1234, 4).is_err());
+        assert!(poke_mem(&mut cpu, false, 0x500, 0x1_0000, 2).is_err());
+        assert!(poke_mem(&mut cpu, true, PHYSMEM_MAX - 1, 0, 4).is_err());
+
+        match run_until_breakpoint(&mut cpu, &HashMap::new()) {
+            RunOutcome::Halted => {}
+            _ => panic!("expected the program to halt"),
+        }
+        assert_eq!(cpu.regfile[1], 0x12FE_BABE);
+
+        // User mode with an empty TLB: the write is reported, not dropped silently.
+        cpu.cregfile[0] = 0;
+        let err = poke_mem(&mut cpu, false, 0x500, 1, 4).unwrap_err();
+        assert!(err.contains("TLB miss"), "{}", err);
+    }
+
+    #[test]
+    fn virtual_fill_stops_at_the_first_tlb_miss() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        // User mode: only page 2 is mapped (to physical page 5).
+        cpu.cregfile[0] = 0;
+        cpu.tlb.write(0, 2, (5 << 12) | 0xB);
+        let msg = fill_range_debug(&mut cpu, false, 0x2FFE, 4, 0x5A);
+        assert_eq!(
+            msg,
+            "Filled 2 of 4 bytes at 00002FFE; TLB miss at vaddr 0x00003000"
+        );
+        assert_eq!(cpu.memory.peek(0x5FFE), Some(0x5A));
+        assert_eq!(cpu.memory.peek(0x5FFF), Some(0x5A));
+        assert_eq!(cpu.memory.peek(0x3000), Some(0));
+    }
+
+    #[test]
+    fn find_reports_every_match_of_a_pattern() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        for (offset, byte) in b"xxMAGICxMAGIC".iter().enumerate() {
+            cpu.memory.write(0x2000 + offset as u32, *byte);
+        }
+
+        let pattern = parse_find_pattern("\"MAGIC\"").unwrap();
+        assert_eq!(pattern, b"MAGIC");
+        assert_eq!(
+            find_pattern_debug(&mut cpu, true, 0x2000, 0x20, &pattern),
+            (vec![0x2002, 0x2008], 2)
+        );
+        // Hex bytes in memory order; a range ending mid-match finds only the first.
+        let pattern = parse_find_pattern("4d 0x4147 49").unwrap();
+        assert_eq!(pattern, b"MAGI");
+        assert_eq!(
+            find_pattern_debug(&mut cpu, false, 0x2000, 0xB, &pattern),
+            (vec![0x2002], 1)
+        );
+        assert!(parse_find_pattern("abc").is_err());
+        assert!(parse_find_pattern("\"\"").is_err());
+
+        // Only the first FIND_MAX_MATCHES addresses are kept.
+        let (found, total) = find_pattern_debug(&mut cpu, true, 0x3000, 0x100, &[0, 0]);
+        assert_eq!((found.len(), total), (FIND_MAX_MATCHES, 0xFF));
+        assert_eq!(found[1], 0x3001);
+    }
+
+    #[test]
+    fn virtual_find_leaves_the_tlb_untouched() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.tlb = RandomCache::new(DEFAULT_TLB_ENTRIES, TlbEvictionPolicy::Lru);
+        // User mode: pages 2 and 3 map to physical pages 5 and 6; page 4 is unmapped.
+        cpu.cregfile[0] = 0;
+        cpu.tlb.write(0, 2, (5 << 12) | 0xB);
+        cpu.tlb.write(0, 3, (6 << 12) | 0xB);
+        for (offset, byte) in b"MAGIC".iter().enumerate() {
+            cpu.memory.write(0x6000 + offset as u32, *byte);
+        }
+        let entries = cpu.tlb.entries();
+        let stats = cpu.tlb.stats();
+
+        assert_eq!(
+            find_pattern_debug(&mut cpu, false, 0x3000, 0x2000, b"MAGIC"),
+            (vec![0x3000], 1)
+        );
+        assert_eq!(cpu.tlb.entries(), entries);
+        assert_eq!(cpu.tlb.stats(), stats);
+        assert_eq!(cpu.pending_tlb_fault, None);
+    }
+
+    #[test]
+    fn tlb_commands_edit_the_current_tlb() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+
+        let msg = tlb_command(&mut cpu, &["set", "1", "0x8", "0x9003"]).unwrap();
+        assert_eq!(
+            msg,
+            "pid 00000001 vpn 00000008 -> 00009003 (ppn 00009 RW---)"
+        );
+        assert_eq!(cpu.tlb.read(1, 8), Some(0x9003));
+        assert_eq!(
+            tlb_command(&mut cpu, &["read", "1", "8"]).unwrap(),
+            "pid 00000001 vpn 00000008 -> 00009003 (ppn 00009 RW---)"
+        );
+
+        // Global entries answer for every pid.
+        tlb_command(&mut cpu, &["set", "0", "0x10", "0x2015"]).unwrap();
+        assert!(
+            tlb_command(&mut cpu, &["read", "7", "0x10"])
+                .unwrap()
+                .ends_with("R-X-G)")
+        );
+
+        let msg = tlb_command(&mut cpu, &["inv", "1", "8"]).unwrap();
+        assert!(msg.ends_with("(no entry)"), "{}", msg);
+        assert_eq!(cpu.tlb.read(1, 8), None);
+
+        tlb_command(&mut cpu, &["clear"]).unwrap();
+        assert_eq!(cpu.tlb.read(7, 0x10), None);
+
+        assert!(tlb_command(&mut cpu, &["set", "1", "8"]).is_err());
+        assert!(tlb_command(&mut cpu, &["read", "1", "zz"]).is_err());
+    }
+
+    #[test]
+    fn disas_marks_pc_labels_and_unmapped_words() {
+        // add r1, r1, 1; mode halt
+        let words = [0x0842_E001u32, 0xF800_2800];
+        let mut ram = HashMap::new();
+        for (idx, word) in words.iter().enumerate() {
+            for (byte_idx, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(0x400 + (idx * 4 + byte_idx) as u32, *byte);
+            }
+        }
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        let mut labels = HashMap::new();
+        labels.insert(0x404, vec!["done".to_string()]);
+
+        let lines = disas_lines(&mut cpu, 0x401, 2, &labels);
+        assert_eq!(
+            lines,
+            vec![
+                "=> 00000400: 0842E001  add r1, r1, 1",
+                "done:",
+                "   00000404: F8002800  mode halt",
+            ]
+        );
+
+        // User mode with an empty TLB: every word is unmapped.
+        cpu.cregfile[0] = 0;
+        let lines = disas_lines(&mut cpu, 0x1000, 1, &labels);
+        assert_eq!(lines, vec!["   00001000: ??"]);
+        assert_eq!(cpu.pc, 0x400, "disas must not raise a TLB miss");
+    }
+
+    #[test]
+    fn dump_and_loadbin_round_trip_through_a_file() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        let pattern: Vec<u8> = (0..40u8).map(|b| b.wrapping_mul(7)).collect();
+        assert_eq!(write_range_debug(&mut cpu, true, 0x2000, &pattern), 40);
+
+        let mut bytes = Vec::new();
+        let unmapped = dump_range_debug(&mut cpu, false, 0x2000, 40, &mut bytes).unwrap();
+        assert_eq!((bytes.as_slice(), unmapped), (pattern.as_slice(), 0));
+        let mut path = std::env::temp_dir();
+        path.push(format!("dioptase_dump_{}.bin", std::process::id()));
+        fs::write(&path, &bytes).unwrap();
+
+        let loaded = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(write_range_debug(&mut cpu, false, 0x3001, &loaded), 40);
+        let mut copy = Vec::new();
+        dump_range_debug(&mut cpu, true, 0x3001, 40, &mut copy).unwrap();
+        assert_eq!(copy, pattern);
+
+        // User mode with an empty TLB: nothing is mapped.
+        cpu.cregfile[0] = 0;
+        let mut bytes = Vec::new();
+        let unmapped = dump_range_debug(&mut cpu, false, 0x2000, 4, &mut bytes).unwrap();
+        assert_eq!((bytes, unmapped), (vec![0; 4], 4));
+        assert_eq!(write_range_debug(&mut cpu, false, 0x2000, &pattern), 0);
+        assert_eq!(cpu.pc, 0x400, "dump/loadbin must not raise a TLB miss");
+    }
+
+    #[test]
+    fn command_rest_keeps_inner_spaces() {
+        assert_eq!(command_rest("set str 0x10 \"a  b\" ", 3), "\"a  b\"");
+        assert_eq!(command_rest("set str", 3), "");
+    }
 }
@@ -0,0 +1,201 @@
+// Bounded reverse execution for the debugger's `back` command: the core state
+// before each recorded step plus the RAM bytes that step overwrote.
+//
+// Not undone: device side effects (UART output, SD DMA progress, audio, input
+// queues, interrupt-controller state), stores to device registers and edits
+// made with debugger commands.
+
+use std::collections::VecDeque;
+
+use super::{Emulator, ExceptionEntry, HaltReason};
+
+pub const DEFAULT_UNDO_DEPTH: usize = 10_000;
+
+#[derive(Clone, Debug)]
+struct UndoEntry {
+    regfile: [u32; 32],
+    cregfile: [u32; 13],
+    pc: u32,
+    asleep: bool,
+    sleep_armed: bool,
+    halted: bool,
+    halt_reason: Option<HaltReason>,
+    count: u32,
+    retired: u64,
+    exc_cause: u32,
+    last_exception: Option<ExceptionEntry>,
+    pit: (u32, u32, u32),
+    // (pid, vpn, entry) in eviction order, as in snapshots.
+    tlb: Vec<(u32, u32, u32)>,
+    // (physical address, old byte) in store order; restored in reverse.
+    ram: Vec<(u32, u8)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct UndoHistory {
+    entries: VecDeque<UndoEntry>,
+    depth: usize,
+    // Old bytes of the step in progress, or None outside a recorded step.
+    recording: Option<Vec<(u32, u8)>>,
+}
+
+impl UndoHistory {
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.recording = None;
+    }
+}
+
+impl Emulator {
+    // Keep up to `depth` steps of history for `step_back`; 0 disables it and
+    // drops what was recorded.
+    pub fn set_undo_depth(&mut self, depth: usize) {
+        self.history.depth = depth;
+        while self.history.entries.len() > depth {
+            self.history.entries.pop_front();
+        }
+    }
+
+    // Steps `step_back` can currently undo.
+    pub fn undo_len(&self) -> usize {
+        self.history.entries.len()
+    }
+
+    // Purpose: note the old contents of RAM about to be overwritten.
+    // Inputs: physical address and store size in bytes.
+    // Outputs: appended to the current step's record; a no-op when no step is
+    // being recorded. Device registers are noted too, but `poke` leaves them
+    // alone on the way back.
+    pub(super) fn record_undo_bytes(&mut self, paddr: u32, len: u32) {
+        let Some(ram) = self.history.recording.as_mut() else {
+            return;
+        };
+        for offset in 0..len {
+            let addr = paddr.wrapping_add(offset);
+            if let Some(byte) = self.memory.peek(addr) {
+                ram.push((addr, byte));
+            }
+        }
+    }
+
+    // Purpose: wrap one `step` so it can be undone.
+    // Inputs: the step to run.
+    // Outputs: its result; the pre-step state is pushed onto the history.
+    pub(super) fn recorded_step<T>(&mut self, step: impl FnOnce(&mut Emulator) -> T) -> T {
+        if self.history.depth == 0 {
+            return step(self);
+        }
+        let mut entry = UndoEntry {
+            regfile: self.regfile,
+            cregfile: self.cregfile,
+            pc: self.pc,
+            asleep: self.asleep,
+            sleep_armed: self.sleep_armed,
+            halted: self.halted,
+            halt_reason: self.halt_reason.clone(),
+            count: self.count,
+            retired: self.retired,
+            exc_cause: self.exc_cause,
+            last_exception: self.last_exception,
+            pit: self.memory.pit_state(),
+            tlb: self.tlb.entries(),
+            ram: Vec::new(),
+        };
+        self.history.recording = Some(Vec::new());
+        let result = step(self);
+        entry.ram = self.history.recording.take().unwrap_or_default();
+        if self.history.entries.len() == self.history.depth {
+            self.history.entries.pop_front();
+        }
+        self.history.entries.push_back(entry);
+        result
+    }
+
+    // Purpose: undo the most recent recorded step.
+    // Inputs: none.
+    // Outputs: false when there is no history left; otherwise registers, PC,
+    // counters, TLB, PIT and the RAM bytes the step wrote are restored.
+    pub fn step_back(&mut self) -> bool {
+        let Some(entry) = self.history.entries.pop_back() else {
+            return false;
+        };
+        for &(addr, byte) in entry.ram.iter().rev() {
+            self.memory.poke(addr, byte);
+        }
+        self.regfile = entry.regfile;
+        self.cregfile = entry.cregfile;
+        self.pc = entry.pc;
+        self.asleep = entry.asleep;
+        self.sleep_armed = entry.sleep_armed;
+        self.halted = entry.halted;
+        self.halt_reason = entry.halt_reason;
+        self.count = entry.count;
+        self.busy_cycles = 0;
+        self.retired = entry.retired;
+        self.exc_cause = entry.exc_cause;
+        self.last_exception = entry.last_exception;
+        let (reload, countdown, ctrl) = entry.pit;
+        self.memory.restore_pit_state(reload, countdown, ctrl);
+        self.tlb.restore_entries(&entry.tlb);
+        self.pending_tlb_fault = None;
+        self.pending_misaligned_addr = None;
+        self.watchpoint_hit = None;
+        self.creg_watch_hit = None;
+        self.catch_hit = None;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::super::DEFAULT_TLB_ENTRIES;
+    use super::*;
+
+    #[test]
+    fn stepping_back_restores_registers_and_ram() {
+        // add r1, r0, 5; sw r1, [r0 + 0x200]; add r1, r1, 1; mode halt
+        let program = [
+            0x0840_E005u32,
+            (3 << 27) | (1 << 22) | 0x200,
+            0x0842_E001,
+            0xF800_2800,
+        ];
+        let mut ram = HashMap::new();
+        for (index, word) in program.iter().enumerate() {
+            for (offset, byte) in word.to_le_bytes().iter().enumerate() {
+                ram.insert(0x400 + (index * 4 + offset) as u32, *byte);
+            }
+        }
+        ram.insert(0x200, 0xAA);
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.set_undo_depth(8);
+
+        let mut states = Vec::new();
+        while !cpu.halted {
+            states.push((cpu.regfile, cpu.pc, cpu.count, cpu.memory.peek(0x200)));
+            cpu.step();
+        }
+        assert_eq!(cpu.regfile[1], 6);
+        assert_eq!(cpu.memory.peek(0x200), Some(5));
+        assert_eq!(cpu.undo_len(), program.len());
+
+        while let Some(expected) = states.pop() {
+            assert!(cpu.step_back());
+            assert_eq!(
+                (cpu.regfile, cpu.pc, cpu.count, cpu.memory.peek(0x200)),
+                expected
+            );
+        }
+        assert!(!cpu.halted);
+        assert!(!cpu.step_back());
+
+        // The history is bounded by the configured depth.
+        cpu.set_undo_depth(2);
+        while !cpu.halted {
+            cpu.step();
+        }
+        assert_eq!(cpu.undo_len(), 2);
+    }
+}
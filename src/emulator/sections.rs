@@ -0,0 +1,150 @@
+// Sectioned binary program images, an alternative to the `@addr` + hex-word
+// text format for larger programs. `load_program` picks this format whenever a
+// file starts with SECTIONS_MAGIC, whatever its extension.
+//
+// Layout (all words little-endian):
+//   magic "DIOSECT\0", version, entry PC, section count,
+//   then per section: load address, byte length, the bytes themselves.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+
+use super::snapshot::{invalid_data, read_u32, write_u32};
+
+pub const SECTIONS_MAGIC: &[u8; 8] = b"DIOSECT\0";
+const SECTIONS_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SectionImage {
+    pub entry: u32,
+    // (load address, bytes); sections may not run past the top of the
+    // address space. Later sections overwrite earlier ones where they overlap.
+    pub sections: Vec<(u32, Vec<u8>)>,
+}
+
+impl SectionImage {
+    // Purpose: build an image from a byte map such as `load_hex` returns.
+    // Inputs: address -> byte map and the entry PC.
+    // Outputs: one section per run of consecutive addresses, in address order.
+    pub fn from_bytes(bytes: &HashMap<u32, u8>, entry: u32) -> SectionImage {
+        let ordered: BTreeMap<u32, u8> = bytes.iter().map(|(&addr, &byte)| (addr, byte)).collect();
+        let mut sections: Vec<(u32, Vec<u8>)> = Vec::new();
+        for (addr, byte) in ordered {
+            match sections.last_mut() {
+                Some((base, data)) if base.wrapping_add(data.len() as u32) == addr => {
+                    data.push(byte)
+                }
+                _ => sections.push((addr, vec![byte])),
+            }
+        }
+        SectionImage { entry, sections }
+    }
+
+    // Flatten the sections into the address -> byte map used to seed RAM.
+    pub fn to_bytes(&self) -> HashMap<u32, u8> {
+        let mut bytes = HashMap::new();
+        for (base, data) in &self.sections {
+            for (offset, byte) in data.iter().enumerate() {
+                bytes.insert(base.wrapping_add(offset as u32), *byte);
+            }
+        }
+        bytes
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(SECTIONS_MAGIC)?;
+        write_u32(out, SECTIONS_VERSION)?;
+        write_u32(out, self.entry)?;
+        write_u32(out, self.sections.len() as u32)?;
+        for (base, data) in &self.sections {
+            write_u32(out, *base)?;
+            write_u32(out, data.len() as u32)?;
+            out.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<SectionImage> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != SECTIONS_MAGIC {
+            return Err(invalid_data("not a sectioned program image".to_string()));
+        }
+        let version = read_u32(input)?;
+        if version != SECTIONS_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported program image version {}",
+                version
+            )));
+        }
+        let entry = read_u32(input)?;
+        let count = read_u32(input)?;
+        let mut sections = Vec::new();
+        for _ in 0..count {
+            let base = read_u32(input)?;
+            let len = read_u32(input)?;
+            if len > 0 && base.checked_add(len - 1).is_none() {
+                return Err(invalid_data(format!(
+                    "section at {:08X} with {} bytes runs past FFFFFFFF",
+                    base, len
+                )));
+            }
+            // Grow as bytes arrive so a corrupt length cannot force a huge
+            // allocation before read_exact fails.
+            let mut data = Vec::new();
+            input.take(u64::from(len)).read_to_end(&mut data)?;
+            if data.len() != len as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("section at {:08X} is truncated", base),
+                ));
+            }
+            sections.push((base, data));
+        }
+        Ok(SectionImage { entry, sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::emulator::{EmulatorConfig, load_program};
+
+    #[test]
+    fn section_image_round_trips_and_loads_with_its_entry() {
+        let mut bytes = HashMap::new();
+        // add r1, r0, 5; mode halt at 0x1000, plus a data byte at 0x8000.
+        for (offset, byte) in [0x0840_E005u32, 0xF800_2800]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .enumerate()
+        {
+            bytes.insert(0x1000 + offset as u32, byte);
+        }
+        bytes.insert(0x8000, 0xAB);
+
+        let image = SectionImage::from_bytes(&bytes, 0x1000);
+        assert_eq!(image.sections.len(), 2);
+        assert_eq!(image.sections[0].0, 0x1000);
+        assert_eq!(image.sections[1], (0x8000, vec![0xAB]));
+
+        let mut encoded = Vec::new();
+        image.write_to(&mut encoded).unwrap();
+        let decoded = SectionImage::read_from(&mut encoded.as_slice()).unwrap();
+        assert_eq!(decoded, image);
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        let mut path = std::env::temp_dir();
+        path.push(format!("dioptase_sections_{}.img", std::process::id()));
+        fs::write(&path, &encoded).unwrap();
+        let program = load_program(&path.to_string_lossy(), &EmulatorConfig::default());
+        let _ = fs::remove_file(&path);
+        assert_eq!(program.instructions, bytes);
+        assert_eq!(program.entry, 0x1000);
+
+        encoded.truncate(encoded.len() - 1);
+        assert!(SectionImage::read_from(&mut encoded.as_slice()).is_err());
+    }
+}
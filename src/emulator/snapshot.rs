@@ -0,0 +1,272 @@
+// Whole-machine snapshots for the debugger's `save`/`load` commands.
+
+use std::io::{self, Read, Write};
+
+use crate::memory::{Memory, RAM_PAGE_SIZE};
+
+use super::Emulator;
+
+// File header: magic followed by a format version, both checked on load.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"DIOSNAP\0";
+const SNAPSHOT_VERSION: u32 = 4;
+
+const FLAG_ASLEEP: u32 = 1 << 0;
+const FLAG_SLEEP_ARMED: u32 = 1 << 1;
+const FLAG_HALTED: u32 = 1 << 2;
+
+// CPU, TLB, PIT and RAM state of one core and its memory.
+//
+// Excluded: graphics-owned state (pixel/tile framebuffers, tile and sprite
+// maps, scroll/scale and VGA registers), SD cards, audio devices, pending
+// keyboard/UART input, the clock register, interrupt-controller state and
+// debugger configuration such as watchpoints. Kernel mode needs no separate
+// field: it is derived from PSR (cr0).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmulatorSnapshot {
+    regfile: [u32; 32],
+    cregfile: [u32; 13],
+    pc: u32,
+    asleep: bool,
+    sleep_armed: bool,
+    halted: bool,
+    count: u32,
+    retired: u64,
+    exc_cause: u32,
+    pit_reload: u32,
+    pit_countdown: u32,
+    pit_ctrl: u32,
+    // (pid, vpn, entry) in eviction order.
+    tlb: Vec<(u32, u32, u32)>,
+    // (page base, RAM_PAGE_SIZE bytes) for every non-zero RAM page.
+    ram_pages: Vec<(u32, Vec<u8>)>,
+}
+
+impl Emulator {
+    pub fn save_snapshot(&self) -> EmulatorSnapshot {
+        let (pit_reload, pit_countdown, pit_ctrl) = self.memory.pit_state();
+        EmulatorSnapshot {
+            regfile: self.regfile,
+            cregfile: self.cregfile,
+            pc: self.pc,
+            asleep: self.asleep,
+            sleep_armed: self.sleep_armed,
+            halted: self.halted,
+            count: self.count,
+            retired: self.retired,
+            exc_cause: self.exc_cause,
+            pit_reload,
+            pit_countdown,
+            pit_ctrl,
+            tlb: self.tlb.entries(),
+            ram_pages: self.memory.ram_snapshot(),
+        }
+    }
+
+    // Restore the state captured by `save_snapshot`. Anything the snapshot
+    // excludes (see EmulatorSnapshot) keeps its current value.
+    pub fn load_snapshot(&mut self, snap: &EmulatorSnapshot) {
+        self.regfile = snap.regfile;
+        self.cregfile = snap.cregfile;
+        self.pc = snap.pc;
+        self.asleep = snap.asleep;
+        self.sleep_armed = snap.sleep_armed;
+        self.halted = snap.halted;
+        self.halt_reason = None;
+        self.count = snap.count;
+        // Snapshots are taken between debugger steps, which never leave an
+        // instruction in flight.
+        self.busy_cycles = 0;
+        self.retired = snap.retired;
+        self.exc_cause = snap.exc_cause;
+        self.tlb.restore_entries(&snap.tlb);
+        self.memory
+            .restore_pit_state(snap.pit_reload, snap.pit_countdown, snap.pit_ctrl);
+        self.memory.restore_ram(&snap.ram_pages);
+        self.pending_tlb_fault = None;
+        self.pending_misaligned_addr = None;
+        self.watchpoint_hit = None;
+        self.creg_watch_hit = None;
+        self.catch_hit = None;
+        self.history.clear();
+    }
+}
+
+impl EmulatorSnapshot {
+    // Serialize as the magic/version header followed by little-endian words;
+    // RAM pages are stored as a base address plus the raw page bytes.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(SNAPSHOT_MAGIC)?;
+        write_u32(out, SNAPSHOT_VERSION)?;
+        for value in self.regfile.iter().chain(self.cregfile.iter()) {
+            write_u32(out, *value)?;
+        }
+        let mut flags = 0;
+        if self.asleep {
+            flags |= FLAG_ASLEEP;
+        }
+        if self.sleep_armed {
+            flags |= FLAG_SLEEP_ARMED;
+        }
+        if self.halted {
+            flags |= FLAG_HALTED;
+        }
+        for value in [
+            self.pc,
+            flags,
+            self.count,
+            self.retired as u32,
+            (self.retired >> 32) as u32,
+            self.exc_cause,
+            self.pit_reload,
+            self.pit_countdown,
+            self.pit_ctrl,
+        ] {
+            write_u32(out, value)?;
+        }
+        write_u32(out, self.tlb.len() as u32)?;
+        for &(pid, vpn, entry) in &self.tlb {
+            write_u32(out, pid)?;
+            write_u32(out, vpn)?;
+            write_u32(out, entry)?;
+        }
+        write_u32(out, self.ram_pages.len() as u32)?;
+        for (base, bytes) in &self.ram_pages {
+            write_u32(out, *base)?;
+            out.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<EmulatorSnapshot> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(invalid_data("not an emulator snapshot".to_string()));
+        }
+        let version = read_u32(input)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+        let mut regfile = [0u32; 32];
+        for value in regfile.iter_mut() {
+            *value = read_u32(input)?;
+        }
+        let mut cregfile = [0u32; 13];
+        for value in cregfile.iter_mut() {
+            *value = read_u32(input)?;
+        }
+        let pc = read_u32(input)?;
+        let flags = read_u32(input)?;
+        let count = read_u32(input)?;
+        let retired = u64::from(read_u32(input)?) | (u64::from(read_u32(input)?) << 32);
+        let exc_cause = read_u32(input)?;
+        let pit_reload = read_u32(input)?;
+        let pit_countdown = read_u32(input)?;
+        let pit_ctrl = read_u32(input)?;
+
+        let tlb_len = read_u32(input)?;
+        let mut tlb = Vec::new();
+        for _ in 0..tlb_len {
+            tlb.push((read_u32(input)?, read_u32(input)?, read_u32(input)?));
+        }
+
+        let page_count = read_u32(input)?;
+        let mut ram_pages = Vec::new();
+        for _ in 0..page_count {
+            let base = read_u32(input)?;
+            if !Memory::is_ram_page(base) {
+                return Err(invalid_data(format!("bad RAM page address {:08X}", base)));
+            }
+            let mut bytes = vec![0u8; RAM_PAGE_SIZE];
+            input.read_exact(&mut bytes)?;
+            ram_pages.push((base, bytes));
+        }
+
+        Ok(EmulatorSnapshot {
+            regfile,
+            cregfile,
+            pc,
+            asleep: flags & FLAG_ASLEEP != 0,
+            sleep_armed: flags & FLAG_SLEEP_ARMED != 0,
+            halted: flags & FLAG_HALTED != 0,
+            count,
+            retired,
+            exc_cause,
+            pit_reload,
+            pit_countdown,
+            pit_ctrl,
+            tlb,
+            ram_pages,
+        })
+    }
+}
+
+pub(super) fn write_u32<W: Write>(out: &mut W, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub(super) fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(super) fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::emulator::DEFAULT_TLB_ENTRIES;
+
+    #[test]
+    fn snapshot_round_trips_through_bytes_and_restores_state() {
+        let mut ram = HashMap::new();
+        ram.insert(0x400, 0x11);
+        ram.insert(0x2_0003, 0x22);
+        let mut cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.regfile[5] = 0xDEAD_BEEF;
+        cpu.cregfile[3] = 0x8000_0001;
+        cpu.pc = 0x1234;
+        cpu.count = 77;
+        cpu.retired = 0x1_0000_0042;
+        cpu.exc_cause = 0x83;
+        cpu.tlb.write(3, 0x10, 0x0004_5007);
+        cpu.tlb.write(0, 0x20, 0x0004_6017);
+        cpu.memory.restore_pit_state(500, 42, 1);
+
+        let snap = cpu.save_snapshot();
+        assert_eq!(snap.ram_pages.len(), 2);
+        let mut bytes = Vec::new();
+        snap.write_to(&mut bytes).unwrap();
+        let decoded = EmulatorSnapshot::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded, snap);
+
+        cpu.reset();
+        cpu.memory.restore_ram(&[]);
+        cpu.memory.restore_pit_state(0, 0, 0);
+        cpu.halted = true;
+        cpu.load_snapshot(&decoded);
+
+        assert_eq!(cpu.regfile[5], 0xDEAD_BEEF);
+        assert_eq!(cpu.cregfile[3], 0x8000_0001);
+        assert_eq!((cpu.pc, cpu.count, cpu.halted), (0x1234, 77, false));
+        assert_eq!(cpu.retired, 0x1_0000_0042);
+        assert_eq!(cpu.exc_cause, 0x83);
+        assert_eq!(cpu.tlb.entries(), snap.tlb);
+        assert_eq!(cpu.memory.pit_state(), (500, 42, 1));
+        assert_eq!(cpu.memory.read(0x400), 0x11);
+        assert_eq!(cpu.memory.read(0x2_0003), 0x22);
+        assert_eq!(cpu.save_snapshot(), snap);
+
+        assert!(EmulatorSnapshot::read_from(&mut &bytes[..20]).is_err());
+        bytes[0] = b'X';
+        assert!(EmulatorSnapshot::read_from(&mut bytes.as_slice()).is_err());
+    }
+}
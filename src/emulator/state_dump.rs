@@ -0,0 +1,182 @@
+// Machine state as JSON for editors and visualizers: the debugger's
+// `info json` and --dump-state-on-exit. Unlike `save` snapshots this is meant
+// to be read by tools, not loaded back, and leaves out RAM.
+
+use serde::{Deserialize, Serialize};
+
+use super::debugger::CREG_NAMES;
+use super::{
+    CREG_CAUSE, CREG_FLG, Emulator, TLB_FLAG_EXEC, TLB_FLAG_GLOBAL, TLB_FLAG_READ, TLB_FLAG_USER,
+    TLB_FLAG_WRITE,
+};
+
+// Names of the virtual registers after the stored cregfile (cr13-cr15).
+const VIRTUAL_CREG_NAMES: [&str; 3] = ["cnt", "cnth", "cause"];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateDump {
+    pub core: u32,
+    pub pc: u32,
+    pub kmode: bool,
+    pub asleep: bool,
+    pub halted: bool,
+    // HaltReason text, e.g. "mode halt"; None while the core can still run.
+    pub halt_reason: Option<String>,
+    pub retired: u64,
+    // r0-r31.
+    pub regs: Vec<u32>,
+    // cr0-cr15, including the virtual counter and cause registers.
+    pub cregs: Vec<CregValue>,
+    pub flags: Flags,
+    // Resident entries in eviction order.
+    pub tlb: Vec<TlbEntryDump>,
+    // Device registers, named as in `info io`.
+    pub devices: Vec<DeviceRegister>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CregValue {
+    pub index: usize,
+    pub name: String,
+    pub value: u32,
+}
+
+// The arithmetic flags held in FLG (cr5).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flags {
+    pub carry: bool,
+    pub zero: bool,
+    pub sign: bool,
+    pub overflow: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlbEntryDump {
+    // None for global entries, which match every pid.
+    pub pid: Option<u32>,
+    pub vpn: u32,
+    pub ppn: u32,
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    pub user: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceRegister {
+    pub name: String,
+    pub value: u32,
+}
+
+impl Emulator {
+    // Purpose: capture this core's registers, TLB and device registers.
+    // Inputs: none.
+    // Outputs: the state `state_json` serializes; reading it has no side
+    // effects.
+    pub fn state_dump(&self) -> StateDump {
+        let names = CREG_NAMES.iter().chain(VIRTUAL_CREG_NAMES.iter());
+        let cregs = names
+            .enumerate()
+            .map(|(index, name)| CregValue {
+                index,
+                name: name.to_string(),
+                value: self.read_creg(index),
+            })
+            .collect::<Vec<_>>();
+        debug_assert_eq!(cregs.len(), CREG_CAUSE + 1);
+        let flg = self.cregfile[CREG_FLG];
+        let tlb = self
+            .tlb
+            .entries()
+            .into_iter()
+            .map(|(pid, vpn, entry)| TlbEntryDump {
+                pid: (entry & TLB_FLAG_GLOBAL == 0).then_some(pid),
+                vpn,
+                ppn: entry >> 12,
+                read: entry & TLB_FLAG_READ != 0,
+                write: entry & TLB_FLAG_WRITE != 0,
+                exec: entry & TLB_FLAG_EXEC != 0,
+                user: entry & TLB_FLAG_USER != 0,
+            })
+            .collect();
+        StateDump {
+            core: self.core_id,
+            pc: self.pc,
+            kmode: self.get_kmode(),
+            asleep: self.asleep,
+            halted: self.halted,
+            halt_reason: self.halt_reason.as_ref().map(|reason| reason.to_string()),
+            retired: self.retired,
+            regs: (0..32).map(|reg| self.get_reg(reg)).collect(),
+            cregs,
+            flags: Flags {
+                carry: flg & 1 != 0,
+                zero: flg & 2 != 0,
+                sign: flg & 4 != 0,
+                overflow: flg & 8 != 0,
+            },
+            tlb,
+            devices: self
+                .memory
+                .io_registers()
+                .into_iter()
+                .map(|(name, value)| DeviceRegister {
+                    name: name.to_string(),
+                    value,
+                })
+                .collect(),
+        }
+    }
+
+    // Purpose: `state_dump` as pretty-printed JSON.
+    pub fn state_json(&self) -> String {
+        serde_json::to_string_pretty(&self.state_dump()).expect("state dump serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::super::DEFAULT_TLB_ENTRIES;
+    use super::*;
+
+    #[test]
+    fn state_json_round_trips_with_named_registers() {
+        let mut cpu =
+            Emulator::from_instructions(HashMap::new(), false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        cpu.regfile[1] = 5;
+        cpu.cregfile[CREG_FLG] = 0b0110;
+        cpu.tlb
+            .write(3, 0x10, (0x22 << 12) | TLB_FLAG_READ | TLB_FLAG_USER);
+        cpu.tlb
+            .write(0, 0x11, (0x23 << 12) | TLB_FLAG_EXEC | TLB_FLAG_GLOBAL);
+
+        let json = cpu.state_json();
+        let parsed: StateDump = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, cpu.state_dump());
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["pc"], cpu.pc);
+        assert_eq!(value["kmode"], true);
+        assert_eq!(value["regs"].as_array().unwrap().len(), 32);
+        assert_eq!(value["regs"][1], 5);
+        assert_eq!(value["cregs"][5]["name"], "flg");
+        assert_eq!(value["cregs"][15]["name"], "cause");
+        assert_eq!(
+            value["flags"],
+            serde_json::json!({"carry": false, "zero": true, "sign": true, "overflow": false})
+        );
+        assert_eq!(value["tlb"][0]["pid"], 3);
+        assert_eq!(value["tlb"][0]["ppn"], 0x22);
+        assert_eq!(value["tlb"][1]["pid"], serde_json::Value::Null);
+        assert_eq!(value["tlb"][1]["exec"], true);
+        assert!(
+            value["devices"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|reg| reg["name"] == "pit_reload")
+        );
+    }
+}
@@ -1,22 +1,64 @@
-use ::image::{ImageBuffer, Rgba};
+use ::image::{ImageResult, imageops};
 use piston_window::*;
 use std::{
     collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
     sync::{
         Arc, Mutex, RwLock,
         atomic::{AtomicBool, AtomicU32, Ordering},
     },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::memory::*;
+use crate::render::{Frame, FrameSources, render_and_present_frame};
 
 const SCREEN_WIDTH: u32 = 640;
 const SCREEN_HEIGHT: u32 = 480;
 // Purpose: scale the host window without changing logical resolution.
-// Invariants: buffer remains FRAME_WIDTH x FRAME_HEIGHT.
-const DISPLAY_SCALE: u32 = 2;
-const WINDOW_WIDTH: u32 = SCREEN_WIDTH * DISPLAY_SCALE;
-const WINDOW_HEIGHT: u32 = SCREEN_HEIGHT * DISPLAY_SCALE;
+// Invariants: buffer remains FRAME_WIDTH x FRAME_HEIGHT; the window is
+// SCREEN_WIDTH x SCREEN_HEIGHT times the scale, set by --window-scale.
+pub const DEFAULT_WINDOW_SCALE: u32 = 2;
+pub const MAX_WINDOW_SCALE: u32 = 8;
+
+// Purpose: host window settings from the command line, fixed when the window
+// opens.
+#[derive(Clone, Copy, Debug)]
+pub struct WindowConfig {
+    // Scale factor in 1..=MAX_WINDOW_SCALE (--window-scale).
+    pub scale: u32,
+    // Redraw cap in 1..=MAX_REFRESH_RATE (--fps).
+    pub max_fps: u32,
+    // Update (vblank) rate in 1..=MAX_REFRESH_RATE (--ups).
+    pub ups: u32,
+    // What keyboard events the guest reads from PS2_STREAM (--ps2-set2).
+    // Off: one entry per event in the guest keycode contract below. On: one
+    // entry per byte of PS/2 scan code set 2.
+    pub ps2_set2: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            scale: DEFAULT_WINDOW_SCALE,
+            max_fps: DEFAULT_REFRESH_RATE,
+            ups: DEFAULT_REFRESH_RATE,
+            ps2_set2: false,
+        }
+    }
+}
+
+// Purpose: host refresh rates, set by --fps and --ups.
+// Invariants: every update tick composites a frame, bumps the VGA frame
+// register and raises the vblank interrupt, so UPS is the guest-visible
+// vblank rate. FPS only caps how often the window redraws. Neither depends
+// on the CPU clock divider at CLK_REG_START.
+pub const DEFAULT_REFRESH_RATE: u32 = 60;
+pub const MAX_REFRESH_RATE: u32 = 240;
+
+// Host hotkey that saves the current frame as a PNG in the working
+// directory. The key is still forwarded to the guest.
+const SCREENSHOT_KEY: Key = Key::F12;
 
 // Guest-visible PS/2 keycode contract:
 // - bit 8 is the release flag
@@ -174,6 +216,117 @@ fn guest_keycode_for_key(key: Key) -> Option<u8> {
     }
 }
 
+// Purpose: PS/2 scan code set 2 make code for a guest keycode.
+// Inputs: guest keycode from `guest_keycode_for_key` or the text fallback.
+// Outputs: `Some((extended, make))`; extended keys are sent after an 0xE0
+// prefix. Numpad keys arrive already normalized to their base keys, so they
+// use the main-block codes.
+fn set2_scancode(code: u8) -> Option<(bool, u8)> {
+    let make = match code {
+        b'a' => 0x1C,
+        b'b' => 0x32,
+        b'c' => 0x21,
+        b'd' => 0x23,
+        b'e' => 0x24,
+        b'f' => 0x2B,
+        b'g' => 0x34,
+        b'h' => 0x33,
+        b'i' => 0x43,
+        b'j' => 0x3B,
+        b'k' => 0x42,
+        b'l' => 0x4B,
+        b'm' => 0x3A,
+        b'n' => 0x31,
+        b'o' => 0x44,
+        b'p' => 0x4D,
+        b'q' => 0x15,
+        b'r' => 0x2D,
+        b's' => 0x1B,
+        b't' => 0x2C,
+        b'u' => 0x3C,
+        b'v' => 0x2A,
+        b'w' => 0x1D,
+        b'x' => 0x22,
+        b'y' => 0x35,
+        b'z' => 0x1A,
+        b'0' => 0x45,
+        b'1' => 0x16,
+        b'2' => 0x1E,
+        b'3' => 0x26,
+        b'4' => 0x25,
+        b'5' => 0x2E,
+        b'6' => 0x36,
+        b'7' => 0x3D,
+        b'8' => 0x3E,
+        b'9' => 0x46,
+        b'`' => 0x0E,
+        b'-' => 0x4E,
+        b'=' => 0x55,
+        b'[' => 0x54,
+        b']' => 0x5B,
+        b'\\' => 0x5D,
+        b';' => 0x4C,
+        b'\'' => 0x52,
+        b',' => 0x41,
+        b'.' => 0x49,
+        b'/' => 0x4A,
+        b' ' => 0x29,
+        0x08 => 0x66,
+        0x09 => 0x0D,
+        0x0D => 0x5A,
+        0x1B => 0x76,
+        KEY_F1 => 0x05,
+        KEY_F2 => 0x06,
+        KEY_F3 => 0x04,
+        KEY_F4 => 0x0C,
+        KEY_F5 => 0x03,
+        KEY_F6 => 0x0B,
+        KEY_F7 => 0x83,
+        KEY_F8 => 0x0A,
+        KEY_F9 => 0x01,
+        KEY_F10 => 0x09,
+        KEY_F11 => 0x78,
+        KEY_F12 => 0x07,
+        KEY_LEFT_SHIFT => 0x12,
+        KEY_RIGHT_SHIFT => 0x59,
+        KEY_LEFT_CTRL => 0x14,
+        KEY_LEFT_ALT => 0x11,
+        KEY_RIGHT_CTRL => return Some((true, 0x14)),
+        KEY_RIGHT_ALT => return Some((true, 0x11)),
+        0x7F => return Some((true, 0x71)),
+        KEY_INSERT => return Some((true, 0x70)),
+        KEY_HOME => return Some((true, 0x6C)),
+        KEY_PAGE_UP => return Some((true, 0x7D)),
+        KEY_END => return Some((true, 0x69)),
+        KEY_PAGE_DOWN => return Some((true, 0x7A)),
+        KEY_RIGHT => return Some((true, 0x74)),
+        KEY_LEFT => return Some((true, 0x6B)),
+        KEY_DOWN => return Some((true, 0x72)),
+        KEY_UP => return Some((true, 0x75)),
+        _ => return None,
+    };
+    Some((false, make))
+}
+
+// Purpose: encode a guest key event as the bytes a set 2 keyboard sends.
+// Inputs: event from `encode_guest_key_event` (bit 8 = release).
+// Outputs: [0xE0 if extended] [0xF0 on release] make code; empty when the
+// key has no set 2 code.
+fn set2_bytes(event: u16) -> Vec<u8> {
+    let Some((extended, make)) = set2_scancode(event as u8) else {
+        return Vec::new();
+    };
+    let mut bytes = Vec::with_capacity(3);
+    if extended {
+        bytes.push(0xE0);
+    }
+    if event & 0x0100 != 0 {
+        bytes.push(0xF0);
+    }
+    bytes.push(make);
+    bytes
+}
+
 // Purpose: recover the unshifted base key identity from the text event that
 // follows a backend `Key::Unknown` press.
 // Inputs: composed host character.
@@ -341,78 +494,75 @@ impl GuestKeyboardMapper {
     }
 }
 
-// Purpose: expand an 8-bit sprite/tile color into 4-bit RGB channels.
-// Inputs: 8-bit color in RGB332 format.
-// Outputs: (r4, g4, b4) in 0..=15.
-fn expand_rgb332(color: u8) -> (u8, u8, u8) {
-    let r3 = (color >> 5) & 0x7;
-    let g3 = (color >> 2) & 0x7;
-    let b2 = color & 0x3;
-    let r4 = (r3 << 1) | (r3 >> 2);
-    let g4 = (g3 << 1) | (g3 >> 2);
-    let b4 = (b2 << 2) | b2;
-    (r4, g4, b4)
+// Purpose: write a frame the way the window shows it.
+// Inputs: composited frame, window scale factor and destination path (the
+// image format follows the extension).
+// Outputs: nearest-neighbor upscaled image, so guest pixels stay sharp.
+fn save_scaled_frame(buffer: &Frame, scale: u32, path: &Path) -> ImageResult<()> {
+    let scaled = imageops::resize(
+        buffer,
+        buffer.width() * scale,
+        buffer.height() * scale,
+        imageops::FilterType::Nearest,
+    );
+    scaled.save(path)
 }
 
-// Purpose: decode a signed 16-bit scroll offset from two MMIO bytes.
-// Inputs: (low, high) bytes in little-endian order.
-// Outputs: signed pixel offset.
-fn decode_scroll_offset(pair: (u8, u8)) -> i32 {
-    i32::from(i16::from_le_bytes([pair.0, pair.1]))
+// Purpose: map a host cursor position to logical frame coordinates.
+// Inputs: cursor position in window coordinates and the window scale.
+// Invariants: results are clamped to the FRAME_WIDTH x FRAME_HEIGHT frame.
+fn window_to_frame(pos: [f64; 2], window_scale: u32) -> (u16, u16) {
+    let scale = window_scale as f64;
+    let x = (pos[0] / scale).clamp(0.0, (FRAME_WIDTH - 1) as f64);
+    let y = (pos[1] / scale).clamp(0.0, (FRAME_HEIGHT - 1) as f64);
+    (x as u16, y as u16)
+}
+
+fn mouse_button_bit(button: MouseButton) -> Option<u8> {
+    match button {
+        MouseButton::Left => Some(MOUSE_BUTTON_LEFT),
+        MouseButton::Right => Some(MOUSE_BUTTON_RIGHT),
+        MouseButton::Middle => Some(MOUSE_BUTTON_MIDDLE),
+        _ => None,
+    }
 }
 
 pub struct Graphics {
     window: PistonWindow,
-    buffer: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    // Last finished frame: what the texture, the window and captures show.
+    buffer: Frame,
+    // Frame being composited; swapped with `buffer` once complete.
+    back_buffer: Frame,
     texture: G2dTexture,
-    pixel_frame_buffer: Arc<RwLock<PixelFrameBuffer>>,
-    tile_frame_buffer: Arc<RwLock<TileFrameBuffer>>,
-    tile_map: Arc<RwLock<TileMap>>,
+    window_scale: u32,
+    frame: FrameSources,
     io_buffer: Arc<RwLock<VecDeque<u16>>>,
+    key_buffer_cap: usize,
     input_pending: Arc<AtomicBool>,
-    tile_vscroll_register: Arc<RwLock<(u8, u8)>>,
-    tile_hscroll_register: Arc<RwLock<(u8, u8)>>,
-    pixel_vscroll_register: Arc<RwLock<(u8, u8)>>,
-    pixel_hscroll_register: Arc<RwLock<(u8, u8)>>,
-    tile_scale_register: Arc<RwLock<u8>>,
-    pixel_scale_register: Arc<RwLock<u8>>,
-    sprite_scale_registers: Arc<RwLock<Vec<u8>>>,
     vga_status_register: Arc<RwLock<u8>>,
     vga_frame_register: Arc<RwLock<(u8, u8, u8, u8)>>,
     pending_interrupt: Arc<AtomicU32>,
-    sprite_map: Arc<RwLock<SpriteMap>>,
+    mouse_register: Arc<RwLock<(u16, u16, u8)>>,
     keyboard_mapper: GuestKeyboardMapper,
     keyboard_debug: bool,
+    ps2_set2: bool,
 }
 
 impl Graphics {
-    pub fn new(
-        pixel_frame_buffer: Arc<RwLock<PixelFrameBuffer>>,
-        tile_frame_buffer: Arc<RwLock<TileFrameBuffer>>,
-        tile_map: Arc<RwLock<TileMap>>,
-        io_buffer: Arc<RwLock<VecDeque<u16>>>,
-        input_pending: Arc<AtomicBool>,
-        tile_vscroll_register: Arc<RwLock<(u8, u8)>>,
-        tile_hscroll_register: Arc<RwLock<(u8, u8)>>,
-        pixel_vscroll_register: Arc<RwLock<(u8, u8)>>,
-        pixel_hscroll_register: Arc<RwLock<(u8, u8)>>,
-        sprite_map: Arc<RwLock<SpriteMap>>,
-        tile_scale_register: Arc<RwLock<u8>>,
-        pixel_scale_register: Arc<RwLock<u8>>,
-        sprite_scale_registers: Arc<RwLock<Vec<u8>>>,
-        vga_status_register: Arc<RwLock<u8>>,
-        vga_frame_register: Arc<RwLock<(u8, u8, u8, u8)>>,
-        pending_interrupt: Arc<AtomicU32>,
-    ) -> Graphics {
-        let mut window: PistonWindow =
-            WindowSettings::new("Dioptase", [WINDOW_WIDTH, WINDOW_HEIGHT])
-                .exit_on_esc(true)
-                .build()
-                .unwrap();
-        window.set_max_fps(60);
-        window.set_ups(60);
-
-        let buffer: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(FRAME_WIDTH, FRAME_HEIGHT);
+    // Purpose: open the window for `memory`'s display and input devices.
+    // Inputs: the shared memory, whose device registers the window drives,
+    // and the window settings.
+    pub fn new(memory: &Memory, config: WindowConfig) -> Graphics {
+        let window_scale = config.scale;
+        let window_size = [SCREEN_WIDTH * window_scale, SCREEN_HEIGHT * window_scale];
+        let mut window: PistonWindow = WindowSettings::new("Dioptase", window_size)
+            .exit_on_esc(true)
+            .build()
+            .unwrap();
+        window.set_max_fps(u64::from(config.max_fps));
+        window.set_ups(u64::from(config.ups));
+
+        let buffer = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
         let texture = Texture::from_image(
             &mut window.create_texture_context(),
             &buffer,
@@ -423,25 +573,20 @@ impl Graphics {
         Graphics {
             window,
             buffer,
+            back_buffer: Frame::new(FRAME_WIDTH, FRAME_HEIGHT),
             texture,
-            pixel_frame_buffer,
-            tile_frame_buffer,
-            tile_map,
-            io_buffer,
-            input_pending,
-            tile_vscroll_register,
-            tile_hscroll_register,
-            pixel_vscroll_register,
-            pixel_hscroll_register,
-            sprite_map,
-            tile_scale_register,
-            pixel_scale_register,
-            sprite_scale_registers,
-            vga_status_register,
-            vga_frame_register,
-            pending_interrupt,
+            window_scale,
+            frame: FrameSources::from_memory(memory),
+            io_buffer: memory.get_io_buffer(),
+            key_buffer_cap: memory.key_buffer_cap(),
+            input_pending: memory.get_input_pending(),
+            vga_status_register: memory.get_vga_status_register(),
+            vga_frame_register: memory.get_vga_frame_register(),
+            pending_interrupt: memory.get_pending_interrupt(),
+            mouse_register: memory.get_mouse_register(),
             keyboard_mapper: GuestKeyboardMapper::new(),
             keyboard_debug: std::env::var_os("PS2_DEBUG").is_some(),
+            ps2_set2: config.ps2_set2,
         }
     }
 
@@ -458,7 +603,9 @@ impl Graphics {
                 Event::Loop(Loop::Render(_args)) => {
                     self.window.draw_2d(&event, |context, graphics, _| {
                         clear([0.0; 4], graphics); // black background
-                        let scale = DISPLAY_SCALE as f64;
+                        // The texture uses nearest filtering, so scaled
+                        // guest pixels stay sharp.
+                        let scale = self.window_scale as f64;
                         image(
                             &self.texture,
                             context.transform.scale(scale, scale),
@@ -479,14 +626,13 @@ impl Graphics {
                             "ps2 host button: key={key:?} state={state:?} scancode={scancode:?}"
                         );
                     }
+                    if key == SCREENSHOT_KEY && state == ButtonState::Press {
+                        self.save_screenshot();
+                    }
                     if let Some(event_code) =
                         self.keyboard_mapper.translate_button(key, state, scancode)
                     {
-                        if self.keyboard_debug {
-                            eprintln!("ps2 guest event: 0x{event_code:04X}");
-                        }
-                        self.io_buffer.write().unwrap().push_back(event_code);
-                        self.input_pending.store(true, Ordering::SeqCst);
+                        self.push_key_event(event_code);
                     }
                 }
                 Event::Input(Input::Text(text), _) => {
@@ -494,120 +640,83 @@ impl Graphics {
                         eprintln!("ps2 host text: {text:?}");
                     }
                     if let Some(event_code) = self.keyboard_mapper.translate_text(&text) {
-                        if self.keyboard_debug {
-                            eprintln!("ps2 guest event: 0x{event_code:04X}");
+                        self.push_key_event(event_code);
+                    }
+                }
+                Event::Input(
+                    Input::Button(ButtonArgs {
+                        button: Button::Mouse(button),
+                        state,
+                        ..
+                    }),
+                    _,
+                ) => {
+                    if let Some(bit) = mouse_button_bit(button) {
+                        let mut mouse = self.mouse_register.write().unwrap();
+                        match state {
+                            ButtonState::Press => mouse.2 |= bit,
+                            ButtonState::Release => mouse.2 &= !bit,
                         }
-                        self.io_buffer.write().unwrap().push_back(event_code);
-                        self.input_pending.store(true, Ordering::SeqCst);
                     }
                 }
+                Event::Input(Input::Move(Motion::MouseCursor(pos)), _) => {
+                    let (x, y) = window_to_frame(pos, self.window_scale);
+                    let mut mouse = self.mouse_register.write().unwrap();
+                    mouse.0 = x;
+                    mouse.1 = y;
+                }
                 Event::Input(Input::Focus(false), _) => {
                     self.keyboard_mapper.clear();
+                    // Releases are not delivered while unfocused.
+                    self.mouse_register.write().unwrap().2 = 0;
                 }
                 _ => {}
             }
         }
     }
 
-    fn tile_layer_update(&mut self) {
-        // draw the tile layer over the pixel layer
-        let fb = self.tile_frame_buffer.read().unwrap();
-        let tile_map = self.tile_map.read().unwrap();
-        let scale = 1 << (*self.tile_scale_register.read().unwrap() as u32);
-        for x in 0..fb.width_tiles {
-            for y in 0..fb.height_tiles {
-                let (tile_ptr, tile_color) = fb.get_tile_entry(x, y);
-                let tile = &tile_map.tiles[tile_ptr as usize];
-                for px in 0..TILE_WIDTH {
-                    for py in 0..TILE_WIDTH {
-                        let addr = (2 * (px + py * TILE_WIDTH)) as usize;
-                        let tile_pixel_low = tile.pixels[addr];
-                        let tile_pixel_high = tile.pixels[addr + 1];
-                        // 0xFXXX pixels are transparent in the tile layer.
-                        let transparent = (tile_pixel_high & 0xf0) == 0xf0;
-                        if transparent {
-                            continue;
-                        }
-                        let use_tile_color = (tile_pixel_high & 0xf0) == 0xc0;
-                        let (red, green, blue) = if use_tile_color {
-                            let (r4, g4, b4) = expand_rgb332(tile_color);
-                            (r4 * 16, g4 * 16, b4 * 16)
-                        } else {
-                            (
-                                (tile_pixel_low & 0x0f) as u8 * 16,
-                                ((tile_pixel_low & 0xf0) >> 4) as u8 * 16,
-                                (tile_pixel_high & 0x0f) as u8 * 16,
-                            )
-                        };
-                        let pixel = Rgba([red, green, blue, 255]);
-
-                        // positions in the logical screen
-                        let scroll_x_pair = *self.tile_hscroll_register.read().unwrap();
-                        let scroll_y_pair = *self.tile_vscroll_register.read().unwrap();
-                        let scroll_x = decode_scroll_offset(scroll_x_pair);
-                        let scroll_y = decode_scroll_offset(scroll_y_pair);
-                        let raw_x: i32 = (x * TILE_WIDTH) as i32 + px as i32 + scroll_x;
-                        let raw_y: i32 = (y * TILE_WIDTH) as i32 + py as i32 + scroll_y;
-                        // Scroll registers are signed; use Euclidean modulo so large negative
-                        // offsets continue wrapping correctly after many screens of scroll.
-                        let final_x: u32 = raw_x.rem_euclid(FRAME_WIDTH as i32) as u32;
-                        let final_y: u32 = raw_y.rem_euclid(FRAME_HEIGHT as i32) as u32;
-
-                        // print the pixel rgba in the physical screen
-                        for i in 0..scale {
-                            for j in 0..scale {
-                                let screen_x: u32 = final_x * scale + i;
-                                let screen_y: u32 = final_y * scale + j;
-
-                                if screen_x < SCREEN_WIDTH && screen_y < SCREEN_HEIGHT {
-                                    self.buffer.put_pixel(screen_x, screen_y, pixel);
-                                }
-                            }
-                        }
-                    }
-                }
+    // Purpose: queue a guest key event for PS2_STREAM.
+    // Inputs: event in the guest keycode contract; with --ps2-set2 it is
+    // queued as its set 2 bytes instead, one entry per byte.
+    // Outputs: events that do not fit under the --key-buffer cap are dropped.
+    fn push_key_event(&self, event_code: u16) {
+        if self.keyboard_debug {
+            eprintln!("ps2 guest event: 0x{event_code:04X}");
+        }
+        let entries: Vec<u16> = if self.ps2_set2 {
+            set2_bytes(event_code).into_iter().map(u16::from).collect()
+        } else {
+            vec![event_code]
+        };
+        if entries.is_empty() {
+            return;
+        }
+        let mut io_buffer = self.io_buffer.write().unwrap();
+        if !push_input_event(&mut io_buffer, &entries, self.key_buffer_cap) {
+            if self.keyboard_debug {
+                eprintln!("ps2 buffer full, dropped event: 0x{event_code:04X}");
             }
+            return;
         }
+        self.input_pending.store(true, Ordering::SeqCst);
     }
 
-    fn pixel_layer_update(&mut self) {
-        // draw the pixel layer as the background
-        let fb = self.pixel_frame_buffer.read().unwrap();
-        // Pixel layer uses an exponent with an implicit +1 so that:
-        // n=0 -> 2x, n=1 -> 4x, matching 320x240 -> 640x480 at n=0.
-        let scale = 1 << ((*self.pixel_scale_register.read().unwrap() as u32) + 1);
-        for x in 0..fb.width_pixels {
-            for y in 0..fb.height_pixels {
-                let pixel = fb.get_pixel(x, y);
-                let red = (pixel & 0x0F) as u8 * 16;
-                let green = ((pixel & 0xF0) >> 4) as u8 * 16;
-                let blue = ((pixel & 0xF00) >> 8) as u8 * 16;
-                let pixel = Rgba([red, green, blue, 255]);
-
-                // positions in the logical screen
-                let scroll_x_pair = *self.pixel_hscroll_register.read().unwrap();
-                let scroll_y_pair = *self.pixel_vscroll_register.read().unwrap();
-                let scroll_x = decode_scroll_offset(scroll_x_pair);
-                let scroll_y = decode_scroll_offset(scroll_y_pair);
-                let raw_x: i32 = x as i32 + scroll_x;
-                let raw_y: i32 = y as i32 + scroll_y;
-                // Scroll registers are signed; use Euclidean modulo so large negative
-                // offsets continue wrapping correctly after many screens of scroll.
-                let final_x: u32 = raw_x.rem_euclid(FRAME_WIDTH as i32) as u32;
-                let final_y: u32 = raw_y.rem_euclid(FRAME_HEIGHT as i32) as u32;
-
-                // print the pixel rgba in the physical screen
-                for i in 0..scale {
-                    for j in 0..scale {
-                        let screen_x: u32 = final_x * scale + i;
-                        let screen_y: u32 = final_y * scale + j;
-
-                        if screen_x < SCREEN_WIDTH && screen_y < SCREEN_HEIGHT {
-                            self.buffer.put_pixel(screen_x, screen_y, pixel);
-                        }
-                    }
-                }
-            }
+    // Purpose: save the current frame to `path`.
+    // Outputs: the image is scaled by the window scale, matching the window,
+    // with the guest's tile/pixel/sprite scale registers already applied.
+    pub fn capture(&self, path: &Path) -> ImageResult<()> {
+        save_scaled_frame(&self.buffer, self.window_scale, path)
+    }
+
+    fn save_screenshot(&self) {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = PathBuf::from(format!("dioptase-screenshot-{}.png", stamp));
+        match self.capture(&path) {
+            Ok(()) => eprintln!("Saved screenshot to {}", path.display()),
+            Err(err) => eprintln!("Failed to save screenshot {}: {}", path.display(), err),
         }
     }
 
@@ -615,56 +724,12 @@ impl Graphics {
         // set status to busy
         *self.vga_status_register.write().unwrap() = 0;
 
-        // Updates buffer from emulated frame buffers and tile map.
-        self.pixel_layer_update();
-        self.tile_layer_update();
-
-        // draw the sprites of the sprite map
-        let sprite_map = self.sprite_map.read().unwrap();
-        let sprite_scales = self.sprite_scale_registers.read().unwrap();
-        for (sprite_index, sprite) in sprite_map.sprites.iter().enumerate() {
-            let scale = 1 << (sprite_scales.get(sprite_index).copied().unwrap_or(0) as u32);
-            // Sprite coordinates are signed 16-bit little-endian MMIO values.
-            let sprite_x = i32::from(i16::from_le_bytes([sprite.x.0, sprite.x.1]));
-            let sprite_y = i32::from(i16::from_le_bytes([sprite.y.0, sprite.y.1]));
-            for px in 0..SPRITE_WIDTH {
-                for py in 0..SPRITE_WIDTH {
-                    let addr = (2 * (px + py * SPRITE_WIDTH)) as usize;
-                    let tile_pixel_low = sprite.pixels[addr];
-                    let tile_pixel_high = sprite.pixels[addr + 1];
-                    let red = (tile_pixel_low & 0x0f) as u8 * 16;
-                    let green = ((tile_pixel_low & 0xf0) >> 4) as u8 * 16;
-                    let blue = (tile_pixel_high & 0x0f) as u8 * 16;
-                    let transparent = (tile_pixel_high & 0xf0) == 0xf0;
-                    if transparent {
-                        continue;
-                    }
-
-                    let pixel = Rgba([red, green, blue, 255]);
-                    // Reconstruct the full coordinate before adding the per-pixel offset so carry
-                    // from the low byte is preserved (the previous bytewise OR math dropped carry).
-                    let final_x = sprite_x + px as i32;
-                    let final_y = sprite_y + py as i32;
-                    if final_x < 0 || final_y < 0 {
-                        continue;
-                    }
-                    let final_x = final_x as u32;
-                    let final_y = final_y as u32;
-
-                    // print the pixel rgba in the physical screen
-                    for i in 0..scale {
-                        for j in 0..scale {
-                            let screen_x: u32 = final_x * scale + i;
-                            let screen_y: u32 = final_y * scale + j;
-
-                            if screen_x < SCREEN_WIDTH && screen_y < SCREEN_HEIGHT {
-                                self.buffer.put_pixel(screen_x, screen_y, pixel);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        // Composite the whole frame from one locked snapshot of the display
+        // state into the back buffer, then swap, so the window never shows a
+        // half-drawn frame. The composited pixel layer is presented under the
+        // same lock.
+        render_and_present_frame(&self.frame, &mut self.back_buffer);
+        std::mem::swap(&mut self.buffer, &mut self.back_buffer);
 
         // increment frame register
         let mut vga_frame_register = self.vga_frame_register.write().unwrap();
@@ -687,7 +752,8 @@ impl Graphics {
         )
         .unwrap();
 
-        // set status to idle
+        // set status to idle; busy spans compositing, the swap and the
+        // texture upload, so idle means the new frame is on screen
         *self.vga_status_register.write().unwrap() = 3;
 
         // send vblank interrupt
@@ -698,8 +764,48 @@ impl Graphics {
 
 #[cfg(test)]
 mod tests {
+    use ::image::Rgba;
+
     use super::*;
 
+    #[test]
+    fn saved_frame_is_upscaled_like_the_window() {
+        let mut buffer = Frame::new(4, 3);
+        buffer.put_pixel(1, 2, Rgba([10, 20, 30, 255]));
+        let mut path = std::env::temp_dir();
+        path.push(format!("dioptase_capture_{}.png", std::process::id()));
+
+        save_scaled_frame(&buffer, DEFAULT_WINDOW_SCALE, &path).unwrap();
+        let saved = ::image::open(&path).unwrap().to_rgba8();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            saved.dimensions(),
+            (4 * DEFAULT_WINDOW_SCALE, 3 * DEFAULT_WINDOW_SCALE)
+        );
+        assert_eq!(
+            *saved.get_pixel(DEFAULT_WINDOW_SCALE + 1, 2 * DEFAULT_WINDOW_SCALE + 1),
+            Rgba([10, 20, 30, 255])
+        );
+    }
+
+    #[test]
+    fn mouse_cursor_scales_back_to_frame_coordinates() {
+        assert_eq!(window_to_frame([0.0, 0.0], 2), (0, 0));
+        assert_eq!(window_to_frame([101.0, 51.0], 2), (50, 25));
+        assert_eq!(window_to_frame([101.0, 51.0], 3), (33, 17));
+        assert_eq!(window_to_frame([101.0, 51.0], 1), (101, 51));
+        assert_eq!(
+            window_to_frame([(SCREEN_WIDTH * 4) as f64 + 10.0, -4.0], 4),
+            ((FRAME_WIDTH - 1) as u16, 0)
+        );
+        assert_eq!(
+            mouse_button_bit(MouseButton::Right),
+            Some(MOUSE_BUTTON_RIGHT)
+        );
+        assert_eq!(mouse_button_bit(MouseButton::X1), None);
+    }
+
     #[test]
     fn guest_keycode_preserves_unshifted_printable_identity() {
         assert_eq!(guest_keycode_for_key(Key::A), Some(b'a'));
@@ -727,6 +833,42 @@ mod tests {
         assert_eq!(guest_keycode_for_key(Key::Caret), Some(b'6'));
     }
 
+    #[test]
+    fn set2_translation_uses_real_scancodes_and_break_prefixes() {
+        let press = |key| {
+            set2_bytes(encode_guest_key_event(
+                guest_keycode_for_key(key).unwrap(),
+                ButtonState::Press,
+            ))
+        };
+        let release = |key| {
+            set2_bytes(encode_guest_key_event(
+                guest_keycode_for_key(key).unwrap(),
+                ButtonState::Release,
+            ))
+        };
+
+        assert_eq!(press(Key::A), vec![0x1C]);
+        assert_eq!(release(Key::A), vec![0xF0, 0x1C]);
+        assert_eq!(press(Key::D1), vec![0x16]);
+        assert_eq!(press(Key::NumPad1), vec![0x16]);
+        assert_eq!(press(Key::Space), vec![0x29]);
+        assert_eq!(press(Key::Return), vec![0x5A]);
+        assert_eq!(press(Key::LShift), vec![0x12]);
+        assert_eq!(release(Key::RShift), vec![0xF0, 0x59]);
+        assert_eq!(press(Key::F7), vec![0x83]);
+        assert_eq!(press(Key::Up), vec![0xE0, 0x75]);
+        assert_eq!(release(Key::Up), vec![0xE0, 0xF0, 0x75]);
+        assert_eq!(release(Key::RCtrl), vec![0xE0, 0xF0, 0x14]);
+        assert_eq!(press(Key::Delete), vec![0xE0, 0x71]);
+        // Text-fallback keys go through the same table.
+        let quote = guest_keycode_from_text_char('"').unwrap();
+        assert_eq!(
+            set2_bytes(encode_guest_key_event(quote, ButtonState::Press)),
+            vec![0x52]
+        );
+    }
+
     #[test]
     fn text_fallback_recovers_base_key_from_shifted_punctuation() {
         assert_eq!(guest_keycode_from_text_char('!'), Some(b'1'));
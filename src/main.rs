@@ -1,18 +1,34 @@
 use std::env;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, LineWriter};
+use std::path::Path;
 use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub mod audio;
+pub mod console;
 pub mod disassembler;
 pub mod emulator;
 pub mod graphics;
 pub mod memory;
+pub mod render;
+pub mod stdin_keys;
 pub mod tests;
 
-use emulator::{AudioMode, Emulator, ScheduleMode, set_trace_interrupts};
-use memory::SdSlot;
+use console::DEFAULT_CONSOLE_RATE;
+use emulator::{
+    AudioMode, DEFAULT_TLB_ENTRIES, DEFAULT_UNDO_DEPTH, Emulator, EmulatorConfig, Endianness,
+    HaltReason, InstrTrace, RunOptions, RunReport, ScheduleMode, StateDump, TlbEvictionPolicy,
+    disassemble_program, read_debug_script, set_log_exceptions, set_trace_interrupts,
+};
+use graphics::{MAX_REFRESH_RATE, MAX_WINDOW_SCALE, WindowConfig};
+use memory::{
+    DEFAULT_KEY_BUFFER_CAP, DEFAULT_PHYSMEM_SIZE, FramebufferVisibility, Memory, SdSlot,
+    check_physmem_size,
+};
 
-const USAGE: &str = "Usage: cargo run -- --ram <file>.hex [--sd0 <sd0.bin>] [--sd1 <sd1.bin>] [--sd0-out <sd0-out.bin>] [--sd1-out <sd1-out.bin>] [--vga] [--audio|--audio-fast] [--uart] [--debug|--debugc] [--trace-ints] [--cores N] [--sched free|rr|random] [--max-cycles N] [--sd-dma-ticks N]";
+const USAGE: &str = "Usage: cargo run -- --ram <file>.hex [<more>.hex]... [--sd0 <sd0.bin>] [--sd1 <sd1.bin>] [--sd0-out <sd0-out.bin>] [--sd1-out <sd1-out.bin>] [--sd-image <sd.bin>] [--vga] [--window-scale N] [--fps N] [--ups N] [--ascii-vga] [--ascii-fps N] [--ps2-set2] [--key-buffer N] [--render-frame <file>.png] [--audio|--audio-fast] [--uart] [--uart-input <file>] [--stdin-keys] [--debug|--debugc] [--debug-script <file>] [--undo-depth N] [--disasm] [--disasm-out <file>] [--profile-out <file>] [--dump-state-on-exit <file>] [--entry <addr>] [--trace-ints] [--log-exceptions] [--trace] [--trace-file <file>] [--trace-range <start>:<end>] [--cores N] [--sched free|rr|random] [--max-cycles N|--max-iters N] [--timeout-secs S] [--sd-dma-ticks N] [--fb-visibility write-through|displayed] [--tlb-policy fifo|lru] [--tlb-entries N] [--tlb-stats] [--bench] [--strict-align] [--endian little|big] [--physmem <bytes>] [--max-exception-depth N] [--watch [r:|w:|rw:]<addr>]... [--watch-dump]";
 
 fn print_usage_and_exit() -> ! {
     println!("{}", USAGE);
@@ -36,29 +52,211 @@ where
     }
 }
 
+// Write the per-page access profile as CSV when --profile-out was given.
+fn write_profile(path: Option<&str>, memory: &Memory) {
+    if let Some(path) = path {
+        fs::write(path, memory.access_stats().pages_csv()).unwrap_or_else(|err| {
+            println!("Failed to write profile {}: {}", path, err);
+            process::exit(1);
+        });
+    }
+}
+
+// Write the final core states as a JSON array when --dump-state-on-exit was
+// given. A failure is reported but does not change the run's result.
+fn write_state_dump(path: Option<&str>, states: &[StateDump]) {
+    if let Some(path) = path {
+        let json = serde_json::to_string_pretty(states).expect("state dump serializes");
+        if let Err(err) = fs::write(path, json + "\n") {
+            println!("Failed to write state dump {}: {}", path, err);
+        }
+    }
+}
+
+// Positional arguments after the first are further programs when they look
+// like hex output, and SD images otherwise.
+fn is_program_path(arg: &str) -> bool {
+    Path::new(arg)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("hex") || ext.eq_ignore_ascii_case("debug"))
+}
+
+// Parse a decimal or 0x-prefixed hex address.
+fn parse_u32(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse::<u32>().ok(),
+    }
+}
+
+// Parse a `<start>:<end>` trace window (end exclusive).
+fn parse_trace_range(value: &str) -> (u32, u32) {
+    let range = value
+        .split_once(':')
+        .and_then(|(start, end)| Some((parse_u32(start)?, parse_u32(end)?)));
+    match range {
+        Some((start, end)) if start < end => (start, end),
+        _ => {
+            println!("Invalid trace range: {} (expected <start>:<end>)", value);
+            process::exit(1);
+        }
+    }
+}
+
+// Exit statuses for runs stopped by --max-cycles and --timeout-secs or by an
+// emulator panic, so scripts can tell a runaway program from a fault (status 1).
+const EXIT_MAX_CYCLES: i32 = 2;
+const EXIT_TIMEOUT: i32 = 3;
+// The emulator panicked; the panic message is on stderr.
+const EXIT_PANIC: i32 = 4;
+// An exception arrived at the --max-exception-depth nesting limit.
+const EXIT_DOUBLE_FAULT: i32 = 5;
+
+// Print the --bench summary: instructions retired per wall-clock second of the
+// run itself, excluding program load and result reporting.
+fn report_bench(report: &RunReport, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    let mips = if secs > 0.0 {
+        report.retired as f64 / secs / 1_000_000.0
+    } else {
+        0.0
+    };
+    eprintln!(
+        "Bench: {} instructions, {} cycles in {:.3} s ({:.2} MIPS)",
+        report.retired, report.cycles, secs, mips
+    );
+}
+
+// Print the halt reason and the program result; runs that did not halt
+// normally exit with a failure status instead of printing a result.
+fn report_run(report: RunReport) {
+    eprintln!("Halt reason: {} ({} cycles)", report.reason, report.cycles);
+    match report.result() {
+        Some(result) => println!("{:08x}", result),
+        None => {
+            println!("Program did not terminate: {}", report.reason);
+            process::exit(match report.reason {
+                HaltReason::MaxCycles => EXIT_MAX_CYCLES,
+                HaltReason::Timeout => EXIT_TIMEOUT,
+                HaltReason::Panic(_) => EXIT_PANIC,
+                HaltReason::DoubleFault => EXIT_DOUBLE_FAULT,
+                _ => 1,
+            });
+        }
+    }
+}
+
+// Parse a --timeout-secs value; a zero timeout is rejected.
+fn parse_timeout_secs(value: &str) -> u64 {
+    match value.parse::<u64>() {
+        Ok(secs) if secs > 0 => secs,
+        _ => {
+            println!("Invalid timeout: {} (expected whole seconds >= 1)", value);
+            process::exit(1);
+        }
+    }
+}
+
+// Parse a --fps/--ups value; 0 and rates above MAX_REFRESH_RATE are rejected.
+fn parse_refresh_rate(flag: &str, value: &str) -> u32 {
+    match value.parse::<u32>() {
+        Ok(rate) if (1..=MAX_REFRESH_RATE).contains(&rate) => rate,
+        _ => {
+            println!(
+                "Invalid {} value: {} (expected 1..={})",
+                flag, value, MAX_REFRESH_RATE
+            );
+            process::exit(1);
+        }
+    }
+}
+
+// Purpose: write the composited VGA frame for --render-frame.
+// Inputs: output path (format follows the extension) and the run's memory.
+fn write_render_frame(path: &str, memory: &Memory) {
+    render::save_frame(memory, Path::new(path)).unwrap_or_else(|err| {
+        println!("Failed to write frame {}: {}", path, err);
+        process::exit(1);
+    });
+}
+
+// Headless renders of programs that draw and then spin stop at --max-cycles,
+// so that stop is not a failure there.
+fn report_render_run(report: RunReport) {
+    if report.reason == HaltReason::MaxCycles {
+        eprintln!("Halt reason: {} ({} cycles)", report.reason, report.cycles);
+    } else {
+        report_run(report);
+    }
+}
+
 fn main() {
     let args = env::args().collect::<Vec<_>>();
 
     let mut with_graphics = false;
+    let mut ascii_vga = false;
+    let mut ascii_fps: Option<u32> = None;
+    let mut stdin_keys = false;
     let mut audio_mode = AudioMode::Disabled;
     let mut use_uart_rx = false;
     let mut debug = false;
     let mut debugc = false;
+    let mut debug_script: Option<String> = None;
+    let mut undo_depth: Option<usize> = None;
+    let mut key_buffer: Option<usize> = None;
+    let mut physmem: Option<u32> = None;
+    let mut entry: Option<u32> = None;
     let mut trace_interrupts = false;
+    let mut log_exceptions = false;
+    let mut strict_alignment = false;
+    let mut endian = Endianness::Little;
+    let mut max_exception_depth: u32 = u32::MAX;
+    let mut trace = false;
+    let mut trace_file: Option<String> = None;
+    let mut disasm = false;
+    let mut disasm_out: Option<String> = None;
+    let mut profile_out: Option<String> = None;
+    let mut dump_state_out: Option<String> = None;
+    let mut trace_range: Option<(u32, u32)> = None;
     let mut cores: usize = 1;
     let mut sched = ScheduleMode::Free;
     let mut max_cycles: u32 = 0;
+    let mut timeout_secs: Option<u64> = None;
+    let mut watches: Vec<String> = Vec::new();
+    let mut watch_dump = false;
+    let mut tlb_stats = false;
+    let mut bench = false;
     let mut sd_dma_ticks_per_word: u32 = 1;
+    let mut fb_visibility = FramebufferVisibility::WriteThrough;
+    let mut tlb_policy = TlbEvictionPolicy::Fifo;
+    let mut tlb_entries: usize = DEFAULT_TLB_ENTRIES;
     let mut ram_path: Option<String> = None;
+    // Program files after the first, loaded over it in order.
+    let mut extra_ram_paths: Vec<String> = Vec::new();
     let mut sd0_path: Option<String> = None;
     let mut sd1_path: Option<String> = None;
     let mut sd0_out_path: Option<String> = None;
     let mut sd1_out_path: Option<String> = None;
+    let mut sd_image_path: Option<String> = None;
+    let mut uart_input_path: Option<String> = None;
+    let mut render_frame_path: Option<String> = None;
+    let mut window_scale: Option<u32> = None;
+    let mut ps2_set2 = false;
+    let mut fps: Option<u32> = None;
+    let mut ups: Option<u32> = None;
 
     let mut iter = args.iter().skip(1).peekable();
     while let Some(arg) = iter.next() {
         match arg.as_str() {
             "--vga" => with_graphics = true,
+            "--ascii-vga" => ascii_vga = true,
+            "--ascii-fps" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --ascii-fps");
+                    process::exit(1);
+                });
+                ascii_fps = Some(parse_refresh_rate("--ascii-fps", value));
+            }
             "--audio" => {
                 if audio_mode == AudioMode::Fast {
                     println!("Error: --audio and --audio-fast are mutually exclusive");
@@ -74,9 +272,84 @@ fn main() {
                 audio_mode = AudioMode::Fast;
             }
             "--uart" => use_uart_rx = true,
+            "--stdin-keys" => stdin_keys = true,
+            "--ps2-set2" => ps2_set2 = true,
             "--debug" => debug = true,
             "--debugc" => debugc = true,
+            // Runs the script's commands first; implies --debug.
+            "--debug-script" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --debug-script");
+                    process::exit(1);
+                });
+                debug_script = Some(value.clone());
+                debug = true;
+            }
             "--trace-ints" | "--trace-interrupts" => trace_interrupts = true,
+            "--log-exceptions" => log_exceptions = true,
+            "--strict-align" => strict_alignment = true,
+            "--watch-dump" => watch_dump = true,
+            "--tlb-stats" => tlb_stats = true,
+            "--bench" => bench = true,
+            "--watch" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --watch");
+                    process::exit(1);
+                });
+                watches.push(value.clone());
+            }
+            "--entry" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --entry");
+                    process::exit(1);
+                });
+                let addr = parse_u32(value).unwrap_or_else(|| {
+                    println!("Invalid entry address: {}", value);
+                    process::exit(1);
+                });
+                if addr & 3 != 0 {
+                    println!("Entry address {} is not word aligned", value);
+                    process::exit(1);
+                }
+                entry = Some(addr);
+            }
+            "--trace" => trace = true,
+            "--disasm" => disasm = true,
+            "--disasm-out" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --disasm-out");
+                    process::exit(1);
+                });
+                disasm_out = Some(value.clone());
+            }
+            "--profile-out" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --profile-out");
+                    process::exit(1);
+                });
+                profile_out = Some(value.clone());
+            }
+            "--dump-state-on-exit" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --dump-state-on-exit");
+                    process::exit(1);
+                });
+                dump_state_out = Some(value.clone());
+            }
+            "--trace-file" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --trace-file");
+                    process::exit(1);
+                });
+                trace_file = Some(value.clone());
+            }
+            "--trace-range" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --trace-range");
+                    process::exit(1);
+                });
+                trace_range = Some(parse_trace_range(value));
+            }
             "--cores" => {
                 let value = iter.next().unwrap_or_else(|| {
                     println!("Missing value for --cores");
@@ -97,9 +370,10 @@ fn main() {
                     process::exit(1);
                 });
             }
-            "--max-cycles" => {
+            // --max-iters is an alias for --max-cycles.
+            "--max-cycles" | "--max-iters" => {
                 let value = iter.next().unwrap_or_else(|| {
-                    println!("Missing value for --max-cycles");
+                    println!("Missing value for {}", arg);
                     process::exit(1);
                 });
                 max_cycles = value.parse::<u32>().unwrap_or_else(|_| {
@@ -107,6 +381,13 @@ fn main() {
                     process::exit(1);
                 });
             }
+            "--timeout-secs" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --timeout-secs");
+                    process::exit(1);
+                });
+                timeout_secs = Some(parse_timeout_secs(value));
+            }
             "--sd-dma-ticks" => {
                 let value = iter.next().unwrap_or_else(|| {
                     println!("Missing value for --sd-dma-ticks");
@@ -117,12 +398,96 @@ fn main() {
                     process::exit(1);
                 });
             }
+            "--fb-visibility" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --fb-visibility");
+                    process::exit(1);
+                });
+                fb_visibility = FramebufferVisibility::parse(value).unwrap_or_else(|| {
+                    println!("Unknown framebuffer visibility: {}", value);
+                    process::exit(1);
+                });
+            }
+            "--tlb-policy" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --tlb-policy");
+                    process::exit(1);
+                });
+                tlb_policy = TlbEvictionPolicy::parse(value).unwrap_or_else(|| {
+                    println!("Unknown TLB eviction policy: {}", value);
+                    process::exit(1);
+                });
+            }
+            "--endian" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --endian");
+                    process::exit(1);
+                });
+                endian = Endianness::parse(value).unwrap_or_else(|| {
+                    println!("Unknown byte order: {}", value);
+                    process::exit(1);
+                });
+            }
+            "--max-exception-depth" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --max-exception-depth");
+                    process::exit(1);
+                });
+                max_exception_depth = value.parse::<u32>().unwrap_or_else(|_| {
+                    println!("Invalid exception depth: {}", value);
+                    process::exit(1);
+                });
+            }
+            "--physmem" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --physmem");
+                    process::exit(1);
+                });
+                physmem = Some(parse_u32(value).unwrap_or_else(|| {
+                    println!("Invalid physical memory size: {}", value);
+                    process::exit(1);
+                }));
+            }
+            "--key-buffer" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --key-buffer");
+                    process::exit(1);
+                });
+                key_buffer = Some(value.parse::<usize>().unwrap_or_else(|_| {
+                    println!("Invalid key buffer size: {}", value);
+                    process::exit(1);
+                }));
+            }
+            "--undo-depth" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --undo-depth");
+                    process::exit(1);
+                });
+                undo_depth = Some(value.parse::<usize>().unwrap_or_else(|_| {
+                    println!("Invalid undo depth: {}", value);
+                    process::exit(1);
+                }));
+            }
+            "--tlb-entries" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --tlb-entries");
+                    process::exit(1);
+                });
+                tlb_entries = value.parse::<usize>().unwrap_or_else(|_| {
+                    println!("Invalid TLB entry count: {}", value);
+                    process::exit(1);
+                });
+            }
             "--ram" => {
                 let value = iter.next().unwrap_or_else(|| {
                     println!("Missing value for --ram");
                     process::exit(1);
                 });
-                ram_path = Some(value.clone());
+                if ram_path.is_none() {
+                    ram_path = Some(value.clone());
+                } else {
+                    extra_ram_paths.push(value.clone());
+                }
             }
             "--sd0" => {
                 let value = iter.next().unwrap_or_else(|| {
@@ -152,6 +517,51 @@ fn main() {
                 });
                 sd1_out_path = Some(value.clone());
             }
+            "--uart-input" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --uart-input");
+                    process::exit(1);
+                });
+                uart_input_path = Some(value.clone());
+            }
+            "--window-scale" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --window-scale");
+                    process::exit(1);
+                });
+                window_scale = Some(value.parse::<u32>().unwrap_or_else(|_| {
+                    println!("Invalid window scale: {}", value);
+                    process::exit(1);
+                }));
+            }
+            "--fps" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --fps");
+                    process::exit(1);
+                });
+                fps = Some(parse_refresh_rate("--fps", value));
+            }
+            "--ups" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --ups");
+                    process::exit(1);
+                });
+                ups = Some(parse_refresh_rate("--ups", value));
+            }
+            "--render-frame" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --render-frame");
+                    process::exit(1);
+                });
+                render_frame_path = Some(value.clone());
+            }
+            "--sd-image" => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("Missing value for --sd-image");
+                    process::exit(1);
+                });
+                sd_image_path = Some(value.clone());
+            }
             _ if arg.starts_with("--cores=") => {
                 let value = &arg["--cores=".len()..];
                 cores = value.parse::<usize>().unwrap_or_else(|_| {
@@ -166,16 +576,27 @@ fn main() {
                     process::exit(1);
                 });
             }
-            _ if arg.starts_with("--max-cycles=") => {
-                let value = &arg["--max-cycles=".len()..];
+            _ if arg.starts_with("--max-cycles=") || arg.starts_with("--max-iters=") => {
+                let value = &arg[arg.find('=').unwrap() + 1..];
                 max_cycles = value.parse::<u32>().unwrap_or_else(|_| {
                     println!("Invalid max cycle count: {}", value);
                     process::exit(1);
                 });
             }
+            _ if arg.starts_with("--watch=") => {
+                watches.push(arg["--watch=".len()..].to_string());
+            }
+            _ if arg.starts_with("--timeout-secs=") => {
+                let value = &arg["--timeout-secs=".len()..];
+                timeout_secs = Some(parse_timeout_secs(value));
+            }
             _ if arg.starts_with("--ram=") => {
                 let value = &arg["--ram=".len()..];
-                ram_path = Some(value.to_string());
+                if ram_path.is_none() {
+                    ram_path = Some(value.to_string());
+                } else {
+                    extra_ram_paths.push(value.to_string());
+                }
             }
             _ if arg.starts_with("--sd0=") => {
                 let value = &arg["--sd0=".len()..];
@@ -193,6 +614,54 @@ fn main() {
                 let value = &arg["--sd1-out=".len()..];
                 sd1_out_path = Some(value.to_string());
             }
+            _ if arg.starts_with("--disasm-out=") => {
+                disasm_out = Some(arg["--disasm-out=".len()..].to_string());
+            }
+            _ if arg.starts_with("--profile-out=") => {
+                profile_out = Some(arg["--profile-out=".len()..].to_string());
+            }
+            _ if arg.starts_with("--dump-state-on-exit=") => {
+                dump_state_out = Some(arg["--dump-state-on-exit=".len()..].to_string());
+            }
+            _ if arg.starts_with("--trace-file=") => {
+                let value = &arg["--trace-file=".len()..];
+                trace_file = Some(value.to_string());
+            }
+            _ if arg.starts_with("--trace-range=") => {
+                let value = &arg["--trace-range=".len()..];
+                trace_range = Some(parse_trace_range(value));
+            }
+            _ if arg.starts_with("--uart-input=") => {
+                let value = &arg["--uart-input=".len()..];
+                uart_input_path = Some(value.to_string());
+            }
+            _ if arg.starts_with("--window-scale=") => {
+                let value = &arg["--window-scale=".len()..];
+                window_scale = Some(value.parse::<u32>().unwrap_or_else(|_| {
+                    println!("Invalid window scale: {}", value);
+                    process::exit(1);
+                }));
+            }
+            _ if arg.starts_with("--fps=") => {
+                fps = Some(parse_refresh_rate("--fps", &arg["--fps=".len()..]));
+            }
+            _ if arg.starts_with("--ups=") => {
+                ups = Some(parse_refresh_rate("--ups", &arg["--ups=".len()..]));
+            }
+            _ if arg.starts_with("--ascii-fps=") => {
+                ascii_fps = Some(parse_refresh_rate(
+                    "--ascii-fps",
+                    &arg["--ascii-fps=".len()..],
+                ));
+            }
+            _ if arg.starts_with("--render-frame=") => {
+                let value = &arg["--render-frame=".len()..];
+                render_frame_path = Some(value.to_string());
+            }
+            _ if arg.starts_with("--sd-image=") => {
+                let value = &arg["--sd-image=".len()..];
+                sd_image_path = Some(value.to_string());
+            }
             _ if arg.starts_with("--sd-dma-ticks=") => {
                 let value = &arg["--sd-dma-ticks=".len()..];
                 sd_dma_ticks_per_word = value.parse::<u32>().unwrap_or_else(|_| {
@@ -200,6 +669,62 @@ fn main() {
                     process::exit(1);
                 });
             }
+            _ if arg.starts_with("--fb-visibility=") => {
+                let value = &arg["--fb-visibility=".len()..];
+                fb_visibility = FramebufferVisibility::parse(value).unwrap_or_else(|| {
+                    println!("Unknown framebuffer visibility: {}", value);
+                    process::exit(1);
+                });
+            }
+            _ if arg.starts_with("--tlb-policy=") => {
+                let value = &arg["--tlb-policy=".len()..];
+                tlb_policy = TlbEvictionPolicy::parse(value).unwrap_or_else(|| {
+                    println!("Unknown TLB eviction policy: {}", value);
+                    process::exit(1);
+                });
+            }
+            _ if arg.starts_with("--endian=") => {
+                let value = &arg["--endian=".len()..];
+                endian = Endianness::parse(value).unwrap_or_else(|| {
+                    println!("Unknown byte order: {}", value);
+                    process::exit(1);
+                });
+            }
+            _ if arg.starts_with("--max-exception-depth=") => {
+                let value = &arg["--max-exception-depth=".len()..];
+                max_exception_depth = value.parse::<u32>().unwrap_or_else(|_| {
+                    println!("Invalid exception depth: {}", value);
+                    process::exit(1);
+                });
+            }
+            _ if arg.starts_with("--physmem=") => {
+                let value = &arg["--physmem=".len()..];
+                physmem = Some(parse_u32(value).unwrap_or_else(|| {
+                    println!("Invalid physical memory size: {}", value);
+                    process::exit(1);
+                }));
+            }
+            _ if arg.starts_with("--key-buffer=") => {
+                let value = &arg["--key-buffer=".len()..];
+                key_buffer = Some(value.parse::<usize>().unwrap_or_else(|_| {
+                    println!("Invalid key buffer size: {}", value);
+                    process::exit(1);
+                }));
+            }
+            _ if arg.starts_with("--undo-depth=") => {
+                let value = &arg["--undo-depth=".len()..];
+                undo_depth = Some(value.parse::<usize>().unwrap_or_else(|_| {
+                    println!("Invalid undo depth: {}", value);
+                    process::exit(1);
+                }));
+            }
+            _ if arg.starts_with("--tlb-entries=") => {
+                let value = &arg["--tlb-entries=".len()..];
+                tlb_entries = value.parse::<usize>().unwrap_or_else(|_| {
+                    println!("Invalid TLB entry count: {}", value);
+                    process::exit(1);
+                });
+            }
             _ if arg.starts_with('-') => {
                 println!("Unknown flag: {}", arg);
                 process::exit(1);
@@ -207,6 +732,8 @@ fn main() {
             _ => {
                 if ram_path.is_none() {
                     ram_path = Some(arg.clone());
+                } else if is_program_path(arg) {
+                    extra_ram_paths.push(arg.clone());
                 } else if sd0_path.is_none() {
                     sd0_path = Some(arg.clone());
                 } else if sd1_path.is_none() {
@@ -224,6 +751,19 @@ fn main() {
         print_usage_and_exit();
     };
 
+    // --sd-image backs SD0 with one file: load it if it exists (a missing
+    // file is an empty card) and write the final image back on exit.
+    if let Some(path) = sd_image_path {
+        if sd0_path.is_some() || sd0_out_path.is_some() {
+            println!("Error: --sd-image cannot be combined with an SD0 image or --sd0-out");
+            process::exit(1);
+        }
+        if Path::new(&path).exists() {
+            sd0_path = Some(path.clone());
+        }
+        sd0_out_path = Some(path);
+    }
+
     let sd0_image = sd0_path.as_ref().map(|path| {
         fs::read(path).unwrap_or_else(|err| {
             println!("Failed to read SD0 image {}: {}", path, err);
@@ -237,20 +777,135 @@ fn main() {
         })
     });
 
+    // Scripted serial input is read through UART_RX, so it implies --uart.
+    let uart_input = uart_input_path.as_ref().map(|path| {
+        fs::read(path).unwrap_or_else(|err| {
+            println!("Failed to read UART input {}: {}", path, err);
+            process::exit(1);
+        })
+    });
+    if uart_input.is_some() {
+        use_uart_rx = true;
+    }
+
     set_trace_interrupts(trace_interrupts);
+    set_log_exceptions(log_exceptions);
+    let physmem_size = physmem.unwrap_or(DEFAULT_PHYSMEM_SIZE);
+    if let Err(err) = check_physmem_size(physmem_size) {
+        println!("Invalid physical memory size 0x{:X}: {}", physmem_size, err);
+        process::exit(1);
+    }
+    if max_exception_depth == 0 {
+        println!("--max-exception-depth must be >= 1");
+        process::exit(1);
+    }
+    if tlb_entries == 0 {
+        println!("--tlb-entries must be >= 1");
+        process::exit(1);
+    }
+    if key_buffer == Some(0) {
+        println!("--key-buffer must be >= 1");
+        process::exit(1);
+    }
+    let mut config = EmulatorConfig {
+        tlb_entries,
+        tlb_policy,
+        strict_alignment,
+        endianness: endian,
+        max_exception_depth,
+        undo_depth: undo_depth.unwrap_or(DEFAULT_UNDO_DEPTH),
+        // Fetch counting costs a little per instruction, so it is only on when
+        // the profile is exported or a debugger can show it.
+        fetch_profiling: profile_out.is_some() || debug || debugc,
+        key_buffer_cap: key_buffer.unwrap_or(DEFAULT_KEY_BUFFER_CAP),
+        physmem_size,
+        entry,
+        extra_programs: extra_ram_paths,
+        // Opened below, after --disasm has had its chance to exit.
+        instr_trace: None,
+    };
+    // --disasm-out implies --disasm: list the program and exit without running it.
+    if disasm || disasm_out.is_some() {
+        let listing = disassemble_program(&ram_path, &config);
+        match &disasm_out {
+            Some(path) => fs::write(path, listing).unwrap_or_else(|err| {
+                println!("Failed to write disassembly {}: {}", path, err);
+                process::exit(1);
+            }),
+            None => print!("{}", listing),
+        }
+        return;
+    }
+    let mut options = RunOptions::default();
+    for spec in &watches {
+        if let Err(msg) = options.add_watchpoint(spec) {
+            println!("{}", msg);
+            process::exit(1);
+        }
+    }
+    options.watch_dump = watch_dump;
+    options.dump_state = dump_state_out.is_some();
+    options.timeout = timeout_secs.map(Duration::from_secs);
+    // --trace-file and --trace-range imply --trace; the trace goes to stderr
+    // unless a file is given so it never mixes with UART output.
+    if trace || trace_file.is_some() || trace_range.is_some() {
+        let sink: Box<dyn io::Write + Send> = match &trace_file {
+            Some(path) => Box::new(LineWriter::new(File::create(path).unwrap_or_else(|err| {
+                println!("Failed to create trace file {}: {}", path, err);
+                process::exit(1);
+            }))),
+            None => Box::new(io::stderr()),
+        };
+        config.instr_trace = Some(Arc::new(InstrTrace::new(sink, trace_range)));
+    }
     if sd_dma_ticks_per_word == 0 {
         println!("--sd-dma-ticks must be >= 1");
         process::exit(1);
     }
+    let mut window = WindowConfig::default();
+    if let Some(scale) = window_scale {
+        if !(1..=MAX_WINDOW_SCALE).contains(&scale) {
+            println!("--window-scale must be in 1..={}", MAX_WINDOW_SCALE);
+            process::exit(1);
+        }
+        window.scale = scale;
+    }
+    if let Some(fps) = fps {
+        window.max_fps = fps;
+    }
+    window.ps2_set2 = ps2_set2;
+    if let Some(ups) = ups {
+        window.ups = ups;
+    }
+    options.window = window;
     if debug && debugc {
         println!("Error: --debug and --debugc are mutually exclusive");
         process::exit(1);
     }
+    if with_graphics && render_frame_path.is_some() {
+        println!("Error: --vga and --render-frame are mutually exclusive");
+        process::exit(1);
+    }
+    if with_graphics && ascii_vga {
+        println!("Error: --vga and --ascii-vga are mutually exclusive");
+        process::exit(1);
+    }
+    if with_graphics && stdin_keys {
+        println!("Error: --vga and --stdin-keys are mutually exclusive");
+        process::exit(1);
+    }
+    // --ascii-fps implies --ascii-vga.
+    if ascii_fps.is_some() {
+        ascii_vga = true;
+    }
     // file to run is passed as a command line argument
     if debugc {
         if with_graphics {
             println!("Warning: --vga is ignored in debugc mode");
         }
+        if ascii_vga {
+            println!("Warning: --ascii-vga is ignored in debugc mode");
+        }
         if audio_mode != AudioMode::Disabled {
             println!("Warning: host audio flags are ignored in debugc mode");
         }
@@ -263,12 +918,37 @@ fn main() {
         if max_cycles != 0 {
             println!("Warning: --max-cycles is ignored in debugc mode");
         }
+        if timeout_secs.is_some() {
+            println!("Warning: --timeout-secs is ignored in debugc mode");
+        }
+        if !watches.is_empty() || watch_dump {
+            println!("Warning: --watch is ignored in debugc mode; use the watch command");
+        }
+        if fb_visibility != FramebufferVisibility::WriteThrough {
+            println!("Warning: --fb-visibility is ignored in debugc mode");
+        }
+        if uart_input.is_some() {
+            println!("Warning: --uart-input is ignored in debugc mode");
+        }
+        if stdin_keys {
+            println!("Warning: --stdin-keys is ignored in debugc mode");
+        }
+        if render_frame_path.is_some() {
+            println!("Warning: --render-frame is ignored in debugc mode");
+        }
+        if tlb_stats {
+            println!("Warning: --tlb-stats is ignored in debugc mode; use info tlbstats");
+        }
+        if bench {
+            println!("Warning: --bench is ignored in debugc mode");
+        }
         let cpu = Emulator::debug_c(
             ram_path,
             use_uart_rx,
             sd_dma_ticks_per_word,
             sd0_image.as_deref(),
             sd1_image.as_deref(),
+            &config,
         );
         write_sd_export(sd0_out_path.as_deref(), SdSlot::Sd0, || {
             cpu.dump_sd_image(SdSlot::Sd0)
@@ -276,10 +956,15 @@ fn main() {
         write_sd_export(sd1_out_path.as_deref(), SdSlot::Sd1, || {
             cpu.dump_sd_image(SdSlot::Sd1)
         });
+        write_profile(profile_out.as_deref(), &cpu.shared_memory());
+        write_state_dump(dump_state_out.as_deref(), &[cpu.state_dump()]);
     } else if debug {
         if with_graphics {
             println!("Warning: --vga is ignored in debug mode");
         }
+        if ascii_vga {
+            println!("Warning: --ascii-vga is ignored in debug mode");
+        }
         if audio_mode != AudioMode::Disabled {
             println!("Warning: host audio flags are ignored in debug mode");
         }
@@ -292,12 +977,45 @@ fn main() {
         if max_cycles != 0 {
             println!("Warning: --max-cycles is ignored in debug mode");
         }
+        if timeout_secs.is_some() {
+            println!("Warning: --timeout-secs is ignored in debug mode");
+        }
+        if !watches.is_empty() || watch_dump {
+            println!("Warning: --watch is ignored in debug mode; use the watch command");
+        }
+        if fb_visibility != FramebufferVisibility::WriteThrough {
+            println!("Warning: --fb-visibility is ignored in debug mode");
+        }
+        if uart_input.is_some() {
+            println!("Warning: --uart-input is ignored in debug mode");
+        }
+        if stdin_keys {
+            println!("Warning: --stdin-keys is ignored in debug mode");
+        }
+        if render_frame_path.is_some() {
+            println!("Warning: --render-frame is ignored in debug mode");
+        }
+        if tlb_stats {
+            println!("Warning: --tlb-stats is ignored in debug mode; use info tlbstats");
+        }
+        if bench {
+            println!("Warning: --bench is ignored in debug mode");
+        }
+        let script = match debug_script {
+            Some(path) => read_debug_script(&path).unwrap_or_else(|err| {
+                println!("{}", err);
+                process::exit(1);
+            }),
+            None => Vec::new(),
+        };
         let cpu = Emulator::debug(
             ram_path,
             use_uart_rx,
             sd_dma_ticks_per_word,
             sd0_image.as_deref(),
             sd1_image.as_deref(),
+            &config,
+            script,
         );
         write_sd_export(sd0_out_path.as_deref(), SdSlot::Sd0, || {
             cpu.dump_sd_image(SdSlot::Sd0)
@@ -305,7 +1023,30 @@ fn main() {
         write_sd_export(sd1_out_path.as_deref(), SdSlot::Sd1, || {
             cpu.dump_sd_image(SdSlot::Sd1)
         });
+        write_profile(profile_out.as_deref(), &cpu.shared_memory());
+        write_state_dump(dump_state_out.as_deref(), &[cpu.state_dump()]);
     } else {
+        if bench {
+            // Measure emulation alone: no window, terminal frames or host audio.
+            if with_graphics {
+                println!("Warning: --vga is ignored with --bench");
+                with_graphics = false;
+            }
+            if ascii_vga {
+                println!("Warning: --ascii-vga is ignored with --bench");
+                ascii_vga = false;
+            }
+            if audio_mode != AudioMode::Disabled {
+                println!("Warning: host audio flags are ignored with --bench");
+                audio_mode = AudioMode::Disabled;
+            }
+            if cores != 1 {
+                println!("Error: --bench measures a single core; drop --cores");
+                process::exit(1);
+            }
+        }
+        options.ascii_console = ascii_vga.then(|| ascii_fps.unwrap_or(DEFAULT_CONSOLE_RATE));
+        options.stdin_keys = stdin_keys;
         if cores == 0 || cores > 4 {
             println!("--cores must be in 1..=4");
             process::exit(1);
@@ -317,20 +1058,40 @@ fn main() {
                 sd_dma_ticks_per_word,
                 sd0_image.as_deref(),
                 sd1_image.as_deref(),
+                &config,
             );
+            if let Some(input) = &uart_input {
+                cpu.queue_uart_input(input);
+            }
             let memory = cpu.shared_memory();
-            let result = cpu
-                .run(max_cycles, with_graphics, audio_mode)
-                .expect("did not terminate"); // programs should return a value in r1
+            memory.set_framebuffer_visibility(fb_visibility);
+            // programs should return a value in r1
+            let started = Instant::now();
+            let report = cpu.run_report_with(max_cycles, with_graphics, audio_mode, &options);
+            let elapsed = started.elapsed();
             write_sd_export(sd0_out_path.as_deref(), SdSlot::Sd0, || {
                 memory.dump_sd_image(SdSlot::Sd0)
             });
             write_sd_export(sd1_out_path.as_deref(), SdSlot::Sd1, || {
                 memory.dump_sd_image(SdSlot::Sd1)
             });
-            println!("{:08x}", result);
+            write_profile(profile_out.as_deref(), &memory);
+            write_state_dump(dump_state_out.as_deref(), &report.states);
+            if tlb_stats {
+                eprintln!("{}", report.tlb);
+            }
+            if bench {
+                report_bench(&report, elapsed);
+            }
+            match &render_frame_path {
+                Some(path) => {
+                    write_render_frame(path, &memory);
+                    report_render_run(report);
+                }
+                None => report_run(report),
+            }
         } else {
-            let (result, memory) = Emulator::run_multicore_with_memory(
+            let (report, memory) = Emulator::run_multicore_with_memory(
                 ram_path,
                 cores,
                 sched,
@@ -338,18 +1099,32 @@ fn main() {
                 with_graphics,
                 audio_mode,
                 use_uart_rx,
+                uart_input.as_deref().unwrap_or(&[]),
                 sd_dma_ticks_per_word,
                 sd0_image.as_deref(),
                 sd1_image.as_deref(),
+                fb_visibility,
+                &config,
+                &options,
             );
-            let result = result.expect("did not terminate");
             write_sd_export(sd0_out_path.as_deref(), SdSlot::Sd0, || {
                 memory.dump_sd_image(SdSlot::Sd0)
             });
             write_sd_export(sd1_out_path.as_deref(), SdSlot::Sd1, || {
                 memory.dump_sd_image(SdSlot::Sd1)
             });
-            println!("{:08x}", result);
+            write_profile(profile_out.as_deref(), &memory);
+            write_state_dump(dump_state_out.as_deref(), &report.states);
+            if tlb_stats {
+                eprintln!("{}", report.tlb);
+            }
+            match &render_frame_path {
+                Some(path) => {
+                    write_render_frame(path, &memory);
+                    report_render_run(report);
+                }
+                None => report_run(report),
+            }
         }
     }
 }
@@ -3,7 +3,7 @@ use std::collections::VecDeque;
 use std::convert::TryFrom;
 
 use std::io::{self, Write};
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time::Duration;
@@ -137,15 +137,19 @@ const TILE_FRAME_BUFFER_SIZE: u32 =
 // Align the tile framebuffer to the 4KB page size for TLB mappings.
 const TILE_FRAME_BUFFER_START: u32 = (PIXEL_FRAME_BUFFER_START - TILE_FRAME_BUFFER_SIZE) & !0xFFF;
 const IO_START: u32 = AUDIO_RING_BUFFER_START;
-const RAM_PAGE_SIZE: usize = 4096;
+pub const RAM_PAGE_SIZE: usize = 4096;
 const RAM_PAGE_SHIFT: u32 = 12;
 const RAM_PAGE_MASK: usize = RAM_PAGE_SIZE - 1;
-const RAM_PAGE_COUNT: usize = (IO_START as usize) / RAM_PAGE_SIZE;
 
 const PS2_STREAM: u32 = 0x7FE5800;
 const UART_TX: u32 = 0x7FE5802;
 const UART_RX: u32 = 0x7FE5803;
+// PIT: writing the reload value at PIT_START (re)starts the countdown; reading
+// it returns the live count. Bit 0 of PIT_CTRL selects one-shot mode, in which
+// the counter stops at 0 instead of reloading.
 pub const PIT_START: u32 = 0x7FE5804;
+pub const PIT_CTRL_START: u32 = 0x7FE5808;
+pub const PIT_CTRL_ONESHOT: u32 = 1 << 0;
 
 const SD_DMA_MEM_ADDR: u32 = 0x7FE5810;
 const SD2_DMA_MEM_ADDR: u32 = 0x7FE5828;
@@ -162,6 +166,10 @@ const SD_DMA_CTRL_START: u32 = 1 << 0;
 const SD_DMA_CTRL_DIR_RAM_TO_SD: u32 = 1 << 1;
 const SD_DMA_CTRL_IRQ_ENABLE: u32 = 1 << 2;
 const SD_DMA_CTRL_INIT: u32 = 1 << 3;
+// CRC mode, the DMA counterpart of CMD59: each block in RAM is followed by a
+// trailer word holding its CRC16 in the low halfword. Reads write the trailer,
+// writes check it and reject a block whose CRC does not match.
+const SD_DMA_CTRL_CRC: u32 = 1 << 4;
 
 const SD_DMA_STATUS_BUSY: u32 = 1 << 0;
 const SD_DMA_STATUS_DONE: u32 = 1 << 1;
@@ -171,6 +179,11 @@ const SD_DMA_ERR_NONE: u32 = 0;
 const SD_DMA_ERR_BUSY: u32 = 1;
 const SD_DMA_ERR_ZERO_LEN: u32 = 2;
 const SD_DMA_ERR_NOT_INITIALIZED: u32 = 3;
+const SD_DMA_ERR_CRC: u32 = 4;
+// The transfer's RAM range leaves physical memory.
+const SD_DMA_ERR_RANGE: u32 = 5;
+// Bytes of RAM per block in CRC mode: the block plus its trailer word.
+const SD_CRC_FRAME_SIZE: u32 = SD_BLOCK_SIZE_U32 + 4;
 const SD_INIT_TICKS: u32 = 32;
 
 const SPRITE_COUNT: u32 = 16;
@@ -185,43 +198,248 @@ const PIXEL_H_SCROLL_START: u32 = 0x7FE5B50;
 const PIXEL_V_SCROLL_START: u32 = 0x7FE5B52;
 const PIXEL_SCALE_REGISTER_START: u32 = 0x7FE5B54; // each pixel is repeated 2^(n+1) times
 
+// Background tile layer, drawn between the pixel layer and the primary tile
+// layer with its own scroll pair (for parallax). It shares the tile map, tile
+// scale and palette mode with the primary layer; entries pointing at tile 0
+// are empty, so the all-zero power-on state leaves the layer blank.
+const BG_TILE_H_SCROLL_START: u32 = 0x7FE5B58;
+const BG_TILE_V_SCROLL_START: u32 = 0x7FE5B5A;
+const BG_TILE_FRAME_BUFFER_START: u32 = 0x7FF8000;
+
+// Indexed color: PALETTE_MODE selects, per layer, whether pixel/tile values
+// are direct 12-bit colors (bit clear, the default) or palette indices (low
+// nibble of the low byte; tile entries with the tile-color flag use the low
+// nibble of the entry's color byte). Transparency flags are unchanged.
+// PALETTE holds PALETTE_ENTRIES little-endian u16 colors in the same 12-bit
+// layout as direct pixels (bits 0-3 red, 4-7 green, 8-11 blue).
+const PALETTE_MODE_REGISTER_START: u32 = 0x7FE5B56;
+pub const PALETTE_MODE_PIXEL: u8 = 1 << 0;
+pub const PALETTE_MODE_TILE: u8 = 1 << 1;
+const PALETTE_START: u32 = 0x7FE5B80;
+pub const PALETTE_ENTRIES: usize = 16;
+const PALETTE_SIZE: u32 = PALETTE_ENTRIES as u32 * 2;
+// Power-on palette: the 16 CGA colors.
+const DEFAULT_PALETTE: [u16; PALETTE_ENTRIES] = [
+    0x000, 0xA00, 0x0A0, 0xAA0, 0x00A, 0xA0A, 0x05A, 0xAAA, 0x555, 0xF55, 0x5F5, 0xFF5, 0x55F,
+    0xF5F, 0x5FF, 0xFFF,
+];
+
 const SPRITE_SCALE_START: u32 = 0x7FE5B60;
 const SPRITE_SCALE_SIZE: u32 = SPRITE_COUNT;
+// One draw-priority byte per sprite: higher values are composited on top, and
+// equal priorities keep sprite-map order (higher index on top). Resets to 0.
+pub const SPRITE_PRIORITY_START: u32 = 0x7FE5BA0;
+const SPRITE_PRIORITY_SIZE: u32 = SPRITE_COUNT;
 const VGA_STATUS_REGISTER_START: u32 = 0x7FE5B46;
 const VGA_FRAME_REGISTER_START: u32 = 0x7FE5B48;
 
 pub const CLK_REG_START: u32 = 0x7FE5B4C;
 
+// Mouse registers, updated by the VGA window and read-only to the guest:
+// - MOUSE_X (u16): cursor x in logical FRAME_WIDTH x FRAME_HEIGHT coordinates
+// - MOUSE_Y (u16): cursor y in the same space
+// - MOUSE_BUTTONS (u8): held buttons, see the MOUSE_BUTTON_* bits
+// The registers keep their last value while the cursor is outside the window.
+const MOUSE_X_START: u32 = 0x7FE5B70;
+const MOUSE_Y_START: u32 = 0x7FE5B72;
+const MOUSE_BUTTONS_START: u32 = 0x7FE5B74;
+const MOUSE_REGISTERS_SIZE: u32 = 5;
+pub const MOUSE_BUTTON_LEFT: u8 = 1 << 0;
+pub const MOUSE_BUTTON_RIGHT: u8 = 1 << 1;
+pub const MOUSE_BUTTON_MIDDLE: u8 = 1 << 2;
+
 const TILE_MAP_START: u32 = 0x7FE8000;
 const TILE_MAP_SIZE: u32 = 0x8000;
 
 const SPRITE_MAP_START: u32 = 0x7FF0000;
 const SPRITE_MAP_SIZE: u32 = 0x8000;
 
+// What backs a range of the physical memory map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemRegionKind {
+    Ram,
+    // Device control/status registers.
+    Registers,
+    // Device-owned memory such as the audio ring and tile/sprite maps.
+    Buffer,
+    Framebuffer,
+}
+
+// One range of the physical memory map, `start..end` (end exclusive).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemRegion {
+    pub name: &'static str,
+    pub start: u32,
+    pub end: u32,
+    pub kind: MemRegionKind,
+}
+
+const fn mem_region(name: &'static str, start: u32, size: u32, kind: MemRegionKind) -> MemRegion {
+    MemRegion {
+        name,
+        start,
+        end: start + size,
+        kind,
+    }
+}
+
+// The device half of the physical memory map in address order, built from the
+// same constants as the MMIO dispatch; `Memory::regions` puts RAM in front. A
+// guest access to an IO address outside every region panics with "unmapped IO
+// address"; `peek` returns None for it instead.
+const DEVICE_MAP: [MemRegion; 22] = [
+    mem_region(
+        "audio_ring",
+        AUDIO_RING_BUFFER_START,
+        AUDIO_RING_BUFFER_SIZE,
+        MemRegionKind::Buffer,
+    ),
+    mem_region(
+        "synth_audio",
+        SYNTH_AUDIO_START,
+        SYNTH_AUDIO_SIZE,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "tile_fb",
+        TILE_FRAME_BUFFER_START,
+        TILE_FRAME_BUFFER_SIZE,
+        MemRegionKind::Framebuffer,
+    ),
+    mem_region(
+        "pixel_fb",
+        PIXEL_FRAME_BUFFER_START,
+        PIXEL_FRAME_BUFFER_SIZE,
+        MemRegionKind::Framebuffer,
+    ),
+    mem_region("ps2", PS2_STREAM, 2, MemRegionKind::Registers),
+    mem_region("uart", UART_TX, 2, MemRegionKind::Registers),
+    mem_region("pit", PIT_START, 8, MemRegionKind::Registers),
+    mem_region(
+        "sd0_dma",
+        SD_DMA_MEM_ADDR,
+        SD_DMA_RANGE_SIZE,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "sd1_dma",
+        SD2_DMA_MEM_ADDR,
+        SD_DMA_RANGE_SIZE,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "audio_regs",
+        AUDIO_CTRL_START,
+        AUDIO_WATERMARK_START + 4 - AUDIO_CTRL_START,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "sprite_regs",
+        SPRITE_REGISTERS_START,
+        SPRITE_REGISTERS_SIZE,
+        MemRegionKind::Registers,
+    ),
+    // Tile scroll and scale, VGA status and the VGA frame counter.
+    mem_region(
+        "vga_regs",
+        TILE_H_SCROLL_START,
+        VGA_FRAME_REGISTER_START + 4 - TILE_H_SCROLL_START,
+        MemRegionKind::Registers,
+    ),
+    mem_region("clock", CLK_REG_START, 4, MemRegionKind::Registers),
+    // Pixel scroll and scale and the palette mode register.
+    mem_region(
+        "pixel_regs",
+        PIXEL_H_SCROLL_START,
+        PALETTE_MODE_REGISTER_START + 1 - PIXEL_H_SCROLL_START,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "bg_tile_regs",
+        BG_TILE_H_SCROLL_START,
+        4,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "sprite_scale",
+        SPRITE_SCALE_START,
+        SPRITE_SCALE_SIZE,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "mouse",
+        MOUSE_X_START,
+        MOUSE_REGISTERS_SIZE,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "palette",
+        PALETTE_START,
+        PALETTE_SIZE,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "sprite_priority",
+        SPRITE_PRIORITY_START,
+        SPRITE_PRIORITY_SIZE,
+        MemRegionKind::Registers,
+    ),
+    mem_region(
+        "tile_map",
+        TILE_MAP_START,
+        TILE_MAP_SIZE,
+        MemRegionKind::Buffer,
+    ),
+    mem_region(
+        "sprite_map",
+        SPRITE_MAP_START,
+        SPRITE_MAP_SIZE,
+        MemRegionKind::Buffer,
+    ),
+    mem_region(
+        "bg_tile_fb",
+        BG_TILE_FRAME_BUFFER_START,
+        TILE_FRAME_BUFFER_SIZE,
+        MemRegionKind::Framebuffer,
+    ),
+];
+
 pub struct Memory {
     // Ordinary RAM is sharded by 4KB page so unrelated cores can access
     // different pages concurrently. Each page lock also guards lazy allocation.
     ram_pages: Box<[RwLock<RamPage>]>,
+    // RAM covers 0..physmem_size; see DEFAULT_PHYSMEM_SIZE.
+    physmem_size: u32,
     // Multi-byte MMIO operations must stay tear-free even though device state is
     // stored behind separate locks, so MMIO accesses share one sequencing lock.
     mmio_lock: Mutex<()>,
     pixel_frame_buffer: Arc<RwLock<PixelFrameBuffer>>,
     tile_frame_buffer: Arc<RwLock<TileFrameBuffer>>,
+    bg_tile_frame_buffer: Arc<RwLock<TileFrameBuffer>>,
     tile_map: Arc<RwLock<TileMap>>,
     io_buffer: Arc<RwLock<VecDeque<u16>>>,
+    // Most window input entries `io_buffer` may hold; see DEFAULT_KEY_BUFFER_CAP.
+    key_buffer_cap: usize,
     input_pending: Arc<AtomicBool>,
     tile_vscroll_register: Arc<RwLock<(u8, u8)>>,
     tile_hscroll_register: Arc<RwLock<(u8, u8)>>,
+    bg_tile_vscroll_register: Arc<RwLock<(u8, u8)>>,
+    bg_tile_hscroll_register: Arc<RwLock<(u8, u8)>>,
     pixel_vscroll_register: Arc<RwLock<(u8, u8)>>,
     pixel_hscroll_register: Arc<RwLock<(u8, u8)>>,
     tile_scale_register: Arc<RwLock<u8>>,
     pixel_scale_register: Arc<RwLock<u8>>,
     sprite_scale_registers: Arc<RwLock<Vec<u8>>>,
+    palette_mode_register: Arc<RwLock<u8>>,
+    palette: Arc<RwLock<[u16; PALETTE_ENTRIES]>>,
     vga_status_register: Arc<RwLock<u8>>,
     vga_frame_register: Arc<RwLock<(u8, u8, u8, u8)>>,
     clk_register: Arc<RwLock<(u8, u8, u8, u8)>>,
+    // (x, y, buttons)
+    mouse_register: Arc<RwLock<(u16, u16, u8)>>,
     pit_reload: Arc<AtomicU32>,
     pit_countdown: Arc<Mutex<u32>>,
+    pit_ctrl: Arc<AtomicU32>,
     sprite_map: Arc<RwLock<SpriteMap>>,
     sd_card: Arc<RwLock<SdCard>>,
     sd_card2: Arc<RwLock<SdCard>>,
@@ -230,12 +448,203 @@ pub struct Memory {
     fast_audio_active: AtomicBool,
     pending_interrupt: Arc<AtomicU32>,
     use_uart_rx: bool,
+    // UART_TX output goes here; None writes to stdout with a flush per byte.
+    uart_tx_sink: Mutex<Option<Box<dyn Write + Send>>>,
+    access_stats: MemStats,
 }
 
 struct RamPage {
     bytes: [u8; RAM_PAGE_SIZE],
 }
 
+// Device regions tracked by the access counters; the discriminant indexes
+// STATS_REGIONS for the display name.
+#[derive(Clone, Copy, PartialEq)]
+enum StatsRegion {
+    Ram,
+    AudioRing,
+    AudioRegs,
+    SynthAudio,
+    TileFb,
+    PixelFb,
+    TileMap,
+    SpriteMap,
+    SpriteRegs,
+    VgaRegs,
+    Sd0Dma,
+    Sd1Dma,
+    Ps2,
+    Mouse,
+    Uart,
+    Pit,
+    Clock,
+    OtherIo,
+}
+
+const STATS_REGIONS: [&str; 18] = [
+    "ram",
+    "audio_ring",
+    "audio_regs",
+    "synth_audio",
+    "tile_fb",
+    "pixel_fb",
+    "tile_map",
+    "sprite_map",
+    "sprite_regs",
+    "vga_regs",
+    "sd0_dma",
+    "sd1_dma",
+    "ps2",
+    "mouse",
+    "uart",
+    "pit",
+    "clock",
+    "other_io",
+];
+
+// Purpose: most entries the VGA window may leave queued for PS2_STREAM /
+// UART_RX (--key-buffer), so a program that never reads input cannot grow
+// the queue without bound. Reads drain it and make room again; scripted
+// --uart-input bytes are not limited.
+pub const DEFAULT_KEY_BUFFER_CAP: usize = 256;
+
+// Purpose: bytes of RAM at the bottom of physical memory (--physmem).
+// Invariants: the device registers stay at IO_START..=PHYSMEM_MAX whatever
+// the size, since guests hard-code their addresses; physical addresses
+// between the end of RAM and IO_START are out of range.
+pub const DEFAULT_PHYSMEM_SIZE: u32 = IO_START;
+
+// Purpose: validate a --physmem size.
+// Outputs: Err with the reason when the size is not a whole number of RAM
+// pages or would run into the device registers.
+pub fn check_physmem_size(size: u32) -> Result<(), String> {
+    if size == 0 || !(size as usize).is_multiple_of(RAM_PAGE_SIZE) {
+        return Err(format!(
+            "size must be a non-zero multiple of {} bytes",
+            RAM_PAGE_SIZE
+        ));
+    }
+    if size > IO_START {
+        return Err(format!(
+            "RAM would overlap the device registers at 0x{:08X}",
+            IO_START
+        ));
+    }
+    Ok(())
+}
+
+// Purpose: queue the entries of one window input event.
+// Inputs: the input queue, the event's entries (several for a set 2
+// sequence) and the memory's `key_buffer_cap`.
+// Outputs: false when the event would push the queue past the cap; it is
+// then dropped whole, keeping the older events and multi-byte sequences
+// intact.
+pub fn push_input_event(io_buffer: &mut VecDeque<u16>, entries: &[u16], cap: usize) -> bool {
+    if io_buffer.len() + entries.len() > cap {
+        return false;
+    }
+    io_buffer.extend(entries);
+    true
+}
+
+// Purpose: guest access tallies per device region and per 4KB RAM page.
+// Counters are relaxed atomics so every core can bump them without taking
+// the page or MMIO locks; multi-byte accesses count once, by start address.
+// Instruction fetches are only counted per page, and only while profiling.
+struct MemStats {
+    region_reads: [AtomicU64; STATS_REGIONS.len()],
+    region_writes: [AtomicU64; STATS_REGIONS.len()],
+    page_reads: Box<[AtomicU64]>,
+    page_writes: Box<[AtomicU64]>,
+    page_fetches: Box<[AtomicU64]>,
+    count_fetches: AtomicBool,
+}
+
+// Snapshot of the non-zero access counters, as (name, reads, writes) per
+// region and (page base, reads, writes, fetches) per RAM page.
+pub struct MemStatsReport {
+    pub regions: Vec<(&'static str, u64, u64)>,
+    pub pages: Vec<(u32, u64, u64, u64)>,
+}
+
+impl MemStatsReport {
+    // Purpose: the per-page counters as CSV for --profile-out.
+    // Inputs: none.
+    // Outputs: a header line, then one `page,reads,writes,fetches` row per
+    // touched page in address order.
+    pub fn pages_csv(&self) -> String {
+        let mut csv = String::from("page,reads,writes,fetches\n");
+        for (base, reads, writes, fetches) in &self.pages {
+            csv.push_str(&format!(
+                "0x{:08X},{},{},{}\n",
+                base, reads, writes, fetches
+            ));
+        }
+        csv
+    }
+}
+
+impl MemStats {
+    fn new(page_count: usize) -> Self {
+        MemStats {
+            region_reads: std::array::from_fn(|_| AtomicU64::new(0)),
+            region_writes: std::array::from_fn(|_| AtomicU64::new(0)),
+            page_reads: (0..page_count).map(|_| AtomicU64::new(0)).collect(),
+            page_writes: (0..page_count).map(|_| AtomicU64::new(0)).collect(),
+            page_fetches: (0..page_count).map(|_| AtomicU64::new(0)).collect(),
+            count_fetches: AtomicBool::new(false),
+        }
+    }
+
+    fn record_fetch(&self, addr: u32) {
+        if self.count_fetches.load(Ordering::Relaxed)
+            && Memory::stats_region(addr) == StatsRegion::Ram
+        {
+            self.page_fetches[Memory::ram_page_index(addr)].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn record(&self, addr: u32, is_write: bool) {
+        let region = Memory::stats_region(addr);
+        let (regions, pages) = if is_write {
+            (&self.region_writes, &self.page_writes)
+        } else {
+            (&self.region_reads, &self.page_reads)
+        };
+        regions[region as usize].fetch_add(1, Ordering::Relaxed);
+        if region == StatsRegion::Ram {
+            pages[Memory::ram_page_index(addr)].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn report(&self) -> MemStatsReport {
+        let regions = STATS_REGIONS
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                (
+                    *name,
+                    self.region_reads[i].load(Ordering::Relaxed),
+                    self.region_writes[i].load(Ordering::Relaxed),
+                )
+            })
+            .filter(|(_, reads, writes)| reads + writes > 0)
+            .collect();
+        let pages = (0..self.page_reads.len())
+            .map(|page| {
+                (
+                    (page as u32) << RAM_PAGE_SHIFT,
+                    self.page_reads[page].load(Ordering::Relaxed),
+                    self.page_writes[page].load(Ordering::Relaxed),
+                    self.page_fetches[page].load(Ordering::Relaxed),
+                )
+            })
+            .filter(|(_, reads, writes, fetches)| reads + writes + fetches > 0)
+            .collect();
+        MemStatsReport { regions, pages }
+    }
+}
+
 // Purpose: fixed-format PCM sink exposed through MMIO registers plus a byte ring buffer.
 // Inputs/outputs: software writes PCM bytes + producer index; the device advances the
 // consumer index at the fixed sample rate and raises an interrupt only when
@@ -322,13 +731,38 @@ pub struct TileFrameBuffer {
     entries: Vec<u8>,
 }
 
+// Which copy of the pixel framebuffer guest MMIO reads observe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramebufferVisibility {
+    // Reads return the most recent guest writes, even before they are displayed.
+    WriteThrough,
+    // Reads return the last frame the display composited; guest writes become
+    // readable only after the next `present`.
+    Displayed,
+}
+
+impl FramebufferVisibility {
+    pub fn parse(token: &str) -> Option<Self> {
+        match token.to_ascii_lowercase().as_str() {
+            "write-through" | "writethrough" | "written" => {
+                Some(FramebufferVisibility::WriteThrough)
+            }
+            "displayed" | "write-back" | "writeback" => Some(FramebufferVisibility::Displayed),
+            _ => None,
+        }
+    }
+}
+
 // Purpose: pixel layer for the VGA output (16-bit little-endian pixels).
 // Inputs/outputs: MMIO reads/writes map to raw bytes; rendering reads u16 pixels.
-// Invariants: byte length == width_pixels * height_pixels * 2.
+// Invariants: byte length == width_pixels * height_pixels * 2; `displayed` is
+// empty in write-through mode and mirrors the last presented frame otherwise.
 pub struct PixelFrameBuffer {
     pub width_pixels: u32,
     pub height_pixels: u32,
     bytes: Vec<u8>,
+    displayed: Vec<u8>,
+    visibility: FramebufferVisibility,
 }
 
 pub struct TileMap {
@@ -348,6 +782,7 @@ pub struct SpriteMap {
 pub struct Sprite {
     pub x: (u8, u8),
     pub y: (u8, u8),
+    pub priority: u8,    // draw order; higher is on top
     pub pixels: Vec<u8>, // a 32x32 tile of pixels
 }
 
@@ -419,9 +854,41 @@ struct SdCard {
     init_active: bool,
     init_ticks_remaining: u32,
     initialized: bool,
+    // CRC mode latched at START, the offset into the current block + trailer
+    // frame, and the block being written, held back until its CRC checks.
+    dma_crc: bool,
+    dma_frame_offset: u32,
+    dma_block_buf: Vec<u8>,
+}
+
+// Purpose: CRC16 of an SD data block (CRC-16-CCITT, polynomial 0x1021, seed 0).
+// Inputs: block bytes in transfer order.
+// Outputs: the 16-bit CRC a card sends after the block.
+fn sd_crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
 }
 
 impl SdCard {
+    // STATUS as the guest reads it: the ERR bit mirrors a non-zero error code.
+    fn guest_status(&self) -> u32 {
+        if self.dma_err != SD_DMA_ERR_NONE {
+            self.dma_status | SD_DMA_STATUS_ERR
+        } else {
+            self.dma_status & !SD_DMA_STATUS_ERR
+        }
+    }
+
     fn new(dma_ticks_per_word: u32) -> Self {
         let ticks_per_word = dma_ticks_per_word.max(1);
         SdCard {
@@ -442,6 +909,9 @@ impl SdCard {
             init_active: false,
             init_ticks_remaining: 0,
             initialized: false,
+            dma_crc: false,
+            dma_frame_offset: 0,
+            dma_block_buf: Vec::new(),
         }
     }
 
@@ -468,9 +938,10 @@ impl SdCard {
     }
 
     // Purpose: start a DMA transfer using the current register values.
-    // Inputs: DMA registers already written by MMIO.
+    // Inputs: DMA registers already written by MMIO; `is_physical_range`
+    // checks the RAM side of the transfer.
     // Outputs: updates DMA state and returns true if an immediate interrupt is needed.
-    fn start_dma(&mut self) -> bool {
+    fn start_dma(&mut self, is_physical_range: impl Fn(u32, u32) -> bool) -> bool {
         let irq_enable = (self.dma_ctrl & SD_DMA_CTRL_IRQ_ENABLE) != 0;
         let is_busy = (self.dma_status & SD_DMA_STATUS_BUSY) != 0;
         if is_busy {
@@ -489,13 +960,23 @@ impl SdCard {
 
         // SD_DMA_LEN is architecturally defined in blocks. Internally the DMA engine
         // tracks a byte countdown, so convert blocks->bytes with 32-bit truncation.
-        let len_bytes = self.dma_len.wrapping_mul(SD_BLOCK_SIZE_U32);
+        // CRC mode also counts each block's trailer word.
+        self.dma_crc = (self.dma_ctrl & SD_DMA_CTRL_CRC) != 0;
+        let frame_size = if self.dma_crc {
+            SD_CRC_FRAME_SIZE
+        } else {
+            SD_BLOCK_SIZE_U32
+        };
+        let len_bytes = self.dma_len.wrapping_mul(frame_size);
         if len_bytes == 0 {
             self.dma_err = SD_DMA_ERR_ZERO_LEN;
             self.dma_status = SD_DMA_STATUS_DONE | SD_DMA_STATUS_ERR;
             self.dma_active = false;
             return irq_enable;
         }
+        if !is_physical_range(mem_addr, len_bytes) {
+            return self.fail_dma(SD_DMA_ERR_RANGE);
+        }
 
         self.dma_mem_cursor = mem_addr;
         self.dma_sd_byte_cursor = (self.dma_sd_block as u64) * (SD_BLOCK_SIZE as u64);
@@ -504,9 +985,33 @@ impl SdCard {
         self.dma_status = SD_DMA_STATUS_BUSY;
         self.dma_active = true;
         self.dma_tick_countdown = 0;
+        self.dma_frame_offset = 0;
+        self.dma_block_buf.clear();
         false
     }
 
+    // Purpose: abort a transfer, e.g. a CRC-mode write whose block failed its
+    // CRC check; the rejected block has not reached storage.
+    // Inputs: the error code to report.
+    // Outputs: DONE|ERR with `err`; true if an interrupt is needed.
+    fn fail_dma(&mut self, err: u32) -> bool {
+        self.dma_active = false;
+        self.dma_remaining = 0;
+        self.dma_block_buf.clear();
+        self.dma_err = err;
+        self.dma_status = SD_DMA_STATUS_DONE | SD_DMA_STATUS_ERR;
+        (self.dma_ctrl & SD_DMA_CTRL_IRQ_ENABLE) != 0
+    }
+
+    // CRC16 of the stored block that ends at `end_offset` (a byte offset).
+    fn stored_block_crc(&self, end_offset: u64) -> u16 {
+        let start = end_offset - SD_BLOCK_SIZE as u64;
+        let block: Vec<u8> = (start..end_offset)
+            .map(|offset| self.read_storage_byte(offset))
+            .collect();
+        sd_crc16(&block)
+    }
+
     // Purpose: clear DONE/ERR status and reset the error code.
     // Inputs/outputs: updates status bits and dma_err in-place.
     fn clear_status(&mut self) {
@@ -1544,23 +2049,45 @@ fn read_sd_dma_mmio(addr: u32, base: u32, sd: &SdCard) -> Option<u8> {
         return Some(read_reg_byte(sd.dma_ctrl, addr, base + SD_DMA_OFFSET_CTRL));
     }
     if addr >= base + SD_DMA_OFFSET_STATUS && addr < base + SD_DMA_OFFSET_STATUS + 4 {
-        let mut status = sd.dma_status;
-        if sd.dma_err != SD_DMA_ERR_NONE {
-            status |= SD_DMA_STATUS_ERR;
-        } else {
-            status &= !SD_DMA_STATUS_ERR;
-        }
-        return Some(read_reg_byte(status, addr, base + SD_DMA_OFFSET_STATUS));
+        return Some(read_reg_byte(
+            sd.guest_status(),
+            addr,
+            base + SD_DMA_OFFSET_STATUS,
+        ));
     }
     Some(read_reg_byte(sd.dma_err, addr, base + SD_DMA_OFFSET_ERR))
 }
 
 impl Memory {
     pub fn new(ram: HashMap<u32, u8>, use_uart_rx: bool, sd_dma_ticks_per_word: u32) -> Memory {
+        Self::with_physmem_size(
+            ram,
+            use_uart_rx,
+            sd_dma_ticks_per_word,
+            DEFAULT_PHYSMEM_SIZE,
+        )
+    }
+
+    // Purpose: build memory with `physmem_size` bytes of RAM instead of
+    // DEFAULT_PHYSMEM_SIZE.
+    // Inputs: as `new`, plus a size accepted by `check_physmem_size`.
+    // Outputs: image bytes past the end of RAM are dropped, as are bytes
+    // aimed at device registers.
+    pub fn with_physmem_size(
+        ram: HashMap<u32, u8>,
+        use_uart_rx: bool,
+        sd_dma_ticks_per_word: u32,
+        physmem_size: u32,
+    ) -> Memory {
+        if let Err(err) = check_physmem_size(physmem_size) {
+            panic!("invalid physical memory size 0x{:X}: {}", physmem_size, err);
+        }
         let ticks_per_word = sd_dma_ticks_per_word.max(1);
+        let page_count = physmem_size as usize / RAM_PAGE_SIZE;
 
         Memory {
-            ram_pages: Self::build_ram_pages(ram),
+            ram_pages: Self::build_ram_pages(ram, page_count),
+            physmem_size,
             mmio_lock: Mutex::new(()),
             pixel_frame_buffer: Arc::new(RwLock::new(PixelFrameBuffer::new(
                 PIXEL_FRAME_WIDTH,
@@ -1572,21 +2099,33 @@ impl Memory {
                 FRAME_HEIGHT,
                 TILE_FRAME_BUFFER_SIZE,
             ))),
+            bg_tile_frame_buffer: Arc::new(RwLock::new(TileFrameBuffer::new(
+                FRAME_WIDTH,
+                FRAME_HEIGHT,
+                TILE_FRAME_BUFFER_SIZE,
+            ))),
             tile_map: Arc::new(RwLock::new(TileMap::new(TILE_MAP_SIZE))),
             io_buffer: Arc::new(RwLock::new(VecDeque::new())),
+            key_buffer_cap: DEFAULT_KEY_BUFFER_CAP,
             input_pending: Arc::new(AtomicBool::new(false)),
             tile_vscroll_register: Arc::new(RwLock::new((0, 0))),
             tile_hscroll_register: Arc::new(RwLock::new((0, 0))),
+            bg_tile_vscroll_register: Arc::new(RwLock::new((0, 0))),
+            bg_tile_hscroll_register: Arc::new(RwLock::new((0, 0))),
             pixel_vscroll_register: Arc::new(RwLock::new((0, 0))),
             pixel_hscroll_register: Arc::new(RwLock::new((0, 0))),
             tile_scale_register: Arc::new(RwLock::new(0)),
             pixel_scale_register: Arc::new(RwLock::new(0)),
             sprite_scale_registers: Arc::new(RwLock::new(vec![0; SPRITE_COUNT as usize])),
+            palette_mode_register: Arc::new(RwLock::new(0)),
+            palette: Arc::new(RwLock::new(DEFAULT_PALETTE)),
             vga_status_register: Arc::new(RwLock::new(0)),
             vga_frame_register: Arc::new(RwLock::new((0, 0, 0, 0))),
             clk_register: Arc::new(RwLock::new((0, 0, 0, 0))),
+            mouse_register: Arc::new(RwLock::new((0, 0, 0))),
             pit_reload: Arc::new(AtomicU32::new(0)),
             pit_countdown: Arc::new(Mutex::new(0)),
+            pit_ctrl: Arc::new(AtomicU32::new(0)),
             sprite_map: Arc::new(RwLock::new(SpriteMap::new(SPRITE_MAP_SIZE))),
             sd_card: Arc::new(RwLock::new(SdCard::new(ticks_per_word))),
             sd_card2: Arc::new(RwLock::new(SdCard::new(ticks_per_word))),
@@ -1595,21 +2134,23 @@ impl Memory {
             fast_audio_active: AtomicBool::new(false),
             pending_interrupt: Arc::new(AtomicU32::new(0)),
             use_uart_rx: use_uart_rx,
+            uart_tx_sink: Mutex::new(None),
+            access_stats: MemStats::new(page_count),
         }
     }
 
-    fn build_ram_pages(image: HashMap<u32, u8>) -> Box<[RwLock<RamPage>]> {
+    fn build_ram_pages(image: HashMap<u32, u8>, page_count: usize) -> Box<[RwLock<RamPage>]> {
         // The kernel's physical frame allocator first-touches nearly every RAM
         // page during boot, so sparse per-page host allocations make early boot
         // disproportionately expensive. Keep page-level locking for multicore
         // safety, but back each page with dense zero-initialized storage.
-        let mut pages: Vec<RwLock<RamPage>> = Vec::with_capacity(RAM_PAGE_COUNT);
-        pages.resize_with(RAM_PAGE_COUNT, || RwLock::new(RamPage::new()));
+        let mut pages: Vec<RwLock<RamPage>> = Vec::with_capacity(page_count);
+        pages.resize_with(page_count, || RwLock::new(RamPage::new()));
         for (addr, value) in image {
-            if addr >= IO_START {
+            let page = Self::ram_page_index(addr);
+            if page >= page_count {
                 continue;
             }
-            let page = Self::ram_page_index(addr);
             let offset = Self::ram_page_offset(addr);
             pages[page].get_mut().unwrap().write_byte(offset, value);
         }
@@ -1637,7 +2178,7 @@ impl Memory {
         pages
     }
 
-    fn addr_touches_mmio(addr: u32) -> bool {
+    pub fn addr_touches_mmio(addr: u32) -> bool {
         addr >= IO_START
     }
 
@@ -1738,18 +2279,26 @@ impl Memory {
         self.pit_reload.load(Ordering::SeqCst)
     }
 
-    fn write_pit_reload_byte(&self, addr: u32, data: u8) {
-        let mut reload = self.read_pit_reload();
-        write_reg_byte(&mut reload, addr, PIT_START, data);
-        self.pit_reload.store(reload, Ordering::SeqCst);
+    fn read_pit_count(&self) -> u32 {
+        *self.pit_countdown.lock().unwrap()
     }
 
+    // Any write to the reload register restarts the countdown from the new
+    // value; writing 0 stops the timer.
     fn write_pit_reload_bytes(&self, addrs: &[u32], data: &[u8]) {
+        let mut countdown = self.pit_countdown.lock().unwrap();
         let mut reload = self.read_pit_reload();
         for (addr, byte) in addrs.iter().zip(data.iter()) {
             write_reg_byte(&mut reload, *addr, PIT_START, *byte);
         }
         self.pit_reload.store(reload, Ordering::SeqCst);
+        *countdown = reload;
+    }
+
+    fn write_pit_ctrl_byte(&self, addr: u32, data: u8) {
+        let mut ctrl = self.pit_ctrl.load(Ordering::SeqCst);
+        write_reg_byte(&mut ctrl, addr, PIT_CTRL_START, data);
+        self.pit_ctrl.store(ctrl, Ordering::SeqCst);
     }
 
     fn raise_pending_interrupt(&self, interrupt_bit: u32) {
@@ -1758,6 +2307,16 @@ impl Memory {
     }
 
     fn read_phys_bytes_inner(&self, addrs: &[u32], out: &mut [u8]) {
+        if Self::addrs_are_contiguous_pit_bytes(addrs) {
+            // One lock for the whole read so a multi-byte load of the live
+            // count cannot straddle a PIT tick.
+            let count = self.read_pit_count();
+            for (slot, addr) in out.iter_mut().zip(addrs.iter()) {
+                *slot = read_reg_byte(count, *addr, PIT_START);
+            }
+            return;
+        }
+
         if let Some(page_index) = Self::single_ram_page(addrs) {
             let page = self.ram_pages[page_index].read().unwrap();
             for (slot, addr) in out.iter_mut().zip(addrs.iter()) {
@@ -1819,15 +2378,261 @@ impl Memory {
     pub fn get_pixel_frame_buffer(&self) -> Arc<RwLock<PixelFrameBuffer>> {
         Arc::clone(&self.pixel_frame_buffer)
     }
+
+    // Purpose: select whether guest reads of the pixel framebuffer see pending
+    // writes or only the last displayed frame.
+    pub fn set_framebuffer_visibility(&self, visibility: FramebufferVisibility) {
+        self.pixel_frame_buffer
+            .write()
+            .unwrap()
+            .set_visibility(visibility);
+    }
+
     pub fn get_tile_frame_buffer(&self) -> Arc<RwLock<TileFrameBuffer>> {
         Arc::clone(&self.tile_frame_buffer)
     }
+
+    pub fn get_bg_tile_frame_buffer(&self) -> Arc<RwLock<TileFrameBuffer>> {
+        Arc::clone(&self.bg_tile_frame_buffer)
+    }
     pub fn get_tile_map(&self) -> Arc<RwLock<TileMap>> {
         return Arc::clone(&self.tile_map);
     }
+    // Redirect UART_TX output (e.g. into a buffer for tests); the default
+    // sink is stdout.
+    pub fn set_uart_tx_sink(&self, sink: Box<dyn Write + Send>) {
+        *self.uart_tx_sink.lock().unwrap() = Some(sink);
+    }
+
+    // Purpose: flush device output when a run or debug session ends.
+    // Inputs: none.
+    // Outputs: anything a UART_TX sink (or stdout) still buffers is written
+    // out, including before main exits with process::exit, which skips
+    // destructors. SD cards need no flush: they live in memory and
+    // --sd0-out/--sd1-out/--sd-image write them back after this.
+    pub fn flush_output(&self) {
+        if let Some(sink) = self.uart_tx_sink.lock().unwrap().as_mut() {
+            let _ = sink.flush();
+        }
+        let _ = io::stdout().flush();
+    }
+
+    fn write_uart_tx(&self, data: u8) {
+        let mut sink = self.uart_tx_sink.lock().unwrap();
+        match sink.as_mut() {
+            Some(sink) => {
+                let _ = sink.write_all(&[data]);
+            }
+            None => {
+                print!("{}", data as char);
+                io::stdout().flush().unwrap();
+            }
+        }
+    }
+
+    // Whether guest input is read through UART_RX (--uart) instead of
+    // PS2_STREAM.
+    pub fn uart_rx_enabled(&self) -> bool {
+        self.use_uart_rx
+    }
+
+    // Queue scripted bytes behind any pending input. In UART mode each
+    // UART_RX read pops one byte and reads return 0 once the queue drains.
+    // Unlike window input this ignores the --key-buffer cap.
+    pub fn queue_uart_input(&self, bytes: &[u8]) {
+        let mut io_buffer = self.io_buffer.write().unwrap();
+        io_buffer.extend(bytes.iter().map(|&byte| u16::from(byte)));
+        self.input_pending
+            .store(!io_buffer.is_empty(), Ordering::SeqCst);
+    }
+
+    // The physical memory map, in address order; RAM ends at this memory's
+    // --physmem size.
+    pub fn regions(&self) -> Vec<MemRegion> {
+        let ram = mem_region("ram", 0, self.physmem_size, MemRegionKind::Ram);
+        std::iter::once(ram).chain(DEVICE_MAP).collect()
+    }
+
+    // The memory-map region holding physical address `addr`, if any.
+    pub fn region_at(&self, addr: u32) -> Option<MemRegion> {
+        if addr < self.physmem_size {
+            return Some(mem_region("ram", 0, self.physmem_size, MemRegionKind::Ram));
+        }
+        Self::device_region_at(addr)
+    }
+
+    // The device region holding physical address `addr`; devices sit at the
+    // same addresses whatever the RAM size.
+    pub fn device_region_at(addr: u32) -> Option<MemRegion> {
+        DEVICE_MAP
+            .iter()
+            .find(|region| (region.start..region.end).contains(&addr))
+            .copied()
+    }
+
+    // True when `addr` is the base of a guest RAM page (below the MMIO region).
+    pub fn is_ram_page(addr: u32) -> bool {
+        addr < IO_START && (addr as usize) & RAM_PAGE_MASK == 0
+    }
+
+    // Bytes of RAM this memory was built with.
+    pub fn physmem_size(&self) -> u32 {
+        self.physmem_size
+    }
+
+    // True when `addr` is in RAM or in the device window.
+    pub fn is_physical(&self, addr: u32) -> bool {
+        addr < self.physmem_size || (IO_START..=PHYSMEM_MAX).contains(&addr)
+    }
+
+    // True when all `len` bytes from `addr` are physical; a range may not
+    // span the gap between the end of RAM and the devices.
+    pub fn is_physical_range(&self, addr: u32, len: u32) -> bool {
+        let Some(end) = len.checked_sub(1).and_then(|last| addr.checked_add(last)) else {
+            return false;
+        };
+        self.is_physical(addr)
+            && self.is_physical(end)
+            && (addr >= IO_START || end < self.physmem_size)
+    }
+
+    // Guest loads and stores never reach here with an address the emulator
+    // cannot map, so an out-of-range address is an emulator bug.
+    fn assert_physical(&self, addr: u32) {
+        assert!(
+            self.is_physical(addr),
+            "Physical memory address out of bounds: 0x{:08X}",
+            addr
+        );
+    }
+
+    // Copy out every RAM page holding a non-zero byte as (base address, bytes).
+    // All-zero pages are skipped so snapshots of a mostly empty RAM stay small.
+    pub fn ram_snapshot(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut pages = Vec::new();
+        for (index, page) in self.ram_pages.iter().enumerate() {
+            let page = page.read().unwrap();
+            if page.bytes.iter().any(|&byte| byte != 0) {
+                pages.push(((index * RAM_PAGE_SIZE) as u32, page.bytes.to_vec()));
+            }
+        }
+        pages
+    }
+
+    // Replace the contents of guest RAM: listed pages are copied in and every
+    // other page is zeroed. Bases must satisfy `is_ram_page` and each page must
+    // be RAM_PAGE_SIZE bytes; pages past the end of this memory's RAM (from a
+    // larger --physmem) are dropped.
+    pub fn restore_ram(&self, pages: &[(u32, Vec<u8>)]) {
+        for page in self.ram_pages.iter() {
+            page.write().unwrap().bytes.fill(0);
+        }
+        for (base, bytes) in pages {
+            assert!(Self::is_ram_page(*base) && bytes.len() == RAM_PAGE_SIZE);
+            let Some(page) = self.ram_pages.get(Self::ram_page_index(*base)) else {
+                continue;
+            };
+            page.write().unwrap().bytes.copy_from_slice(bytes);
+        }
+    }
+
+    // PIT state as (reload value, current countdown, control register).
+    pub fn pit_state(&self) -> (u32, u32, u32) {
+        (
+            self.read_pit_reload(),
+            self.read_pit_count(),
+            self.pit_ctrl.load(Ordering::SeqCst),
+        )
+    }
+
+    // Purpose: side-effect-free snapshot of the peripheral registers for the
+    // debugger's `info io`.
+    // Inputs: none.
+    // Outputs: (name, value) pairs; multi-byte registers are assembled
+    // little-endian as the guest would read them. `pending_irqs` holds device
+    // interrupts raised but not yet delivered to a core.
+    pub fn io_registers(&self) -> Vec<(&'static str, u32)> {
+        let pair = |reg: &Arc<RwLock<(u8, u8)>>| {
+            let (lo, hi) = *reg.read().unwrap();
+            u32::from(u16::from_le_bytes([lo, hi]))
+        };
+        let quad = |reg: &Arc<RwLock<(u8, u8, u8, u8)>>| {
+            let (b0, b1, b2, b3) = *reg.read().unwrap();
+            u32::from_le_bytes([b0, b1, b2, b3])
+        };
+        let (pit_reload, pit_countdown, pit_ctrl) = self.pit_state();
+        let mut regs = vec![
+            ("tile_hscroll", pair(&self.tile_hscroll_register)),
+            ("tile_vscroll", pair(&self.tile_vscroll_register)),
+            ("bg_tile_hscroll", pair(&self.bg_tile_hscroll_register)),
+            ("bg_tile_vscroll", pair(&self.bg_tile_vscroll_register)),
+            (
+                "tile_scale",
+                u32::from(*self.tile_scale_register.read().unwrap()),
+            ),
+            ("pixel_hscroll", pair(&self.pixel_hscroll_register)),
+            ("pixel_vscroll", pair(&self.pixel_vscroll_register)),
+            (
+                "pixel_scale",
+                u32::from(*self.pixel_scale_register.read().unwrap()),
+            ),
+            (
+                "palette_mode",
+                u32::from(*self.palette_mode_register.read().unwrap()),
+            ),
+            (
+                "vga_status",
+                u32::from(*self.vga_status_register.read().unwrap()),
+            ),
+            ("vga_frame", quad(&self.vga_frame_register)),
+            ("clock", quad(&self.clk_register)),
+            ("pit_reload", pit_reload),
+            ("pit_countdown", pit_countdown),
+            ("pit_ctrl", pit_ctrl),
+            (
+                "pending_irqs",
+                self.pending_interrupt.load(Ordering::SeqCst),
+            ),
+        ];
+        let cards = [
+            (
+                ["sd0_status", "sd0_err", "sd0_busy", "sd0_init"],
+                &self.sd_card,
+            ),
+            (
+                ["sd1_status", "sd1_err", "sd1_busy", "sd1_init"],
+                &self.sd_card2,
+            ),
+        ];
+        for (names, card) in cards {
+            let sd = card.read().unwrap();
+            // init: 1 once initialized, 2 while the init sequence is running.
+            let init = u32::from(sd.initialized) | (u32::from(sd.init_active) << 1);
+            regs.push((names[0], sd.guest_status()));
+            regs.push((names[1], sd.dma_err));
+            regs.push((names[2], u32::from(sd.dma_active)));
+            regs.push((names[3], init));
+        }
+        regs
+    }
+
+    pub fn restore_pit_state(&self, reload: u32, countdown: u32, ctrl: u32) {
+        self.pit_reload.store(reload, Ordering::SeqCst);
+        *self.pit_countdown.lock().unwrap() = countdown;
+        self.pit_ctrl.store(ctrl, Ordering::SeqCst);
+    }
+
     pub fn get_io_buffer(&self) -> Arc<RwLock<VecDeque<u16>>> {
         return Arc::clone(&self.io_buffer);
     }
+    // Set the --key-buffer cap; it must be set before the memory is shared.
+    pub fn set_key_buffer_cap(&mut self, cap: usize) {
+        assert!(cap >= 1, "key buffer must hold at least one entry");
+        self.key_buffer_cap = cap;
+    }
+    pub fn key_buffer_cap(&self) -> usize {
+        self.key_buffer_cap
+    }
     pub fn get_input_pending(&self) -> Arc<AtomicBool> {
         Arc::clone(&self.input_pending)
     }
@@ -1837,6 +2642,12 @@ impl Memory {
     pub fn get_tile_hscroll_register(&self) -> Arc<RwLock<(u8, u8)>> {
         Arc::clone(&self.tile_hscroll_register)
     }
+    pub fn get_bg_tile_vscroll_register(&self) -> Arc<RwLock<(u8, u8)>> {
+        Arc::clone(&self.bg_tile_vscroll_register)
+    }
+    pub fn get_bg_tile_hscroll_register(&self) -> Arc<RwLock<(u8, u8)>> {
+        Arc::clone(&self.bg_tile_hscroll_register)
+    }
     pub fn get_pixel_vscroll_register(&self) -> Arc<RwLock<(u8, u8)>> {
         Arc::clone(&self.pixel_vscroll_register)
     }
@@ -1861,6 +2672,15 @@ impl Memory {
     pub fn get_vga_frame_register(&self) -> Arc<RwLock<(u8, u8, u8, u8)>> {
         return Arc::clone(&self.vga_frame_register);
     }
+    pub fn get_mouse_register(&self) -> Arc<RwLock<(u16, u16, u8)>> {
+        Arc::clone(&self.mouse_register)
+    }
+    pub fn get_palette_mode_register(&self) -> Arc<RwLock<u8>> {
+        Arc::clone(&self.palette_mode_register)
+    }
+    pub fn get_palette(&self) -> Arc<RwLock<[u16; PALETTE_ENTRIES]>> {
+        Arc::clone(&self.palette)
+    }
     pub fn get_pending_interrupt(&self) -> Arc<AtomicU32> {
         return Arc::clone(&self.pending_interrupt);
     }
@@ -1877,7 +2697,89 @@ impl Memory {
         self.input_pending.load(Ordering::SeqCst)
     }
 
+    // The clock divider register as a word. Cores read it every tick, so this
+    // skips the byte-wise MMIO dispatch that a guest load goes through.
+    pub fn clock_divider(&self) -> u32 {
+        let (b0, b1, b2, b3) = *self.clk_register.read().unwrap();
+        u32::from_le_bytes([b0, b1, b2, b3])
+    }
+
+    // Purpose: tally one guest load/store for `info memstats`.
+    // Inputs: physical start address of the access and its direction.
+    // Outputs: bumps the region counter (and the RAM page counter for RAM).
+    pub fn record_access(&self, addr: u32, is_write: bool) {
+        self.access_stats.record(addr, is_write);
+    }
+
+    // Count one instruction fetch from physical `addr` when profiling is on.
+    pub fn record_fetch(&self, addr: u32) {
+        self.access_stats.record_fetch(addr);
+    }
+
+    pub fn set_fetch_profiling(&self, enabled: bool) {
+        self.access_stats
+            .count_fetches
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn access_stats(&self) -> MemStatsReport {
+        self.access_stats.report()
+    }
+
+    // Classify a physical address for the access counters, following the
+    // same address ranges as the MMIO read/write dispatch.
+    fn stats_region(addr: u32) -> StatsRegion {
+        let in_range = |start: u32, size: u32| addr >= start && addr < start + size;
+        if addr < IO_START {
+            StatsRegion::Ram
+        } else if in_range(AUDIO_RING_BUFFER_START, AUDIO_RING_BUFFER_SIZE) {
+            StatsRegion::AudioRing
+        } else if (AUDIO_CTRL_START..AUDIO_WATERMARK_START + 4).contains(&addr) {
+            StatsRegion::AudioRegs
+        } else if in_range(SYNTH_AUDIO_START, SYNTH_AUDIO_SIZE) {
+            StatsRegion::SynthAudio
+        } else if in_range(TILE_FRAME_BUFFER_START, TILE_FRAME_BUFFER_SIZE)
+            || in_range(BG_TILE_FRAME_BUFFER_START, TILE_FRAME_BUFFER_SIZE)
+        {
+            StatsRegion::TileFb
+        } else if in_range(PIXEL_FRAME_BUFFER_START, PIXEL_FRAME_BUFFER_SIZE) {
+            StatsRegion::PixelFb
+        } else if in_range(TILE_MAP_START, TILE_MAP_SIZE) {
+            StatsRegion::TileMap
+        } else if in_range(SPRITE_MAP_START, SPRITE_MAP_SIZE) {
+            StatsRegion::SpriteMap
+        } else if in_range(SPRITE_REGISTERS_START, SPRITE_REGISTERS_SIZE)
+            || in_range(SPRITE_PRIORITY_START, SPRITE_PRIORITY_SIZE)
+        {
+            StatsRegion::SpriteRegs
+        } else if in_range(CLK_REG_START, 4) {
+            StatsRegion::Clock
+        } else if (TILE_H_SCROLL_START..VGA_FRAME_REGISTER_START + 4).contains(&addr)
+            || (PIXEL_H_SCROLL_START..=PALETTE_MODE_REGISTER_START).contains(&addr)
+            || in_range(BG_TILE_H_SCROLL_START, 4)
+            || in_range(SPRITE_SCALE_START, SPRITE_SCALE_SIZE)
+            || in_range(PALETTE_START, PALETTE_SIZE)
+        {
+            StatsRegion::VgaRegs
+        } else if in_range(SD_DMA_MEM_ADDR, SD_DMA_RANGE_SIZE) {
+            StatsRegion::Sd0Dma
+        } else if in_range(SD2_DMA_MEM_ADDR, SD_DMA_RANGE_SIZE) {
+            StatsRegion::Sd1Dma
+        } else if in_range(PS2_STREAM, 2) {
+            StatsRegion::Ps2
+        } else if in_range(MOUSE_X_START, MOUSE_REGISTERS_SIZE) {
+            StatsRegion::Mouse
+        } else if addr == UART_TX || addr == UART_RX {
+            StatsRegion::Uart
+        } else if in_range(PIT_START, 8) {
+            StatsRegion::Pit
+        } else {
+            StatsRegion::OtherIo
+        }
+    }
+
     pub fn read(&self, addr: u32) -> u8 {
+        self.assert_physical(addr);
         if Self::addr_touches_mmio(addr) {
             let value = {
                 let _mmio = self.mmio_lock.lock().unwrap();
@@ -1899,8 +2801,55 @@ impl Memory {
         }
     }
 
+    // Purpose: side-effect-free read for debuggers, tests and embedders.
+    // Inputs: physical address.
+    // Outputs: the byte a guest load would see, without popping input queues
+    // or yielding to audio threads. None for addresses past the end of RAM
+    // outside every device region, or in write-only registers (UART TX).
+    pub fn peek(&self, addr: u32) -> Option<u8> {
+        if !self.is_physical(addr) {
+            return None;
+        }
+        if !Self::addr_touches_mmio(addr) {
+            let page = self.ram_pages[Self::ram_page_index(addr)].read().unwrap();
+            return Some(page.read_byte(Self::ram_page_offset(addr)));
+        }
+        Self::device_region_at(addr)?;
+        let _mmio = self.mmio_lock.lock().unwrap();
+        // Input registers show the head of the queue instead of consuming it.
+        let head = || *self.io_buffer.read().unwrap().front().unwrap_or(&0);
+        if addr == UART_TX {
+            None
+        } else if addr == PS2_STREAM + 1 {
+            Some(if self.use_uart_rx {
+                0
+            } else {
+                (head() >> 8) as u8
+            })
+        } else if addr == UART_RX {
+            let value = if self.use_uart_rx { head() } else { 0 };
+            Some(if value & 0xFF00 != 0 { 0 } else { value as u8 })
+        } else {
+            Some(self.read_mmio_byte(addr))
+        }
+    }
+
+    // Purpose: side-effect-free write counterpart of `peek`.
+    // Inputs: physical address and byte.
+    // Outputs: true if the byte was stored. Only RAM can be poked; device
+    // addresses are refused because any store to them runs device logic.
+    pub fn poke(&self, addr: u32, data: u8) -> bool {
+        if Self::addr_touches_mmio(addr) || !self.is_physical(addr) {
+            return false;
+        }
+        let mut page = self.ram_pages[Self::ram_page_index(addr)].write().unwrap();
+        page.write_byte(Self::ram_page_offset(addr), data);
+        true
+    }
+
     pub fn read_u16(&self, addr: u32) -> u16 {
         let addr = addr & 0xFFFFFFFE;
+        self.assert_physical(addr);
         if let Some(page_index) = Self::ram_range_within_single_page(addr, 2) {
             Self::maybe_warn_null_read(addr);
             let page = self.ram_pages[page_index].read().unwrap();
@@ -1914,6 +2863,7 @@ impl Memory {
 
     pub fn read_u32(&self, addr: u32) -> u32 {
         let addr = addr & 0xFFFFFFFC;
+        self.assert_physical(addr);
         if let Some(page_index) = Self::ram_range_within_single_page(addr, 4) {
             Self::maybe_warn_null_read(addr);
             let page = self.ram_pages[page_index].read().unwrap();
@@ -1928,6 +2878,9 @@ impl Memory {
     // Read specific physical addresses under one lock to avoid tearing.
     pub fn read_phys_bytes(&self, addrs: &[u32], out: &mut [u8]) {
         assert_eq!(addrs.len(), out.len());
+        for addr in addrs {
+            self.assert_physical(*addr);
+        }
         if Self::addrs_touch_mmio(addrs) {
             {
                 let _mmio = self.mmio_lock.lock().unwrap();
@@ -1946,33 +2899,26 @@ impl Memory {
     }
 
     pub fn atomic_swap_u32(&self, addr: u32, value: u32) -> u32 {
-        let addr = addr & 0xFFFFFFFC;
-        let bytes = value.to_le_bytes();
-        if Self::addr_touches_mmio(addr) {
-            let _mmio = self.mmio_lock.lock().unwrap();
-            let mut prev = [0u8; 4];
-            for (offset, slot) in prev.iter_mut().enumerate() {
-                *slot = self.read_mmio_byte(addr + offset as u32);
-            }
-            for (offset, byte) in bytes.iter().enumerate() {
-                self.write_mmio_byte(addr + offset as u32, *byte);
-            }
-            u32::from_le_bytes(prev)
-        } else {
-            let mut page = self.ram_pages[Self::ram_page_index(addr)].write().unwrap();
-            let mut prev = [0u8; 4];
-            for (offset, slot) in prev.iter_mut().enumerate() {
-                *slot = page.read_byte(Self::ram_page_offset(addr + offset as u32));
-            }
-            for (offset, byte) in bytes.iter().enumerate() {
-                page.write_byte(Self::ram_page_offset(addr + offset as u32), *byte);
-            }
-            u32::from_le_bytes(prev)
-        }
+        self.atomic_update_u32(addr, |_| Some(value))
+    }
+
+    // Store `value` only if the word currently holds `expected`; returns the
+    // previous word either way.
+    pub fn atomic_cas_u32(&self, addr: u32, expected: u32, value: u32) -> u32 {
+        self.atomic_update_u32(addr, |prev| (prev == expected).then_some(value))
     }
 
-    pub fn atomic_add_u32(&self, addr: u32, value: u32) -> u32 {
+    // Purpose: read-modify-write one word with no other core in between.
+    // Inputs: word address (low bits ignored) and a function from the previous
+    // word to the new one, or None to leave memory untouched.
+    // Outputs: the previous word. MMIO words hold mmio_lock, RAM words hold
+    // their page's write lock for the whole update.
+    pub fn atomic_update_u32<F>(&self, addr: u32, update: F) -> u32
+    where
+        F: FnOnce(u32) -> Option<u32>,
+    {
         let addr = addr & 0xFFFFFFFC;
+        self.assert_physical(addr);
         if Self::addr_touches_mmio(addr) {
             let _mmio = self.mmio_lock.lock().unwrap();
             let mut prev = [0u8; 4];
@@ -1980,9 +2926,10 @@ impl Memory {
                 *slot = self.read_mmio_byte(addr + offset as u32);
             }
             let prev_u32 = u32::from_le_bytes(prev);
-            let next = prev_u32.wrapping_add(value).to_le_bytes();
-            for (offset, byte) in next.iter().enumerate() {
-                self.write_mmio_byte(addr + offset as u32, *byte);
+            if let Some(next) = update(prev_u32) {
+                for (offset, byte) in next.to_le_bytes().iter().enumerate() {
+                    self.write_mmio_byte(addr + offset as u32, *byte);
+                }
             }
             prev_u32
         } else {
@@ -1992,9 +2939,10 @@ impl Memory {
                 *slot = page.read_byte(Self::ram_page_offset(addr + offset as u32));
             }
             let prev_u32 = u32::from_le_bytes(prev);
-            let next = prev_u32.wrapping_add(value).to_le_bytes();
-            for (offset, byte) in next.iter().enumerate() {
-                page.write_byte(Self::ram_page_offset(addr + offset as u32), *byte);
+            if let Some(next) = update(prev_u32) {
+                for (offset, byte) in next.to_le_bytes().iter().enumerate() {
+                    page.write_byte(Self::ram_page_offset(addr + offset as u32), *byte);
+                }
             }
             prev_u32
         }
@@ -2071,7 +3019,8 @@ impl Memory {
             sd.dma_ctrl &= SD_DMA_CTRL_START
                 | SD_DMA_CTRL_DIR_RAM_TO_SD
                 | SD_DMA_CTRL_IRQ_ENABLE
-                | SD_DMA_CTRL_INIT;
+                | SD_DMA_CTRL_INIT
+                | SD_DMA_CTRL_CRC;
             let should_start = (sd.dma_ctrl & SD_DMA_CTRL_START) != 0;
             let should_init = (sd.dma_ctrl & SD_DMA_CTRL_INIT) != 0;
             if should_start || should_init {
@@ -2080,7 +3029,7 @@ impl Memory {
                 let interrupt = if should_init {
                     sd.start_init()
                 } else {
-                    sd.start_dma()
+                    sd.start_dma(|addr, len| self.is_physical_range(addr, len))
                 };
                 if interrupt {
                     self.raise_pending_interrupt(interrupt_bit);
@@ -2123,6 +3072,14 @@ impl Memory {
                 .read()
                 .unwrap()
                 .get_byte(addr - TILE_FRAME_BUFFER_START);
+        } else if (BG_TILE_FRAME_BUFFER_START..BG_TILE_FRAME_BUFFER_START + TILE_FRAME_BUFFER_SIZE)
+            .contains(&addr)
+        {
+            return self
+                .bg_tile_frame_buffer
+                .read()
+                .unwrap()
+                .get_byte(addr - BG_TILE_FRAME_BUFFER_START);
         } else if addr >= PIXEL_FRAME_BUFFER_START
             && addr < PIXEL_FRAME_BUFFER_START + PIXEL_FRAME_BUFFER_SIZE
         {
@@ -2144,7 +3101,8 @@ impl Memory {
             }
             return self.io_buffer.read().unwrap().front().unwrap_or(&0).clone() as u8;
         } else if addr == PS2_STREAM + 1 {
-            // read of upper byte will cause a pop
+            // read of upper byte will cause a pop, freeing a slot under the
+            // --key-buffer cap
             if self.use_uart_rx {
                 return 0;
             }
@@ -2167,6 +3125,14 @@ impl Memory {
                 .read()
                 .unwrap()
                 .get_sprite_reg((addr - SPRITE_REGISTERS_START) as u32);
+        } else if (SPRITE_PRIORITY_START..SPRITE_PRIORITY_START + SPRITE_PRIORITY_SIZE)
+            .contains(&addr)
+        {
+            return self
+                .sprite_map
+                .read()
+                .unwrap()
+                .get_sprite_priority(addr - SPRITE_PRIORITY_START);
         } else if addr == TILE_V_SCROLL_START {
             return self.tile_vscroll_register.read().unwrap().0;
         } else if addr == TILE_V_SCROLL_START + 1 {
@@ -2177,6 +3143,14 @@ impl Memory {
             return self.tile_hscroll_register.read().unwrap().1;
         } else if addr == TILE_SCALE_REGISTER_START {
             return *self.tile_scale_register.read().unwrap();
+        } else if addr == BG_TILE_V_SCROLL_START {
+            return self.bg_tile_vscroll_register.read().unwrap().0;
+        } else if addr == BG_TILE_V_SCROLL_START + 1 {
+            return self.bg_tile_vscroll_register.read().unwrap().1;
+        } else if addr == BG_TILE_H_SCROLL_START {
+            return self.bg_tile_hscroll_register.read().unwrap().0;
+        } else if addr == BG_TILE_H_SCROLL_START + 1 {
+            return self.bg_tile_hscroll_register.read().unwrap().1;
         } else if addr == PIXEL_V_SCROLL_START {
             return self.pixel_vscroll_register.read().unwrap().0;
         } else if addr == PIXEL_V_SCROLL_START + 1 {
@@ -2187,6 +3161,11 @@ impl Memory {
             return self.pixel_hscroll_register.read().unwrap().1;
         } else if addr == PIXEL_SCALE_REGISTER_START {
             return *self.pixel_scale_register.read().unwrap();
+        } else if addr == PALETTE_MODE_REGISTER_START {
+            return *self.palette_mode_register.read().unwrap();
+        } else if (PALETTE_START..PALETTE_START + PALETTE_SIZE).contains(&addr) {
+            let offset = (addr - PALETTE_START) as usize;
+            return self.palette.read().unwrap()[offset / 2].to_le_bytes()[offset % 2];
         } else if addr >= SPRITE_SCALE_START && addr < SPRITE_SCALE_START + SPRITE_SCALE_SIZE {
             let idx = (addr - SPRITE_SCALE_START) as usize;
             return self.sprite_scale_registers.read().unwrap()[idx];
@@ -2200,6 +3179,13 @@ impl Memory {
             return self.vga_frame_register.read().unwrap().2;
         } else if addr == VGA_FRAME_REGISTER_START + 3 {
             return self.vga_frame_register.read().unwrap().3;
+        } else if (MOUSE_X_START..MOUSE_X_START + MOUSE_REGISTERS_SIZE).contains(&addr) {
+            let (x, y, buttons) = *self.mouse_register.read().unwrap();
+            return match addr {
+                MOUSE_BUTTONS_START => buttons,
+                _ if addr >= MOUSE_Y_START => y.to_le_bytes()[(addr - MOUSE_Y_START) as usize],
+                _ => x.to_le_bytes()[(addr - MOUSE_X_START) as usize],
+            };
         } else if addr == UART_TX {
             panic!("attempting to read output port (address {:X})", UART_TX);
         } else if addr == UART_RX {
@@ -2216,14 +3202,10 @@ impl Memory {
             } else {
                 return 0;
             }
-        } else if addr == PIT_START {
-            return read_reg_byte(self.read_pit_reload(), addr, PIT_START);
-        } else if addr == PIT_START + 1 {
-            return read_reg_byte(self.read_pit_reload(), addr, PIT_START);
-        } else if addr == PIT_START + 2 {
-            return read_reg_byte(self.read_pit_reload(), addr, PIT_START);
-        } else if addr == PIT_START + 3 {
-            return read_reg_byte(self.read_pit_reload(), addr, PIT_START);
+        } else if (PIT_START..PIT_START + 4).contains(&addr) {
+            return read_reg_byte(self.read_pit_count(), addr, PIT_START);
+        } else if (PIT_CTRL_START..PIT_CTRL_START + 4).contains(&addr) {
+            return read_reg_byte(self.pit_ctrl.load(Ordering::SeqCst), addr, PIT_CTRL_START);
         } else if addr == CLK_REG_START {
             return self.clk_register.read().unwrap().0;
         } else if addr == CLK_REG_START + 1 {
@@ -2244,6 +3226,7 @@ impl Memory {
     }
 
     pub fn write(&self, addr: u32, data: u8) {
+        self.assert_physical(addr);
         if Self::addr_touches_mmio(addr) {
             let _mmio = self.mmio_lock.lock().unwrap();
             self.write_mmio_byte(addr, data);
@@ -2254,6 +3237,7 @@ impl Memory {
 
     pub fn write_u16(&self, addr: u32, data: u16) {
         let addr = addr & 0xFFFFFFFE;
+        self.assert_physical(addr);
         if let Some(page_index) = Self::ram_range_within_single_page(addr, 2) {
             Self::maybe_warn_null_write(addr, data.to_le_bytes()[0]);
             let mut page = self.ram_pages[page_index].write().unwrap();
@@ -2267,6 +3251,7 @@ impl Memory {
 
     pub fn write_u32(&self, addr: u32, data: u32) {
         let addr = addr & 0xFFFFFFFC;
+        self.assert_physical(addr);
         if let Some(page_index) = Self::ram_range_within_single_page(addr, 4) {
             Self::maybe_warn_null_write(addr, data.to_le_bytes()[0]);
             let mut page = self.ram_pages[page_index].write().unwrap();
@@ -2281,6 +3266,9 @@ impl Memory {
     // Write specific physical addresses under one lock to avoid tearing.
     pub fn write_phys_bytes(&self, addrs: &[u32], data: &[u8]) {
         assert_eq!(addrs.len(), data.len());
+        for addr in addrs {
+            self.assert_physical(*addr);
+        }
         if Self::addrs_touch_mmio(addrs) {
             let _mmio = self.mmio_lock.lock().unwrap();
             let was_low_water = self.audio.read().unwrap().low_water();
@@ -2339,6 +3327,14 @@ impl Memory {
                 .unwrap()
                 .set_byte((addr - TILE_FRAME_BUFFER_START) as u32, data);
             handled = true;
+        } else if (BG_TILE_FRAME_BUFFER_START..BG_TILE_FRAME_BUFFER_START + TILE_FRAME_BUFFER_SIZE)
+            .contains(&addr)
+        {
+            self.bg_tile_frame_buffer
+                .write()
+                .unwrap()
+                .set_byte(addr - BG_TILE_FRAME_BUFFER_START, data);
+            handled = true;
         } else if addr >= PIXEL_FRAME_BUFFER_START
             && addr < PIXEL_FRAME_BUFFER_START + PIXEL_FRAME_BUFFER_SIZE
         {
@@ -2366,8 +3362,7 @@ impl Memory {
         } else if addr == PS2_STREAM {
             panic!("attempting to write input port (address {:X})", PS2_STREAM);
         } else if addr == UART_TX {
-            print!("{}", data as char);
-            io::stdout().flush().unwrap();
+            self.write_uart_tx(data);
             handled = true;
         } else if addr == UART_RX {
             panic!("attempting to write input port (address {:X})", UART_RX);
@@ -2386,6 +3381,18 @@ impl Memory {
         } else if addr == TILE_SCALE_REGISTER_START {
             *self.tile_scale_register.write().unwrap() = data;
             handled = true;
+        } else if addr == BG_TILE_V_SCROLL_START {
+            self.bg_tile_vscroll_register.write().unwrap().0 = data;
+            handled = true;
+        } else if addr == BG_TILE_V_SCROLL_START + 1 {
+            self.bg_tile_vscroll_register.write().unwrap().1 = data;
+            handled = true;
+        } else if addr == BG_TILE_H_SCROLL_START {
+            self.bg_tile_hscroll_register.write().unwrap().0 = data;
+            handled = true;
+        } else if addr == BG_TILE_H_SCROLL_START + 1 {
+            self.bg_tile_hscroll_register.write().unwrap().1 = data;
+            handled = true;
         } else if addr == PIXEL_V_SCROLL_START {
             self.pixel_vscroll_register.write().unwrap().0 = data;
             handled = true;
@@ -2401,6 +3408,16 @@ impl Memory {
         } else if addr == PIXEL_SCALE_REGISTER_START {
             *self.pixel_scale_register.write().unwrap() = data;
             handled = true;
+        } else if addr == PALETTE_MODE_REGISTER_START {
+            *self.palette_mode_register.write().unwrap() = data;
+            handled = true;
+        } else if (PALETTE_START..PALETTE_START + PALETTE_SIZE).contains(&addr) {
+            let offset = (addr - PALETTE_START) as usize;
+            let mut palette = self.palette.write().unwrap();
+            let mut bytes = palette[offset / 2].to_le_bytes();
+            bytes[offset % 2] = data;
+            palette[offset / 2] = u16::from_le_bytes(bytes);
+            handled = true;
         } else if addr >= SPRITE_SCALE_START && addr < SPRITE_SCALE_START + SPRITE_SCALE_SIZE {
             let idx = (addr - SPRITE_SCALE_START) as usize;
             self.sprite_scale_registers.write().unwrap()[idx] = data;
@@ -2419,17 +3436,19 @@ impl Memory {
                 .unwrap()
                 .set_sprite_reg((addr - SPRITE_REGISTERS_START) as u32, data);
             handled = true;
-        } else if addr == PIT_START {
-            self.write_pit_reload_byte(addr, data);
-            handled = true;
-        } else if addr == PIT_START + 1 {
-            self.write_pit_reload_byte(addr, data);
+        } else if (SPRITE_PRIORITY_START..SPRITE_PRIORITY_START + SPRITE_PRIORITY_SIZE)
+            .contains(&addr)
+        {
+            self.sprite_map
+                .write()
+                .unwrap()
+                .set_sprite_priority(addr - SPRITE_PRIORITY_START, data);
             handled = true;
-        } else if addr == PIT_START + 2 {
-            self.write_pit_reload_byte(addr, data);
+        } else if (PIT_START..PIT_START + 4).contains(&addr) {
+            self.write_pit_reload_bytes(&[addr], &[data]);
             handled = true;
-        } else if addr == PIT_START + 3 {
-            self.write_pit_reload_byte(addr, data);
+        } else if (PIT_CTRL_START..PIT_CTRL_START + 4).contains(&addr) {
+            self.write_pit_ctrl_byte(addr, data);
             handled = true;
         } else if addr == CLK_REG_START {
             self.clk_register.write().unwrap().0 = data;
@@ -2453,6 +3472,11 @@ impl Memory {
                 "attempting to write read-only VGA frame register (0x{:08X})",
                 VGA_FRAME_REGISTER_START
             );
+        } else if (MOUSE_X_START..MOUSE_X_START + MOUSE_REGISTERS_SIZE).contains(&addr) {
+            panic!(
+                "attempting to write read-only mouse register (0x{:08X})",
+                addr
+            );
         } else if addr == 0 {
             println!(
                 "Warning: writing to physical address 0x00000000: 0x{:08X}",
@@ -2510,7 +3534,7 @@ impl Memory {
             }
         }
 
-        let (mem_addr, sd_offset, bytes, dir_ram_to_sd, done_after, irq_enable) = {
+        let (mem_addr, sd_offset, bytes, dir_ram_to_sd, done_after, irq_enable, crc, trailer) = {
             let mut sd = sd.write().unwrap();
             if !sd.dma_active {
                 return;
@@ -2526,10 +3550,28 @@ impl Memory {
                 SD_DMA_BYTES_PER_TICK
             };
             let mem_addr = sd.dma_mem_cursor;
+            if !self.is_physical_range(mem_addr, bytes) {
+                // Stop with an error instead of touching memory that is not there.
+                let irq = sd.fail_dma(SD_DMA_ERR_RANGE);
+                drop(sd);
+                if irq {
+                    self.raise_pending_interrupt(interrupt_bit);
+                }
+                return;
+            }
             let sd_offset = sd.dma_sd_byte_cursor;
             let dir_ram_to_sd = (sd.dma_ctrl & SD_DMA_CTRL_DIR_RAM_TO_SD) != 0;
+            let crc = sd.dma_crc;
+            // In CRC mode the last word of each frame is the trailer, which
+            // moves through RAM but not through SD storage.
+            let trailer = crc && sd.dma_frame_offset >= SD_BLOCK_SIZE_U32;
+            if crc {
+                sd.dma_frame_offset = (sd.dma_frame_offset + bytes) % SD_CRC_FRAME_SIZE;
+            }
             sd.dma_mem_cursor = sd.dma_mem_cursor.wrapping_add(bytes);
-            sd.dma_sd_byte_cursor = sd.dma_sd_byte_cursor.wrapping_add(bytes as u64);
+            if !trailer {
+                sd.dma_sd_byte_cursor = sd.dma_sd_byte_cursor.wrapping_add(bytes as u64);
+            }
             sd.dma_remaining = sd.dma_remaining.wrapping_sub(bytes);
             let done_after = sd.dma_remaining == 0;
             if done_after {
@@ -2548,6 +3590,8 @@ impl Memory {
                 dir_ram_to_sd,
                 done_after,
                 irq_enable,
+                crc,
+                trailer,
             )
         };
 
@@ -2555,7 +3599,37 @@ impl Memory {
             return;
         }
 
-        if dir_ram_to_sd {
+        if trailer {
+            // `sd_offset` is the end of the block this trailer belongs to.
+            let mut word = [0u8; 4];
+            let addrs: [u32; 4] = std::array::from_fn(|i| mem_addr.wrapping_add(i as u32));
+            if dir_ram_to_sd {
+                self.read_phys_bytes(&addrs, &mut word);
+                let mut sd = sd.write().unwrap();
+                let block = std::mem::take(&mut sd.dma_block_buf);
+                if sd_crc16(&block) == u16::from_le_bytes([word[0], word[1]]) {
+                    let start = sd_offset - SD_BLOCK_SIZE as u64;
+                    for (i, byte) in block.iter().enumerate() {
+                        sd.write_storage_byte(start + i as u64, *byte);
+                    }
+                } else if sd.fail_dma(SD_DMA_ERR_CRC) && !done_after {
+                    // The last block already raises the DONE interrupt below.
+                    drop(sd);
+                    self.raise_pending_interrupt(interrupt_bit);
+                }
+            } else {
+                let crc = sd.read().unwrap().stored_block_crc(sd_offset);
+                word = u32::from(crc).to_le_bytes();
+                self.write_phys_bytes(&addrs, &word);
+            }
+        } else if dir_ram_to_sd && crc {
+            let mut buf = [0u8; SD_DMA_BYTES_PER_TICK as usize];
+            let addrs: [u32; SD_DMA_BYTES_PER_TICK as usize] =
+                std::array::from_fn(|i| mem_addr.wrapping_add(i as u32));
+            self.read_phys_bytes(&addrs[..bytes as usize], &mut buf[..bytes as usize]);
+            let mut sd = sd.write().unwrap();
+            sd.dma_block_buf.extend_from_slice(&buf[..bytes as usize]);
+        } else if dir_ram_to_sd {
             let mut buf = [0u8; SD_DMA_BYTES_PER_TICK as usize];
             let mut addrs = [0u32; SD_DMA_BYTES_PER_TICK as usize];
             for i in 0..bytes {
@@ -2586,19 +3660,22 @@ impl Memory {
         }
     }
 
-    // Purpose: advance the shared PIT countdown by one core-0 tick.
-    // Inputs: none.
-    // Outputs: true if a timer interrupt should be raised this tick.
+    // Purpose: advance the shared PIT countdown by one core-0 clock edge.
+    // Inputs: none. Callers apply the clock divider, so the timer counts
+    // emulated instruction slots rather than emulator loop iterations.
+    // Outputs: true if a timer interrupt should be raised this tick. A
+    // stopped counter (0) stays stopped until the reload register is written.
     pub fn tick_pit(&self) -> bool {
         let mut countdown = self.pit_countdown.lock().unwrap();
         if *countdown == 0 {
-            let reload = self.read_pit_reload();
-            if reload != 0 {
-                *countdown = reload;
-                return true;
+            return false;
+        }
+        *countdown -= 1;
+        if *countdown == 0 {
+            if self.pit_ctrl.load(Ordering::SeqCst) & PIT_CTRL_ONESHOT == 0 {
+                *countdown = self.read_pit_reload();
             }
-        } else {
-            *countdown -= 1;
+            return true;
         }
         false
     }
@@ -2706,6 +3783,145 @@ mod tests {
         );
     }
 
+    #[test]
+    fn io_registers_report_device_state_without_side_effects() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        memory.write_u16(TILE_H_SCROLL_START, 0x0123);
+        memory.write_u16(BG_TILE_V_SCROLL_START, 0xFFF8);
+        memory.write_u32(PIT_START, 500);
+        memory.raise_pending_interrupt(SD_INTERRUPT_BIT);
+
+        let regs: HashMap<_, _> = memory.io_registers().into_iter().collect();
+        assert_eq!(regs["tile_hscroll"], 0x0123);
+        assert_eq!(regs["bg_tile_vscroll"], 0xFFF8);
+        assert_eq!(
+            memory.get_bg_tile_vscroll_register().read().unwrap().1,
+            0xFF
+        );
+        assert_eq!(regs["pit_reload"], 500);
+        assert_eq!(regs["pending_irqs"], SD_INTERRUPT_BIT);
+        assert_eq!((regs["sd0_busy"], regs["sd0_init"]), (0, 0));
+        assert_eq!(
+            memory.check_interrupts(),
+            SD_INTERRUPT_BIT,
+            "inspection must not consume pending interrupts"
+        );
+    }
+
+    #[test]
+    fn memory_map_reports_sd_register_bounds_in_address_order() {
+        let memory = Memory::with_physmem_size(HashMap::new(), false, 1, 0x10000);
+        let regions = memory.regions();
+        assert_eq!(
+            (regions[0].name, regions[0].start, regions[0].end),
+            ("ram", 0, 0x10000)
+        );
+        let sd0 = regions
+            .iter()
+            .find(|region| region.name == "sd0_dma")
+            .expect("sd0 region");
+        assert_eq!((sd0.start, sd0.end), (0x7FE5810, 0x7FE5828));
+        assert_eq!(sd0.kind, MemRegionKind::Registers);
+        assert_eq!(memory.region_at(0x7FE5827).map(|r| r.name), Some("sd0_dma"));
+        assert_eq!(memory.region_at(0x7FE5828).map(|r| r.name), Some("sd1_dma"));
+        assert_eq!(
+            memory.region_at(0x1000).map(|r| r.kind),
+            Some(MemRegionKind::Ram)
+        );
+        // Past the end of RAM and in the gap between devices: nothing.
+        assert_eq!(memory.region_at(0x10000), None);
+        assert_eq!(memory.region_at(0x7FE580C), None);
+        for pair in regions.windows(2) {
+            assert!(
+                pair[0].end <= pair[1].start,
+                "{:?} overlaps {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn write_through_framebuffer_reads_back_pending_writes() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        memory.write(PIXEL_FRAME_BUFFER_START + 6, 0x5A);
+        assert_eq!(memory.read(PIXEL_FRAME_BUFFER_START + 6), 0x5A);
+    }
+
+    #[test]
+    fn pixel_framebuffer_maps_coordinates_to_packed_pixels() {
+        let mut fb = PixelFrameBuffer::new(
+            PIXEL_FRAME_WIDTH,
+            PIXEL_FRAME_HEIGHT,
+            PIXEL_FRAME_BUFFER_SIZE,
+        );
+        let row = 2 * PIXEL_FRAME_WIDTH;
+        fb.set_byte(0, 0x21);
+        fb.set_byte(1, 0x03);
+        fb.set_byte(2 * 5, 0xBC);
+        fb.set_byte(2 * 5 + 1, 0x0A);
+        fb.set_byte(3 * row + 2 * 7, 0x65);
+        fb.set_byte(3 * row + 2 * 7 + 1, 0x04);
+        let last = PIXEL_FRAME_BUFFER_SIZE - 2;
+        fb.set_byte(last, 0xFF);
+        fb.set_byte(last + 1, 0x0F);
+
+        assert_eq!(fb.get_pixel(0, 0), Some(0x0321));
+        assert_eq!(fb.get_pixel(5, 0), Some(0x0ABC));
+        assert_eq!(fb.get_pixel(7, 3), Some(0x0465));
+        assert_eq!(fb.get_pixel(3, 7), Some(0));
+        assert_eq!(
+            fb.get_pixel(PIXEL_FRAME_WIDTH - 1, PIXEL_FRAME_HEIGHT - 1),
+            Some(0x0FFF)
+        );
+        assert_eq!(fb.get_pixel(PIXEL_FRAME_WIDTH, 0), None);
+        assert_eq!(fb.get_pixel(0, PIXEL_FRAME_HEIGHT), None);
+    }
+
+    #[test]
+    fn displayed_framebuffer_reads_last_presented_frame() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        memory.write(PIXEL_FRAME_BUFFER_START + 6, 0x11);
+        memory.set_framebuffer_visibility(FramebufferVisibility::Displayed);
+        memory.write(PIXEL_FRAME_BUFFER_START + 6, 0x22);
+        assert_eq!(
+            memory.read(PIXEL_FRAME_BUFFER_START + 6),
+            0x11,
+            "displayed mode must hide writes until the display presents them",
+        );
+        memory.get_pixel_frame_buffer().write().unwrap().present();
+        assert_eq!(memory.read(PIXEL_FRAME_BUFFER_START + 6), 0x22);
+    }
+
+    #[test]
+    fn access_stats_tally_regions_and_ram_pages() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        memory.record_access(0x2004, false);
+        memory.record_access(0x2008, true);
+        memory.record_access(0x5000, false);
+        memory.record_access(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS, false);
+        memory.record_access(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS, false);
+        memory.record_access(CLK_REG_START, false);
+
+        let stats = memory.access_stats();
+        assert_eq!(
+            stats.regions,
+            vec![("ram", 2, 1), ("sd0_dma", 2, 0), ("clock", 1, 0)]
+        );
+        assert_eq!(stats.pages, vec![(0x2000, 1, 1, 0), (0x5000, 1, 0, 0)]);
+
+        memory.record_fetch(0x2000);
+        memory.set_fetch_profiling(true);
+        memory.record_fetch(0x2000);
+        memory.record_fetch(CLK_REG_START);
+        let stats = memory.access_stats();
+        assert_eq!(stats.pages[0], (0x2000, 1, 1, 1));
+        assert_eq!(
+            stats.pages_csv(),
+            "page,reads,writes,fetches\n0x00002000,1,1,1\n0x00005000,1,0,0\n"
+        );
+    }
+
     #[test]
     fn sd_dump_preserves_loaded_image_length() {
         let mut sd = SdCard::new(1);
@@ -2726,6 +3942,179 @@ mod tests {
         assert_eq!(image[511], 0xCC);
     }
 
+    // Tick the SD DMA engines until SD0 leaves BUSY; panics if it never does.
+    fn wait_sd0_idle(memory: &Memory) {
+        for _ in 0..0x10000 {
+            memory.tick_sd_dma();
+            if memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS) & SD_DMA_STATUS_BUSY == 0 {
+                return;
+            }
+        }
+        panic!("SD0 DMA did not finish");
+    }
+
+    #[test]
+    fn sd_dma_transfers_consecutive_blocks_both_ways() {
+        // SD_DMA_LEN counts blocks, so one START moves several blocks, the
+        // DMA equivalent of a multi-block read/write command.
+        const BLOCKS: u32 = 3;
+        const SRC: u32 = 0x10000;
+        const DST: u32 = 0x20000;
+        const SD_BLOCK: u32 = 5;
+        let mut image = HashMap::new();
+        for offset in 0..BLOCKS * SD_BLOCK_SIZE_U32 {
+            image.insert(
+                SRC + offset,
+                (offset / SD_BLOCK_SIZE_U32 + 1) as u8 ^ offset as u8,
+            );
+        }
+        let memory = Memory::new(image, false, 1);
+
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL, SD_DMA_CTRL_INIT);
+        wait_sd0_idle(&memory);
+
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_MEM_ADDR, SRC);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_SD_BLOCK, SD_BLOCK);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_LEN, BLOCKS);
+        memory.write_u32(
+            SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL,
+            SD_DMA_CTRL_START | SD_DMA_CTRL_DIR_RAM_TO_SD,
+        );
+        wait_sd0_idle(&memory);
+        {
+            let sd = memory.sd_card.read().unwrap();
+            let mut blocks: Vec<u32> = sd.storage.keys().copied().collect();
+            blocks.sort();
+            assert_eq!(blocks, vec![5, 6, 7]);
+            for block in 0..BLOCKS {
+                let data = &sd.storage[&(SD_BLOCK + block)];
+                for (offset, byte) in data.iter().enumerate() {
+                    let src = SRC + block * SD_BLOCK_SIZE_U32 + offset as u32;
+                    assert_eq!(*byte, memory.read(src), "block {} byte {}", block, offset);
+                }
+            }
+        }
+
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS, 0);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_MEM_ADDR, DST);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL, SD_DMA_CTRL_START);
+        wait_sd0_idle(&memory);
+        assert_eq!(
+            memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_ERR),
+            SD_DMA_ERR_NONE
+        );
+        for offset in 0..BLOCKS * SD_BLOCK_SIZE_U32 {
+            assert_eq!(memory.read(DST + offset), memory.read(SRC + offset));
+        }
+        assert_eq!(memory.read(DST + BLOCKS * SD_BLOCK_SIZE_U32), 0);
+    }
+
+    #[test]
+    fn sd_dma_outside_physical_memory_reports_an_error() {
+        const PHYSMEM: u32 = 0x10000;
+        let memory = Memory::with_physmem_size(HashMap::new(), false, 1, PHYSMEM);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL, SD_DMA_CTRL_INIT);
+        wait_sd0_idle(&memory);
+
+        // Past the end of RAM, and straddling it: both fail before moving a byte.
+        for mem_addr in [PHYSMEM + 0x1000, PHYSMEM - 0x100] {
+            memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS, 0);
+            memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_MEM_ADDR, mem_addr);
+            memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_LEN, 1);
+            memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL, SD_DMA_CTRL_START);
+            wait_sd0_idle(&memory);
+            assert_eq!(
+                memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_ERR),
+                SD_DMA_ERR_RANGE
+            );
+            let status = memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS);
+            assert_eq!(status, SD_DMA_STATUS_DONE | SD_DMA_STATUS_ERR);
+        }
+        assert_eq!(memory.read(PHYSMEM - 0x100), 0);
+
+        // The check also covers each tick, should the cursor leave RAM.
+        {
+            let mut sd = memory.sd_card.write().unwrap();
+            sd.clear_status();
+            sd.dma_active = true;
+            sd.dma_status = SD_DMA_STATUS_BUSY;
+            sd.dma_mem_cursor = PHYSMEM;
+            sd.dma_remaining = SD_BLOCK_SIZE_U32;
+        }
+        memory.tick_sd_dma();
+        let sd = memory.sd_card.read().unwrap();
+        assert!(!sd.dma_active);
+        assert_eq!(sd.dma_err, SD_DMA_ERR_RANGE);
+    }
+
+    #[test]
+    fn sd_crc16_matches_a_known_block() {
+        // The CRC16 of a block of 0xFF bytes from the SD specification.
+        assert_eq!(sd_crc16(&[0xFF; SD_BLOCK_SIZE]), 0x7FA1);
+        assert_eq!(sd_crc16(&[]), 0);
+    }
+
+    #[test]
+    fn sd_dma_crc_mode_appends_and_checks_block_crcs() {
+        const BUF: u32 = 0x10000;
+        let memory = Memory::new(HashMap::new(), false, 1);
+        let mut image = vec![0xFF; SD_BLOCK_SIZE];
+        image.extend((0..SD_BLOCK_SIZE).map(|i| i as u8));
+        memory.sd_card.write().unwrap().load_image(&image);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL, SD_DMA_CTRL_INIT);
+        wait_sd0_idle(&memory);
+
+        // Read both blocks: each lands in RAM followed by its CRC word.
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_MEM_ADDR, BUF);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_SD_BLOCK, 0);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_LEN, 2);
+        memory.write_u32(
+            SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL,
+            SD_DMA_CTRL_START | SD_DMA_CTRL_CRC,
+        );
+        wait_sd0_idle(&memory);
+        assert_eq!(memory.read(BUF + 511), 0xFF);
+        assert_eq!(memory.read_u32(BUF + 512), 0x7FA1);
+        assert_eq!(memory.read(BUF + 516 + 5), 5);
+        assert_eq!(
+            memory.read_u32(BUF + 516 + 512),
+            u32::from(sd_crc16(&image[SD_BLOCK_SIZE..]))
+        );
+
+        // Writing the frames back to blocks 4 and 5 checks each trailer.
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS, 0);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_SD_BLOCK, 4);
+        memory.write_u32(
+            SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL,
+            SD_DMA_CTRL_START | SD_DMA_CTRL_DIR_RAM_TO_SD | SD_DMA_CTRL_CRC,
+        );
+        wait_sd0_idle(&memory);
+        assert_eq!(
+            memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_ERR),
+            SD_DMA_ERR_NONE
+        );
+        assert_eq!(memory.sd_card.read().unwrap().storage[&5][5], 5);
+
+        // A bad CRC on the second block rejects it and ends the transfer.
+        memory.write(BUF + 516 + 512, 0);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS, 0);
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_SD_BLOCK, 8);
+        memory.write_u32(
+            SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL,
+            SD_DMA_CTRL_START | SD_DMA_CTRL_DIR_RAM_TO_SD | SD_DMA_CTRL_CRC,
+        );
+        wait_sd0_idle(&memory);
+        assert_eq!(
+            memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_ERR),
+            SD_DMA_ERR_CRC
+        );
+        let status = memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS);
+        assert_eq!(status, SD_DMA_STATUS_DONE | SD_DMA_STATUS_ERR);
+        let sd = memory.sd_card.read().unwrap();
+        assert!(sd.storage.contains_key(&8));
+        assert!(!sd.storage.contains_key(&9));
+    }
+
     #[test]
     fn sd_dump_zero_fills_sparse_gaps() {
         let mut sd = SdCard::new(1);
@@ -2738,6 +4127,34 @@ mod tests {
         assert_eq!(image[600], 0x5A);
     }
 
+    #[test]
+    fn mouse_registers_expose_shared_state() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        *memory.get_mouse_register().write().unwrap() =
+            (0x0234, 0x01DF, MOUSE_BUTTON_LEFT | MOUSE_BUTTON_MIDDLE);
+
+        assert_eq!(memory.read_u16(MOUSE_X_START), 0x0234);
+        assert_eq!(memory.read_u16(MOUSE_Y_START), 0x01DF);
+        assert_eq!(memory.read(MOUSE_Y_START + 1), 0x01);
+        assert_eq!(memory.read(MOUSE_BUTTONS_START), 0b101);
+    }
+
+    #[test]
+    fn palette_registers_are_shared_with_the_display() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        assert_eq!(memory.read_u16(PALETTE_START + 2 * 15), 0x0FFF);
+
+        memory.write_u16(PALETTE_START + 2 * 3, 0x0123);
+        memory.write(PALETTE_MODE_REGISTER_START, PALETTE_MODE_TILE);
+
+        assert_eq!(memory.get_palette().read().unwrap()[3], 0x0123);
+        assert_eq!(memory.read(PALETTE_START + 2 * 3 + 1), 0x01);
+        assert_eq!(
+            *memory.get_palette_mode_register().read().unwrap(),
+            PALETTE_MODE_TILE
+        );
+    }
+
     #[test]
     fn ram_reads_zero_from_unallocated_pages() {
         let memory = Memory::new(HashMap::new(), false, 1);
@@ -2773,14 +4190,74 @@ mod tests {
     }
 
     #[test]
-    fn pit_tick_uses_latest_written_reload() {
+    fn pit_counts_down_from_the_written_reload() {
         let memory = Memory::new(HashMap::new(), false, 1);
 
         memory.write_u32(PIT_START, 3);
-
-        assert!(memory.tick_pit());
-        assert_eq!(*memory.pit_countdown.lock().unwrap(), 3);
         assert_eq!(memory.read_u32(PIT_START), 3);
+        // Periodic: fires every third tick and the live count reloads.
+        let fired: Vec<bool> = (0..6).map(|_| memory.tick_pit()).collect();
+        assert_eq!(fired, [false, false, true, false, false, true]);
+        assert_eq!(memory.read_u32(PIT_START), 3);
+        assert!(!memory.tick_pit());
+        assert_eq!(memory.read_u32(PIT_START), 2);
+        assert_eq!(memory.pit_state(), (3, 2, 0));
+
+        // One-shot: fires once, then stays stopped until rewritten.
+        memory.write_u32(PIT_CTRL_START, PIT_CTRL_ONESHOT);
+        memory.write_u32(PIT_START, 2);
+        let fired: Vec<bool> = (0..4).map(|_| memory.tick_pit()).collect();
+        assert_eq!(fired, [false, true, false, false]);
+        assert_eq!(memory.read_u32(PIT_START), 0);
+        assert_eq!(memory.read_u32(PIT_CTRL_START), PIT_CTRL_ONESHOT);
+
+        // Byte writes restart the countdown too; a reload of 0 stops it.
+        memory.write(PIT_START, 1);
+        assert_eq!(memory.read_u32(PIT_START), 1);
+        memory.write_u32(PIT_START, 0);
+        assert!(!memory.tick_pit());
+    }
+
+    #[test]
+    fn peek_and_poke_leave_device_state_alone() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+
+        assert!(memory.poke(0x1234, 0xAB));
+        assert_eq!(memory.peek(0x1234), Some(0xAB));
+        assert!(!memory.poke(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_CTRL, 1));
+        assert!(!memory.poke(PIT_START, 5));
+        assert_eq!(memory.read_u32(PIT_START), 0);
+
+        // Peeking the SD control and status registers does not start a DMA.
+        memory.write_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_LEN, 1);
+        let status = memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS);
+        for offset in 0..SD_DMA_RANGE_SIZE {
+            memory.peek(SD_DMA_MEM_ADDR + offset).unwrap();
+        }
+        assert_eq!(
+            memory.read_u32(SD_DMA_MEM_ADDR + SD_DMA_OFFSET_STATUS),
+            status
+        );
+        assert_eq!(
+            memory
+                .io_registers()
+                .into_iter()
+                .find(|r| r.0 == "sd0_busy"),
+            Some(("sd0_busy", 0))
+        );
+
+        // Peeking the PS/2 stream shows the pending key without popping it.
+        memory.get_io_buffer().write().unwrap().push_back(0x0141);
+        assert_eq!(memory.peek(PS2_STREAM), Some(0x41));
+        assert_eq!(memory.peek(PS2_STREAM + 1), Some(0x01));
+        assert_eq!(memory.get_io_buffer().read().unwrap().len(), 1);
+        assert_eq!(memory.read_u16(PS2_STREAM), 0x0141);
+        assert!(memory.get_io_buffer().read().unwrap().is_empty());
+
+        // Write-only, unmapped and out-of-range addresses have nothing to show.
+        assert_eq!(memory.peek(UART_TX), None);
+        assert_eq!(memory.peek(0x7FE580C), None);
+        assert_eq!(memory.peek(PHYSMEM_MAX + 1), None);
     }
 
     #[test]
@@ -3216,6 +4693,122 @@ mod tests {
             "host audio output must mix the existing PCM device with the new synth device",
         );
     }
+
+    #[test]
+    fn window_input_queue_stops_growing_at_the_cap() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        let io_buffer = memory.get_io_buffer();
+        let mut queue = io_buffer.write().unwrap();
+        for code in 0..DEFAULT_KEY_BUFFER_CAP as u16 + 10 {
+            push_input_event(&mut queue, &[code], DEFAULT_KEY_BUFFER_CAP);
+        }
+        assert_eq!(queue.len(), DEFAULT_KEY_BUFFER_CAP);
+        // The oldest events are kept and the overflow is dropped.
+        assert_eq!(queue.front(), Some(&0));
+        assert_eq!(queue.back(), Some(&(DEFAULT_KEY_BUFFER_CAP as u16 - 1)));
+
+        // A multi-entry event that does not fit is dropped whole.
+        queue.truncate(DEFAULT_KEY_BUFFER_CAP - 2);
+        assert!(!push_input_event(
+            &mut queue,
+            &[0xF0, 0xE0, 0x1C],
+            DEFAULT_KEY_BUFFER_CAP
+        ));
+        assert_eq!(queue.len(), DEFAULT_KEY_BUFFER_CAP - 2);
+        assert!(push_input_event(
+            &mut queue,
+            &[0xF0, 0x1C],
+            DEFAULT_KEY_BUFFER_CAP
+        ));
+        assert_eq!(queue.len(), DEFAULT_KEY_BUFFER_CAP);
+        drop(queue);
+
+        // Guest reads drain the queue and make room again.
+        memory.read(PS2_STREAM + 1);
+        assert_eq!(io_buffer.read().unwrap().len(), DEFAULT_KEY_BUFFER_CAP - 1);
+    }
+
+    #[test]
+    fn guest_reads_work_through_a_shared_reference() {
+        let memory = Memory::new(HashMap::from([(0x1000, 0xAB)]), false, 1);
+        memory
+            .get_io_buffer()
+            .write()
+            .unwrap()
+            .extend([0x0161, 0x0062]);
+        let shared: &Memory = &memory;
+
+        thread::scope(|scope| {
+            let ram = scope.spawn(|| shared.read(0x1000));
+            let keys = scope.spawn(|| {
+                // The low byte shows the head; reading the upper byte pops it.
+                let low = shared.read(PS2_STREAM);
+                let low_again = shared.read(PS2_STREAM);
+                let high = shared.read(PS2_STREAM + 1);
+                (low, low_again, high, shared.read_u16(PS2_STREAM))
+            });
+            assert_eq!(ram.join().unwrap(), 0xAB);
+            assert_eq!(keys.join().unwrap(), (0x61, 0x61, 0x01, 0x0062));
+        });
+        assert!(memory.get_io_buffer().read().unwrap().is_empty());
+        assert!(!memory.get_input_pending().load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn reduced_physmem_rejects_addresses_past_ram() {
+        let size = 0x10000;
+        let image = HashMap::from([(0x100, 0x11), (size, 0x22)]);
+        let memory = Memory::with_physmem_size(image, false, 1, size);
+        assert_eq!(memory.physmem_size(), size);
+        assert_eq!(memory.peek(0x100), Some(0x11));
+
+        // The image byte past the end of RAM was dropped with the address.
+        assert!(memory.is_physical(size - 1));
+        assert!(!memory.is_physical(size));
+        assert_eq!(memory.peek(size), None);
+        assert!(!memory.poke(size, 0x33));
+        assert!(!memory.is_physical_range(size - 2, 4));
+        assert!(memory.is_physical_range(size - 4, 4));
+
+        // Device registers keep their addresses above the gap.
+        assert!(memory.is_physical_range(PIT_START, 4));
+        memory.write_u32(PIT_START, 5);
+        assert_eq!(memory.read_u32(PIT_START), 5);
+        assert!(!memory.is_physical(PHYSMEM_MAX + 1));
+
+        assert!(check_physmem_size(0).is_err());
+        assert!(check_physmem_size(0x1800).is_err());
+        assert!(check_physmem_size(IO_START + 0x1000).is_err());
+        assert!(check_physmem_size(DEFAULT_PHYSMEM_SIZE).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Physical memory address out of bounds")]
+    fn reduced_physmem_panics_on_direct_access_past_ram() {
+        let memory = Memory::with_physmem_size(HashMap::new(), false, 1, 0x10000);
+        memory.write(0x10000, 1);
+    }
+
+    #[test]
+    fn display_maps_ignore_out_of_range_accesses() {
+        let mut sprites = SpriteMap::new(SPRITE_MAP_SIZE);
+        assert_eq!(sprites.sprites.len(), SPRITE_COUNT as usize);
+        sprites.set_sprite_byte(SPRITE_MAP_SIZE, 0x12);
+        sprites.set_sprite_reg(SPRITE_REGISTERS_SIZE, 0x34);
+        sprites.set_sprite_priority(SPRITE_COUNT, 5);
+        assert_eq!(sprites.get_sprite_byte(SPRITE_MAP_SIZE), 0);
+        assert_eq!(sprites.get_sprite_reg(SPRITE_REGISTERS_SIZE), 0);
+        assert_eq!(sprites.get_sprite_priority(SPRITE_COUNT), 0);
+
+        let mut tiles = TileMap::new(TILE_SIZE * 2);
+        tiles.set_tile_byte(TILE_SIZE * 2, 0x56);
+        assert_eq!(tiles.get_tile_byte(TILE_SIZE * 2), 0);
+
+        let mut tile_fb = TileFrameBuffer::new(FRAME_WIDTH, FRAME_HEIGHT, TILE_FRAME_BUFFER_SIZE);
+        tile_fb.set_byte(TILE_FRAME_BUFFER_SIZE, 0x78);
+        assert_eq!(tile_fb.get_byte(TILE_FRAME_BUFFER_SIZE), 0);
+        assert_eq!(tile_fb.get_tile_entry(tile_fb.width_tiles, 0), (0, 0));
+    }
 }
 
 impl TileFrameBuffer {
@@ -3242,32 +4835,24 @@ impl TileFrameBuffer {
 
     // Purpose: store one MMIO byte into the tile framebuffer backing store.
     // Inputs: byte offset and value.
-    // Outputs: updates tile_indices at the given offset.
+    // Outputs: updates tile_indices at the given offset; out-of-range offsets
+    // are ignored.
     pub fn set_byte(&mut self, offset: u32, value: u8) {
-        if offset < self.entries.len() as u32 {
-            self.entries[offset as usize] = value;
-        } else {
-            panic!("Tile framebuffer offset out of bounds: {}", offset);
+        if let Some(entry) = self.entries.get_mut(offset as usize) {
+            *entry = value;
         }
     }
 
     // Purpose: read one MMIO byte from the tile framebuffer backing store.
     // Inputs: byte offset.
-    // Outputs: stored byte value at the given offset.
+    // Outputs: stored byte value at the given offset, or 0 out of range.
     pub fn get_byte(&self, offset: u32) -> u8 {
-        if offset < self.entries.len() as u32 {
-            self.entries[offset as usize]
-        } else {
-            panic!("Tile framebuffer offset out of bounds: {}", offset);
-        }
+        self.entries.get(offset as usize).copied().unwrap_or(0)
     }
 
-    // Purpose: fetch the tile index at a tile coordinate.
-    // Inputs: tile-space coordinates.
-    // Outputs: 8-bit tile index for the tilemap lookup.
     // Purpose: fetch the tile entry (index + color) at a tile coordinate.
     // Inputs: tile-space coordinates.
-    // Outputs: (tile index, color byte).
+    // Outputs: (tile index, color byte); (0, 0) outside the screen.
     pub fn get_tile_entry(&self, x: u32, y: u32) -> (u8, u8) {
         if x < self.width_tiles && y < self.height_tiles {
             let idx: usize = (x + y * self.width_tiles) as usize;
@@ -3276,7 +4861,7 @@ impl TileFrameBuffer {
             let tile_color = self.entries[entry_offset + 1];
             (tile_index, tile_color)
         } else {
-            panic!("Tile coordinates out of bounds: ({}, {})", x, y);
+            (0, 0)
         }
     }
 }
@@ -3297,6 +4882,32 @@ impl PixelFrameBuffer {
             width_pixels,
             height_pixels,
             bytes: vec![0; size_bytes as usize],
+            displayed: Vec::new(),
+            visibility: FramebufferVisibility::WriteThrough,
+        }
+    }
+
+    // Purpose: choose which copy guest reads observe.
+    // Inputs: visibility model.
+    // Outputs: switching to `Displayed` treats the current contents as already shown.
+    pub fn set_visibility(&mut self, visibility: FramebufferVisibility) {
+        self.visibility = visibility;
+        match visibility {
+            FramebufferVisibility::WriteThrough => self.displayed = Vec::new(),
+            FramebufferVisibility::Displayed => self.displayed = self.bytes.clone(),
+        }
+    }
+
+    pub fn visibility(&self) -> FramebufferVisibility {
+        self.visibility
+    }
+
+    // Purpose: mark the current guest-written contents as displayed.
+    // Inputs: none; called by the display after compositing a frame.
+    // Outputs: in `Displayed` mode, later guest reads return this frame.
+    pub fn present(&mut self) {
+        if self.visibility == FramebufferVisibility::Displayed {
+            self.displayed.copy_from_slice(&self.bytes);
         }
     }
 
@@ -3313,10 +4924,13 @@ impl PixelFrameBuffer {
 
     // Purpose: read one MMIO byte from the pixel framebuffer backing store.
     // Inputs: byte offset.
-    // Outputs: stored byte value at the given offset.
+    // Outputs: written or displayed byte at the given offset, per the visibility model.
     pub fn get_byte(&self, offset: u32) -> u8 {
         if offset < self.bytes.len() as u32 {
-            self.bytes[offset as usize]
+            match self.visibility {
+                FramebufferVisibility::WriteThrough => self.bytes[offset as usize],
+                FramebufferVisibility::Displayed => self.displayed[offset as usize],
+            }
         } else {
             panic!("Pixel framebuffer offset out of bounds: {}", offset);
         }
@@ -3324,14 +4938,19 @@ impl PixelFrameBuffer {
 
     // Purpose: fetch the 16-bit pixel at a logical pixel coordinate.
     // Inputs: pixel-space coordinates.
-    // Outputs: packed 12-bit RGB value stored in 16 bits (little-endian).
-    pub fn get_pixel(&self, x: u32, y: u32) -> u16 {
-        if x < self.width_pixels && y < self.height_pixels {
-            let idx: usize = (x + y * self.width_pixels) as usize;
-            ((u16::from(self.bytes[2 * idx + 1])) << 8) | u16::from(self.bytes[2 * idx])
-        } else {
-            panic!("Pixel coordinates out of bounds: ({}, {})", x, y);
+    // Outputs: packed 12-bit RGB value stored in 16 bits (little-endian), or
+    // None outside the width_pixels x height_pixels grid.
+    // Invariants: rows are width_pixels pixels of two bytes each, so pixel
+    // (x, y) lives at byte offset 2 * (x + y * width_pixels).
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<u16> {
+        if x >= self.width_pixels || y >= self.height_pixels {
+            return None;
         }
+        let offset = 2 * (x as usize + y as usize * self.width_pixels as usize);
+        Some(u16::from_le_bytes([
+            self.bytes[offset],
+            self.bytes[offset + 1],
+        ]))
     }
 }
 
@@ -3354,12 +4973,17 @@ impl TileMap {
         TileMap { tiles }
     }
 
+    // Bytes past the last tile read as 0 and ignore writes.
     pub fn get_tile_byte(&self, addr: u32) -> u8 {
-        return self.tiles[(addr / TILE_SIZE) as usize].pixels[(addr % TILE_SIZE) as usize];
+        self.tiles
+            .get((addr / TILE_SIZE) as usize)
+            .map_or(0, |tile| tile.pixels[(addr % TILE_SIZE) as usize])
     }
 
     pub fn set_tile_byte(&mut self, addr: u32, data: u8) {
-        self.tiles[(addr / TILE_SIZE) as usize].pixels[(addr % TILE_SIZE) as usize] = data;
+        if let Some(tile) = self.tiles.get_mut((addr / TILE_SIZE) as usize) {
+            tile.pixels[(addr % TILE_SIZE) as usize] = data;
+        }
     }
 }
 
@@ -3368,53 +4992,70 @@ impl Sprite {
         Sprite {
             x: (0, 0),
             y: (0, 0),
+            priority: 0,
             pixels: vec![0xFF; SPRITE_SIZE as usize],
         }
     }
 }
 
 impl SpriteMap {
+    // `size` is the sprite map's MMIO size in bytes, one SPRITE_SIZE block per sprite.
     pub fn new(size: u32) -> SpriteMap {
-        let sprites = vec![Sprite::invisible(); size as usize];
+        let sprites = vec![Sprite::invisible(); (size / SPRITE_SIZE) as usize];
         SpriteMap { sprites }
     }
 
-    // this will get a single corrsponding pixel
+    // this will get a single corrsponding pixel; bytes past the last sprite
+    // read as 0 and ignore writes
     pub fn get_sprite_byte(&self, addr: u32) -> u8 {
-        return self.sprites[(addr / SPRITE_SIZE) as usize].pixels[(addr % SPRITE_SIZE) as usize];
+        self.sprites
+            .get((addr / SPRITE_SIZE) as usize)
+            .map_or(0, |sprite| sprite.pixels[(addr % SPRITE_SIZE) as usize])
     }
 
     pub fn set_sprite_byte(&mut self, addr: u32, data: u8) {
-        self.sprites[(addr / SPRITE_SIZE) as usize].pixels[(addr % SPRITE_SIZE) as usize] = data;
+        if let Some(sprite) = self.sprites.get_mut((addr / SPRITE_SIZE) as usize) {
+            sprite.pixels[(addr % SPRITE_SIZE) as usize] = data;
+        }
     }
 
     // returns the either y or x coordinate of the sprite corresponding to the addr/4, addr%4
     pub fn get_sprite_reg(&self, addr: u32) -> u8 {
         let addr = addr as usize;
-        let sprite = &self.sprites[addr / 4];
-        if addr % 4 == 0 {
-            return sprite.x.0;
-        } else if addr % 4 == 1 {
-            return sprite.x.1;
-        } else if addr % 4 == 2 {
-            return sprite.y.0;
-        } else {
-            return sprite.y.1;
+        let Some(sprite) = self.sprites.get(addr / 4) else {
+            return 0;
+        };
+        match addr % 4 {
+            0 => sprite.x.0,
+            1 => sprite.x.1,
+            2 => sprite.y.0,
+            _ => sprite.y.1,
         }
     }
 
     // sets the either y or x coordinate of the sprite corresponding to the addr/4, addr%4
     pub fn set_sprite_reg(&mut self, addr: u32, data: u8) {
         let addr = addr as usize;
-        let sprite = &mut self.sprites[addr / 4];
-        if addr % 4 == 0 {
-            sprite.x.0 = data;
-        } else if addr % 4 == 1 {
-            sprite.x.1 = data;
-        } else if addr % 4 == 2 {
-            sprite.y.0 = data;
-        } else {
-            sprite.y.1 = data;
+        let Some(sprite) = self.sprites.get_mut(addr / 4) else {
+            return;
+        };
+        match addr % 4 {
+            0 => sprite.x.0 = data,
+            1 => sprite.x.1 = data,
+            2 => sprite.y.0 = data,
+            _ => sprite.y.1 = data,
+        }
+    }
+
+    pub fn get_sprite_priority(&self, index: u32) -> u8 {
+        self.sprites
+            .get(index as usize)
+            .map_or(0, |sprite| sprite.priority)
+    }
+
+    pub fn set_sprite_priority(&mut self, index: u32, data: u8) {
+        if let Some(sprite) = self.sprites.get_mut(index as usize) {
+            sprite.priority = data;
         }
     }
 }
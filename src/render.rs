@@ -0,0 +1,662 @@
+use ::image::{ImageBuffer, ImageResult, Rgba};
+use std::{
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use crate::memory::*;
+
+// Composited VGA output: FRAME_WIDTH x FRAME_HEIGHT RGBA.
+pub type Frame = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+// Purpose: the shared display state the compositor reads.
+// Invariants: every field aliases the matching `Memory` getter, so guest
+// writes are visible to the next `render_frame` call.
+pub struct FrameSources {
+    pub pixel_frame_buffer: Arc<RwLock<PixelFrameBuffer>>,
+    pub tile_frame_buffer: Arc<RwLock<TileFrameBuffer>>,
+    pub bg_tile_frame_buffer: Arc<RwLock<TileFrameBuffer>>,
+    pub tile_map: Arc<RwLock<TileMap>>,
+    pub sprite_map: Arc<RwLock<SpriteMap>>,
+    pub tile_vscroll_register: Arc<RwLock<(u8, u8)>>,
+    pub tile_hscroll_register: Arc<RwLock<(u8, u8)>>,
+    pub bg_tile_vscroll_register: Arc<RwLock<(u8, u8)>>,
+    pub bg_tile_hscroll_register: Arc<RwLock<(u8, u8)>>,
+    pub pixel_vscroll_register: Arc<RwLock<(u8, u8)>>,
+    pub pixel_hscroll_register: Arc<RwLock<(u8, u8)>>,
+    pub tile_scale_register: Arc<RwLock<u8>>,
+    pub pixel_scale_register: Arc<RwLock<u8>>,
+    pub sprite_scale_registers: Arc<RwLock<Vec<u8>>>,
+    pub palette_mode_register: Arc<RwLock<u8>>,
+    pub palette: Arc<RwLock<[u16; PALETTE_ENTRIES]>>,
+}
+
+impl FrameSources {
+    pub fn from_memory(memory: &Memory) -> FrameSources {
+        FrameSources {
+            pixel_frame_buffer: memory.get_pixel_frame_buffer(),
+            tile_frame_buffer: memory.get_tile_frame_buffer(),
+            bg_tile_frame_buffer: memory.get_bg_tile_frame_buffer(),
+            tile_map: memory.get_tile_map(),
+            sprite_map: memory.get_sprite_map(),
+            tile_vscroll_register: memory.get_tile_vscroll_register(),
+            tile_hscroll_register: memory.get_tile_hscroll_register(),
+            bg_tile_vscroll_register: memory.get_bg_tile_vscroll_register(),
+            bg_tile_hscroll_register: memory.get_bg_tile_hscroll_register(),
+            pixel_vscroll_register: memory.get_pixel_vscroll_register(),
+            pixel_hscroll_register: memory.get_pixel_hscroll_register(),
+            tile_scale_register: memory.get_tile_scale_register(),
+            pixel_scale_register: memory.get_pixel_scale_register(),
+            sprite_scale_registers: memory.get_sprite_scale_registers(),
+            palette_mode_register: memory.get_palette_mode_register(),
+            palette: memory.get_palette(),
+        }
+    }
+}
+
+// Purpose: expand an 8-bit sprite/tile color into 4-bit RGB channels.
+// Inputs: 8-bit color in RGB332 format.
+// Outputs: (r4, g4, b4) in 0..=15.
+fn expand_rgb332(color: u8) -> (u8, u8, u8) {
+    let r3 = (color >> 5) & 0x7;
+    let g3 = (color >> 2) & 0x7;
+    let b2 = color & 0x3;
+    let r4 = (r3 << 1) | (r3 >> 2);
+    let g4 = (g3 << 1) | (g3 >> 2);
+    let b4 = (b2 << 2) | b2;
+    (r4, g4, b4)
+}
+
+// Purpose: expand a direct 12-bit color into an opaque RGBA pixel.
+// Inputs: bits 0-3 red, 4-7 green, 8-11 blue (the pixel/palette layout).
+fn expand_rgb444(color: u16) -> Rgba<u8> {
+    let red = (color & 0x0F) as u8 * 16;
+    let green = ((color & 0xF0) >> 4) as u8 * 16;
+    let blue = ((color & 0xF00) >> 8) as u8 * 16;
+    Rgba([red, green, blue, 255])
+}
+
+// Purpose: look up an indexed color.
+// Inputs: palette and a value whose low nibble is the entry index.
+fn palette_color(palette: &[u16; PALETTE_ENTRIES], value: u8) -> Rgba<u8> {
+    expand_rgb444(palette[value as usize % PALETTE_ENTRIES])
+}
+
+// Purpose: decode a signed 16-bit scroll offset from two MMIO bytes.
+// Inputs: (low, high) bytes in little-endian order.
+// Outputs: signed pixel offset.
+fn decode_scroll_offset(pair: (u8, u8)) -> i32 {
+    i32::from(i16::from_le_bytes([pair.0, pair.1]))
+}
+
+// Purpose: one consistent view of the display state for a whole frame.
+// Invariants: built while every display lock is held, so a guest write
+// lands either entirely before or entirely after the frame.
+struct FrameState<'a> {
+    pixel_fb: &'a PixelFrameBuffer,
+    tile_fb: &'a TileFrameBuffer,
+    bg_tile_fb: &'a TileFrameBuffer,
+    tile_map: &'a TileMap,
+    sprite_map: &'a SpriteMap,
+    sprite_scales: &'a [u8],
+    palette: &'a [u16; PALETTE_ENTRIES],
+    palette_mode: u8,
+    tile_scroll: (i32, i32),
+    bg_tile_scroll: (i32, i32),
+    pixel_scroll: (i32, i32),
+    tile_scale: u32,
+    pixel_scale: u32,
+}
+
+// Purpose: composite one frame: pixel layer, background tiles, tiles, then
+// sprites.
+// Inputs: shared display state and a FRAME_WIDTH x FRAME_HEIGHT buffer.
+// Outputs: overwrites every covered pixel of `buffer`; no registers change
+// (status, frame counter and vblank are the display's job).
+pub fn render_frame(sources: &FrameSources, buffer: &mut Frame) {
+    let pixel_fb = sources.pixel_frame_buffer.read().unwrap();
+    composite(sources, &pixel_fb, buffer);
+}
+
+// Purpose: composite one frame and mark the pixel framebuffer presented.
+// Outputs: like `render_frame`, but the pixel framebuffer stays locked until
+// after `present`, so `--fb-visibility displayed` reads return exactly the
+// pixels that were composited.
+pub fn render_and_present_frame(sources: &FrameSources, buffer: &mut Frame) {
+    let mut pixel_fb = sources.pixel_frame_buffer.write().unwrap();
+    composite(sources, &pixel_fb, buffer);
+    pixel_fb.present();
+}
+
+fn composite(sources: &FrameSources, pixel_fb: &PixelFrameBuffer, buffer: &mut Frame) {
+    // Hold every lock for the whole frame; each is taken exactly once.
+    let tile_fb = sources.tile_frame_buffer.read().unwrap();
+    let bg_tile_fb = sources.bg_tile_frame_buffer.read().unwrap();
+    let tile_map = sources.tile_map.read().unwrap();
+    let sprite_map = sources.sprite_map.read().unwrap();
+    let sprite_scales = sources.sprite_scale_registers.read().unwrap();
+    let palette = sources.palette.read().unwrap();
+    let palette_mode = sources.palette_mode_register.read().unwrap();
+    let tile_hscroll = sources.tile_hscroll_register.read().unwrap();
+    let tile_vscroll = sources.tile_vscroll_register.read().unwrap();
+    let bg_tile_hscroll = sources.bg_tile_hscroll_register.read().unwrap();
+    let bg_tile_vscroll = sources.bg_tile_vscroll_register.read().unwrap();
+    let pixel_hscroll = sources.pixel_hscroll_register.read().unwrap();
+    let pixel_vscroll = sources.pixel_vscroll_register.read().unwrap();
+    let tile_scale = sources.tile_scale_register.read().unwrap();
+    let pixel_scale = sources.pixel_scale_register.read().unwrap();
+    let state = FrameState {
+        pixel_fb,
+        tile_fb: &tile_fb,
+        bg_tile_fb: &bg_tile_fb,
+        tile_map: &tile_map,
+        sprite_map: &sprite_map,
+        sprite_scales: &sprite_scales,
+        palette: &palette,
+        palette_mode: *palette_mode,
+        tile_scroll: (
+            decode_scroll_offset(*tile_hscroll),
+            decode_scroll_offset(*tile_vscroll),
+        ),
+        bg_tile_scroll: (
+            decode_scroll_offset(*bg_tile_hscroll),
+            decode_scroll_offset(*bg_tile_vscroll),
+        ),
+        pixel_scroll: (
+            decode_scroll_offset(*pixel_hscroll),
+            decode_scroll_offset(*pixel_vscroll),
+        ),
+        tile_scale: u32::from(*tile_scale),
+        pixel_scale: u32::from(*pixel_scale),
+    };
+
+    pixel_layer_update(&state, buffer);
+    draw_tile_layer(&state, state.bg_tile_fb, state.bg_tile_scroll, true, buffer);
+    draw_tile_layer(&state, state.tile_fb, state.tile_scroll, false, buffer);
+    sprite_layer_update(&state, buffer);
+}
+
+// Purpose: render the current display state into a fresh frame and save it.
+// Inputs: memory holding the display state and the destination path (the
+// image format follows the extension).
+// Outputs: a FRAME_WIDTH x FRAME_HEIGHT image, without the window's upscale.
+pub fn save_frame(memory: &Memory, path: &Path) -> ImageResult<()> {
+    let mut buffer = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+    render_frame(&FrameSources::from_memory(memory), &mut buffer);
+    buffer.save(path)
+}
+
+// Purpose: draw one tile layer over what is already in `buffer`.
+// Inputs: the layer's framebuffer and scroll offsets; `skip_empty` makes
+// entries pointing at tile 0 draw nothing (the background layer's convention).
+fn draw_tile_layer(
+    state: &FrameState,
+    fb: &TileFrameBuffer,
+    scroll: (i32, i32),
+    skip_empty: bool,
+    buffer: &mut Frame,
+) {
+    let tile_map = state.tile_map;
+    let scale = 1 << state.tile_scale;
+    let indexed = state.palette_mode & PALETTE_MODE_TILE != 0;
+    let palette = state.palette;
+    // Entries pointing past the end of the tile map draw as black.
+    let missing_tile = Tile::black();
+    for x in 0..fb.width_tiles {
+        for y in 0..fb.height_tiles {
+            let (tile_ptr, tile_color) = fb.get_tile_entry(x, y);
+            if skip_empty && tile_ptr == 0 {
+                continue;
+            }
+            let tile = tile_map
+                .tiles
+                .get(tile_ptr as usize)
+                .unwrap_or(&missing_tile);
+            for px in 0..TILE_WIDTH {
+                for py in 0..TILE_WIDTH {
+                    let addr = (2 * (px + py * TILE_WIDTH)) as usize;
+                    let tile_pixel_low = tile.pixels[addr];
+                    let tile_pixel_high = tile.pixels[addr + 1];
+                    // 0xFXXX pixels are transparent in the tile layer.
+                    let transparent = (tile_pixel_high & 0xf0) == 0xf0;
+                    if transparent {
+                        continue;
+                    }
+                    let use_tile_color = (tile_pixel_high & 0xf0) == 0xc0;
+                    let pixel = match (indexed, use_tile_color) {
+                        (true, true) => palette_color(palette, tile_color),
+                        (true, false) => palette_color(palette, tile_pixel_low),
+                        (false, true) => {
+                            let (r4, g4, b4) = expand_rgb332(tile_color);
+                            Rgba([r4 * 16, g4 * 16, b4 * 16, 255])
+                        }
+                        (false, false) => {
+                            expand_rgb444(u16::from_le_bytes([tile_pixel_low, tile_pixel_high]))
+                        }
+                    };
+
+                    // positions in the logical screen
+                    let (scroll_x, scroll_y) = scroll;
+                    let raw_x: i32 = (x * TILE_WIDTH) as i32 + px as i32 + scroll_x;
+                    let raw_y: i32 = (y * TILE_WIDTH) as i32 + py as i32 + scroll_y;
+                    // Scroll registers are signed; use Euclidean modulo so large negative
+                    // offsets continue wrapping correctly after many screens of scroll.
+                    let final_x: u32 = raw_x.rem_euclid(FRAME_WIDTH as i32) as u32;
+                    let final_y: u32 = raw_y.rem_euclid(FRAME_HEIGHT as i32) as u32;
+
+                    // print the pixel rgba in the physical screen
+                    for i in 0..scale {
+                        for j in 0..scale {
+                            let screen_x: u32 = final_x * scale + i;
+                            let screen_y: u32 = final_y * scale + j;
+
+                            if screen_x < FRAME_WIDTH && screen_y < FRAME_HEIGHT {
+                                buffer.put_pixel(screen_x, screen_y, pixel);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn pixel_layer_update(state: &FrameState, buffer: &mut Frame) {
+    // draw the pixel layer as the background
+    let fb = state.pixel_fb;
+    // Pixel layer uses an exponent with an implicit +1 so that:
+    // n=0 -> 2x, n=1 -> 4x, matching 320x240 -> 640x480 at n=0.
+    let scale = 1 << (state.pixel_scale + 1);
+    let indexed = state.palette_mode & PALETTE_MODE_PIXEL != 0;
+    let palette = state.palette;
+    for x in 0..fb.width_pixels {
+        for y in 0..fb.height_pixels {
+            let Some(pixel) = fb.get_pixel(x, y) else {
+                continue;
+            };
+            let pixel = if indexed {
+                palette_color(palette, pixel as u8)
+            } else {
+                expand_rgb444(pixel)
+            };
+
+            // positions in the logical screen
+            let (scroll_x, scroll_y) = state.pixel_scroll;
+            let raw_x: i32 = x as i32 + scroll_x;
+            let raw_y: i32 = y as i32 + scroll_y;
+            // Scroll registers are signed; use Euclidean modulo so large negative
+            // offsets continue wrapping correctly after many screens of scroll.
+            let final_x: u32 = raw_x.rem_euclid(FRAME_WIDTH as i32) as u32;
+            let final_y: u32 = raw_y.rem_euclid(FRAME_HEIGHT as i32) as u32;
+
+            // print the pixel rgba in the physical screen
+            for i in 0..scale {
+                for j in 0..scale {
+                    let screen_x: u32 = final_x * scale + i;
+                    let screen_y: u32 = final_y * scale + j;
+
+                    if screen_x < FRAME_WIDTH && screen_y < FRAME_HEIGHT {
+                        buffer.put_pixel(screen_x, screen_y, pixel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn sprite_layer_update(state: &FrameState, buffer: &mut Frame) {
+    // draw the sprites of the sprite map, lowest priority first so higher
+    // priorities land on top; the stable sort keeps map order within a level
+    let sprite_map = state.sprite_map;
+    let sprite_scales = state.sprite_scales;
+    let mut draw_order: Vec<usize> = (0..sprite_map.sprites.len()).collect();
+    draw_order.sort_by_key(|&index| sprite_map.sprites[index].priority);
+    for sprite_index in draw_order {
+        let sprite = &sprite_map.sprites[sprite_index];
+        let scale = 1 << (sprite_scales.get(sprite_index).copied().unwrap_or(0) as u32);
+        // Sprite coordinates are signed 16-bit little-endian MMIO values.
+        let sprite_x = i32::from(i16::from_le_bytes([sprite.x.0, sprite.x.1]));
+        let sprite_y = i32::from(i16::from_le_bytes([sprite.y.0, sprite.y.1]));
+        for px in 0..SPRITE_WIDTH {
+            for py in 0..SPRITE_WIDTH {
+                let addr = (2 * (px + py * SPRITE_WIDTH)) as usize;
+                let tile_pixel_low = sprite.pixels[addr];
+                let tile_pixel_high = sprite.pixels[addr + 1];
+                let red = (tile_pixel_low & 0x0f) * 16;
+                let green = ((tile_pixel_low & 0xf0) >> 4) * 16;
+                let blue = (tile_pixel_high & 0x0f) * 16;
+                let transparent = (tile_pixel_high & 0xf0) == 0xf0;
+                if transparent {
+                    continue;
+                }
+
+                let pixel = Rgba([red, green, blue, 255]);
+                // Reconstruct the full coordinate before adding the per-pixel offset so carry
+                // from the low byte is preserved (the previous bytewise OR math dropped carry).
+                let raw_x = sprite_x + px as i32;
+                let raw_y = sprite_y + py as i32;
+                // Wrap like the scrolled tile layer: pixels past an edge of the
+                // logical screen reappear at the opposite edge.
+                let final_x: u32 = raw_x.rem_euclid(FRAME_WIDTH as i32) as u32;
+                let final_y: u32 = raw_y.rem_euclid(FRAME_HEIGHT as i32) as u32;
+
+                // print the pixel rgba in the physical screen
+                for i in 0..scale {
+                    for j in 0..scale {
+                        let screen_x: u32 = final_x * scale + i;
+                        let screen_y: u32 = final_y * scale + j;
+
+                        if screen_x < FRAME_WIDTH && screen_y < FRAME_HEIGHT {
+                            buffer.put_pixel(screen_x, screen_y, pixel);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::emulator::{AudioMode, DEFAULT_TLB_ENTRIES, Emulator};
+
+    // Copies a red pixel into tile 1 of the tile map, points tile entry (1, 0)
+    // at it, then halts:
+    //   lui r2, 0x7FE8000 >> 10    ; tile map
+    //   add r3, r0, 0xF            ; red, opaque
+    //   sh  r3, [r2, 0x80]         ; tile 1, pixel (0, 0)
+    //   lui r4, 0x7FBD000 >> 10    ; tile framebuffer
+    //   add r5, r0, 1
+    //   sb  r5, [r4, 2]            ; entry (1, 0) -> tile 1
+    //   mode halt
+    const TILE_PROGRAM: [u32; 7] = [
+        0x1081FFA0, 0x08C0E00F, 0x30C40080, 0x1101FEF4, 0x0940E001, 0x49480002, 0xF8002800,
+    ];
+
+    #[test]
+    fn headless_frame_shows_a_tile_drawn_by_the_program() {
+        let mut ram = HashMap::new();
+        for (i, word) in TILE_PROGRAM.iter().enumerate() {
+            for (j, byte) in word.to_le_bytes().into_iter().enumerate() {
+                ram.insert(0x400 + 4 * i as u32 + j as u32, byte);
+            }
+        }
+        let cpu = Emulator::from_instructions(ram, false, 1, None, None, DEFAULT_TLB_ENTRIES);
+        let memory = cpu.shared_memory();
+        let report = cpu.run_report(100, false, AudioMode::Disabled);
+        assert!(report.result().is_some(), "program did not halt");
+
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+        render_frame(&FrameSources::from_memory(&memory), &mut frame);
+
+        let red = Rgba([240, 0, 0, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+        assert_eq!(*frame.get_pixel(TILE_WIDTH, 0), red);
+        assert_eq!(*frame.get_pixel(TILE_WIDTH + 1, 0), black);
+        assert_eq!(*frame.get_pixel(TILE_WIDTH, 1), black);
+        assert_eq!(*frame.get_pixel(0, 0), black);
+
+        let mut path = std::env::temp_dir();
+        path.push(format!("dioptase_render_{}.png", std::process::id()));
+        save_frame(&memory, &path).unwrap();
+        let saved = ::image::open(&path).unwrap().to_rgba8();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(saved, frame);
+    }
+
+    #[test]
+    fn indexed_layers_look_up_the_palette() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        {
+            let palette = memory.get_palette();
+            let mut palette = palette.write().unwrap();
+            palette[2] = 0x0F00;
+            palette[5] = 0x00F0;
+            palette[9] = 0x000F;
+        }
+        // Pixel (0, 0) holds index 2; the high byte is ignored when indexed.
+        memory
+            .get_pixel_frame_buffer()
+            .write()
+            .unwrap()
+            .set_byte(0, 0x32);
+        {
+            // Tile 0 is transparent so the pixel layer shows through. Tile 1:
+            // pixel (0, 0) is index 5, pixel (1, 0) takes the entry's color
+            // byte (index 9), pixel (2, 0) is transparent.
+            let tile_map = memory.get_tile_map();
+            let mut tile_map = tile_map.write().unwrap();
+            tile_map.tiles[0].pixels.fill(0xFF);
+            tile_map.tiles[1].pixels[..6].copy_from_slice(&[0x05, 0x00, 0x00, 0xC0, 0x00, 0xF0]);
+        }
+        {
+            let tile_fb = memory.get_tile_frame_buffer();
+            let mut tile_fb = tile_fb.write().unwrap();
+            tile_fb.set_byte(2, 1);
+            tile_fb.set_byte(3, 0x09);
+        }
+        let sources = FrameSources::from_memory(&memory);
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+
+        render_frame(&sources, &mut frame);
+        assert_eq!(*frame.get_pixel(0, 0), Rgba([32, 48, 0, 255]));
+        assert_eq!(*frame.get_pixel(TILE_WIDTH, 0), Rgba([80, 0, 0, 255]));
+
+        *memory.get_palette_mode_register().write().unwrap() =
+            PALETTE_MODE_PIXEL | PALETTE_MODE_TILE;
+        render_frame(&sources, &mut frame);
+        assert_eq!(*frame.get_pixel(0, 0), Rgba([0, 0, 240, 255]));
+        assert_eq!(*frame.get_pixel(1, 1), Rgba([0, 0, 240, 255]));
+        assert_eq!(*frame.get_pixel(TILE_WIDTH, 0), Rgba([0, 240, 0, 255]));
+        assert_eq!(*frame.get_pixel(TILE_WIDTH + 1, 0), Rgba([240, 0, 0, 255]));
+        // Transparent tile pixels still show the pixel layer (index 0 = black).
+        assert_eq!(*frame.get_pixel(TILE_WIDTH + 2, 0), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn tile_entries_past_the_tile_map_render_black() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        let mut sources = FrameSources::from_memory(&memory);
+        // A map with only two tiles, so most 8-bit tile pointers are out of range.
+        let mut tile_map = TileMap::new(2 * TILE_WIDTH * TILE_WIDTH * 2);
+        tile_map.set_tile_byte(2 * TILE_WIDTH * TILE_WIDTH, 0x0F);
+        sources.tile_map = Arc::new(RwLock::new(tile_map));
+        {
+            let tile_fb = memory.get_tile_frame_buffer();
+            let mut tile_fb = tile_fb.write().unwrap();
+            tile_fb.set_byte(0, 200);
+            tile_fb.set_byte(2, 1);
+        }
+        memory
+            .get_pixel_frame_buffer()
+            .write()
+            .unwrap()
+            .set_byte(0, 0xFF);
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+
+        render_frame(&sources, &mut frame);
+        assert_eq!(*frame.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*frame.get_pixel(TILE_WIDTH, 0), Rgba([240, 0, 0, 255]));
+    }
+
+    #[test]
+    fn background_tile_layer_scrolls_under_the_primary_layer() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        {
+            // Tile 0 is transparent, tile 1 is solid red and tile 2 is
+            // transparent apart from a green pixel at (0, 0).
+            let tile_map = memory.get_tile_map();
+            let mut tile_map = tile_map.write().unwrap();
+            tile_map.tiles[0].pixels.fill(0xFF);
+            tile_map.tiles[1]
+                .pixels
+                .chunks_mut(2)
+                .for_each(|pixel| pixel.copy_from_slice(&[0x0F, 0x00]));
+            tile_map.tiles[2].pixels.fill(0xFF);
+            tile_map.tiles[2].pixels[..2].copy_from_slice(&[0xF0, 0x00]);
+        }
+        let sources = FrameSources::from_memory(&memory);
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+        let red = Rgba([240, 0, 0, 255]);
+        let green = Rgba([0, 240, 0, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+
+        // The background layer starts out blank.
+        memory
+            .get_bg_tile_frame_buffer()
+            .write()
+            .unwrap()
+            .set_byte(1, 0x55);
+        render_frame(&sources, &mut frame);
+        assert!(frame.pixels().all(|pixel| *pixel == black));
+
+        // Background entry (0, 0) -> tile 1, scrolled 16 right; primary entry
+        // (2, 0) -> tile 2, scrolled 4 right.
+        memory
+            .get_bg_tile_frame_buffer()
+            .write()
+            .unwrap()
+            .set_byte(0, 1);
+        *memory.get_bg_tile_hscroll_register().write().unwrap() = (16, 0);
+        memory
+            .get_tile_frame_buffer()
+            .write()
+            .unwrap()
+            .set_byte(4, 2);
+        *memory.get_tile_hscroll_register().write().unwrap() = (4, 0);
+        render_frame(&sources, &mut frame);
+
+        assert_eq!(*frame.get_pixel(0, 0), black);
+        assert_eq!(*frame.get_pixel(15, 0), black);
+        assert_eq!(*frame.get_pixel(16, 0), red);
+        assert_eq!(*frame.get_pixel(16, 7), red);
+        // The primary layer draws over the background where it is opaque.
+        assert_eq!(*frame.get_pixel(20, 0), green);
+        assert_eq!(*frame.get_pixel(21, 0), red);
+        assert_eq!(*frame.get_pixel(23, 0), red);
+        assert_eq!(*frame.get_pixel(24, 0), black);
+    }
+
+    #[test]
+    fn presented_frame_matches_displayed_framebuffer_reads() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        memory.set_framebuffer_visibility(FramebufferVisibility::Displayed);
+        memory.get_tile_map().write().unwrap().tiles[0]
+            .pixels
+            .fill(0xFF);
+        memory
+            .get_pixel_frame_buffer()
+            .write()
+            .unwrap()
+            .set_byte(0, 0x0F);
+        let sources = FrameSources::from_memory(&memory);
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+
+        render_frame(&sources, &mut frame);
+        assert_eq!(*frame.get_pixel(0, 0), Rgba([240, 0, 0, 255]));
+        assert_eq!(
+            memory.get_pixel_frame_buffer().read().unwrap().get_byte(0),
+            0
+        );
+
+        render_and_present_frame(&sources, &mut frame);
+        assert_eq!(*frame.get_pixel(0, 0), Rgba([240, 0, 0, 255]));
+        assert_eq!(
+            memory.get_pixel_frame_buffer().read().unwrap().get_byte(0),
+            0x0F
+        );
+    }
+
+    // Gives `sprite` an opaque top row of `color` (low byte first) at (x, y).
+    fn place_sprite_row(memory: &Memory, sprite: u32, x: i16, y: i16, color: [u8; 2]) {
+        let sprite_map = memory.get_sprite_map();
+        let mut sprite_map = sprite_map.write().unwrap();
+        let base = sprite * SPRITE_WIDTH * SPRITE_WIDTH * 2;
+        for px in 0..SPRITE_WIDTH {
+            sprite_map.set_sprite_byte(base + 2 * px, color[0]);
+            sprite_map.set_sprite_byte(base + 2 * px + 1, color[1]);
+        }
+        let [x0, x1] = x.to_le_bytes();
+        let [y0, y1] = y.to_le_bytes();
+        for (offset, byte) in [x0, x1, y0, y1].into_iter().enumerate() {
+            sprite_map.set_sprite_reg(4 * sprite + offset as u32, byte);
+        }
+    }
+
+    #[test]
+    fn sprites_cross_the_low_byte_boundary_and_wrap_at_screen_edges() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        place_sprite_row(&memory, 0, 250, 10, [0x0F, 0x00]);
+        place_sprite_row(&memory, 1, 630, 20, [0xF0, 0x00]);
+        place_sprite_row(&memory, 2, -4, 30, [0x00, 0x0F]);
+
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+        render_frame(&FrameSources::from_memory(&memory), &mut frame);
+
+        let red = Rgba([240, 0, 0, 255]);
+        let green = Rgba([0, 240, 0, 255]);
+        let blue = Rgba([0, 0, 240, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+        // x = 250..282 spans 255 -> 256 without dropping the carry.
+        assert_eq!(*frame.get_pixel(249, 10), black);
+        for x in [250, 255, 256, 281] {
+            assert_eq!(*frame.get_pixel(x, 10), red, "x = {}", x);
+        }
+        assert_eq!(*frame.get_pixel(282, 10), black);
+        assert_eq!(*frame.get_pixel(250, 11), black);
+        // x = 630..662 wraps past the right edge to 0..22.
+        assert_eq!(*frame.get_pixel(639, 20), green);
+        assert_eq!(*frame.get_pixel(21, 20), green);
+        assert_eq!(*frame.get_pixel(22, 20), black);
+        // x = -4..28 wraps its first four pixels to the right edge.
+        assert_eq!(*frame.get_pixel(636, 30), blue);
+        assert_eq!(*frame.get_pixel(635, 30), black);
+        assert_eq!(*frame.get_pixel(27, 30), blue);
+        assert_eq!(*frame.get_pixel(28, 30), black);
+    }
+
+    #[test]
+    fn higher_priority_sprites_are_drawn_on_top() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        // Sprite 0 (red) overlaps sprite 1 (green) but has the higher priority.
+        place_sprite_row(&memory, 0, 100, 40, [0x0F, 0x00]);
+        place_sprite_row(&memory, 1, 100, 40, [0xF0, 0x00]);
+        // Sprite 2 (blue) is drawn at 2x and overlaps both at the lowest priority.
+        place_sprite_row(&memory, 2, 40, 20, [0x00, 0x0F]);
+        {
+            let sprite_map = memory.get_sprite_map();
+            let mut sprite_map = sprite_map.write().unwrap();
+            // A transparent pixel in sprite 0 shows the sprite below it.
+            sprite_map.set_sprite_byte(1, 0xF0);
+        }
+        memory.write(SPRITE_PRIORITY_START, 2);
+        memory.write(SPRITE_PRIORITY_START + 1, 1);
+        memory.get_sprite_scale_registers().write().unwrap()[2] = 1;
+        assert_eq!(
+            memory.get_sprite_map().read().unwrap().sprites[0].priority,
+            2
+        );
+
+        let mut frame = Frame::new(FRAME_WIDTH, FRAME_HEIGHT);
+        render_frame(&FrameSources::from_memory(&memory), &mut frame);
+
+        let red = Rgba([240, 0, 0, 255]);
+        let green = Rgba([0, 240, 0, 255]);
+        let blue = Rgba([0, 0, 240, 255]);
+        // Sprite 2 covers x = 80..144 at y = 40 and 41 but sits under the others.
+        assert_eq!(*frame.get_pixel(100, 40), green);
+        assert_eq!(*frame.get_pixel(101, 40), red);
+        assert_eq!(*frame.get_pixel(131, 40), red);
+        assert_eq!(*frame.get_pixel(132, 40), blue);
+        assert_eq!(*frame.get_pixel(100, 41), blue);
+
+        // With equal priorities the higher sprite index is on top again.
+        memory.write(SPRITE_PRIORITY_START, 0);
+        memory.write(SPRITE_PRIORITY_START + 1, 0);
+        render_frame(&FrameSources::from_memory(&memory), &mut frame);
+        assert_eq!(*frame.get_pixel(101, 40), blue);
+    }
+}
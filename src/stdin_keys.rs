@@ -0,0 +1,251 @@
+// Headless keyboard (--stdin-keys): a thread reads the emulator's stdin and
+// queues each byte the way the VGA window queues key presses, so interactive
+// text programs can run without a window.
+//
+// With --uart each byte is queued as is for UART_RX. Otherwise each byte
+// becomes a press and a release in the guest keycode contract (see
+// `src/graphics.rs`), wrapped in a left Shift press/release for shifted
+// characters of a US layout.
+
+use std::{
+    io::{self, Read},
+    sync::{Arc, Mutex, atomic::Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::memory::{Memory, push_input_event};
+
+const KEY_ENTER: u8 = 0x0D;
+const KEY_LEFT_SHIFT: u8 = 0xE1;
+const KEY_RELEASE: u16 = 0x0100;
+
+// How long the reader waits for the guest to drain a full input queue.
+const FULL_QUEUE_POLL: Duration = Duration::from_millis(1);
+
+// How long the reader waits for stdin before checking whether the run has
+// finished; bounds how long joining the reader takes.
+const STDIN_POLL: Duration = Duration::from_millis(10);
+
+// Purpose: the unshifted key for a character typed with Shift.
+// Outputs: None for characters typed without Shift.
+fn shifted_base(byte: u8) -> Option<u8> {
+    let base = match byte {
+        b'A'..=b'Z' => byte.to_ascii_lowercase(),
+        b'!' => b'1',
+        b'@' => b'2',
+        b'#' => b'3',
+        b'$' => b'4',
+        b'%' => b'5',
+        b'^' => b'6',
+        b'&' => b'7',
+        b'*' => b'8',
+        b'(' => b'9',
+        b')' => b'0',
+        b'_' => b'-',
+        b'+' => b'=',
+        b'{' => b'[',
+        b'}' => b']',
+        b'|' => b'\\',
+        b':' => b';',
+        b'"' => b'\'',
+        b'<' => b',',
+        b'>' => b'.',
+        b'?' => b'/',
+        b'~' => b'`',
+        _ => return None,
+    };
+    Some(base)
+}
+
+// Purpose: translate one stdin byte into input queue entries.
+// Inputs: the byte and whether the guest reads UART_RX.
+// Outputs: the byte itself for UART_RX; otherwise the key events that type
+// it. Newlines press Enter, and bytes with no key (other control
+// characters, non-ASCII) give no entries.
+pub fn stdin_byte_entries(byte: u8, uart: bool) -> Vec<u16> {
+    if uart {
+        return vec![u16::from(byte)];
+    }
+    let (code, shift) = match byte {
+        b'\n' | b'\r' => (KEY_ENTER, false),
+        0x08 | 0x09 | 0x1B | 0x7F => (byte, false),
+        b' '..=b'~' => match shifted_base(byte) {
+            Some(base) => (base, true),
+            None => (byte, false),
+        },
+        _ => return Vec::new(),
+    };
+    let key = [u16::from(code), KEY_RELEASE | u16::from(code)];
+    if shift {
+        let shift = u16::from(KEY_LEFT_SHIFT);
+        vec![shift, key[0], key[1], KEY_RELEASE | shift]
+    } else {
+        key.to_vec()
+    }
+}
+
+// Purpose: start the stdin reader for a run.
+// Inputs: the run's memory and the flag the run loop sets when it exits.
+// Outputs: the reader thread; it stops at end of input or within STDIN_POLL
+// of the run finishing, and the run joins it before returning.
+pub fn spawn_stdin_keys(memory: Arc<Memory>, finished: Arc<Mutex<bool>>) -> thread::JoinHandle<()> {
+    thread::spawn(move || feed_keys(&memory, PolledStdin, &finished))
+}
+
+// Stdin read straight from the file descriptor, so no lock on `io::stdin()`
+// is held and no bytes sit in its buffer while the reader waits.
+struct PolledStdin;
+
+impl Read for PolledStdin {
+    // Outputs: Err(WouldBlock) when nothing arrived within STDIN_POLL.
+    #[cfg(unix)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `fd` is a single valid pollfd for the duration of the call.
+        let ready = unsafe { libc::poll(&mut fd, 1, STDIN_POLL.as_millis() as libc::c_int) };
+        if ready < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if ready == 0 {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+        let count = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(count as usize)
+    }
+
+    // Without poll the read blocks, so joining the reader waits for input or
+    // end of input.
+    #[cfg(not(unix))]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::stdin().read(buf)
+    }
+}
+
+// Purpose: queue every byte of `input` for the guest.
+// Inputs: memory owning the input queue, the byte source and the run's
+// finished flag. WouldBlock and Interrupted reads are retried.
+// Outputs: a key event that does not fit under the --key-buffer cap waits
+// for the guest to read, so piped input is never dropped. Nothing is queued
+// once `finished` is set.
+fn feed_keys(memory: &Memory, mut input: impl Read, finished: &Mutex<bool>) {
+    let uart = memory.uart_rx_enabled();
+    let io_buffer = memory.get_io_buffer();
+    let cap = memory.key_buffer_cap();
+    let input_pending = memory.get_input_pending();
+    let mut buf = [0u8; 256];
+    loop {
+        if *finished.lock().unwrap() {
+            return;
+        }
+        let count = match input.read(&mut buf) {
+            Ok(0) => return,
+            Ok(count) => count,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue;
+            }
+            Err(_) => return,
+        };
+        for &byte in &buf[..count] {
+            let entries = stdin_byte_entries(byte, uart);
+            if entries.is_empty() {
+                continue;
+            }
+            loop {
+                // Hold the flag while pushing so no entry lands after the
+                // run loop has marked the run finished.
+                let done = finished.lock().unwrap();
+                if *done {
+                    return;
+                }
+                if push_input_event(&mut io_buffer.write().unwrap(), &entries, cap) {
+                    input_pending.store(true, Ordering::SeqCst);
+                    break;
+                }
+                drop(done);
+                thread::sleep(FULL_QUEUE_POLL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn stdin_bytes_are_queued_as_key_events() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        let finished = Mutex::new(false);
+        feed_keys(&memory, &b"a!\n\x01"[..], &finished);
+
+        let queued: Vec<u16> = memory
+            .get_io_buffer()
+            .read()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(
+            queued,
+            [
+                0x61, 0x161, // a
+                0xE1, 0x31, 0x131, 0x1E1, // Shift + 1
+                0x0D, 0x10D, // Enter
+            ]
+        );
+        assert!(memory.get_input_pending().load(Ordering::SeqCst));
+        // The guest sees them through PS2_STREAM in order.
+        assert_eq!(memory.read_u16(0x7FE5800), 0x61);
+        assert_eq!(memory.read_u16(0x7FE5800), 0x161);
+
+        // With --uart the bytes are queued unchanged.
+        let memory = Memory::new(HashMap::new(), true, 1);
+        feed_keys(&memory, &b"A\n"[..], &finished);
+        assert_eq!(memory.read(0x7FE5803), b'A');
+        assert_eq!(memory.read(0x7FE5803), b'\n');
+        assert_eq!(memory.read(0x7FE5803), 0);
+    }
+
+    // Stdin with nothing typed yet.
+    struct IdleInput;
+
+    impl Read for IdleInput {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(FULL_QUEUE_POLL);
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    #[test]
+    fn reader_stops_once_the_run_finishes() {
+        let memory = Memory::new(HashMap::new(), false, 1);
+        let finished = Arc::new(Mutex::new(false));
+        let reader = thread::spawn({
+            let finished = Arc::clone(&finished);
+            move || feed_keys(&Memory::new(HashMap::new(), false, 1), IdleInput, &finished)
+        });
+        *finished.lock().unwrap() = true;
+        reader.join().unwrap();
+
+        // Bytes still unread when the run finished are not queued.
+        feed_keys(&memory, &b"a"[..], &finished);
+        assert!(memory.get_io_buffer().read().unwrap().is_empty());
+        assert!(!memory.get_input_pending().load(Ordering::SeqCst));
+    }
+}
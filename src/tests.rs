@@ -1,3 +1,6 @@
+#[cfg(test)]
+use std::env;
+
 #[cfg(test)]
 use std::fs;
 
@@ -14,7 +17,7 @@ use std::sync::Once;
 use super::*;
 
 #[cfg(test)]
-use crate::emulator::{AudioMode, ScheduleMode};
+use crate::emulator::{AudioMode, DEFAULT_TLB_ENTRIES, EmulatorBuilder, ScheduleMode, load_hex};
 
 #[cfg(test)]
 fn assembler_profile() -> &'static str {
@@ -32,7 +35,7 @@ fn build_assembler() {
     BUILD.call_once(|| {
         let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
         let asm_dir = manifest.join("../../Dioptase-Assembler");
-        // Build the assembler once so tests can run in clean environments.
+        // Only fixture regeneration gets here; build the assembler once.
         let status = Command::new("make")
             .arg(assembler_profile())
             .current_dir(asm_dir)
@@ -60,32 +63,66 @@ fn assembler_path() -> PathBuf {
 }
 
 #[cfg(test)]
-fn ensure_hex_dir() {
-    let hex_dir = Path::new("tests/hex");
-    fs::create_dir_all(hex_dir).expect("failed to create tests/hex dir");
-}
-
-#[cfg(test)]
-fn run_test(asm_file: &'static str, expected: u32) {
-    ensure_hex_dir();
-
-    // Build hex file path by replacing asm path prefix/suffix
-    let hex_file = {
-        let asm_path = Path::new(asm_file);
-        let stem = asm_path.file_stem().unwrap(); // e.g., "add"
-        PathBuf::from("tests/hex").join(format!("{}.hex", stem.to_string_lossy()))
-    };
-
-    // assemble test case
+fn assemble_to(asm_file: &Path, hex_file: &Path) {
     let assembler = assembler_path();
     let status = Command::new(&assembler)
-        .args([asm_file, "-o", hex_file.to_str().unwrap(), "-kernel"])
+        .arg(asm_file)
+        .args(["-o", hex_file.to_str().unwrap(), "-kernel"])
         .status()
         .expect("failed to run assembler");
     assert!(status.success(), "assembler failed");
+}
+
+// Tests run from committed hex fixtures and never need the external assembler.
+// Set this variable to rewrite each fixture from its asm source before it is
+// loaded.
+#[cfg(test)]
+const REGEN_FIXTURES_ENV: &str = "DIOPTASE_REGEN_FIXTURES";
+
+#[cfg(test)]
+const FIXTURE_DIR: &str = "tests/fixtures";
+
+#[cfg(test)]
+fn fixture_path(asm_file: &Path) -> PathBuf {
+    let stem = asm_file.file_stem().unwrap().to_string_lossy();
+    Path::new(FIXTURE_DIR).join(format!("{}.hex", stem))
+}
+
+// Purpose: pick the hex image a test program runs from.
+// Inputs: asm source path.
+// Outputs: tests/fixtures/<stem>.hex, reassembled first if
+// DIOPTASE_REGEN_FIXTURES is set, or an error naming the missing fixture.
+#[cfg(test)]
+fn program_hex(asm_file: &Path) -> Result<PathBuf, String> {
+    let fixture = fixture_path(asm_file);
+    if env::var_os(REGEN_FIXTURES_ENV).is_some() {
+        assemble_to(asm_file, &fixture);
+    }
+    if fixture.exists() {
+        Ok(fixture)
+    } else {
+        Err(format!(
+            "{}: no fixture {}; assemble it with {}=1",
+            asm_file.display(),
+            fixture.display(),
+            REGEN_FIXTURES_ENV
+        ))
+    }
+}
+
+#[cfg(test)]
+fn run_test(asm_file: &'static str, expected: u32) {
+    let hex_file = program_hex(Path::new(asm_file)).unwrap_or_else(|err| panic!("{}", err));
 
-    // execute hex file
-    let cpu = Emulator::new(hex_file.to_string_lossy().to_string(), false, 1, None, None);
+    // execute the image in-process
+    let cpu = Emulator::from_instructions(
+        load_hex(&hex_file.to_string_lossy()),
+        false,
+        1,
+        None,
+        None,
+        DEFAULT_TLB_ENTRIES,
+    );
     let result = cpu.run(10000, false, AudioMode::Disabled);
 
     // check result
@@ -94,20 +131,7 @@ fn run_test(asm_file: &'static str, expected: u32) {
 
 #[cfg(test)]
 fn run_multicore_test(asm_file: &'static str, expected: u32, cores: usize) {
-    ensure_hex_dir();
-
-    let hex_file = {
-        let asm_path = Path::new(asm_file);
-        let stem = asm_path.file_stem().unwrap();
-        PathBuf::from("tests/hex").join(format!("{}.hex", stem.to_string_lossy()))
-    };
-
-    let assembler = assembler_path();
-    let status = Command::new(&assembler)
-        .args([asm_file, "-o", hex_file.to_str().unwrap(), "-kernel"])
-        .status()
-        .expect("failed to run assembler");
-    assert!(status.success(), "assembler failed");
+    let hex_file = program_hex(Path::new(asm_file)).unwrap_or_else(|err| panic!("{}", err));
 
     let result = Emulator::run_multicore(
         hex_file.to_string_lossy().to_string(),
@@ -120,10 +144,118 @@ fn run_multicore_test(asm_file: &'static str, expected: u32, cores: usize) {
         1,
         None,
         None,
+        DEFAULT_TLB_ENTRIES,
     );
     assert_eq!(result, Some(expected));
 }
 
+// Corpus runner: every `.s` file in tests/asm that declares an expected r1
+// value is run from its fixture without needing its own #[test] function.
+// The expectation comes from a companion `<stem>.expected` file or from a
+// `# expect: <value>` comment in the source. A program without a committed
+// fixture in tests/fixtures/<stem>.hex fails the run.
+#[cfg(test)]
+const CORPUS_DIR: &str = "tests/asm";
+
+#[cfg(test)]
+fn parse_expected_value(text: &str) -> Option<u32> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(neg) = text.strip_prefix('-') {
+        neg.parse::<u32>().ok().map(u32::wrapping_neg)
+    } else {
+        text.parse::<u32>().ok()
+    }
+}
+
+#[cfg(test)]
+fn corpus_expectation(asm_file: &Path) -> Option<Result<u32, String>> {
+    let expected_file = asm_file.with_extension("expected");
+    if let Ok(text) = fs::read_to_string(&expected_file) {
+        return Some(
+            parse_expected_value(&text)
+                .ok_or_else(|| format!("bad value in {}", expected_file.display())),
+        );
+    }
+
+    let source = fs::read_to_string(asm_file).ok()?;
+    source.lines().find_map(|line| {
+        let (_, comment) = line.split_once('#')?;
+        let value = comment.trim().strip_prefix("expect:")?;
+        Some(
+            parse_expected_value(value)
+                .ok_or_else(|| format!("bad expect comment in {}", asm_file.display())),
+        )
+    })
+}
+
+#[cfg(test)]
+fn corpus_programs() -> Vec<(PathBuf, Result<u32, String>)> {
+    let mut programs: Vec<_> = fs::read_dir(CORPUS_DIR)
+        .expect("failed to read test corpus dir")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "s"))
+        .filter_map(|path| corpus_expectation(&path).map(|expected| (path, expected)))
+        .collect();
+    programs.sort_by(|a, b| a.0.cmp(&b.0));
+    programs
+}
+
+#[cfg(test)]
+#[test]
+fn asm_corpus() {
+    let programs = corpus_programs();
+    assert!(
+        !programs.is_empty(),
+        "no programs with expectations in {}",
+        CORPUS_DIR
+    );
+
+    let mut failures = Vec::new();
+    for (asm_file, expected) in programs {
+        let expected = match expected {
+            Ok(value) => value,
+            Err(err) => {
+                failures.push(err);
+                continue;
+            }
+        };
+
+        let hex_file = match program_hex(&asm_file) {
+            Ok(hex_file) => hex_file,
+            Err(err) => {
+                failures.push(err);
+                continue;
+            }
+        };
+
+        let cpu = Emulator::from_instructions(
+            load_hex(&hex_file.to_string_lossy()),
+            false,
+            1,
+            None,
+            None,
+            DEFAULT_TLB_ENTRIES,
+        );
+        let result = cpu.run(10000, false, AudioMode::Disabled);
+        if result != Some(expected) {
+            failures.push(format!(
+                "{}: expected {:08x}, got {:?}",
+                asm_file.display(),
+                expected,
+                result.map(|value| format!("{:08x}", value))
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "corpus failures:\n{}",
+        failures.join("\n")
+    );
+}
+
 // I/O tests that must be run manually (12):
 // cdiv.s (run with --vga)
 // colors.s (--vga)
@@ -568,3 +700,78 @@ fn atomic_swap() {
 fn carry() {
     run_test("tests/asm/carry.s", 42);
 }
+
+// Purpose: execute one register-form ALU op, r1 <- r2 op r3.
+// Inputs: ALU op number, r2, the shift amount in r3 and the incoming carry.
+// Outputs: r1 and the carry flag afterwards.
+#[cfg(test)]
+fn shift_with_carry(op: u32, value: u32, amount: u32, carry_in: u32) -> (u32, bool) {
+    let instr = (1u32 << 22) | (2 << 17) | (op << 5) | 3;
+    let mut cpu = EmulatorBuilder::new()
+        .load_bytes(0x400, &instr.to_le_bytes())
+        .entry(0x400)
+        .reg(2, value)
+        .reg(3, amount)
+        .creg(5, carry_in)
+        .build();
+    cpu.step();
+    let state = cpu.state_dump();
+    (state.regs[1], state.flags.carry)
+}
+
+#[test]
+fn shifts_by_0_31_and_32() {
+    // lsl: a zero shift reports any set bit as carry.
+    assert_eq!(shift_with_carry(7, 0x8000_0001, 0, 0), (0x8000_0001, true));
+    assert_eq!(shift_with_carry(7, 0x0000_0003, 31, 0), (0x8000_0000, true));
+    assert_eq!(shift_with_carry(7, 0x0000_0001, 32, 0), (0, true));
+    assert_eq!(shift_with_carry(7, 0x0000_0001, 40, 0), (0, true));
+    assert_eq!(shift_with_carry(7, 0, 32, 0), (0, false));
+    // lsr
+    assert_eq!(shift_with_carry(8, 0x8000_0001, 0, 0), (0x8000_0001, false));
+    assert_eq!(shift_with_carry(8, 0xC000_0000, 31, 0), (1, true));
+    assert_eq!(shift_with_carry(8, 0x8000_0000, 32, 0), (0, true));
+    // asr: carry is bit 0 of the operand whatever the amount.
+    assert_eq!(shift_with_carry(9, 0x0000_0003, 0, 0), (3, true));
+    assert_eq!(shift_with_carry(9, 0x0000_0002, 2, 0), (0, false));
+    assert_eq!(shift_with_carry(9, 0x8000_0001, 31, 0), (0xFFFF_FFFF, true));
+    assert_eq!(
+        shift_with_carry(9, 0x8000_0000, 32, 0),
+        (0xFFFF_FFFF, false)
+    );
+    assert_eq!(shift_with_carry(9, 0x4000_0001, 32, 0), (0, true));
+}
+
+#[test]
+fn rotates_and_carry_shifts_by_large_amounts() {
+    // rotl/rotr wrap the amount modulo 32.
+    assert_eq!(
+        shift_with_carry(10, 0x8000_0001, 32, 0),
+        (0x8000_0001, true)
+    );
+    assert_eq!(
+        shift_with_carry(10, 0x8000_0001, 33, 0),
+        (0x0000_0003, true)
+    );
+    assert_eq!(
+        shift_with_carry(11, 0x8000_0001, 31, 0),
+        (0x0000_0003, true)
+    );
+    assert_eq!(
+        shift_with_carry(11, 0x8000_0001, 64, 0),
+        (0x8000_0001, false)
+    );
+    // lslc/lsrc shift the previous carry in, also for a zero shift of lsrc.
+    assert_eq!(
+        shift_with_carry(12, 0x0000_0001, 32, 1),
+        (0x8000_0000, true)
+    );
+    assert_eq!(
+        shift_with_carry(13, 0x8000_0000, 0, 1),
+        (0x8000_0001, false)
+    );
+    assert_eq!(
+        shift_with_carry(13, 0x0000_0001, 32, 1),
+        (0x0000_0001, true)
+    );
+}
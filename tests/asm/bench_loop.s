@@ -0,0 +1,12 @@
+  .global _start
+  .origin 0x400
+  jmp _start
+_start:
+  add  r1 r0 0
+  add  r3 r0 1
+  lsl  r3 r3 18     # 262144 iterations
+loop:
+  add  r1 r1 r3     # sum 262144 + ... + 1
+  add  r3 r3 -1
+  bnz  loop
+  mode halt         # result: 0x20000 (too long for the corpus cycle cap)
@@ -0,0 +1,10 @@
+  .global _start
+  .origin 0x400
+  jmp _start
+_start:
+  add  r3 r0 6
+  add  r4 r0 7
+  add  r5 r3 r4     # 13
+  lsl  r5 r5 2      # 52
+  add  r1 r5 -10
+  mode halt         # expect: 0x2A
@@ -0,0 +1,11 @@
+  .global _start
+  .origin 0x400
+  jmp _start
+_start:
+  add  r1 r0 0
+  add  r3 r0 10
+loop:
+  add  r1 r1 r3     # sum 10 + 9 + ... + 1
+  add  r3 r3 -1
+  bnz  loop
+  mode halt         # result is in corpus_branch.expected
@@ -0,0 +1,12 @@
+  .global _start
+  .origin 0x400
+  jmp _start
+_start:
+  lw   r3, [VALUE]
+  add  r3 r3 1
+  sw   r3, [SLOT]
+  lw   r1, [SLOT]
+  mode halt         # expect: 0x65
+
+VALUE: .fill 100
+SLOT:  .fill 0
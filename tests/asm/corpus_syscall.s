@@ -0,0 +1,22 @@
+  .define IVT_TRAP 0x04
+
+  .global _start
+  .origin 0x400
+  jmp _start
+_start:
+  lw   r22, [TRAP_PTR]
+  add  r23 r0 IVT_TRAP
+  swa  r22, [r23]
+
+  add  r1 r0 0      # trap code
+  add  r2 r0 20     # trap argument
+  trap              # handler returns 2 * argument in r1
+  add  r1 r1 1
+  mode halt         # expect: 41
+
+TRAP_HANDLER:
+  add  r1 r2 r2
+  rfe
+
+TRAP_PTR:
+  .fill TRAP_HANDLER
@@ -42,5 +42,5 @@ EXC_PRIV:
   .origin 0x1000
 userland:
   movi r1, 0x42
-  .fill 0xEEEEEEEE
+  .fill 0xB8000000 # signed store, a reserved encoding
   mode halt
@@ -15,7 +15,7 @@ EXC_INSTR:
 _start:
   movi r3, 0x42
   # bad instruction
-  .fill 0xEEEEEEEE
+  .fill 0xB8000000 # signed store, a reserved encoding
 
   # handler should return here
   add  r3, r3, 2
@@ -0,0 +1,69 @@
+mod common;
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::find_emulator_bin;
+
+// jmp + 3 setup instructions + 3 per iteration for 2^18 iterations + mode halt.
+const BENCH_LOOP_INSTRUCTIONS: u64 = 1 + 3 + 3 * (1 << 18) + 1;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+// Parse "Bench: <n> instructions, <c> cycles in <s> s (<m> MIPS)".
+fn parse_bench_line(stderr: &str) -> (u64, u32, f64, f64) {
+    let line = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("Bench: "))
+        .unwrap_or_else(|| panic!("no bench summary in stderr: {}", stderr));
+    let words: Vec<&str> = line.split_whitespace().collect();
+    assert_eq!(words[1], "instructions,", "line: {}", line);
+    assert_eq!(words[3], "cycles", "line: {}", line);
+    (
+        words[0].parse().unwrap(),
+        words[2].parse().unwrap(),
+        words[5].parse().unwrap(),
+        words[7].trim_start_matches('(').parse().unwrap(),
+    )
+}
+
+#[test]
+fn bench_reports_throughput_of_a_compute_loop() {
+    let output = Command::new(find_emulator_bin())
+        .arg(fixture("bench_loop.hex"))
+        .arg("--bench")
+        .output()
+        .expect("failed to run emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+    assert_eq!(stdout.trim(), "00020000");
+    let (instructions, cycles, secs, mips) = parse_bench_line(&stderr);
+    assert_eq!(instructions, BENCH_LOOP_INSTRUCTIONS);
+    assert!(u64::from(cycles) >= instructions);
+    assert!(secs > 0.0 && mips > 0.0, "stderr: {}", stderr);
+}
+
+#[test]
+fn bench_stops_at_the_cycle_cap() {
+    let output = Command::new(find_emulator_bin())
+        .arg(fixture("bench_loop.hex"))
+        .args(["--bench", "--max-cycles", "1000"])
+        .output()
+        .expect("failed to run emulator");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_ne!(output.status.code(), Some(0));
+    let (instructions, _, _, _) = parse_bench_line(&stderr);
+    assert!(
+        instructions > 0 && instructions <= 1001,
+        "stderr: {}",
+        stderr
+    );
+}
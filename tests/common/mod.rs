@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Once;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Search for the emulator binary in Cargo's env vars and target dirs.
+fn locate_emulator_bin() -> (Option<PathBuf>, Vec<PathBuf>) {
+    let name = "Dioptase-Emulator-Full";
+    let direct = format!("CARGO_BIN_EXE_{}", name);
+    if let Ok(val) = std::env::var(&direct) {
+        let path = PathBuf::from(val);
+        if path.exists() {
+            return (Some(path), Vec::new());
+        }
+    }
+    let underscored = format!("CARGO_BIN_EXE_{}", name.replace('-', "_"));
+    if let Ok(val) = std::env::var(&underscored) {
+        let path = PathBuf::from(val);
+        if path.exists() {
+            return (Some(path), Vec::new());
+        }
+    }
+
+    let mut name_candidates = Vec::new();
+    name_candidates.push(name.to_string());
+    name_candidates.push(name.to_ascii_lowercase());
+    name_candidates.push(name.replace('-', "_"));
+    name_candidates.push(name.replace('-', "_").to_ascii_lowercase());
+
+    let mut dirs = Vec::new();
+    if let Ok(dir) = std::env::var("CARGO_TARGET_DIR") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Ok(dir) = std::env::var("CARGO_MANIFEST_DIR") {
+        let manifest = PathBuf::from(dir);
+        dirs.push(manifest.join("target"));
+        if let Some(parent) = Path::new(&manifest).parent() {
+            dirs.push(parent.join("target"));
+        }
+    }
+
+    let mut tried = Vec::new();
+    for dir in dirs {
+        for name in &name_candidates {
+            for suffix in ["", ".exe"] {
+                let candidate = dir.join("debug").join(format!("{}{}", name, suffix));
+                if candidate.exists() {
+                    return (Some(candidate), tried);
+                }
+                tried.push(candidate);
+            }
+        }
+    }
+
+    (None, tried)
+}
+
+// Build the emulator binary once if it isn't found.
+fn build_emulator_bin() {
+    static BUILD: Once = Once::new();
+    BUILD.call_once(|| {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set");
+        let status = Command::new("cargo")
+            .args(["build", "--bin", "Dioptase-Emulator-Full"])
+            .current_dir(manifest_dir)
+            .status()
+            .expect("failed to run cargo build");
+        assert!(status.success(), "failed to build emulator binary");
+    });
+}
+
+pub fn find_emulator_bin() -> PathBuf {
+    let (found, _) = locate_emulator_bin();
+    if let Some(path) = found {
+        return path;
+    }
+
+    // If the test runs without a built binary, compile it on demand.
+    build_emulator_bin();
+    let (found, tried) = locate_emulator_bin();
+    if let Some(path) = found {
+        return path;
+    }
+
+    let tried_list = tried
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    panic!(
+        "Missing emulator binary env var for Dioptase-Emulator-Full\nTried:\n{}",
+        tried_list
+    );
+}
+
+// Unique scratch file path under the system temp dir; not every test binary
+// that includes this module writes files.
+#[allow(dead_code)]
+pub fn temp_path(name: &str) -> PathBuf {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_nanos();
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "dioptase_full_{}_{}_{}",
+        std::process::id(),
+        stamp,
+        name
+    ));
+    path
+}
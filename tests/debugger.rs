@@ -1,99 +1,12 @@
+mod common;
+
 use std::fs;
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::sync::Once;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-// Search for the emulator binary in Cargo's env vars and target dirs.
-fn locate_emulator_bin() -> (Option<PathBuf>, Vec<PathBuf>) {
-    let name = "Dioptase-Emulator-Full";
-    let direct = format!("CARGO_BIN_EXE_{}", name);
-    if let Ok(val) = std::env::var(&direct) {
-        let path = PathBuf::from(val);
-        if path.exists() {
-            return (Some(path), Vec::new());
-        }
-    }
-    let underscored = format!("CARGO_BIN_EXE_{}", name.replace('-', "_"));
-    if let Ok(val) = std::env::var(&underscored) {
-        let path = PathBuf::from(val);
-        if path.exists() {
-            return (Some(path), Vec::new());
-        }
-    }
-
-    let mut name_candidates = Vec::new();
-    name_candidates.push(name.to_string());
-    name_candidates.push(name.to_ascii_lowercase());
-    name_candidates.push(name.replace('-', "_"));
-    name_candidates.push(name.replace('-', "_").to_ascii_lowercase());
-
-    let mut dirs = Vec::new();
-    if let Ok(dir) = std::env::var("CARGO_TARGET_DIR") {
-        dirs.push(PathBuf::from(dir));
-    }
-    if let Ok(dir) = std::env::var("CARGO_MANIFEST_DIR") {
-        let manifest = PathBuf::from(dir);
-        dirs.push(manifest.join("target"));
-        if let Some(parent) = Path::new(&manifest).parent() {
-            dirs.push(parent.join("target"));
-        }
-    }
-
-    let mut tried = Vec::new();
-    for dir in dirs {
-        for name in &name_candidates {
-            for suffix in ["", ".exe"] {
-                let candidate = dir.join("debug").join(format!("{}{}", name, suffix));
-                if candidate.exists() {
-                    return (Some(candidate), tried);
-                }
-                tried.push(candidate);
-            }
-        }
-    }
-
-    (None, tried)
-}
-
-// Build the emulator binary once if it isn't found.
-fn build_emulator_bin() {
-    static BUILD: Once = Once::new();
-    BUILD.call_once(|| {
-        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set");
-        let status = Command::new("cargo")
-            .args(["build", "--bin", "Dioptase-Emulator-Full"])
-            .current_dir(manifest_dir)
-            .status()
-            .expect("failed to run cargo build");
-        assert!(status.success(), "failed to build emulator binary");
-    });
-}
-
-fn find_emulator_bin() -> PathBuf {
-    let (found, _) = locate_emulator_bin();
-    if let Some(path) = found {
-        return path;
-    }
-
-    // If the test runs without a built binary, compile it on demand.
-    build_emulator_bin();
-    let (found, tried) = locate_emulator_bin();
-    if let Some(path) = found {
-        return path;
-    }
-
-    let tried_list = tried
-        .iter()
-        .map(|p| p.display().to_string())
-        .collect::<Vec<_>>()
-        .join("\n");
-    panic!(
-        "Missing emulator binary env var for Dioptase-Emulator-Full\nTried:\n{}",
-        tried_list
-    );
-}
+use common::find_emulator_bin;
 
 fn write_temp_debug(contents: &str) -> PathBuf {
     let stamp = SystemTime::now()
@@ -165,3 +78,308 @@ q
 
     let _ = fs::remove_file(debug_file);
 }
+
+#[test]
+fn debug_script_runs_commands_then_reads_stdin() {
+    // 0x400: add r1, r0, 5; 0x404: mode halt
+    let debug_file = write_temp_debug("@00000100\n0840E005\nF8002800\n#label done 00000404\n");
+    let mut nested = debug_file.clone();
+    nested.set_extension("nested.dbgscript");
+    fs::write(&nested, "# sourced from the main script\ninfo r1\n")
+        .expect("failed to write nested script");
+    let mut script = debug_file.clone();
+    script.set_extension("dbgscript");
+    fs::write(
+        &script,
+        format!(
+            "# stop before the halt\nbreak done\n\nr\nsource {}\n",
+            nested.display()
+        ),
+    )
+    .expect("failed to write script");
+    let bin = find_emulator_bin();
+
+    // stdin is closed, so the debugger exits once the script runs out.
+    let output = Command::new(bin)
+        .arg("--debug-script")
+        .arg(&script)
+        .arg(&debug_file)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    assert!(stdout.contains("dbg> break done"));
+    assert!(stdout.contains("Breakpoint set at 00000404"));
+    assert!(stdout.contains("dbg> info r1"));
+    assert!(stdout.contains("r1 = 00000005"));
+    assert!(!stdout.contains("stop before the halt"));
+
+    let _ = fs::remove_file(debug_file);
+    let _ = fs::remove_file(script);
+    let _ = fs::remove_file(nested);
+}
+
+#[test]
+fn fill_and_zero_write_ranges_seen_by_x() {
+    let debug_file = write_temp_debug("@00000100\nF8002800\n");
+    let bin = find_emulator_bin();
+
+    let mut child = Command::new(bin)
+        .arg("--debug")
+        .arg(&debug_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start emulator");
+
+    let commands = "\
+fill 0x2000 20 0xAB
+zero p 0x2002 3
+x p 0x2000 20
+fill 0x2000 4
+fill 0x2000 4 0x100
+q
+";
+    {
+        let mut stdin = child.stdin.take().expect("missing stdin");
+        stdin
+            .write_all(commands.as_bytes())
+            .expect("failed to write commands");
+    }
+
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait on emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    assert!(stdout.contains("Filled 20 bytes at 00002000 with AB"));
+    assert!(stdout.contains("Filled 3 bytes at 00002002 with 00"));
+    assert!(stdout.contains("00002000: AB AB 00 00 00 AB AB AB AB AB AB AB AB AB AB AB"));
+    assert!(stdout.contains("00002010: AB AB AB AB"));
+    assert!(stdout.contains("Usage: fill [v|p] <addr> <len> <byte>"));
+    assert!(stdout.contains("Invalid byte 0x100"));
+
+    let _ = fs::remove_file(debug_file);
+}
+
+#[test]
+fn back_undoes_steps_and_stores() {
+    // add r1, r0, 5; sw r1, [r0 + 0x200]; add r1, r1, 1; mode halt
+    let debug_file = write_temp_debug("@00000100\n0840E005\n18400200\n0842E001\nF8002800\n");
+    let bin = find_emulator_bin();
+
+    let mut child = Command::new(bin)
+        .arg("--debug")
+        .arg(&debug_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start emulator");
+
+    let commands = "\
+c
+info r1
+back 2
+info r1
+x p 0x200 4
+back 5
+rn
+back x
+q
+";
+    {
+        let mut stdin = child.stdin.take().expect("missing stdin");
+        stdin
+            .write_all(commands.as_bytes())
+            .expect("failed to write commands");
+    }
+
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait on emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    assert!(stdout.contains("r1 = 00000006"));
+    assert!(stdout.contains("=> 00000408: 0842E001  add r1, r1, 1"));
+    assert!(stdout.contains("r1 = 00000005"));
+    assert!(stdout.contains("00000200: 05 00 00 00"));
+    assert!(stdout.contains("Stepped back 2 of 5 instructions; history exhausted."));
+    assert!(stdout.contains("=> 00000400: 0840E005  add r1, r0, 5"));
+    assert!(stdout.contains("No history to step back through."));
+    assert!(stdout.contains("Usage: back [count]"));
+
+    let _ = fs::remove_file(debug_file);
+}
+
+#[test]
+fn step_count_runs_straight_line_code() {
+    // add r1, r1, 1 six times, then mode halt
+    let debug_file = write_temp_debug(
+        "@00000100\n0842E001\n0842E001\n0842E001\n0842E001\n0842E001\n0842E001\nF8002800\n",
+    );
+    let bin = find_emulator_bin();
+
+    let mut child = Command::new(bin)
+        .arg("--debug")
+        .arg(&debug_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start emulator");
+
+    let commands = "\
+n 5
+info pc
+info r1
+si 1 all
+n 4
+n 0
+n 2 every
+q
+";
+    {
+        let mut stdin = child.stdin.take().expect("missing stdin");
+        stdin
+            .write_all(commands.as_bytes())
+            .expect("failed to write commands");
+    }
+
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait on emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    // Only the fifth instruction is printed.
+    assert!(!stdout.contains("00000400: 0842E001"));
+    assert!(stdout.contains("00000410: 0842E001  add r1, r1, 1"));
+    assert!(stdout.contains("pc = 00000414"));
+    assert!(stdout.contains("r1 = 00000005"));
+    assert!(stdout.contains("00000414: 0842E001  add r1, r1, 1"));
+    assert!(stdout.contains("Program halted"));
+    assert!(stdout.contains("Stopped after 1 of 4 instructions."));
+    assert!(stdout.contains("Usage: n [count] [all]"));
+
+    let _ = fs::remove_file(debug_file);
+}
+
+#[test]
+fn display_prints_after_each_step() {
+    // add r1, r0, 5; sw r1, [r0 + 0x200]; add r1, r1, 1; mode halt
+    let debug_file = write_temp_debug("@00000100\n0840E005\n18400200\n0842E001\nF8002800\n");
+    let bin = find_emulator_bin();
+
+    let mut child = Command::new(bin)
+        .arg("--debug")
+        .arg(&debug_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start emulator");
+
+    let commands = "\
+display r1
+display p 0x200
+display
+n
+n
+undisplay 1
+n
+undisplay 7
+display v
+q
+";
+    {
+        let mut stdin = child.stdin.take().expect("missing stdin");
+        stdin
+            .write_all(commands.as_bytes())
+            .expect("failed to write commands");
+    }
+
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait on emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    assert!(stdout.contains("1: r1 = 00000000"));
+    assert!(stdout.contains("2: paddr 00000200 = 00000000"));
+    assert!(stdout.contains("2: p 00000200"));
+    assert!(stdout.contains("1: r1 = 00000005"));
+    assert!(stdout.contains("2: paddr 00000200 = 00000005"));
+    assert!(stdout.contains("Display 1 removed"));
+    assert!(!stdout.contains("1: r1 = 00000006"));
+    assert!(stdout.contains("No display 7"));
+    assert!(stdout.contains("Usage: display [<reg>|[v|p] <addr>]"));
+
+    let _ = fs::remove_file(debug_file);
+}
+
+#[test]
+fn diffregs_reports_only_changed_registers() {
+    // add r1, r0, 5; sw r1, [r0 + 0x200]; add r1, r1, 1; mode halt
+    let debug_file = write_temp_debug("@00000100\n0840E005\n18400200\n0842E001\nF8002800\n");
+    let bin = find_emulator_bin();
+
+    let mut child = Command::new(bin)
+        .arg("--debug")
+        .arg(&debug_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start emulator");
+
+    let commands = "\
+set diffregs on
+n
+n
+n
+set diffregs off
+set diffregs maybe
+q
+";
+    {
+        let mut stdin = child.stdin.take().expect("missing stdin");
+        stdin
+            .write_all(commands.as_bytes())
+            .expect("failed to write commands");
+    }
+
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait on emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    assert!(stdout.contains("Register diffs after steps on"));
+    // The store changes no register, so only the two adds report r1.
+    let diffs: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.contains(" -> "))
+        .collect();
+    assert_eq!(
+        diffs,
+        ["r1: 00000000 -> 00000005", "r1: 00000005 -> 00000006"],
+        "stdout: {}",
+        stdout
+    );
+    assert!(stdout.contains("Register diffs after steps off"));
+    assert!(stdout.contains("Usage: set diffregs on|off"));
+
+    let _ = fs::remove_file(debug_file);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::{find_emulator_bin, temp_path};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+#[test]
+fn disasm_lists_the_program_without_running_it() {
+    let expected =
+        fs::read_to_string(fixture("disasm.expected")).expect("failed to read golden listing");
+
+    let output = Command::new(find_emulator_bin())
+        .arg("--disasm")
+        .arg(fixture("disasm.hex"))
+        .output()
+        .expect("failed to run emulator");
+    assert!(
+        output.status.success(),
+        "emulator exited with {}",
+        output.status
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    assert!(
+        !String::from_utf8_lossy(&output.stderr).contains("Halt reason"),
+        "--disasm must not execute the program"
+    );
+
+    let out_path = temp_path("listing.txt");
+    let output = Command::new(find_emulator_bin())
+        .arg(fixture("disasm.hex"))
+        .arg(format!("--disasm-out={}", out_path.display()))
+        .output()
+        .expect("failed to run emulator");
+    let written = fs::read_to_string(&out_path);
+    let _ = fs::remove_file(&out_path);
+    assert!(
+        output.status.success(),
+        "emulator exited with {}",
+        output.status
+    );
+    assert!(output.stdout.is_empty());
+    assert_eq!(written.expect("listing file was not written"), expected);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::{find_emulator_bin, temp_path};
+
+// Halts with r1 = 1 when started at the reset PC and r1 = 5 when started at
+// 0x1000.
+const TWO_ENTRY_PROGRAM: &str = "\
+@100
+0840e001
+f8002800
+@400
+0840e005
+f8002800
+";
+
+fn run_program(source: &str, args: &[&str]) -> String {
+    let program = temp_path("entry.hex");
+    fs::write(&program, source).expect("failed to write program");
+    let output = Command::new(find_emulator_bin())
+        .arg(&program)
+        .args(args)
+        .output()
+        .expect("failed to run emulator");
+    let _ = fs::remove_file(&program);
+    assert!(output.status.success(), "emulator failed: {:?}", output);
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn entry_flag_and_directive_choose_the_initial_pc() {
+    assert_eq!(run_program(TWO_ENTRY_PROGRAM, &[]).trim(), "00000001");
+    assert_eq!(
+        run_program(TWO_ENTRY_PROGRAM, &["--entry", "0x1000"]).trim(),
+        "00000005"
+    );
+
+    let with_directive = format!("#entry 00001000\n{}", TWO_ENTRY_PROGRAM);
+    assert_eq!(run_program(&with_directive, &[]).trim(), "00000005");
+    // The flag wins over the file.
+    assert_eq!(
+        run_program(&with_directive, &["--entry", "0x400"]).trim(),
+        "00000001"
+    );
+}
+
+#[test]
+fn extra_hex_files_load_at_their_own_addresses() {
+    // The main program branches from the reset PC to 0x1000, where the second
+    // file puts add r1, r0, 7; mode halt.
+    let kernel = temp_path("kernel.hex");
+    let user = temp_path("user.hex");
+    let clash = temp_path("clash.hex");
+    fs::write(&kernel, "@100\n600002ff\n").expect("failed to write program");
+    fs::write(&user, "@400\n0840e007\nf8002800\n").expect("failed to write program");
+    fs::write(&clash, "@100\n0840e009\nf8002800\n").expect("failed to write program");
+
+    let run = |args: &[&PathBuf]| {
+        let output = Command::new(find_emulator_bin())
+            .args(args)
+            .output()
+            .expect("failed to run emulator");
+        assert!(output.status.success(), "emulator failed: {:?}", output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let merged = run(&[&kernel, &user]);
+    // A later file that overlaps an earlier one wins, with a warning.
+    let overlapping = run(&[&kernel, &user, &clash]);
+    for path in [&kernel, &user, &clash] {
+        let _ = fs::remove_file(path);
+    }
+
+    assert_eq!(merged.trim(), "00000007");
+    assert!(!merged.contains("Warning"), "{}", merged);
+    assert!(
+        overlapping.contains("overwrites 4 bytes already loaded, starting at 0x00000400"),
+        "{}",
+        overlapping
+    );
+    assert!(
+        overlapping.trim_end().ends_with("00000009"),
+        "{}",
+        overlapping
+    );
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::{find_emulator_bin, temp_path};
+
+// `br .` at the reset PC: never halts.
+const SPIN_PROGRAM: &str = "\
+@100
+63ffffff
+";
+
+// Reads the unmapped device address 0x7FE580C, which panics the emulator:
+// lui r2, 0x1FF96; lbu r1, [r2 + 12]; mode halt
+const UNMAPPED_READ_PROGRAM: &str = "\
+@100
+1081ff96
+4845000c
+f8002800
+";
+
+fn run_spin(args: &[&str]) -> (Option<i32>, String) {
+    run_program(SPIN_PROGRAM, args)
+}
+
+fn run_program(source: &str, args: &[&str]) -> (Option<i32>, String) {
+    let program = temp_path("program.hex");
+    fs::write(&program, source).expect("failed to write program");
+    let output = Command::new(find_emulator_bin())
+        .arg(&program)
+        .args(args)
+        .output()
+        .expect("failed to run emulator");
+    let _ = fs::remove_file(&program);
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn max_iters_stops_a_program_that_never_halts() {
+    let (code, stdout) = run_spin(&["--max-iters", "1000"]);
+    assert_eq!(code, Some(2), "stdout: {}", stdout);
+    assert!(stdout.contains("max cycles reached"), "stdout: {}", stdout);
+}
+
+#[test]
+fn timeout_stops_a_program_that_never_halts() {
+    let (code, stdout) = run_spin(&["--timeout-secs=1"]);
+    assert_eq!(code, Some(3), "stdout: {}", stdout);
+    assert!(stdout.contains("timeout reached"), "stdout: {}", stdout);
+}
+
+#[test]
+fn emulator_panic_exits_with_its_own_status() {
+    for args in [&[][..], &["--cores", "2"][..]] {
+        let (code, stdout) = run_program(UNMAPPED_READ_PROGRAM, args);
+        assert_eq!(code, Some(4), "args {:?}, stdout: {}", args, stdout);
+        assert!(stdout.contains("emulator panic"), "stdout: {}", stdout);
+    }
+}
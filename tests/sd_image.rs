@@ -0,0 +1,72 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::{find_emulator_bin, temp_path};
+
+// Initialize SD0, DMA the word pair at 0x1000 into SD block 1, then halt
+// with SD_DMA_ERR in r1.
+const SD_WRITE_PROGRAM: &str = "\
+@100
+10c1ff96
+08c62010
+09002008
+1906000c
+19470010
+094a0001
+60bffffd
+09802110
+19860000
+09c02001
+19c60004
+19c60008
+0a402003
+1a46000c
+19470010
+094a0001
+60bffffd
+18470014
+f8002800
+@400
+deadbeef
+01234567
+";
+
+#[test]
+fn sd_image_is_loaded_and_written_back() {
+    let program = temp_path("sd_write.hex");
+    let image = temp_path("sd.bin");
+    fs::write(&program, SD_WRITE_PROGRAM).expect("failed to write program");
+    // Shorter than the block the program writes, so the tail is zero-filled.
+    let original: Vec<u8> = (0..700u32).map(|i| (i % 251) as u8).collect();
+    fs::write(&image, &original).expect("failed to write SD image");
+
+    let output = Command::new(find_emulator_bin())
+        .arg("--ram")
+        .arg(&program)
+        .arg("--sd-image")
+        .arg(&image)
+        .output()
+        .expect("failed to run emulator");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    assert_eq!(stdout.trim(), "00000000", "SD DMA reported an error");
+
+    let written = fs::read(&image).expect("failed to read SD image back");
+    assert_eq!(written.len(), 1024);
+    assert_eq!(
+        &written[..512],
+        &original[..512],
+        "block 0 must be preserved"
+    );
+    assert_eq!(
+        &written[512..520],
+        &[0xEF, 0xBE, 0xAD, 0xDE, 0x67, 0x45, 0x23, 0x01]
+    );
+    assert!(written[520..].iter().all(|&byte| byte == 0));
+
+    let _ = fs::remove_file(program);
+    let _ = fs::remove_file(image);
+}
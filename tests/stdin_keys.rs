@@ -0,0 +1,60 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use common::{find_emulator_bin, temp_path};
+
+// Sums PS2_STREAM events until Enter is pressed:
+//   lui r2, 0x7FE5800
+// loop:
+//   lda r3, [r2, 0]
+//   cmp r3, 0
+//   bz loop
+//   add r1, r1, r3
+//   cmp r3, 13
+//   bnz loop
+//   mode halt
+const SUM_KEYS_PROGRAM: &str = "\
+@100
+1081ff96
+30c50000
+08070000
+607ffffd
+004201c3
+0807000d
+60bffffa
+f8002800
+";
+
+#[test]
+fn stdin_keys_feed_a_headless_program() {
+    let program = temp_path("sum_keys.hex");
+    fs::write(&program, SUM_KEYS_PROGRAM).expect("failed to write program");
+
+    for cores in ["1", "2"] {
+        let mut child = Command::new(find_emulator_bin())
+            .arg(&program)
+            .args(["--stdin-keys", "--cores", cores, "--timeout-secs", "10"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("failed to run emulator");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"hi\n")
+            .expect("failed to write stdin");
+        let output = child.wait_with_output().expect("failed to wait");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        // h and i are pressed and released, then Enter is pressed:
+        // 0x68 + 0x168 + 0x69 + 0x169 + 0x0D.
+        assert_eq!(output.status.code(), Some(0), "stdout: {}", stdout);
+        assert_eq!(stdout.trim(), "000003af", "cores {}", cores);
+    }
+    let _ = fs::remove_file(&program);
+}
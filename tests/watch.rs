@@ -0,0 +1,50 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::{find_emulator_bin, temp_path};
+
+// lui r2, 0x7FE5B4C >> 10; sw r0, [r2 + 0x34C] (the CLK divider register);
+// add r1, r0, 7; mode halt
+const CLK_WRITE_PROGRAM: &str = "\
+@100
+1081ff96
+1804034c
+0840e007
+f8002800
+";
+
+#[test]
+fn watch_flag_reports_device_register_writes() {
+    let program = temp_path("clk_write.hex");
+    fs::write(&program, CLK_WRITE_PROGRAM).expect("failed to write program");
+
+    let output = Command::new(find_emulator_bin())
+        .arg(&program)
+        .args(["--watch", "w:0x7FE5B4E", "--watch=0x500", "--watch-dump"])
+        .output()
+        .expect("failed to run emulator");
+    let _ = fs::remove_file(&program);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "emulator failed: {}", stderr);
+    assert!(
+        stdout
+            .contains("[core 0] Watchpoint hit (write at 07FE5B4E = 00000000) pc 00000408 [clock]"),
+        "stdout: {}",
+        stdout
+    );
+    assert_eq!(
+        stdout.matches("Watchpoint hit").count(),
+        1,
+        "stdout: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("r02: 07FE5800"),
+        "--watch-dump prints registers"
+    );
+    assert!(stdout.ends_with("00000007\n"), "stdout: {}", stdout);
+}